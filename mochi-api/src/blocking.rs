@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::sync::OnceLock;
+
+use tokio::runtime::Runtime;
+
+use crate::models::{Bookmark, Card, CardId, CardPatch, Deck, DeckId, Template};
+use crate::{BulkResult, CardWithDeck, ChangedCard, Config, MochiError, ProgressHook};
+
+// Blocking API
+//
+// The client's calls for code without an async runtime, e.g. small scripts
+// or build scripts. Each one runs its async counterpart to completion on a
+// runtime shared by the process, so they must not be called from inside
+// async code: that panics, as with `reqwest::blocking`.
+
+type CardsByDeck = HashMap<DeckId, Box<[Card]>>;
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("mochi-blocking")
+            .build()
+            .expect("a tokio runtime for the blocking API")
+    })
+}
+
+fn wait<F: Future>(future: F) -> F::Output {
+    runtime().block_on(future)
+}
+
+pub fn list_decks(config: &Config) -> Result<Box<[Deck]>, Box<dyn Error>> {
+    wait(crate::list_decks(config))
+}
+
+pub fn list_templates(config: &Config) -> Result<Box<[Template]>, Box<dyn Error>> {
+    wait(crate::list_templates(config))
+}
+
+pub fn list_cards(
+    config: &Config,
+    deck_id: &DeckId,
+    limit: Option<usize>,
+) -> Result<Box<[Card]>, Box<dyn Error>> {
+    wait(crate::list_cards(config, deck_id, limit))
+}
+
+pub fn list_cards_for_decks(
+    config: &Config,
+    deck_ids: &[DeckId],
+    per_deck_limit: Option<usize>,
+) -> Result<CardsByDeck, Box<dyn Error>> {
+    wait(crate::list_cards_for_decks(
+        config,
+        deck_ids,
+        per_deck_limit,
+    ))
+}
+
+pub fn list_all_cards(config: &Config) -> Result<Box<[CardWithDeck]>, Box<dyn Error>> {
+    wait(crate::list_all_cards(config))
+}
+
+pub fn list_cards_page(
+    config: &Config,
+    deck_id: &DeckId,
+    bookmark: Option<&Bookmark>,
+    limit: Option<usize>,
+) -> Result<(Vec<Card>, Option<Bookmark>), MochiError> {
+    wait(crate::list_cards_page(config, deck_id, bookmark, limit))
+}
+
+pub fn get_card(config: &Config, card_id: &CardId) -> Result<Card, MochiError> {
+    wait(crate::get_card(config, card_id))
+}
+
+pub fn create_deck(config: &Config, deck: &Deck) -> Result<Deck, MochiError> {
+    wait(crate::create_deck(config, deck))
+}
+
+pub fn update_deck(config: &Config, deck: &Deck) -> Result<Deck, MochiError> {
+    wait(crate::update_deck(config, deck))
+}
+
+pub fn create_template(config: &Config, template: &Template) -> Result<Template, MochiError> {
+    wait(crate::create_template(config, template))
+}
+
+pub fn create_card(config: &Config, card: &Card) -> Result<Card, MochiError> {
+    wait(crate::create_card(config, card))
+}
+
+pub fn update_card_fields(
+    config: &Config,
+    card_id: &CardId,
+    patch: &CardPatch,
+) -> Result<Card, MochiError> {
+    wait(crate::update_card_fields(config, card_id, patch))
+}
+
+pub fn add_attachment(
+    config: &Config,
+    card_id: &CardId,
+    filename: &str,
+    bytes: Vec<u8>,
+) -> Result<(), MochiError> {
+    wait(crate::add_attachment(config, card_id, filename, bytes))
+}
+
+pub fn get_attachment(
+    config: &Config,
+    card_id: &CardId,
+    filename: &str,
+) -> Result<Vec<u8>, MochiError> {
+    wait(crate::get_attachment(config, card_id, filename))
+}
+
+pub fn update_cards(config: &Config, cards: &[Card]) -> BulkResult {
+    wait(crate::update_cards(config, cards))
+}
+
+pub fn patch_cards(config: &Config, patches: &[(CardId, CardPatch)]) -> BulkResult {
+    wait(crate::patch_cards(config, patches))
+}
+
+pub fn patch_cards_with_progress(
+    config: &Config,
+    patches: &[(CardId, CardPatch)],
+    progress: Option<&ProgressHook>,
+) -> BulkResult {
+    wait(crate::patch_cards_with_progress(config, patches, progress))
+}
+
+pub fn update_changed_cards(
+    config: &Config,
+    changed: &[ChangedCard],
+    progress: Option<&ProgressHook>,
+) -> BulkResult {
+    wait(crate::update_changed_cards(config, changed, progress))
+}
+
+pub fn archive_cards(config: &Config, card_ids: &[CardId]) -> BulkResult {
+    wait(crate::archive_cards(config, card_ids))
+}
+
+pub fn unarchive_cards(config: &Config, card_ids: &[CardId]) -> BulkResult {
+    wait(crate::unarchive_cards(config, card_ids))
+}
+
+pub fn trash_cards(config: &Config, card_ids: &[CardId]) -> BulkResult {
+    wait(crate::trash_cards(config, card_ids))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockMochiServer;
+
+    #[test]
+    fn test_blocking() {
+        // The mock server runs on its own runtime; the calls block this thread.
+        let server_runtime = Runtime::new().unwrap();
+        let server = server_runtime
+            .block_on(MockMochiServer::with_fixture())
+            .unwrap();
+        let config = server.config();
+
+        assert_eq!(list_decks(&config).unwrap().len(), 3);
+        let cards = list_cards(&config, &"N5DECK".into(), None).unwrap();
+        assert_eq!(cards.len(), 3);
+
+        let result = archive_cards(&config, &[cards[0].id.clone()]);
+        assert!(result.is_success());
+        assert!(get_card(&config, &cards[0].id).unwrap().archived);
+    }
+}
@@ -0,0 +1,35 @@
+use keyring::Entry;
+
+// Keyring Credentials
+//
+// The API key of each profile can be kept in the OS credential store instead
+// of the config file: under the `mochi-utils` service, with the profile name
+// (or `default`) as the user. `Config::build` checks MOCHI_KEY first, then
+// the keyring, then the profile's `api_key`.
+
+pub const KEYRING_SERVICE: &str = "mochi-utils";
+
+fn entry(profile: Option<&str>) -> keyring::Result<Entry> {
+    Entry::new(KEYRING_SERVICE, profile.unwrap_or("default"))
+}
+
+// None if no key is stored for the profile.
+pub fn load_api_key(profile: Option<&str>) -> keyring::Result<Option<String>> {
+    match entry(profile)?.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn store_api_key(profile: Option<&str>, key: &str) -> keyring::Result<()> {
+    entry(profile)?.set_password(key)
+}
+
+// Deleting a key that isn't stored isn't an error.
+pub fn delete_api_key(profile: Option<&str>) -> keyring::Result<()> {
+    match entry(profile)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
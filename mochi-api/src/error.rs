@@ -0,0 +1,130 @@
+use std::error::Error;
+use std::fmt;
+
+use reqwest::StatusCode;
+use serde_json::Value;
+
+#[derive(Debug)]
+pub enum MochiError {
+    // The request could not be sent or the response could not be read.
+    Http(reqwest::Error),
+    // The API answered with a non-success status.
+    Api { status: StatusCode, body: Value },
+    Json(serde_json::Error),
+    // A lookup by name found nothing, e.g. kind "deck".
+    NotFound { kind: &'static str, name: String },
+    // The request body is over the API's limit. `limit` is None when the API
+    // rejected the body rather than the local check.
+    PayloadTooLarge { bytes: usize, limit: Option<usize> },
+    // A bulk helper's task for the card panicked or was cancelled.
+    Task(tokio::task::JoinError),
+}
+
+impl fmt::Display for MochiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MochiError::Http(err) => write!(f, "HTTP error: {}", err),
+            MochiError::Api { status, body } => {
+                write!(f, "API error {} with body {}", status, body)
+            }
+            MochiError::Json(err) => write!(f, "JSON error: {}", err),
+            MochiError::NotFound { kind, name } => write!(f, "no {} named {}", kind, name),
+            MochiError::PayloadTooLarge { bytes, limit } => match limit {
+                Some(limit) => write!(f, "payload of {} bytes is over {} bytes", bytes, limit),
+                None => write!(f, "payload of {} bytes rejected as too large", bytes),
+            },
+            MochiError::Task(err) => write!(f, "task failed: {}", err),
+        }
+    }
+}
+
+impl Error for MochiError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MochiError::Http(err) => Some(err),
+            MochiError::Api { .. } => None,
+            MochiError::Json(err) => Some(err),
+            MochiError::Task(err) => Some(err),
+            MochiError::NotFound { .. } | MochiError::PayloadTooLarge { .. } => None,
+        }
+    }
+}
+
+impl MochiError {
+    // Whether repeating the same request might succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            MochiError::Http(err) => err.is_timeout() || err.is_connect(),
+            MochiError::Api { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            MochiError::Json(_)
+            | MochiError::NotFound { .. }
+            | MochiError::PayloadTooLarge { .. }
+            | MochiError::Task(_) => false,
+        }
+    }
+
+    // Turn a 413 from the API into `PayloadTooLarge` for a body of `bytes`.
+    pub(crate) fn with_payload_size(self, bytes: usize) -> MochiError {
+        match self {
+            MochiError::Api { status, .. } if status == StatusCode::PAYLOAD_TOO_LARGE => {
+                MochiError::PayloadTooLarge { bytes, limit: None }
+            }
+            err => err,
+        }
+    }
+}
+
+impl From<reqwest::Error> for MochiError {
+    fn from(err: reqwest::Error) -> Self {
+        MochiError::Http(err)
+    }
+}
+
+impl From<serde_json::Error> for MochiError {
+    fn from(err: serde_json::Error) -> Self {
+        MochiError::Json(err)
+    }
+}
+
+// A listing that failed part way, keeping everything fetched before the
+// failing page. Listing again from `bookmark` resumes where it stopped.
+#[derive(Debug)]
+pub struct PartialListError<T> {
+    pub docs: Vec<T>,
+    pub bookmark: Option<String>,
+    pub source: MochiError,
+}
+
+impl<T> fmt::Display for PartialListError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "listing failed after {} objects at bookmark {:?}: {}",
+            self.docs.len(),
+            self.bookmark,
+            self.source
+        )
+    }
+}
+
+impl<T: fmt::Debug> Error for PartialListError<T> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+// Turn a non-success response into an `Api` error carrying the response body.
+pub(crate) async fn check_response(
+    resp: reqwest::Response,
+) -> Result<reqwest::Response, MochiError> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+
+    let text = resp.text().await?;
+    let body = serde_json::from_str(text.as_str()).unwrap_or(Value::String(text));
+    Err(MochiError::Api { status, body })
+}
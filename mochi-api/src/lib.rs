@@ -0,0 +1,976 @@
+// Mochi API
+//
+// A client for the Mochi cards API (https://mochi.cards/docs/api/): the
+// models, settings and config file profiles, and the requests themselves.
+// Nothing here knows about Japanese; see mochi-jp for that.
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "keyring")]
+pub mod credentials;
+mod error;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod models;
+pub mod payload;
+pub mod profiles;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{cmp, env};
+
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use reqwest::Response;
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Semaphore;
+use tokio::task::{self, JoinSet};
+use tracing::{debug, info, warn};
+
+use crate::error::check_response;
+pub use crate::error::{MochiError, PartialListError};
+use crate::models::{
+    Bookmark, Card, CardId, CardPatch, Deck, DeckId, PaginatedResponse, Template, TemplateId,
+    Timestamp,
+};
+
+// API Client
+//
+// Settings, listing with pagination and retries, single requests, and bulk
+// updates a bounded number at a time.
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub mochi_key: String,
+    // Installed gallery templates, by gallery key.
+    pub templates: HashMap<String, TemplateId>,
+    // The config file profile the settings came from, if any.
+    pub profile: Option<String>,
+    // Deck id or path, for commands given no deck.
+    pub default_deck: Option<String>,
+    // Requests at once when updating cards in bulk.
+    pub bulk_concurrency: usize,
+    // Decks listed at once.
+    pub listing_concurrency: usize,
+    // The API's root, e.g. a mock server's address in tests.
+    pub base_url: String,
+    // For each request as a whole. None waits as long as it takes.
+    pub timeout: Option<Duration>,
+    // Proxy for every request, e.g. `http://proxy.corp:8080`. Without one
+    // the HTTP_PROXY and HTTPS_PROXY environment variables are used.
+    pub proxy: Option<String>,
+    // Trusted on top of the system's roots, e.g. a corporate proxy's CA.
+    pub root_certificates: Vec<reqwest::Certificate>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            mochi_key: String::new(),
+            templates: HashMap::new(),
+            profile: None,
+            default_deck: None,
+            bulk_concurrency: BULK_CONCURRENCY,
+            listing_concurrency: DECK_LISTING_CONCURRENCY,
+            base_url: MOCHI_BASE.to_string(),
+            timeout: None,
+            proxy: None,
+            root_certificates: vec![],
+        }
+    }
+}
+
+impl Config {
+    // The MOCHI_PROFILE (or default) profile of the config file, if any.
+    pub fn build() -> Result<Config, Box<dyn std::error::Error>> {
+        Config::build_for_profile(None)
+    }
+
+    // The named profile of the config file. The key is MOCHI_KEY if set, else
+    // the profile's key in the OS keyring (with the `keyring` feature), else
+    // its `api_key`. Without a config file MOCHI_KEY is all that's needed.
+    pub fn build_for_profile(name: Option<&str>) -> Result<Config, Box<dyn std::error::Error>> {
+        let file = match profiles::ConfigFile::default_path() {
+            Some(path) => profiles::ConfigFile::load(&path)?,
+            None => profiles::ConfigFile::default(),
+        };
+        let name = name
+            .map(str::to_string)
+            .or_else(|| env::var("MOCHI_PROFILE").ok());
+        let mut config = match file.profile(name.as_deref())? {
+            Some((name, profile)) => profile.to_config(name)?,
+            None => Config::default(),
+        };
+        if let Ok(base_url) = env::var("MOCHI_BASE_URL") {
+            config.base_url = base_url;
+        }
+        if let Ok(key) = env::var("MOCHI_KEY") {
+            config.mochi_key = key;
+        } else {
+            // A keyring that can't be reached falls through to the profile.
+            #[cfg(feature = "keyring")]
+            if let Ok(Some(key)) = crate::credentials::load_api_key(config.profile.as_deref()) {
+                config.mochi_key = key;
+            }
+        }
+        if config.mochi_key.is_empty() {
+            return Err("no API key: set MOCHI_KEY, store one in the keyring or set api_key in the config profile".into());
+        }
+        Ok(config)
+    }
+
+    // The API URL of `path`, e.g. `cards/abc`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    // A client with the config's timeout, proxy and root certificates.
+    pub fn http_client(&self) -> Result<reqwest::Client, MochiError> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        for cert in self.root_certificates.iter() {
+            builder = builder.add_root_certificate(cert.clone());
+        }
+        Ok(builder.build()?)
+    }
+}
+
+pub const MOCHI_BASE: &str = "https://app.mochi.cards/api/";
+
+// Sends the request, logging its method, endpoint, status and duration.
+pub(crate) async fn send(request: reqwest::RequestBuilder) -> Result<Response, MochiError> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let method = request.method().clone();
+    let endpoint = request.url().path().to_string();
+    let started = Instant::now();
+    let resp = client.execute(request).await;
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    match &resp {
+        Ok(resp) if resp.status().is_success() => {
+            debug!(%method, endpoint, status = resp.status().as_u16(), elapsed_ms, "request");
+        }
+        Ok(resp) => {
+            warn!(%method, endpoint, status = resp.status().as_u16(), elapsed_ms, "request failed");
+        }
+        Err(err) => warn!(%method, endpoint, elapsed_ms, error = %err, "request failed"),
+    }
+    Ok(resp?)
+}
+
+// LIST
+
+const MAX_PAGE_RETRIES: u32 = 3;
+const PAGE_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+// Every object of a paginated endpoint, e.g. `decks/`, up to the limit.
+pub async fn list<T>(
+    endpoint: String,
+    additional_args: &HashMap<String, serde_json::Value>,
+    config: &Config,
+    limit: Option<usize>,
+) -> Result<Box<[T]>, PartialListError<T>>
+where
+    T: for<'a> Deserialize<'a> + std::fmt::Debug,
+{
+    let mut mochi_objects: Vec<T> = vec![];
+    let client = config.http_client().map_err(|source| PartialListError {
+        docs: vec![],
+        bookmark: None,
+        source,
+    })?;
+    let mut bookmark: Option<String> = None;
+    loop {
+        // Retry the same page with exponential backoff before giving up.
+        let mut attempt = 0;
+        let page = loop {
+            match fetch_page(
+                &client,
+                &endpoint,
+                additional_args,
+                config,
+                bookmark.as_ref(),
+            )
+            .await
+            {
+                Ok(page) => break page,
+                Err(err) if err.is_retryable() && attempt < MAX_PAGE_RETRIES => {
+                    warn!(endpoint, retry = attempt + 1, error = %err, "retrying page");
+                    tokio::time::sleep(PAGE_RETRY_BACKOFF * 2u32.pow(attempt)).await;
+                    attempt += 1;
+                }
+                Err(err) => {
+                    return Err(PartialListError {
+                        docs: mochi_objects,
+                        bookmark,
+                        source: err,
+                    })
+                }
+            }
+        };
+
+        if page.docs.is_empty() {
+            break;
+        }
+
+        debug!(endpoint, objects = page.docs.len(), "page");
+        mochi_objects.extend(page.docs);
+        bookmark = page.bookmark;
+
+        if let Some(limit) = limit {
+            if mochi_objects.len() >= limit {
+                mochi_objects.truncate(limit);
+                return Ok(mochi_objects.into_boxed_slice());
+            }
+        }
+    }
+
+    Ok(mochi_objects.into_boxed_slice())
+}
+
+async fn fetch_page<T>(
+    client: &reqwest::Client,
+    endpoint: &str,
+    additional_args: &HashMap<String, serde_json::Value>,
+    config: &Config,
+    bookmark: Option<&String>,
+) -> Result<PaginatedResponse<T>, MochiError>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    let url = config.url(endpoint);
+    let mut query_args = additional_args
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect::<Vec<_>>();
+    if let Some(bookmark) = bookmark {
+        query_args.push(("bookmark".to_string(), serde_json::to_value(bookmark)?));
+    }
+
+    let resp = send(
+        client
+            .get(url)
+            .basic_auth(&config.mochi_key, Some(""))
+            .query(&query_args),
+    )
+    .await?;
+
+    let resp = check_response(resp).await?;
+    Ok(resp.json::<PaginatedResponse<T>>().await?)
+}
+
+// Yields objects as their pages arrive instead of buffering every page.
+pub fn stream<'a, T>(
+    endpoint: &'a str,
+    additional_args: HashMap<String, serde_json::Value>,
+    config: &'a Config,
+) -> impl Stream<Item = Result<T, MochiError>> + 'a
+where
+    T: for<'de> Deserialize<'de> + 'a,
+{
+    // The client is built with the first page, so a bad proxy is the
+    // stream's first error.
+    let pages = stream::try_unfold(
+        (
+            None::<reqwest::Client>,
+            additional_args,
+            None::<String>,
+            false,
+        ),
+        move |(client, args, bookmark, done)| async move {
+            if done {
+                return Ok(None);
+            }
+
+            let client = match client {
+                Some(client) => client,
+                None => config.http_client()?,
+            };
+            let page: PaginatedResponse<T> =
+                fetch_page(&client, endpoint, &args, config, bookmark.as_ref()).await?;
+            let done = page.docs.is_empty();
+            Ok::<_, MochiError>(Some((page.docs, (Some(client), args, page.bookmark, done))))
+        },
+    );
+
+    pages
+        .map_ok(|docs| stream::iter(docs.into_iter().map(Ok)))
+        .try_flatten()
+}
+
+pub async fn list_decks(config: &Config) -> Result<Box<[Deck]>, Box<dyn Error>> {
+    let additional_args = HashMap::new();
+    let decks = list("decks".to_string(), &additional_args, config, None).await?;
+    Ok(decks)
+}
+
+pub async fn list_templates(config: &Config) -> Result<Box<[Template]>, Box<dyn Error>> {
+    let additional_args = HashMap::new();
+    let templates = list("templates".to_string(), &additional_args, config, None).await?;
+    Ok(templates)
+}
+
+// The query of a card listing for one deck.
+pub fn card_args(deck_id: &DeckId, limit: Option<usize>) -> HashMap<String, serde_json::Value> {
+    let per_call_limit = cmp::min(limit.unwrap_or(100), 100); // Max allowed is 100.
+    HashMap::from([
+        (
+            "deck-id".to_string(),
+            serde_json::to_value(deck_id).unwrap(),
+        ),
+        (
+            "limit".to_string(),
+            serde_json::to_value(per_call_limit).unwrap(),
+        ),
+    ])
+}
+
+pub async fn list_cards(
+    config: &Config,
+    deck_id: &DeckId,
+    limit: Option<usize>,
+) -> Result<Box<[Card]>, Box<dyn Error>> {
+    let additional_args = card_args(deck_id, limit);
+    let cards = list("cards".to_string(), &additional_args, config, limit).await?;
+    Ok(cards)
+}
+
+const DECK_LISTING_CONCURRENCY: usize = 4;
+
+// List several decks at once, at most `listing_concurrency` at a time.
+pub async fn list_cards_for_decks(
+    config: &Config,
+    deck_ids: &[DeckId],
+    per_deck_limit: Option<usize>,
+) -> Result<HashMap<DeckId, Box<[Card]>>, Box<dyn Error>> {
+    let mut listings = stream::iter(deck_ids.iter())
+        .map(|deck_id| async move {
+            let cards = list_cards(config, deck_id, per_deck_limit).await;
+            (deck_id.clone(), cards)
+        })
+        .buffer_unordered(config.listing_concurrency);
+
+    let mut cards_by_deck = HashMap::with_capacity(deck_ids.len());
+    while let Some((deck_id, cards)) = listings.next().await {
+        cards_by_deck.insert(deck_id, cards?);
+    }
+
+    Ok(cards_by_deck)
+}
+
+#[derive(Debug, Clone)]
+pub struct CardWithDeck {
+    pub card: Card,
+    // None if the card's deck is not in the deck listing (e.g. trashed).
+    pub deck: Option<Deck>,
+}
+
+// List every card in the account. The cards endpoint lists across all decks
+// when no deck-id is given, so this is one paginated listing plus the decks.
+pub async fn list_all_cards(config: &Config) -> Result<Box<[CardWithDeck]>, Box<dyn Error>> {
+    let decks = list_decks(config).await?;
+    let decks: HashMap<&DeckId, &Deck> = decks.iter().map(|d| (&d.id, d)).collect();
+
+    let additional_args =
+        HashMap::from([("limit".to_string(), serde_json::to_value(100).unwrap())]);
+    let cards: Box<[Card]> = list("cards".to_string(), &additional_args, config, None).await?;
+
+    let cards = cards
+        .into_vec()
+        .into_iter()
+        .map(|card| CardWithDeck {
+            deck: decks.get(&card.deck_id).map(|d| (*d).clone()),
+            card,
+        })
+        .collect::<Vec<_>>();
+    Ok(cards.into_boxed_slice())
+}
+
+// Fetch a single page of cards. The returned bookmark resumes after this page
+// and is None once the deck is exhausted, so long jobs can checkpoint it.
+pub async fn list_cards_page(
+    config: &Config,
+    deck_id: &DeckId,
+    bookmark: Option<&Bookmark>,
+    limit: Option<usize>,
+) -> Result<(Vec<Card>, Option<Bookmark>), MochiError> {
+    let client = config.http_client()?;
+    let additional_args = card_args(deck_id, limit);
+    let page: PaginatedResponse<Card> =
+        fetch_page(&client, "cards", &additional_args, config, bookmark).await?;
+
+    if page.docs.is_empty() {
+        Ok((page.docs, None))
+    } else {
+        Ok((page.docs, page.bookmark))
+    }
+}
+
+pub fn stream_cards<'a>(
+    config: &'a Config,
+    deck_id: &DeckId,
+) -> impl Stream<Item = Result<Card, MochiError>> + 'a {
+    stream("cards", card_args(deck_id, None), config)
+}
+
+pub async fn get_card(config: &Config, card_id: &CardId) -> Result<Card, MochiError> {
+    let client = config.http_client()?;
+    let url = config.url(&format!("cards/{}", card_id));
+    let resp = send(client.get(url).basic_auth(&config.mochi_key, Some(""))).await?;
+
+    let resp = check_response(resp).await?;
+    Ok(resp.json::<Card>().await?)
+}
+
+// Create Decks.
+pub async fn create_deck(config: &Config, deck: &Deck) -> Result<Deck, MochiError> {
+    let client = config.http_client()?;
+    let url = config.url("decks/");
+    let resp = send(
+        client
+            .post(url)
+            .basic_auth(&config.mochi_key, Some(""))
+            .json(deck),
+    )
+    .await?;
+
+    let resp = check_response(resp).await?;
+    Ok(resp.json::<Deck>().await?)
+}
+
+// Create Templates.
+pub async fn create_template(config: &Config, template: &Template) -> Result<Template, MochiError> {
+    let client = config.http_client()?;
+    let url = config.url("templates/");
+    let resp = send(
+        client
+            .post(url)
+            .basic_auth(&config.mochi_key, Some(""))
+            .json(template),
+    )
+    .await?;
+
+    let resp = check_response(resp).await?;
+    Ok(resp.json::<Template>().await?)
+}
+
+// Update Decks.
+pub async fn update_deck(config: &Config, deck: &Deck) -> Result<Deck, MochiError> {
+    let client = config.http_client()?;
+    let url = config.url(&format!("decks/{}", deck.id));
+    let resp = send(
+        client
+            .post(url)
+            .basic_auth(&config.mochi_key, Some(""))
+            .json(deck),
+    )
+    .await?;
+
+    let resp = check_response(resp).await?;
+    Ok(resp.json::<Deck>().await?)
+}
+
+// Create Cards.
+pub async fn create_card(config: &Config, card: &Card) -> Result<Card, MochiError> {
+    let bytes = payload::check_payload(card)?;
+    let client = config.http_client()?;
+    let url = config.url("cards/");
+    let resp = send(
+        client
+            .post(url)
+            .basic_auth(&config.mochi_key, Some(""))
+            .json(card),
+    )
+    .await?;
+
+    let resp = check_response(resp)
+        .await
+        .map_err(|e| e.with_payload_size(bytes))?;
+    Ok(resp.json::<Card>().await?)
+}
+
+// Update Cards.
+pub async fn update_card(
+    config: Arc<Config>,
+    cards: Arc<[Card]>,
+    index: usize,
+) -> Result<Response, MochiError> {
+    let client = config.http_client()?;
+    let card = cards[index].clone();
+    let bytes = payload::check_payload(&card)?;
+    let url = config.url(&format!("cards/{}", card.id));
+    let resp = send(
+        client
+            .post(url)
+            .basic_auth(&config.mochi_key, Some(""))
+            .json(&card),
+    )
+    .await?;
+
+    check_response(resp)
+        .await
+        .map_err(|e| e.with_payload_size(bytes))
+}
+
+// Send only the given changes, leaving everything else on the card untouched.
+pub async fn update_card_fields(
+    config: &Config,
+    card_id: &CardId,
+    patch: &CardPatch,
+) -> Result<Card, MochiError> {
+    let bytes = payload::check_payload(patch)?;
+    let client = config.http_client()?;
+    let url = config.url(&format!("cards/{}", card_id));
+    let resp = send(
+        client
+            .post(url)
+            .basic_auth(&config.mochi_key, Some(""))
+            .json(patch),
+    )
+    .await?;
+
+    let resp = check_response(resp)
+        .await
+        .map_err(|e| e.with_payload_size(bytes))?;
+    Ok(resp.json::<Card>().await?)
+}
+
+// Attach a file to the card. Card content and fields refer to it as
+// `@media/<filename>`.
+pub async fn add_attachment(
+    config: &Config,
+    card_id: &CardId,
+    filename: &str,
+    bytes: Vec<u8>,
+) -> Result<(), MochiError> {
+    let client = config.http_client()?;
+    let url = config.url(&format!("cards/{}/attachments/{}", card_id, filename));
+    let part = reqwest::multipart::Part::bytes(bytes).file_name(filename.to_string());
+    let resp = send(
+        client
+            .post(url)
+            .basic_auth(&config.mochi_key, Some(""))
+            .multipart(reqwest::multipart::Form::new().part("file", part)),
+    )
+    .await?;
+
+    check_response(resp).await?;
+    Ok(())
+}
+
+pub async fn get_attachment(
+    config: &Config,
+    card_id: &CardId,
+    filename: &str,
+) -> Result<Vec<u8>, MochiError> {
+    let client = config.http_client()?;
+    let url = config.url(&format!("cards/{}/attachments/{}", card_id, filename));
+    let resp = send(client.get(url).basic_auth(&config.mochi_key, Some(""))).await?;
+
+    let resp = check_response(resp).await?;
+    Ok(resp.bytes().await?.to_vec())
+}
+
+#[derive(Debug, Default)]
+pub struct BulkResult {
+    pub succeeded: Vec<CardId>,
+    pub failed: Vec<(CardId, MochiError)>,
+}
+
+impl BulkResult {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    pub fn failed_ids(&self) -> Vec<CardId> {
+        self.failed.iter().map(|(id, _)| id.clone()).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub completed: usize,
+    pub total: usize,
+    pub last_card_id: CardId,
+}
+
+pub enum ProgressHook {
+    Callback(Box<dyn Fn(&Progress) + Send + Sync>),
+    Channel(UnboundedSender<Progress>),
+}
+
+impl ProgressHook {
+    pub fn emit(&self, progress: Progress) {
+        match self {
+            ProgressHook::Callback(callback) => callback(&progress),
+            // A closed receiver just means nobody is listening anymore.
+            ProgressHook::Channel(sender) => {
+                let _ = sender.send(progress);
+            }
+        }
+    }
+}
+
+// Update whole cards, at most `bulk_concurrency` requests at a time.
+pub async fn update_cards(config: &Config, cards: &[Card]) -> BulkResult {
+    update_cards_with_progress(config, cards, None).await
+}
+
+pub async fn update_cards_with_progress(
+    config: &Config,
+    cards: &[Card],
+    progress: Option<&ProgressHook>,
+) -> BulkResult {
+    let config: Arc<Config> = Arc::from(config.clone());
+    let cards: Arc<[Card]> = Arc::from(cards);
+    let permits = Arc::new(Semaphore::new(config.bulk_concurrency));
+
+    let mut tasks = JoinSet::new();
+    let mut ids = HashMap::new();
+    for i in 0..cards.len() {
+        let config = Arc::clone(&config);
+        let task_cards = Arc::clone(&cards);
+        let permits = Arc::clone(&permits);
+        let task = tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await.unwrap();
+            update_card(config, task_cards, i).await.map(|_| ())
+        });
+        ids.insert(task.id(), cards[i].id.clone());
+    }
+    join_bulk(tasks, ids, progress).await
+}
+
+// Join the tasks of a bulk update, each known by its card's id, so a task
+// that panicked or was cancelled still counts as that card failing.
+async fn join_bulk(
+    mut tasks: JoinSet<Result<(), MochiError>>,
+    mut ids: HashMap<task::Id, CardId>,
+    progress: Option<&ProgressHook>,
+) -> BulkResult {
+    let total = ids.len();
+    let mut completed = 0usize;
+    let mut result = BulkResult::default();
+    while let Some(joined) = tasks.join_next_with_id().await {
+        let (task, res) = match joined {
+            Ok((task, res)) => (task, res),
+            Err(err) => (err.id(), Err(MochiError::Task(err))),
+        };
+        let id = ids.remove(&task).unwrap_or_default();
+
+        completed += 1;
+        if let Some(progress) = progress {
+            progress.emit(Progress {
+                completed,
+                total,
+                last_card_id: id.clone(),
+            });
+        }
+
+        match res {
+            Ok(_) => result.succeeded.push(id),
+            Err(err) => result.failed.push((id, err)),
+        };
+    }
+
+    info!(
+        updated = result.succeeded.len(),
+        failed = result.failed.len(),
+        "bulk update"
+    );
+    result
+}
+
+const BULK_CONCURRENCY: usize = 8;
+
+// Apply partial updates, at most `bulk_concurrency` requests at a time.
+pub async fn patch_cards(config: &Config, patches: &[(CardId, CardPatch)]) -> BulkResult {
+    patch_cards_with_progress(config, patches, None).await
+}
+
+pub async fn patch_cards_with_progress(
+    config: &Config,
+    patches: &[(CardId, CardPatch)],
+    progress: Option<&ProgressHook>,
+) -> BulkResult {
+    let config: Arc<Config> = Arc::from(config.clone());
+    let permits = Arc::new(Semaphore::new(config.bulk_concurrency));
+
+    let mut tasks = JoinSet::new();
+    let mut ids = HashMap::new();
+    for (id, patch) in patches.iter().cloned() {
+        let config = Arc::clone(&config);
+        let permits = Arc::clone(&permits);
+        let card_id = id.clone();
+        let task = tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await.unwrap();
+            update_card_fields(&config, &card_id, &patch)
+                .await
+                .map(|_| ())
+        });
+        ids.insert(task.id(), id);
+    }
+    join_bulk(tasks, ids, progress).await
+}
+
+// A transformed card with only what differs from the original.
+#[derive(Debug, Clone)]
+pub struct ChangedCard {
+    pub card: Card,
+    pub patch: CardPatch,
+}
+
+// The modified cards that differ from their original (matched by id).
+pub fn changed_cards(originals: &[Card], modified: &[Card]) -> Vec<ChangedCard> {
+    let originals = originals
+        .iter()
+        .map(|c| (&c.id, c))
+        .collect::<HashMap<_, _>>();
+    modified
+        .iter()
+        .filter_map(|card| {
+            let patch = CardPatch::between(originals.get(&card.id)?, card);
+            (!patch.is_empty()).then(|| ChangedCard {
+                card: card.clone(),
+                patch,
+            })
+        })
+        .collect()
+}
+
+// Send only the changed fields of the changed cards.
+pub async fn update_changed_cards(
+    config: &Config,
+    changed: &[ChangedCard],
+    progress: Option<&ProgressHook>,
+) -> BulkResult {
+    let patches = changed
+        .iter()
+        .map(|c| (c.card.id.clone(), c.patch.clone()))
+        .collect::<Vec<_>>();
+    patch_cards_with_progress(config, &patches, progress).await
+}
+
+pub async fn archive_cards(config: &Config, card_ids: &[CardId]) -> BulkResult {
+    set_archived(config, card_ids, true).await
+}
+
+pub async fn unarchive_cards(config: &Config, card_ids: &[CardId]) -> BulkResult {
+    set_archived(config, card_ids, false).await
+}
+
+async fn set_archived(config: &Config, card_ids: &[CardId], archived: bool) -> BulkResult {
+    let patches = card_ids
+        .iter()
+        .map(|id| (id.clone(), CardPatch::new().archived(archived)))
+        .collect::<Vec<_>>();
+    patch_cards(config, &patches).await
+}
+
+// Moves the cards to the trash, from where they can still be restored in the
+// app.
+pub async fn trash_cards(config: &Config, card_ids: &[CardId]) -> BulkResult {
+    let now = iso_timestamp(SystemTime::now());
+    let patches = card_ids
+        .iter()
+        .map(|id| (id.clone(), CardPatch::new().trashed(&now)))
+        .collect::<Vec<_>>();
+    patch_cards(config, &patches).await
+}
+
+// Seconds since the unix epoch, 0 for times before it.
+pub fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn unix_now() -> u64 {
+    unix_secs(SystemTime::now())
+}
+
+// `2024-05-01T12:00:00Z`
+fn iso_timestamp(time: SystemTime) -> String {
+    Timestamp::from_system_time(time).date
+}
+
+// Archive every unarchived card of the deck matching the predicate.
+pub async fn archive_where<F>(
+    config: &Config,
+    deck_id: &DeckId,
+    predicate: F,
+) -> Result<BulkResult, Box<dyn Error>>
+where
+    F: Fn(&Card) -> bool,
+{
+    let cards = list_cards(config, deck_id, None).await?;
+    let card_ids = cards
+        .iter()
+        .filter(|c| !c.archived && predicate(c))
+        .map(|c| c.id.clone())
+        .collect::<Vec<_>>();
+    Ok(archive_cards(config, &card_ids).await)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    #[ignore = "needs MOCHI_KEY or a config profile"]
+    fn read_mochi_key() {
+        // <-- actual test
+        let config = Config::build().unwrap();
+        assert!(!config.mochi_key.is_empty())
+    }
+
+    #[test]
+    fn test_http_settings() {
+        let mut config = Config::default();
+        assert_eq!(
+            config.url("cards/c1"),
+            "https://app.mochi.cards/api/cards/c1"
+        );
+        config.base_url = "http://127.0.0.1:3000".to_string();
+        assert_eq!(config.url("decks/"), "http://127.0.0.1:3000/decks/");
+
+        config.proxy = Some("http://proxy.corp:8080".to_string());
+        assert!(config.http_client().is_ok());
+        config.proxy = Some("not a url".to_string());
+        assert!(config.http_client().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_decks() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        let decks = list_decks(&config).await.unwrap();
+        assert_eq!(decks.len(), 3);
+
+        // A 503 is retried; a 401 isn't.
+        server.fail_next(503, serde_json::json!({}));
+        assert_eq!(list_decks(&config).await.unwrap().len(), 3);
+        server.fail_next(401, serde_json::json!({}));
+        assert!(list_decks(&config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_cards() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        let n3_deck = DeckId::from("MK5LCEAL");
+
+        server.set_page_size(4);
+        let cards = list_cards(&config, &n3_deck, Some(10)).await.unwrap();
+        assert_eq!(cards.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_stream_cards() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        let n3_deck = DeckId::from("MK5LCEAL");
+
+        let cards = stream_cards(&config, &n3_deck)
+            .take(10)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(cards.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_list_cards_page() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        let n3_deck = DeckId::from("MK5LCEAL");
+
+        let (first, bookmark) = list_cards_page(&config, &n3_deck, None, Some(5))
+            .await
+            .unwrap();
+        assert_eq!(first.len(), 5);
+
+        let (second, _) = list_cards_page(&config, &n3_deck, bookmark.as_ref(), Some(5))
+            .await
+            .unwrap();
+        assert!(second.iter().all(|c| first.iter().all(|f| f.id != c.id)));
+    }
+
+    #[tokio::test]
+    async fn test_list_all_cards() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        let cards = list_all_cards(&config).await.unwrap();
+        assert_eq!(cards.len(), 15);
+        assert!(cards.iter().all(|c| c.deck.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_list_cards_for_decks() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        let decks = list_decks(&config).await.unwrap();
+        let deck_ids = decks
+            .iter()
+            .take(3)
+            .map(|d| d.id.clone())
+            .collect::<Vec<_>>();
+
+        let cards = list_cards_for_decks(&config, &deck_ids, Some(5))
+            .await
+            .unwrap();
+        assert_eq!(cards.len(), deck_ids.len());
+        assert!(cards.values().all(|c| c.len() <= 5));
+    }
+
+    #[tokio::test]
+    async fn test_list_template() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        let templates = list_templates(&config).await.unwrap();
+        assert_eq!(templates.len(), 1);
+    }
+
+    #[test]
+    fn test_changed_cards() {
+        let original = mock::CardFixture::new("c1", "deck").content("犬").build();
+        let mut other = original.clone();
+        other.id = CardId::from("c2");
+        let mut modified = original.clone();
+        modified.content = "猫".to_string();
+
+        let changed = changed_cards(&[original, other.clone()], &[modified, other]);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].card.id, "c1");
+        assert_eq!(changed[0].patch, CardPatch::new().content("猫"));
+    }
+
+    #[tokio::test]
+    async fn test_join_bulk_panicked_task() {
+        let mut tasks = JoinSet::new();
+        let mut ids = HashMap::new();
+        let ok = tasks.spawn(async { Ok(()) });
+        ids.insert(ok.id(), CardId::from("ok"));
+        let panicked = tasks.spawn(async { panic!("boom") });
+        ids.insert(panicked.id(), CardId::from("panicked"));
+
+        let result = join_bulk(tasks, ids, None).await;
+        assert_eq!(result.succeeded, [CardId::from("ok")]);
+        assert_eq!(result.failed_ids(), [CardId::from("panicked")]);
+        assert!(matches!(result.failed[0].1, MochiError::Task(_)));
+    }
+
+    #[test]
+    fn test_iso_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(iso_timestamp(time), "2024-02-29T12:34:56Z");
+        assert_eq!(iso_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+    }
+}
@@ -0,0 +1,496 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use reqwest::{StatusCode, Url};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::models::{Card, Template};
+use crate::Config;
+
+// Mock Mochi Server
+//
+// A local stand-in for the Mochi API, so listing, pagination and updates can
+// be tested without an account. It serves decks, templates and cards from
+// memory, pages them `page_size` at a time with numeric bookmarks, applies
+// creates and updates, and records every request. Point a client at it with
+// `config()`. Built for the crate's tests, and for others with the `mock`
+// feature.
+
+pub const MOCK_KEY: &str = "mock-key";
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+// A canned account: Japanese, N3 (MK5LCEAL, 12 cards) and N5 (N5DECK, 3 cards),
+// with one Vocab template of Word, Reading and PitchAccent.
+const FIXTURE: &str = include_str!("../resources/mock_account.json");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockRequest {
+    pub method: String,
+    // Without the `/api/` prefix, e.g. `cards/abc`.
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub body: Option<Value>,
+}
+
+impl MockRequest {
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug)]
+struct Account {
+    decks: Vec<Value>,
+    templates: Vec<Value>,
+    cards: Vec<Value>,
+    page_size: usize,
+    created: usize,
+    // Answers given instead of the next requests', in order.
+    failures: VecDeque<(StatusCode, Value)>,
+    requests: Vec<MockRequest>,
+}
+
+pub struct MockMochiServer {
+    addr: SocketAddr,
+    account: Arc<Mutex<Account>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockMochiServer {
+    // An empty account.
+    pub async fn start() -> io::Result<MockMochiServer> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let account = Arc::new(Mutex::new(Account {
+            decks: vec![],
+            templates: vec![],
+            cards: vec![],
+            page_size: DEFAULT_PAGE_SIZE,
+            created: 0,
+            failures: VecDeque::new(),
+            requests: vec![],
+        }));
+        let served = account.clone();
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(answer(stream, served.clone()));
+            }
+        });
+        Ok(MockMochiServer {
+            addr,
+            account,
+            handle,
+        })
+    }
+
+    // The canned account, see `FIXTURE`.
+    pub async fn with_fixture() -> io::Result<MockMochiServer> {
+        let server = MockMochiServer::start().await?;
+        let fixture: Value = serde_json::from_str(FIXTURE)?;
+        let list = |key: &str| fixture[key].as_array().cloned().unwrap_or_default();
+        {
+            let mut account = server.account.lock().unwrap();
+            account.decks = list("decks");
+            account.templates = list("templates");
+            account.cards = list("cards");
+        }
+        Ok(server)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    // Settings that send every request to this server.
+    pub fn config(&self) -> Config {
+        Config {
+            mochi_key: MOCK_KEY.to_string(),
+            base_url: format!("http://{}/api/", self.addr),
+            ..Config::default()
+        }
+    }
+
+    // Listings return at most this many objects a page.
+    pub fn set_page_size(&self, page_size: usize) {
+        self.account.lock().unwrap().page_size = page_size.max(1);
+    }
+
+    // Answer the next request with `status` and `body`, e.g. a 503 to test
+    // retries. Several calls fail several requests.
+    pub fn fail_next(&self, status: u16, body: Value) {
+        let status = StatusCode::from_u16(status).expect("an HTTP status");
+        let mut account = self.account.lock().unwrap();
+        account.failures.push_back((status, body));
+    }
+
+    pub fn add_deck(&self, deck: Value) {
+        self.account.lock().unwrap().decks.push(deck);
+    }
+
+    pub fn add_template(&self, template: Value) {
+        self.account.lock().unwrap().templates.push(template);
+    }
+
+    pub fn add_card(&self, card: Value) {
+        self.account.lock().unwrap().cards.push(card);
+    }
+
+    // The card as the server has it now.
+    pub fn card(&self, id: &str) -> Option<Card> {
+        let account = self.account.lock().unwrap();
+        let card = account.cards.iter().find(|c| c["id"] == id)?;
+        serde_json::from_value(card.clone()).ok()
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.account.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockMochiServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+// Lower-cased names with their values.
+type Headers = Vec<(String, String)>;
+
+// The method, target, headers and what's read of the body.
+fn read_request(raw: &[u8]) -> Option<(String, String, Headers, &[u8])> {
+    let end = raw.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&raw[..end]).ok()?;
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?.to_string();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    Some((method, target, headers, &raw[end + 4..]))
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+// Reads one request and answers it. Every connection is closed after one
+// response.
+async fn answer(mut stream: TcpStream, account: Arc<Mutex<Account>>) {
+    let mut raw = vec![];
+    let mut chunk = [0u8; 8192];
+    let (method, target, headers, body) = loop {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => raw.extend_from_slice(&chunk[..n]),
+        }
+        if let Some((method, target, headers, body)) = read_request(&raw) {
+            let length = header(&headers, "content-length")
+                .and_then(|l| l.parse().ok())
+                .unwrap_or(0);
+            if body.len() >= length {
+                break (method, target, headers, body[..length].to_vec());
+            }
+        }
+    };
+
+    let (status, body) = respond(&account, &method, &target, &headers, &body);
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or(""),
+        body.len(),
+        body
+    );
+    // A client hanging up early shouldn't stop the server.
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+fn respond(
+    account: &Mutex<Account>,
+    method: &str,
+    target: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+) -> (StatusCode, Value) {
+    let url = match Url::parse(&format!("http://mock{}", target)) {
+        Ok(url) => url,
+        Err(_) => return (StatusCode::BAD_REQUEST, json!({ "error": "bad target" })),
+    };
+    let request = MockRequest {
+        method: method.to_string(),
+        path: url
+            .path()
+            .trim_start_matches("/api/")
+            .trim_matches('/')
+            .to_string(),
+        query: url.query_pairs().into_owned().collect(),
+        body: serde_json::from_slice(body).ok(),
+    };
+
+    let mut account = account.lock().unwrap();
+    account.requests.push(request.clone());
+    if let Some(failure) = account.failures.pop_front() {
+        return failure;
+    }
+    let expected = format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(format!("{}:", MOCK_KEY))
+    );
+    if header(headers, "authorization") != Some(expected.as_str()) {
+        return (StatusCode::UNAUTHORIZED, json!({ "error": "bad key" }));
+    }
+    account.route(&request)
+}
+
+// Sets the update's keys on the object, merging `fields` by field id.
+fn merge(object: &mut Value, update: &Value) {
+    let Some(update) = update.as_object() else {
+        return;
+    };
+    for (key, value) in update {
+        match (key.as_str(), object[key].as_object_mut(), value.as_object()) {
+            ("fields", Some(fields), Some(new_fields)) => {
+                fields.extend(new_fields.iter().map(|(k, v)| (k.clone(), v.clone())));
+            }
+            _ => object[key] = value.clone(),
+        }
+    }
+}
+
+impl Account {
+    fn route(&mut self, request: &MockRequest) -> (StatusCode, Value) {
+        let segments = request.path.split('/').collect::<Vec<_>>();
+        let body = request.body.clone().unwrap_or(Value::Null);
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["decks"]) => (StatusCode::OK, self.page(&self.decks, request)),
+            ("GET", ["templates"]) => (StatusCode::OK, self.page(&self.templates, request)),
+            ("GET", ["cards"]) => {
+                let cards = self
+                    .cards
+                    .iter()
+                    .filter(|c| {
+                        request
+                            .query("deck-id")
+                            .is_none_or(|deck| c["deck-id"] == deck)
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                (StatusCode::OK, self.page(&cards, request))
+            }
+            ("GET", ["cards", id]) => match self.cards.iter().find(|c| c["id"] == *id) {
+                Some(card) => (StatusCode::OK, card.clone()),
+                None => not_found(),
+            },
+            ("POST", ["cards"]) => {
+                let mut card = json!({
+                    "id": self.next_id("card"),
+                    "tags": [],
+                    "references": [],
+                });
+                merge(&mut card, &body);
+                self.cards.push(card.clone());
+                (StatusCode::OK, card)
+            }
+            ("POST", ["decks"]) => {
+                let mut deck = json!({ "id": self.next_id("deck") });
+                merge(&mut deck, &body);
+                self.decks.push(deck.clone());
+                (StatusCode::OK, deck)
+            }
+            ("POST", ["templates"]) => {
+                let mut template = json!({ "id": self.next_id("template") });
+                merge(&mut template, &body);
+                self.templates.push(template.clone());
+                (StatusCode::OK, template)
+            }
+            ("POST", ["cards", id]) => update(&mut self.cards, id, &body),
+            ("POST", ["decks", id]) => update(&mut self.decks, id, &body),
+            _ => not_found(),
+        }
+    }
+
+    fn next_id(&mut self, kind: &str) -> String {
+        self.created += 1;
+        format!("mock-{}-{}", kind, self.created)
+    }
+
+    // Up to `limit` (and the page size) objects after the bookmark, which is
+    // the index to start at.
+    fn page(&self, objects: &[Value], request: &MockRequest) -> Value {
+        let limit = request
+            .query("limit")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(self.page_size)
+            .min(self.page_size);
+        let start = request
+            .query("bookmark")
+            .and_then(|b| b.parse().ok())
+            .unwrap_or(0usize)
+            .min(objects.len());
+        let docs = &objects[start..(start + limit).min(objects.len())];
+        json!({
+            "docs": docs,
+            "bookmark": (start + docs.len()).to_string(),
+        })
+    }
+}
+
+fn update(objects: &mut [Value], id: &str, body: &Value) -> (StatusCode, Value) {
+    match objects.iter_mut().find(|o| o["id"] == id) {
+        Some(object) => {
+            merge(object, body);
+            (StatusCode::OK, object.clone())
+        }
+        None => not_found(),
+    }
+}
+
+fn not_found() -> (StatusCode, Value) {
+    (StatusCode::NOT_FOUND, json!({ "error": "not found" }))
+}
+
+// Test Fixtures
+//
+// Single cards and templates as the API returns them, for tests that don't
+// need a whole account.
+
+pub struct CardFixture(Value);
+
+impl CardFixture {
+    // A card without template, content or tags.
+    pub fn new(id: &str, deck_id: &str) -> CardFixture {
+        CardFixture(json!({
+            "id": id,
+            "content": "",
+            "deck-id": deck_id,
+            "tags": [],
+            "references": [],
+        }))
+    }
+
+    pub fn content(self, content: &str) -> CardFixture {
+        self.set("content", json!(content))
+    }
+
+    pub fn template(self, template_id: &str) -> CardFixture {
+        self.set("template-id", json!(template_id))
+    }
+
+    // A template field by id.
+    pub fn field(mut self, field_id: &str, value: &str) -> CardFixture {
+        let field = json!({ "id": field_id, "value": value });
+        match self.0["fields"].as_object_mut() {
+            Some(fields) => {
+                fields.insert(field_id.to_string(), field);
+            }
+            None => self.0["fields"] = json!({ field_id: field }),
+        }
+        self
+    }
+
+    // The tags the API derives from the content and manual tags.
+    pub fn tags(self, tags: &[&str]) -> CardFixture {
+        self.set("tags", json!(tags))
+    }
+
+    // Also sets `tags`, as the API returns manual tags in both.
+    pub fn manual_tags(self, tags: &[&str]) -> CardFixture {
+        self.set("manual-tags", json!(tags)).tags(tags)
+    }
+
+    pub fn archived(self, archived: bool) -> CardFixture {
+        self.set("archived?", json!(archived))
+    }
+
+    // Any other attribute, e.g. `pos` or `reviews`.
+    pub fn set(mut self, key: &str, value: Value) -> CardFixture {
+        self.0[key] = value;
+        self
+    }
+
+    // As the server would send it, e.g. for `add_card`.
+    pub fn json(self) -> Value {
+        self.0
+    }
+
+    pub fn build(self) -> Card {
+        serde_json::from_value(self.0).expect("a valid card")
+    }
+}
+
+// A template with the (id, name) fields in order.
+pub fn template_fixture(id: &str, content: &str, fields: &[(&str, &str)]) -> Template {
+    let fields = fields
+        .iter()
+        .zip('a'..)
+        .map(|((field_id, name), pos)| {
+            let field = json!({ "id": field_id, "name": name, "pos": pos.to_string() });
+            (field_id.to_string(), field)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    serde_json::from_value(json!({
+        "id": id,
+        "name": id,
+        "content": content,
+        "fields": fields,
+    }))
+    .expect("a valid template")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::CardPatch;
+    use crate::{get_card, list_cards, list_decks, update_card_fields};
+
+    #[tokio::test]
+    async fn test_mock_server() {
+        let server = MockMochiServer::with_fixture().await.unwrap();
+        server.set_page_size(5);
+        let config = server.config();
+
+        let decks = list_decks(&config).await.unwrap();
+        assert_eq!(decks.len(), 3);
+        let cards = list_cards(&config, &"MK5LCEAL".into(), None).await.unwrap();
+        assert_eq!(cards.len(), 12);
+        // Three full pages, a partial one and the empty one that ends it.
+        let pages = server
+            .requests()
+            .iter()
+            .filter(|r| r.path == "cards")
+            .count();
+        assert_eq!(pages, 4);
+
+        let patch = CardPatch::new().field("pitch", "LH");
+        let card = update_card_fields(&config, &cards[0].id, &patch)
+            .await
+            .unwrap();
+        assert_eq!(card.fields.as_ref().unwrap()["pitch"].value, "LH");
+        assert_eq!(card.fields.as_ref().unwrap()["name"].value, "箸");
+        assert_eq!(server.card(cards[0].id.as_str()).unwrap().content, "");
+
+        let wrong_key = Config {
+            mochi_key: "wrong".to_string(),
+            ..config.clone()
+        };
+        assert!(get_card(&wrong_key, &cards[0].id).await.is_err());
+        assert!(get_card(&config, &"missing".into()).await.is_err());
+    }
+}
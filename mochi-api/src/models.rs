@@ -0,0 +1,1012 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Identifiers
+//
+// Distinct types so a deck id can't be passed where a card id is expected.
+// They serialize as the plain id string.
+macro_rules! id_type {
+    ($name:ident) => {
+        #[derive(
+            Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+        )]
+        #[serde(transparent)]
+        pub struct $name(pub String);
+
+        impl $name {
+            pub fn new(id: impl Into<String>) -> $name {
+                $name(id.into())
+            }
+
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.0.is_empty()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(id: &str) -> $name {
+                $name(id.to_string())
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(id: String) -> $name {
+                $name(id)
+            }
+        }
+
+        impl From<&$name> for $name {
+            fn from(id: &$name) -> $name {
+                id.clone()
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        // Lets maps keyed by id be indexed with a plain `&str`.
+        impl Borrow<str> for $name {
+            fn borrow(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+    };
+}
+
+id_type!(CardId);
+id_type!(DeckId);
+id_type!(TemplateId);
+id_type!(FieldId);
+
+pub type Bookmark = String;
+
+// Timestamps
+//
+// The API wraps them in an object, `{"date": "2024-05-01T12:00:00.000Z"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Timestamp {
+    pub date: String,
+}
+
+impl Timestamp {
+    pub fn new(date: impl Into<String>) -> Timestamp {
+        Timestamp { date: date.into() }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.date
+    }
+
+    // Milliseconds since the Unix epoch, None if the date isn't of the
+    // form `2024-05-01T12:00:00Z`, with or without fractional seconds.
+    pub fn unix_millis(&self) -> Option<i64> {
+        let date = self.date.strip_suffix('Z')?;
+        let (date, time) = date.split_once('T')?;
+        let mut ymd = date.splitn(3, '-').map(|n| n.parse::<i64>().ok());
+        let (year, month, day) = (ymd.next()??, ymd.next()??, ymd.next()??);
+        let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+        let mut hms = time.splitn(3, ':').map(|n| n.parse::<i64>().ok());
+        let (hours, minutes, seconds) = (hms.next()??, hms.next()??, hms.next()??);
+        let millis = format!("{:0<3}", fraction).get(..3)?.parse::<i64>().ok()?;
+
+        let days = days_from_civil(year, month, day);
+        let seconds = days * 86400 + hours * 3600 + minutes * 60 + seconds;
+        Some(seconds * 1000 + millis)
+    }
+
+    // `2024-05-01T12:00:00Z`, to the second. Times before the epoch are
+    // taken as the epoch.
+    pub fn from_system_time(time: SystemTime) -> Timestamp {
+        let secs = crate::unix_secs(time) as i64;
+        let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+        let secs = secs.rem_euclid(86400);
+        Timestamp::new(format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        ))
+    }
+
+    pub fn system_time(&self) -> Option<SystemTime> {
+        let millis = self.unix_millis()?;
+        let offset = Duration::from_millis(millis.unsigned_abs());
+        match millis >= 0 {
+            true => UNIX_EPOCH.checked_add(offset),
+            false => UNIX_EPOCH.checked_sub(offset),
+        }
+    }
+}
+
+// Civil Dates
+//
+// Days since the epoch to and from a (year, month, day), counting in 400
+// year eras that start on the 1st of March so leap days come last.
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// Primitive Mochi Types
+//
+// Fields the crate doesn't model yet land in `extra` and are written back
+// as they were, so saving a card never strips what a newer API added.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Deck {
+    pub name: String,
+    #[serde(rename = "parent-id")]
+    pub parent_id: Option<DeckId>,
+    #[serde(rename = "template-id")]
+    pub template_id: Option<TemplateId>,
+    #[serde(rename = "archived?", default)]
+    pub archived: bool,
+    // Position among its sibling decks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<i64>,
+    #[serde(rename = "trashed?", skip_serializing_if = "Option::is_none")]
+    pub trashed: Option<Value>,
+    // Retrieval Only Values
+    #[serde(skip_serializing)]
+    pub id: DeckId,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TemplateField {
+    pub id: FieldId,
+    pub name: String,
+    pub pos: String,
+    pub options: Option<HashMap<String, Value>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Template {
+    pub name: String,
+    pub content: String,
+    pub fields: Option<HashMap<FieldId, TemplateField>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pos: Option<String>,
+    // Retrieval Only Values
+    #[serde(skip_serializing)]
+    pub id: TemplateId,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CardField {
+    pub id: FieldId,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Card {
+    pub content: String,
+    #[serde(rename = "deck-id")]
+    pub deck_id: DeckId,
+    #[serde(rename = "template-id")]
+    pub template_id: Option<TemplateId>,
+    pub fields: Option<HashMap<FieldId, CardField>>,
+    #[serde(rename = "archived?", default)]
+    pub archived: bool,
+    #[serde(rename = "review-reverse?", default)]
+    pub review_reverse: bool,
+    pub pos: Option<String>,
+    #[serde(rename = "manual-tags", skip_serializing_if = "Option::is_none")]
+    pub manual_tags: Option<Vec<String>>,
+    // Retrieval Only Values
+    #[serde(skip_serializing)]
+    pub id: CardId,
+    #[serde(skip_serializing, default)]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing, default)]
+    pub references: Vec<String>,
+    #[serde(skip_serializing)]
+    pub attachments: Option<Value>,
+    #[serde(rename = "trashed?", skip_serializing)]
+    pub trashed: Option<Value>,
+    // The card's title, from its name field or the start of its content.
+    #[serde(skip_serializing)]
+    pub name: Option<String>,
+    #[serde(rename = "created-at", skip_serializing)]
+    pub created_at: Option<Timestamp>,
+    #[serde(rename = "updated-at", skip_serializing)]
+    pub updated_at: Option<Timestamp>,
+    // Not reviewed yet.
+    #[serde(rename = "new?", skip_serializing, default)]
+    pub new: bool,
+    #[serde(skip_serializing, default)]
+    pub reviews: Vec<Review>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Review {
+    pub date: Timestamp,
+    // When the next review was scheduled for.
+    pub due: Option<Timestamp>,
+    #[serde(rename = "remembered?", default)]
+    pub remembered: bool,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl Card {
+    // Created or updated after the given time, e.g. the last run of a
+    // script. Cards without timestamps count as modified.
+    pub fn modified_since(&self, since: SystemTime) -> bool {
+        let modified = self.updated_at.as_ref().or(self.created_at.as_ref());
+        match modified.and_then(Timestamp::system_time) {
+            Some(modified) => modified > since,
+            None => true,
+        }
+    }
+}
+
+// Review History
+//
+// Each review records when it happened, whether the card was remembered and
+// when it was next due, so the latest one holds the card's schedule.
+impl Card {
+    pub fn last_review(&self) -> Option<&Review> {
+        self.reviews
+            .iter()
+            .max_by_key(|review| review.date.unix_millis())
+    }
+
+    // None if the card was never reviewed.
+    pub fn due(&self) -> Option<&Timestamp> {
+        self.last_review()?.due.as_ref()
+    }
+
+    pub fn is_due(&self, at: SystemTime) -> bool {
+        self.due()
+            .and_then(Timestamp::system_time)
+            .is_some_and(|due| due <= at)
+    }
+
+    // From the last review to the next.
+    pub fn interval(&self) -> Option<Duration> {
+        let review = self.last_review()?;
+        let reviewed = review.date.system_time()?;
+        let due = review.due.as_ref()?.system_time()?;
+        due.duration_since(reviewed).ok()
+    }
+
+    pub fn times_remembered(&self) -> usize {
+        self.reviews.iter().filter(|r| r.remembered).count()
+    }
+
+    pub fn times_forgotten(&self) -> usize {
+        self.reviews.iter().filter(|r| !r.remembered).count()
+    }
+}
+
+// Template-Aware Field Access
+impl Template {
+    pub fn field_by_name(&self, name: &str) -> Option<&TemplateField> {
+        self.fields.as_ref()?.values().find(|f| f.name == name)
+    }
+}
+
+impl Card {
+    // None if the card is not of this template or the field is unset.
+    pub fn field_by_name(&self, template: &Template, name: &str) -> Option<&CardField> {
+        if self.template_id.as_ref() != Some(&template.id) {
+            return None;
+        }
+        let field = template.field_by_name(name)?;
+        self.fields.as_ref()?.get(&field.id)
+    }
+
+    // Returns false, leaving the card untouched, if the card is not of this
+    // template or the template has no such field.
+    pub fn set_field_by_name(&mut self, template: &Template, name: &str, value: &str) -> bool {
+        if self.template_id.as_ref() != Some(&template.id) {
+            return false;
+        }
+        let field_id = match template.field_by_name(name) {
+            Some(field) => field.id.clone(),
+            None => return false,
+        };
+        self.fields.get_or_insert_with(HashMap::new).insert(
+            field_id.clone(),
+            CardField {
+                id: field_id,
+                value: value.to_string(),
+            },
+        );
+        true
+    }
+}
+
+// A card joined with its template, if it has one.
+#[derive(Debug, Clone)]
+pub struct ResolvedCard {
+    pub card: Card,
+    pub template: Option<Template>,
+}
+
+impl ResolvedCard {
+    pub fn resolve(cards: &[Card], templates: &[Template]) -> Vec<ResolvedCard> {
+        cards
+            .iter()
+            .map(|card| ResolvedCard {
+                card: card.clone(),
+                template: card
+                    .template_id
+                    .as_ref()
+                    .and_then(|id| templates.iter().find(|t| t.id == *id))
+                    .cloned(),
+            })
+            .collect()
+    }
+
+    pub fn field(&self, name: &str) -> Option<&str> {
+        let template = self.template.as_ref()?;
+        self.card
+            .field_by_name(template, name)
+            .map(|f| f.value.as_str())
+    }
+
+    pub fn has_field(&self, name: &str) -> bool {
+        self.template
+            .as_ref()
+            .and_then(|t| t.field_by_name(name))
+            .is_some()
+    }
+
+    pub fn set_field(&mut self, name: &str, value: &str) -> bool {
+        match &self.template {
+            Some(template) => self.card.set_field_by_name(template, name, value),
+            None => false,
+        }
+    }
+}
+
+// Partial Updates
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct CardPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(rename = "deck-id", skip_serializing_if = "Option::is_none")]
+    pub deck_id: Option<DeckId>,
+    #[serde(rename = "template-id", skip_serializing_if = "Option::is_none")]
+    pub template_id: Option<TemplateId>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub fields: HashMap<FieldId, CardField>,
+    #[serde(rename = "archived?", skip_serializing_if = "Option::is_none")]
+    pub archived: Option<bool>,
+    #[serde(rename = "review-reverse?", skip_serializing_if = "Option::is_none")]
+    pub review_reverse: Option<bool>,
+    #[serde(rename = "manual-tags", skip_serializing_if = "Option::is_none")]
+    pub manual_tags: Option<Vec<String>>,
+    // When the card was trashed, as an ISO 8601 timestamp.
+    #[serde(rename = "trashed?", skip_serializing_if = "Option::is_none")]
+    pub trashed: Option<String>,
+}
+
+impl CardPatch {
+    pub fn new() -> CardPatch {
+        CardPatch::default()
+    }
+
+    pub fn content(mut self, content: &str) -> CardPatch {
+        self.content = Some(content.to_string());
+        self
+    }
+
+    pub fn deck_id(mut self, deck_id: impl Into<DeckId>) -> CardPatch {
+        self.deck_id = Some(deck_id.into());
+        self
+    }
+
+    pub fn template_id(mut self, template_id: impl Into<TemplateId>) -> CardPatch {
+        self.template_id = Some(template_id.into());
+        self
+    }
+
+    pub fn field(mut self, field_id: impl Into<FieldId>, value: &str) -> CardPatch {
+        let field_id = field_id.into();
+        self.fields.insert(
+            field_id.clone(),
+            CardField {
+                id: field_id,
+                value: value.to_string(),
+            },
+        );
+        self
+    }
+
+    pub fn archived(mut self, archived: bool) -> CardPatch {
+        self.archived = Some(archived);
+        self
+    }
+
+    pub fn review_reverse(mut self, review_reverse: bool) -> CardPatch {
+        self.review_reverse = Some(review_reverse);
+        self
+    }
+
+    pub fn manual_tags(mut self, tags: &[String]) -> CardPatch {
+        self.manual_tags = Some(tags.to_vec());
+        self
+    }
+
+    pub fn trashed(mut self, timestamp: &str) -> CardPatch {
+        self.trashed = Some(timestamp.to_string());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == CardPatch::default()
+    }
+
+    // Only what differs between the two versions of a card. Removed manual
+    // tags and fields are cleared: the tags to an empty list, the fields to an
+    // empty value. The API can't take a card's template away, so a modified
+    // card must keep one if the original had it.
+    pub fn between(original: &Card, modified: &Card) -> CardPatch {
+        let mut patch = CardPatch::new();
+        if original.content != modified.content {
+            patch.content = Some(modified.content.clone());
+        }
+        if original.deck_id != modified.deck_id {
+            patch.deck_id = Some(modified.deck_id.clone());
+        }
+        if original.template_id != modified.template_id {
+            debug_assert!(
+                modified.template_id.is_some(),
+                "a card's template can't be removed"
+            );
+            patch.template_id = modified.template_id.clone();
+        }
+        if original.archived != modified.archived {
+            patch.archived = Some(modified.archived);
+        }
+        if original.review_reverse != modified.review_reverse {
+            patch.review_reverse = Some(modified.review_reverse);
+        }
+        if original.manual_tags != modified.manual_tags {
+            patch.manual_tags = Some(modified.manual_tags.clone().unwrap_or_default());
+        }
+
+        let empty = HashMap::new();
+        let original_fields = original.fields.as_ref().unwrap_or(&empty);
+        let modified_fields = modified.fields.as_ref().unwrap_or(&empty);
+        for (id, field) in modified_fields.iter() {
+            if original_fields.get(id).map(|f| &f.value) != Some(&field.value) {
+                patch.fields.insert(id.clone(), field.clone());
+            }
+        }
+        for (id, field) in original_fields.iter() {
+            if !modified_fields.contains_key(id) && !field.value.is_empty() {
+                patch.fields.insert(
+                    id.clone(),
+                    CardField {
+                        id: id.clone(),
+                        value: String::new(),
+                    },
+                );
+            }
+        }
+
+        patch
+    }
+
+    // The changes made to a local copy of the card, as the API would.
+    pub fn apply(&self, card: &mut Card) {
+        if let Some(content) = &self.content {
+            card.content = content.clone();
+        }
+        if let Some(deck_id) = &self.deck_id {
+            card.deck_id = deck_id.clone();
+        }
+        if let Some(template_id) = &self.template_id {
+            card.template_id = Some(template_id.clone());
+        }
+        if !self.fields.is_empty() {
+            let fields = card.fields.get_or_insert_with(HashMap::new);
+            for (id, field) in self.fields.iter() {
+                fields.insert(id.clone(), field.clone());
+            }
+        }
+        if let Some(archived) = self.archived {
+            card.archived = archived;
+        }
+        if let Some(review_reverse) = self.review_reverse {
+            card.review_reverse = review_reverse;
+        }
+        if let Some(tags) = &self.manual_tags {
+            card.manual_tags = Some(tags.clone());
+        }
+        if let Some(trashed) = &self.trashed {
+            card.trashed = Some(Value::String(trashed.clone()));
+        }
+    }
+}
+
+// Card Builder
+//
+// Builds a new card from field names, resolving them to ids through the
+// template. The template's first field is the card's name and must be set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CardBuildError {
+    // Fields were set but no template was given.
+    NoTemplate,
+    UnknownField(String),
+    MissingField(String),
+}
+
+impl fmt::Display for CardBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardBuildError::NoTemplate => write!(f, "fields set without a template"),
+            CardBuildError::UnknownField(name) => write!(f, "template has no field {}", name),
+            CardBuildError::MissingField(name) => write!(f, "required field {} is empty", name),
+        }
+    }
+}
+
+impl std::error::Error for CardBuildError {}
+
+#[derive(Debug, Clone)]
+pub struct CardBuilder {
+    deck_id: DeckId,
+    template: Option<Template>,
+    content: String,
+    fields: Vec<(String, String)>,
+    tags: Vec<String>,
+    review_reverse: bool,
+    archived: bool,
+}
+
+impl CardBuilder {
+    pub fn new(deck_id: impl Into<DeckId>) -> CardBuilder {
+        CardBuilder {
+            deck_id: deck_id.into(),
+            template: None,
+            content: String::new(),
+            fields: vec![],
+            tags: vec![],
+            review_reverse: false,
+            archived: false,
+        }
+    }
+
+    pub fn template(mut self, template: &Template) -> CardBuilder {
+        self.template = Some(template.clone());
+        self
+    }
+
+    pub fn content(mut self, content: &str) -> CardBuilder {
+        self.content = content.to_string();
+        self
+    }
+
+    pub fn field(mut self, name: &str, value: &str) -> CardBuilder {
+        self.fields.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> CardBuilder {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn review_reverse(mut self, review_reverse: bool) -> CardBuilder {
+        self.review_reverse = review_reverse;
+        self
+    }
+
+    pub fn archived(mut self, archived: bool) -> CardBuilder {
+        self.archived = archived;
+        self
+    }
+
+    pub fn build(self) -> Result<Card, CardBuildError> {
+        let (template_id, fields) = match &self.template {
+            None if !self.fields.is_empty() => return Err(CardBuildError::NoTemplate),
+            None => (None, None),
+            Some(template) => {
+                let template_fields = template.fields.clone().unwrap_or_default();
+                let mut fields = HashMap::new();
+                for (name, value) in self.fields.iter() {
+                    let field = template_fields
+                        .values()
+                        .find(|f| f.name == *name)
+                        .ok_or_else(|| CardBuildError::UnknownField(name.clone()))?;
+                    fields.insert(
+                        field.id.clone(),
+                        CardField {
+                            id: field.id.clone(),
+                            value: value.clone(),
+                        },
+                    );
+                }
+
+                if let Some(name_field) = template_fields.values().min_by_key(|f| &f.pos) {
+                    let value = fields.get(&name_field.id).map(|f| f.value.trim());
+                    if value.unwrap_or("").is_empty() {
+                        return Err(CardBuildError::MissingField(name_field.name.clone()));
+                    }
+                }
+
+                (Some(template.id.clone()), Some(fields))
+            }
+        };
+
+        Ok(Card {
+            content: self.content,
+            deck_id: self.deck_id,
+            template_id,
+            fields,
+            archived: self.archived,
+            review_reverse: self.review_reverse,
+            pos: None,
+            manual_tags: (!self.tags.is_empty()).then_some(self.tags),
+            id: CardId::default(),
+            tags: vec![],
+            references: vec![],
+            attachments: None,
+            trashed: None,
+            name: None,
+            created_at: None,
+            updated_at: None,
+            new: true,
+            reviews: vec![],
+            extra: HashMap::new(),
+        })
+    }
+}
+
+// API
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PaginatedResponse<T> {
+    pub bookmark: Option<String>,
+    pub docs: Vec<T>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+    use serde_json::json;
+
+    #[test]
+    fn test_card_patch() {
+        let original = CardFixture::new("card", "deck")
+            .content("犬")
+            .field("word", "犬")
+            .field("pitch", "")
+            .build();
+
+        let mut modified = original.clone();
+        modified
+            .fields
+            .as_mut()
+            .unwrap()
+            .get_mut("pitch")
+            .unwrap()
+            .value = "<div></div>".to_string();
+
+        let patch = CardPatch::between(&original, &modified);
+        assert_eq!(patch, CardPatch::new().field("pitch", "<div></div>"));
+        assert_eq!(
+            serde_json::to_value(&patch).unwrap(),
+            json!({ "fields": { "pitch": { "id": "pitch", "value": "<div></div>" } } })
+        );
+        assert!(CardPatch::between(&original, &original).is_empty());
+    }
+
+    #[test]
+    fn test_card_patch_clears() {
+        let original = CardFixture::new("card", "deck")
+            .content("犬")
+            .template("template")
+            .field("word", "犬")
+            .field("note", "dog")
+            .manual_tags(&["n5"])
+            .build();
+
+        let mut modified = original.clone();
+        modified.manual_tags = None;
+        modified.fields.as_mut().unwrap().remove("note");
+        let patch = CardPatch::between(&original, &modified);
+        assert_eq!(patch, CardPatch::new().manual_tags(&[]).field("note", ""));
+
+        let mut patched = original.clone();
+        patch.apply(&mut patched);
+        assert_eq!(patched.manual_tags, Some(vec![]));
+        assert!(CardPatch::between(&patched, &modified).fields.is_empty());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "template can't be removed")]
+    fn test_card_patch_template_removed() {
+        let original = CardFixture::new("card", "deck")
+            .template("template")
+            .build();
+        let mut modified = original.clone();
+        modified.template_id = None;
+        CardPatch::between(&original, &modified);
+    }
+
+    #[test]
+    fn test_id_types() {
+        let card: Card = serde_json::from_value(json!({
+            "id": "card",
+            "content": "",
+            "deck-id": "deck",
+            "template-id": "template",
+            "tags": [],
+            "references": [],
+        }))
+        .unwrap();
+        assert_eq!(card.id, CardId::new("card"));
+        assert_eq!(card.deck_id, "deck");
+        assert_eq!(card.template_id.unwrap().to_string(), "template");
+        assert_eq!(
+            serde_json::to_value(CardPatch::new().deck_id(&card.deck_id)).unwrap(),
+            json!({ "deck-id": "deck" })
+        );
+    }
+
+    #[test]
+    fn test_card_builder() {
+        let template = template_fixture("vocab", "", &[("name", "Word"), ("meaning", "Meaning")]);
+
+        let card = CardBuilder::new("deck")
+            .template(&template)
+            .field("Word", "犬")
+            .tag("jlpt-n3")
+            .review_reverse(true)
+            .build()
+            .unwrap();
+        assert_eq!(card.template_id, Some(TemplateId::from("vocab")));
+        assert_eq!(card.fields.as_ref().unwrap()["name"].value, "犬");
+        assert_eq!(card.manual_tags, Some(vec!["jlpt-n3".to_string()]));
+        assert!(card.review_reverse);
+
+        let unknown = CardBuilder::new("deck")
+            .template(&template)
+            .field("Word", "犬")
+            .field("Reading", "いぬ")
+            .build();
+        assert_eq!(
+            unknown.unwrap_err(),
+            CardBuildError::UnknownField("Reading".to_string())
+        );
+
+        let missing = CardBuilder::new("deck")
+            .template(&template)
+            .field("Meaning", "dog")
+            .build();
+        assert_eq!(
+            missing.unwrap_err(),
+            CardBuildError::MissingField("Word".to_string())
+        );
+
+        let plain = CardBuilder::new("deck").content("犬\n---\ndog").build();
+        assert!(plain.unwrap().template_id.is_none());
+        assert_eq!(
+            CardBuilder::new("deck")
+                .field("Word", "犬")
+                .build()
+                .unwrap_err(),
+            CardBuildError::NoTemplate
+        );
+    }
+
+    #[test]
+    fn test_field_by_name() {
+        let template = template_fixture("vocab", "", &[("name", "Word"), ("pitch", "PitchAccent")]);
+        let card = CardFixture::new("card", "deck")
+            .template("vocab")
+            .field("name", "犬")
+            .build();
+
+        let mut resolved = ResolvedCard::resolve(&[card], &[template]).remove(0);
+        assert_eq!(resolved.field("Word"), Some("犬"));
+        assert_eq!(resolved.field("PitchAccent"), None);
+        assert!(resolved.has_field("PitchAccent"));
+        assert!(resolved.set_field("PitchAccent", "<span></span>"));
+        assert!(!resolved.set_field("Reading", "いぬ"));
+        assert_eq!(
+            resolved.card.fields.as_ref().unwrap()["pitch"].value,
+            "<span></span>"
+        );
+    }
+
+    #[test]
+    fn test_extra_fields() {
+        let raw = json!({
+            "id": "card",
+            "content": "犬",
+            "deck-id": "deck",
+            "starred?": true,
+            "color": { "name": "red" },
+        });
+        let card: Card = serde_json::from_value(raw).unwrap();
+        assert!(card.tags.is_empty());
+        assert_eq!(card.extra["starred?"], json!(true));
+
+        // Written back as they were, without the retrieval-only id.
+        let written = serde_json::to_value(&card).unwrap();
+        assert_eq!(written["starred?"], json!(true));
+        assert_eq!(written["color"], json!({ "name": "red" }));
+        assert!(written.get("id").is_none());
+
+        let deck: Deck = serde_json::from_value(json!({
+            "id": "deck",
+            "name": "N3",
+            "cards-view": "grid",
+        }))
+        .unwrap();
+        let written = serde_json::to_value(&deck).unwrap();
+        assert_eq!(written["cards-view"], json!("grid"));
+
+        let template: Template = serde_json::from_value(json!({
+            "id": "vocab",
+            "name": "Vocab",
+            "content": "",
+            "style": { "text-alignment": "left" },
+        }))
+        .unwrap();
+        let written = serde_json::to_value(&template).unwrap();
+        assert_eq!(written["style"], json!({ "text-alignment": "left" }));
+        assert!(written.get("id").is_none());
+    }
+
+    #[test]
+    fn test_documented_fields() {
+        let card: Card = serde_json::from_value(json!({
+            "id": "card",
+            "name": "犬",
+            "content": "犬",
+            "deck-id": "deck",
+            "new?": false,
+            "created-at": { "date": "2024-02-29T12:34:56.789Z" },
+            "updated-at": { "date": "2024-05-01T09:00:00Z" },
+            "reviews": [
+                {
+                    "date": { "date": "2024-03-01T08:00:00Z" },
+                    "due": { "date": "2024-03-04T08:00:00Z" },
+                    "remembered?": true,
+                },
+            ],
+        }))
+        .unwrap();
+        assert_eq!(card.name.as_deref(), Some("犬"));
+        assert!(!card.new);
+        assert!(card.reviews[0].remembered);
+        assert!(card.extra.is_empty());
+        assert_eq!(
+            card.created_at.as_ref().unwrap().unix_millis(),
+            Some(1_709_210_096_789)
+        );
+        assert_eq!(
+            Timestamp::new("1970-01-01T00:00:00Z").unix_millis(),
+            Some(0)
+        );
+        assert_eq!(Timestamp::new("yesterday").unix_millis(), None);
+        // Both ways through the same civil date conversion, leap day included.
+        for date in [
+            "2024-02-29T12:34:56Z",
+            "2000-03-01T00:00:00Z",
+            "1999-12-31T23:59:59Z",
+        ] {
+            let time = Timestamp::new(date).system_time().unwrap();
+            assert_eq!(Timestamp::from_system_time(time).as_str(), date);
+        }
+
+        let updated = UNIX_EPOCH + Duration::from_secs(1_714_554_000);
+        assert!(card.modified_since(updated - Duration::from_secs(1)));
+        assert!(!card.modified_since(updated));
+
+        // Retrieval only, so not sent back on update.
+        let written = serde_json::to_value(&card).unwrap();
+        assert!(written.get("updated-at").is_none());
+        assert!(written.get("reviews").is_none());
+
+        let deck: Deck = serde_json::from_value(json!({
+            "id": "deck",
+            "name": "N3",
+            "sort": 2,
+            "trashed?": "2024-05-01T09:00:00Z",
+        }))
+        .unwrap();
+        assert_eq!(deck.sort, Some(2));
+        assert_eq!(serde_json::to_value(&deck).unwrap()["sort"], json!(2));
+        assert!(deck.trashed.is_some());
+    }
+
+    #[test]
+    fn test_review_history() {
+        let review = |date: &str, due: &str, remembered: bool| {
+            json!({
+                "date": { "date": date },
+                "due": { "date": due },
+                "remembered?": remembered,
+            })
+        };
+        let card = CardFixture::new("card", "deck")
+            .content("犬")
+            .set(
+                "reviews",
+                json!([
+                    review("2024-03-04T08:00:00Z", "2024-03-11T08:00:00Z", true),
+                    review("2024-03-01T08:00:00Z", "2024-03-02T08:00:00Z", false),
+                ]),
+            )
+            .build();
+        assert_eq!(card.due().unwrap().as_str(), "2024-03-11T08:00:00Z");
+        assert_eq!(card.interval(), Some(Duration::from_secs(7 * 86400)));
+        assert_eq!(card.times_remembered(), 1);
+        assert_eq!(card.times_forgotten(), 1);
+
+        let due = Timestamp::new("2024-03-11T08:00:00Z")
+            .system_time()
+            .unwrap();
+        assert!(card.is_due(due));
+        assert!(!card.is_due(due - Duration::from_secs(1)));
+        let new = CardFixture::new("new", "deck").build();
+        assert!(new.due().is_none() && !new.is_due(SystemTime::now()));
+    }
+}
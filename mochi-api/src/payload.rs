@@ -0,0 +1,136 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::models::{Card, CardId, FieldId};
+use crate::MochiError;
+
+// Payload Size Limits
+//
+// Generated fields (e.g. long sentence annotations) can push a card past what
+// the API accepts. Payloads are measured before sending so an oversized card
+// fails with `PayloadTooLarge` instead of an opaque 4xx, and callers can
+// truncate fields up front with a warning per card.
+
+// Kept below the API's limits so the rest of the card still fits.
+pub const MAX_PAYLOAD_BYTES: usize = 512 * 1024;
+pub const MAX_FIELD_BYTES: usize = 64 * 1024;
+
+const TRUNCATION_MARKER: &str = "…";
+
+// The serialized size of the payload, or `PayloadTooLarge` if it is over
+// MAX_PAYLOAD_BYTES.
+pub fn check_payload<T: Serialize>(payload: &T) -> Result<usize, MochiError> {
+    let bytes = serde_json::to_vec(payload)?.len();
+    if bytes > MAX_PAYLOAD_BYTES {
+        return Err(MochiError::PayloadTooLarge {
+            bytes,
+            limit: Some(MAX_PAYLOAD_BYTES),
+        });
+    }
+    Ok(bytes)
+}
+
+// Cut HTML to at most `max_bytes`, on a char boundary and outside of any tag,
+// ending with a marker so the truncation is visible on the card.
+pub fn truncate_html(html: &str, max_bytes: usize) -> String {
+    if html.len() <= max_bytes {
+        return html.to_string();
+    }
+
+    let mut end = max_bytes.saturating_sub(TRUNCATION_MARKER.len());
+    while !html.is_char_boundary(end) {
+        end -= 1;
+    }
+    // Don't leave half a tag behind.
+    let head = &html[..end];
+    if let Some(open) = head.rfind('<') {
+        if head[open..].find('>').is_none() {
+            end = open;
+        }
+    }
+    format!("{}{}", &html[..end], TRUNCATION_MARKER)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruncatedField {
+    pub card_id: CardId,
+    pub field_id: FieldId,
+    pub original_bytes: usize,
+    pub truncated_bytes: usize,
+}
+
+impl fmt::Display for TruncatedField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "card {}: field {} truncated from {} to {} bytes",
+            self.card_id, self.field_id, self.original_bytes, self.truncated_bytes
+        )
+    }
+}
+
+// Truncate every field over `max_field_bytes`, returning a warning for each.
+pub fn fit_card_fields(card: &mut Card, max_field_bytes: usize) -> Vec<TruncatedField> {
+    let mut truncated = vec![];
+    let fields = match card.fields.as_mut() {
+        Some(fields) => fields,
+        None => return truncated,
+    };
+
+    let mut field_ids = fields.keys().cloned().collect::<Vec<_>>();
+    field_ids.sort();
+    for field_id in field_ids {
+        let field = fields.get_mut(&field_id).unwrap();
+        if field.value.len() <= max_field_bytes {
+            continue;
+        }
+        let original_bytes = field.value.len();
+        field.value = truncate_html(&field.value, max_field_bytes);
+        truncated.push(TruncatedField {
+            card_id: card.id.clone(),
+            field_id,
+            original_bytes,
+            truncated_bytes: field.value.len(),
+        });
+    }
+    truncated
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::CardFixture;
+
+    #[test]
+    fn test_truncate_html() {
+        assert_eq!(truncate_html("short", 10), "short");
+        // Never splits a multi-byte char.
+        assert_eq!(truncate_html("犬犬犬犬犬", 8), "犬…");
+        // Never leaves an unclosed tag.
+        assert_eq!(truncate_html("ab<span>cd</span>", 9), "ab…");
+    }
+
+    #[test]
+    fn test_fit_card_fields() {
+        let mut card = CardFixture::new("card", "deck")
+            .field("sentence", &"a".repeat(100))
+            .field("word", "犬")
+            .build();
+
+        let truncated = fit_card_fields(&mut card, 20);
+        assert_eq!(truncated.len(), 1);
+        assert_eq!(
+            truncated[0].to_string(),
+            "card card: field sentence truncated from 100 to 20 bytes"
+        );
+        assert_eq!(card.fields.as_ref().unwrap()["word"].value, "犬");
+        assert!(check_payload(&card).is_ok());
+
+        card.content = "a".repeat(MAX_PAYLOAD_BYTES);
+        assert!(matches!(
+            check_payload(&card),
+            Err(MochiError::PayloadTooLarge { .. })
+        ));
+    }
+}
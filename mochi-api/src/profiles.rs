@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::models::TemplateId;
+use crate::Config;
+
+// Config Profiles
+//
+// Named settings in `config.toml` under the user's config directory, so one
+// machine can work with several accounts:
+//
+//   default = "me"
+//
+//   [profiles.me]
+//   api_key = "..."
+//   default_deck = "Japanese/N3"
+//   accent_notation = "html"
+//   accent_color = "#FF6633"
+//   bulk_concurrency = 8
+//   timeout_secs = 30
+//   proxy = "http://proxy.corp:8080"
+//   ca_cert = "/etc/ssl/corp-root.pem"
+//
+//   [profiles.me.templates]
+//   japanese-vocab = "TEMPLATE_ID"
+//
+//   [[profiles.me.schedule]]
+//   pipeline = "pitch"
+//   deck = "Japanese/N3"
+//   every_mins = 60
+//
+// The profile is the one named (e.g. `--profile`), else MOCHI_PROFILE, else
+// `default`, else the only one in the file. MOCHI_KEY overrides its key and
+// MOCHI_BASE_URL its base_url.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub api_key: Option<String>,
+    // Deck id or path, for commands given no deck.
+    pub default_deck: Option<String>,
+    // `html`, `svg`, `numeric` or `low-high`. The accent settings are kept
+    // as written; mochi-lib's `AccentSettings` reads them.
+    pub accent_notation: Option<String>,
+    pub accent_color: Option<String>,
+    pub accent_width: Option<String>,
+    pub bulk_concurrency: Option<usize>,
+    pub listing_concurrency: Option<usize>,
+    pub base_url: Option<String>,
+    pub timeout_secs: Option<u64>,
+    pub proxy: Option<String>,
+    // A PEM file of root certificates to trust.
+    pub ca_cert: Option<PathBuf>,
+    // Installed gallery templates: gallery key to template id.
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+    // Pipelines `mochi daemon` runs on its own.
+    #[serde(default)]
+    pub schedule: Vec<ScheduledPipeline>,
+}
+
+// A pipeline run on a deck every `every_mins`. The field names default to
+// those of the matching CLI command.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledPipeline {
+    // `pitch` or `romaji`.
+    pub pipeline: String,
+    // Deck id or path.
+    pub deck: String,
+    pub every_mins: u64,
+    pub word_field: Option<String>,
+    pub reading_field: Option<String>,
+    pub accent_field: Option<String>,
+    pub romaji_field: Option<String>,
+    // Write the filled field's coverage into the deck's stats card after
+    // each successful run.
+    #[serde(default)]
+    pub coverage: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ConfigFile {
+    pub default: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl ConfigFile {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("mochi-utils").join("config.toml"))
+    }
+
+    pub fn parse(raw: &str) -> Result<ConfigFile, toml::de::Error> {
+        toml::from_str(raw)
+    }
+
+    // An empty file if there is none.
+    pub fn load(path: &Path) -> Result<ConfigFile, Box<dyn Error>> {
+        match fs::read_to_string(path) {
+            Ok(raw) => Ok(ConfigFile::parse(&raw)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(ConfigFile::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Records a gallery template's id under the profile's `templates`,
+    // creating the file and the profile if need be. The rest of the file,
+    // comments included, is left as it was.
+    pub fn save_template(
+        path: &Path,
+        profile: &str,
+        key: &str,
+        template_id: &TemplateId,
+    ) -> Result<(), Box<dyn Error>> {
+        let raw = match fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut document = raw.parse::<toml_edit::DocumentMut>()?;
+        let templates = document
+            .entry("profiles")
+            .or_insert_with(implicit_table)
+            .as_table_mut()
+            .ok_or("profiles is not a table")?
+            .entry(profile)
+            .or_insert_with(toml_edit::table)
+            .as_table_mut()
+            .ok_or_else(|| format!("profiles.{} is not a table", profile))?
+            .entry("templates")
+            .or_insert_with(toml_edit::table)
+            .as_table_mut()
+            .ok_or_else(|| format!("profiles.{}.templates is not a table", profile))?;
+        templates.insert(key, toml_edit::value(template_id.as_str()));
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, document.to_string())?;
+        Ok(())
+    }
+
+    // The named profile, else the file's default, else the only one. Naming
+    // a profile the file lacks is an error; having none to pick isn't.
+    pub fn profile(&self, name: Option<&str>) -> Result<Option<(&str, &Profile)>, String> {
+        let name = match name.or(self.default.as_deref()) {
+            Some(name) => name,
+            None if self.profiles.len() == 1 => self.profiles.keys().next().unwrap(),
+            None => return Ok(None),
+        };
+        match self.profiles.get_key_value(name) {
+            Some((name, profile)) => Ok(Some((name.as_str(), profile))),
+            None => Err(format!("no profile named {}", name)),
+        }
+    }
+}
+
+// A `[profiles]` that only holds `[profiles.NAME]` tables.
+fn implicit_table() -> toml_edit::Item {
+    let mut table = toml_edit::Table::new();
+    table.set_implicit(true);
+    toml_edit::Item::Table(table)
+}
+
+impl Profile {
+    // The profile's API settings over the defaults. The key is left empty if
+    // the profile has none.
+    pub fn to_config(&self, name: &str) -> Result<Config, String> {
+        let mut config = Config {
+            profile: Some(name.to_string()),
+            mochi_key: self.api_key.clone().unwrap_or_default(),
+            default_deck: self.default_deck.clone(),
+            templates: self
+                .templates
+                .iter()
+                .map(|(key, id)| (key.clone(), TemplateId::from(id.as_str())))
+                .collect(),
+            ..Config::default()
+        };
+        if let Some(n) = self.bulk_concurrency {
+            config.bulk_concurrency = n.max(1);
+        }
+        if let Some(n) = self.listing_concurrency {
+            config.listing_concurrency = n.max(1);
+        }
+        if let Some(base_url) = &self.base_url {
+            config.base_url = base_url.clone();
+        }
+        config.timeout = self.timeout_secs.map(Duration::from_secs);
+        config.proxy = self.proxy.clone();
+        if let Some(path) = &self.ca_cert {
+            let pem = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            config.root_certificates = reqwest::Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        let file = ConfigFile::parse(
+            r##"
+            default = "me"
+
+            [profiles.me]
+            api_key = "key-1"
+            default_deck = "Japanese/N3"
+
+            [profiles.partner]
+            api_key = "key-2"
+            accent_notation = "low-high"
+            accent_color = "#3366FF"
+            bulk_concurrency = 0
+            base_url = "http://localhost:8080/api"
+            timeout_secs = 30
+
+            [profiles.partner.templates]
+            japanese-vocab = "tmpl-1"
+
+            [[profiles.partner.schedule]]
+            pipeline = "pitch"
+            deck = "Japanese/N3"
+            every_mins = 60
+            "##,
+        )
+        .unwrap();
+
+        let (name, profile) = file.profile(None).unwrap().unwrap();
+        assert_eq!(name, "me");
+        let config = profile.to_config(name).unwrap();
+        assert_eq!(config.mochi_key, "key-1");
+        assert_eq!(config.default_deck.as_deref(), Some("Japanese/N3"));
+
+        let (name, profile) = file.profile(Some("partner")).unwrap().unwrap();
+        let config = profile.to_config(name).unwrap();
+        assert_eq!(config.profile.as_deref(), Some("partner"));
+        assert_eq!(profile.accent_notation.as_deref(), Some("low-high"));
+        assert_eq!(config.bulk_concurrency, 1);
+        assert_eq!(config.url("cards/c1"), "http://localhost:8080/api/cards/c1");
+        assert_eq!(config.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(profile.schedule[0].deck, "Japanese/N3");
+        assert_eq!(
+            config.templates["japanese-vocab"],
+            TemplateId::from("tmpl-1")
+        );
+
+        assert!(file.profile(Some("work")).is_err());
+        assert_eq!(ConfigFile::default().profile(None), Ok(None));
+        assert!(ConfigFile::parse("[profiles.me]\napi_kye = \"typo\"").is_err());
+    }
+
+    #[test]
+    fn test_save_template() {
+        let path = std::env::temp_dir().join(format!("mochi-config-{}.toml", std::process::id()));
+        fs::write(&path, "# my accounts\n[profiles.me]\napi_key = \"key-1\"\n").unwrap();
+
+        let id = TemplateId::from("tmpl-1");
+        ConfigFile::save_template(&path, "me", "japanese-vocab", &id).unwrap();
+        ConfigFile::save_template(&path, "partner", "japanese-kanji", &id).unwrap();
+
+        let raw = fs::read_to_string(&path).unwrap();
+        assert!(raw.starts_with("# my accounts\n"));
+        let file = ConfigFile::parse(&raw).unwrap();
+        assert_eq!(file.profiles["me"].api_key.as_deref(), Some("key-1"));
+        assert_eq!(file.profiles["me"].templates["japanese-vocab"], "tmpl-1");
+        assert_eq!(
+            file.profiles["partner"].templates["japanese-kanji"],
+            "tmpl-1"
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
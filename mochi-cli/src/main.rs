@@ -0,0 +1,780 @@
+use std::error::Error;
+#[cfg(unix)]
+use std::io::Read;
+use std::io::{self, IsTerminal};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::ExitCode;
+#[cfg(unix)]
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+#[cfg(unix)]
+use mochi_lib::cache::{Cache, DEFAULT_MAX_AGE};
+use mochi_lib::coverage::{refresh_coverage_card, CoverageReport};
+#[cfg(feature = "keyring")]
+use mochi_lib::credentials::{delete_api_key, store_api_key};
+#[cfg(unix)]
+use mochi_lib::daemon::{cache_refresh_job, outbox_job, pipeline_job, Daemon, DaemonOptions};
+use mochi_lib::decks::DeckTree;
+use mochi_lib::diff::diff_cards;
+use mochi_lib::find::NameMatch;
+use mochi_lib::gallery::install_template;
+use mochi_lib::history::{RunHistory, RunRecord};
+use mochi_lib::journal::patch_cards_with_journal;
+use mochi_lib::leeches::{archive_leeches, find_leeches, tag_leeches, LEECH_THRESHOLD};
+use mochi_lib::models::{Card, CardId, CardPatch, Deck, DeckId, Template};
+use mochi_lib::pipeline::{OverwritePolicy, Pipeline, PitchAccentTransformer};
+use mochi_lib::preview::{preview_deck_field, PreviewServer};
+#[cfg(feature = "keyring")]
+use mochi_lib::profiles::ConfigFile;
+use mochi_lib::profiles::{config_profile, AccentSettings};
+use mochi_lib::stats::deck_stats;
+use mochi_lib::tags::add_tags_patch;
+use mochi_lib::{
+    get_card, list_cards, list_decks, list_templates, patch_cards, AccentMap, BulkResult, Config,
+    MochiError,
+};
+use serde_json::json;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+use crate::output::{write_rows, OutputArgs, Row};
+
+mod output;
+
+// Mochi CLI
+//
+// `mochi auth login` keeps the key in the OS keyring, in place of MOCHI_KEY
+// or the config file's api_key.
+//
+// Exit codes: 0 on success, 1 on an error, 2 on bad arguments (from clap)
+// and 3 when some cards failed to update.
+
+const EXIT_PARTIAL: u8 = 3;
+
+// Only events from these crates are logged, not from the HTTP stack.
+const LOG_TARGETS: [&str; 4] = ["mochi_api", "mochi_jp", "mochi_lib", "mochi"];
+
+// RUST_LOG if set, else warnings from our crates, with `-v` adding info,
+// `-vv` debug and `-vvv` trace.
+fn log_filter(verbose: u8) -> EnvFilter {
+    if let Ok(filter) = EnvFilter::try_from_default_env() {
+        return filter;
+    }
+    let level = match verbose {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    let directives = LOG_TARGETS
+        .iter()
+        .map(|target| format!("{}={}", target, level))
+        .collect::<Vec<_>>();
+    EnvFilter::new(format!("off,{}", directives.join(",")))
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "mochi", version, about = "Tools for Mochi cards")]
+struct Cli {
+    /// Config file profile, instead of MOCHI_PROFILE or the default
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Log more to stderr: -v for progress, -vv for every request
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    #[command(subcommand)]
+    Decks(DecksCommand),
+    #[command(subcommand)]
+    Cards(CardsCommand),
+    #[command(subcommand)]
+    Templates(TemplatesCommand),
+    #[command(subcommand)]
+    Pitch(PitchCommand),
+    /// List the cards of a deck forgotten again and again
+    Leeches(LeechesArgs),
+    /// Serve a deck's field HTML in card frames, to check markup by eye
+    Preview(PreviewArgs),
+    /// Cards due today, new cards, leeches and retention of a deck
+    Stats {
+        /// Deck id, name or path (e.g. Japanese/N3), else the profile's
+        #[arg(long)]
+        deck: Option<String>,
+    },
+    /// Keep the cache warm, send the offline outbox and run the profile's
+    /// scheduled pipelines, with the status on a local socket
+    #[cfg(unix)]
+    Daemon(DaemonArgs),
+    #[command(subcommand)]
+    History(HistoryCommand),
+    #[cfg(feature = "keyring")]
+    #[command(subcommand)]
+    Auth(AuthCommand),
+}
+
+#[cfg(unix)]
+#[derive(Debug, Args)]
+struct DaemonArgs {
+    /// Status socket, else daemon.sock in the cache directory
+    #[arg(long)]
+    socket: Option<PathBuf>,
+    /// Print the running daemon's status and exit
+    #[arg(long)]
+    status: bool,
+    /// Seconds to wait between two jobs
+    #[arg(long, default_value_t = 10)]
+    min_gap_secs: u64,
+    #[arg(long, default_value_t = 5)]
+    outbox_mins: u64,
+    #[arg(long, default_value_t = 30)]
+    refresh_mins: u64,
+    /// Re-list every deck's cards at least this often, to pick up edits
+    /// made in the app
+    #[arg(long, default_value_t = DEFAULT_MAX_AGE.as_secs() / 60)]
+    max_age_mins: u64,
+}
+
+#[derive(Debug, Subcommand)]
+enum HistoryCommand {
+    /// List the runs of commands that changed cards, oldest first
+    Runs {
+        /// Only the last N runs
+        #[arg(long)]
+        limit: Option<usize>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[cfg(feature = "keyring")]
+#[derive(Debug, Subcommand)]
+enum AuthCommand {
+    /// Prompt for the profile's API key and keep it in the OS keyring
+    Login,
+    /// Remove the profile's API key from the OS keyring
+    Logout,
+}
+
+#[derive(Debug, Subcommand)]
+enum DecksCommand {
+    /// List every deck with its path
+    List {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TemplatesCommand {
+    /// List every template
+    List {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Create a gallery template (e.g. japanese-vocab) and save its id in
+    /// the profile
+    Install {
+        /// japanese-vocab, japanese-sentence or japanese-kanji
+        key: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CardsCommand {
+    /// List the cards of a deck
+    List {
+        /// Deck id, name or path (e.g. Japanese/N3), else the profile's
+        #[arg(long)]
+        deck: Option<String>,
+        #[arg(long)]
+        limit: Option<usize>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Change a card's fields, content, tags or archived state
+    Update(UpdateArgs),
+}
+
+#[derive(Debug, Args)]
+struct UpdateArgs {
+    /// The card's id
+    card: String,
+    /// Set a template field, as NAME=VALUE
+    #[arg(long = "field", value_parser = parse_assignment)]
+    fields: Vec<(String, String)>,
+    #[arg(long)]
+    content: Option<String>,
+    /// Add a tag
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+    #[arg(long, conflicts_with = "unarchive")]
+    archive: bool,
+    #[arg(long)]
+    unarchive: bool,
+    /// Print the changes instead of making them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+struct LeechesArgs {
+    /// Deck id, name or path (e.g. Japanese/N3), else the profile's
+    #[arg(long)]
+    deck: Option<String>,
+    /// Times forgotten that make a card a leech
+    #[arg(long, default_value_t = LEECH_THRESHOLD)]
+    threshold: usize,
+    /// Tag the leeches `leech`
+    #[arg(long, conflicts_with = "archive")]
+    tag: bool,
+    /// Archive the leeches
+    #[arg(long)]
+    archive: bool,
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+struct PreviewArgs {
+    /// Deck id, name or path (e.g. Japanese/N3), else the profile's
+    #[arg(long)]
+    deck: Option<String>,
+    #[arg(long, default_value = "PitchAccent")]
+    field: String,
+    /// Local port to serve on, else any free one
+    #[arg(long, default_value_t = 0)]
+    port: u16,
+}
+
+#[derive(Debug, Subcommand)]
+enum PitchCommand {
+    /// Fill a deck's pitch accent field from its word field
+    Apply(PitchArgs),
+}
+
+#[derive(Debug, Args)]
+struct PitchArgs {
+    /// Deck id, name or path (e.g. Japanese/N3), else the profile's
+    #[arg(long)]
+    deck: Option<String>,
+    #[arg(long, default_value = "Word")]
+    word_field: String,
+    /// Narrows homographs to the reading on the card
+    #[arg(long)]
+    reading_field: Option<String>,
+    #[arg(long, default_value = "PitchAccent")]
+    accent_field: String,
+    /// Leave accent fields that already have a value alone
+    #[arg(long)]
+    keep_existing: bool,
+    /// Print the changes instead of making them
+    #[arg(long)]
+    dry_run: bool,
+    /// Record the cards before updating them, for `rollback`
+    #[arg(long)]
+    journal: Option<PathBuf>,
+    /// Write the accent field's coverage into the deck's stats card
+    #[arg(long)]
+    coverage: bool,
+}
+
+// The profile `Config::build_for_profile` would pick, for its keyring entry.
+#[cfg(feature = "keyring")]
+fn keyring_profile(profile: Option<&str>) -> Result<Option<String>, Box<dyn Error>> {
+    let file = match ConfigFile::default_path() {
+        Some(path) => ConfigFile::load(&path)?,
+        None => ConfigFile::default(),
+    };
+    let name = profile
+        .map(str::to_string)
+        .or_else(|| std::env::var("MOCHI_PROFILE").ok());
+    Ok(file
+        .profile(name.as_deref())?
+        .map(|(name, _)| name.to_string()))
+}
+
+#[cfg(feature = "keyring")]
+fn auth(profile: Option<&str>, command: AuthCommand) -> Result<(), Box<dyn Error>> {
+    let profile = keyring_profile(profile)?;
+    let name = profile.as_deref().unwrap_or("default");
+    match command {
+        AuthCommand::Login => {
+            let key = rpassword::prompt_password(format!("Mochi API key for {}: ", name))?;
+            let key = key.trim();
+            if key.is_empty() {
+                return Err("no key given".into());
+            }
+            store_api_key(profile.as_deref(), key)?;
+            eprintln!("saved the API key for {} in the keyring", name);
+        }
+        AuthCommand::Logout => {
+            delete_api_key(profile.as_deref())?;
+            eprintln!("removed the API key for {} from the keyring", name);
+        }
+    }
+    Ok(())
+}
+
+fn parse_assignment(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected NAME=VALUE, got {:?}", arg)),
+    }
+}
+
+// By id first, then by path (or name, for top-level decks) ignoring case.
+fn find_deck(decks: &[Deck], deck: &str) -> Option<Deck> {
+    match decks.iter().find(|d| d.id == deck) {
+        Some(found) => Some(found.clone()),
+        None => DeckTree::new(decks)
+            .find_by_path_matching(deck, NameMatch::CaseInsensitive)
+            .cloned(),
+    }
+}
+
+// The given deck, else the profile's default deck.
+async fn resolve_deck(config: &Config, deck: Option<&str>) -> Result<Deck, Box<dyn Error>> {
+    let deck = deck
+        .or(config.default_deck.as_deref())
+        .ok_or("no deck given and the profile has no default_deck")?;
+    let decks = list_decks(config).await?;
+    let found = find_deck(&decks, deck);
+    Ok(found.ok_or(MochiError::NotFound {
+        kind: "deck",
+        name: deck.to_string(),
+    })?)
+}
+
+fn deck_row(deck: &Deck, tree: &DeckTree) -> Row {
+    let path = tree.path(&deck.id).unwrap_or(deck.name.clone());
+    let mut row = Row::new();
+    row.insert("id".to_string(), json!(deck.id));
+    row.insert("name".to_string(), json!(deck.name));
+    row.insert("path".to_string(), json!(path));
+    row.insert("parent-id".to_string(), json!(deck.parent_id));
+    row.insert("archived".to_string(), json!(deck.archived));
+    row
+}
+
+fn template_row(template: &Template) -> Row {
+    let mut fields = template
+        .fields
+        .iter()
+        .flat_map(|f| f.values())
+        .collect::<Vec<_>>();
+    fields.sort_by(|a, b| a.pos.cmp(&b.pos));
+    let mut row = Row::new();
+    row.insert("id".to_string(), json!(template.id));
+    row.insert("name".to_string(), json!(template.name));
+    row.insert(
+        "fields".to_string(),
+        json!(fields.iter().map(|f| &f.name).collect::<Vec<_>>()),
+    );
+    row
+}
+
+// The card's id, name, deck, tags, archived state and due date, then its
+// fields by name.
+fn run_row(run: &RunRecord) -> Row {
+    let mut row = Row::new();
+    row.insert("started".to_string(), json!(run.started));
+    row.insert("finished".to_string(), json!(run.finished));
+    row.insert("command".to_string(), json!(run.command));
+    row.insert("parameters".to_string(), json!(run.parameters));
+    row.insert("counts".to_string(), json!(run.counts));
+    row.insert("audit-file".to_string(), json!(run.audit_file));
+    row
+}
+
+fn card_row(card: &Card, templates: &[Template]) -> Row {
+    let template = templates
+        .iter()
+        .find(|t| Some(&t.id) == card.template_id.as_ref());
+    let mut row = Row::new();
+    row.insert("id".to_string(), json!(card.id));
+    row.insert("name".to_string(), json!(card_name(card, template)));
+    row.insert("deck-id".to_string(), json!(card.deck_id));
+    row.insert("template".to_string(), json!(template.map(|t| &t.name)));
+    row.insert("content".to_string(), json!(card.content));
+    let tags = card.manual_tags.as_ref().unwrap_or(&card.tags);
+    row.insert("tags".to_string(), json!(tags));
+    row.insert("archived".to_string(), json!(card.archived));
+    row.insert("due".to_string(), json!(card.due().map(|d| d.as_str())));
+    if let Some(template) = template {
+        for field in template.fields.iter().flat_map(|f| f.values()) {
+            if let Some(value) = card.field_by_name(template, &field.name) {
+                row.insert(field.name.clone(), json!(value.value));
+            }
+        }
+    }
+    row
+}
+
+// The first field's value, or the first line of the content.
+fn card_name(card: &Card, template: Option<&Template>) -> String {
+    let name = template.and_then(|t| card.field_by_name(t, &t.fields.as_ref()?.get("name")?.name));
+    match name {
+        Some(field) => field.value.clone(),
+        None => card.content.lines().next().unwrap_or("").to_string(),
+    }
+}
+
+fn update_patch(
+    card: &Card,
+    template: Option<&Template>,
+    args: &UpdateArgs,
+) -> Result<CardPatch, Box<dyn Error>> {
+    let mut patch = CardPatch::new();
+    for (name, value) in args.fields.iter() {
+        let field = template
+            .and_then(|t| t.field_by_name(name))
+            .ok_or_else(|| format!("the card's template has no field named {}", name))?;
+        patch = patch.field(&field.id, value);
+    }
+    if let Some(content) = &args.content {
+        patch = patch.content(content);
+    }
+    if let Some(tags) = add_tags_patch(card, &args.tags) {
+        patch.manual_tags = tags.manual_tags;
+    }
+    if args.archive || args.unarchive {
+        patch = patch.archived(args.archive);
+    }
+    Ok(patch)
+}
+
+fn exit_code(result: &BulkResult) -> ExitCode {
+    for (card_id, err) in result.failed.iter() {
+        eprintln!("{}: {}", card_id, err);
+    }
+    eprintln!(
+        "{} updated, {} failed",
+        result.succeeded.len(),
+        result.failed.len()
+    );
+    match result.is_success() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::from(EXIT_PARTIAL),
+    }
+}
+
+// Records the run and marks the deck it changed stale in the cache, so the
+// next refresh lists it again. Best effort: a run that changed cards still
+// succeeded if the cache can't be written.
+fn record_run(record: &mut RunRecord, result: &BulkResult, deck_id: &DeckId) {
+    record.count_result(result);
+    record.finish();
+    let recorded = RunHistory::open_default().and_then(|history| match history {
+        Some(history) => {
+            history.append(record)?;
+            history.into_cache().mark_stale(deck_id)
+        }
+        None => Ok(()),
+    });
+    if let Err(err) = recorded {
+        eprintln!("warning: the run was not recorded: {}", err);
+    }
+}
+
+fn history(command: HistoryCommand) -> Result<(), Box<dyn Error>> {
+    let HistoryCommand::Runs { limit, output } = command;
+    let runs = match RunHistory::open_default()? {
+        Some(history) => history.runs()?,
+        None => vec![],
+    };
+    let skip = limit.map_or(0, |limit| runs.len().saturating_sub(limit));
+    let rows = runs.iter().skip(skip).map(run_row).collect::<Vec<_>>();
+    write_rows(
+        &rows,
+        &["started", "command", "parameters", "counts"],
+        &output,
+        &mut io::stdout().lock(),
+    )?;
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn daemon(config: &Config, args: &DaemonArgs) -> Result<(), Box<dyn Error>> {
+    let socket_path = args
+        .socket
+        .clone()
+        .or_else(DaemonOptions::default_socket_path)
+        .ok_or("no cache directory for the socket, give --socket")?;
+    if args.status {
+        let mut status = String::new();
+        UnixStream::connect(&socket_path)?.read_to_string(&mut status)?;
+        println!("{}", status);
+        return Ok(());
+    }
+    let cache_path = Cache::default_path().ok_or("no cache directory")?;
+    let minutes = |mins: u64| Duration::from_secs(mins * 60);
+
+    let mut daemon = Daemon::new(
+        config,
+        DaemonOptions {
+            socket_path,
+            min_gap: Duration::from_secs(args.min_gap_secs),
+        },
+    );
+    daemon.add_job(outbox_job(&cache_path, minutes(args.outbox_mins)));
+    daemon.add_job(cache_refresh_job(
+        &cache_path,
+        minutes(args.refresh_mins),
+        minutes(args.max_age_mins),
+    ));
+    let accents = AccentSettings::load(config)?;
+    let schedule = config_profile(config)?
+        .map(|p| p.schedule)
+        .unwrap_or_default();
+    for scheduled in schedule.iter() {
+        daemon.add_job(pipeline_job(&cache_path, scheduled, &accents)?);
+    }
+    daemon.run().await
+}
+
+async fn run(cli: Cli) -> Result<ExitCode, Box<dyn Error>> {
+    // Logging in needs no key.
+    #[cfg(feature = "keyring")]
+    if let Command::Auth(command) = cli.command {
+        auth(cli.profile.as_deref(), command)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    // Neither does reading the run history.
+    if let Command::History(command) = cli.command {
+        history(command)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut config = Config::build_for_profile(cli.profile.as_deref())?;
+    match cli.command {
+        Command::Decks(DecksCommand::List { output }) => {
+            let decks = list_decks(&config).await?;
+            let tree = DeckTree::new(&decks);
+            let rows = decks.iter().map(|d| deck_row(d, &tree)).collect::<Vec<_>>();
+            write_rows(&rows, &["id", "path"], &output, &mut io::stdout().lock())?;
+        }
+        Command::Templates(TemplatesCommand::List { output }) => {
+            let templates = list_templates(&config).await?;
+            let rows = templates.iter().map(template_row).collect::<Vec<_>>();
+            write_rows(
+                &rows,
+                &["id", "name", "fields"],
+                &output,
+                &mut io::stdout().lock(),
+            )?;
+        }
+        Command::Templates(TemplatesCommand::Install { key }) => {
+            let template = install_template(&mut config, &key).await?;
+            println!("{}\t{}", template.id, template.name);
+            if config.profile.is_none() {
+                eprintln!(
+                    "no config profile to save the id in, add it to a profile's templates as {} = \"{}\"",
+                    key, template.id
+                );
+            }
+        }
+        Command::Cards(CardsCommand::List {
+            deck,
+            limit,
+            output,
+        }) => {
+            let deck = resolve_deck(&config, deck.as_deref()).await?;
+            let cards = list_cards(&config, &deck.id, limit).await?;
+            let templates = list_templates(&config).await?;
+            let rows = cards
+                .iter()
+                .map(|c| card_row(c, &templates))
+                .collect::<Vec<_>>();
+            write_rows(
+                &rows,
+                &["id", "name", "tags"],
+                &output,
+                &mut io::stdout().lock(),
+            )?;
+        }
+        Command::Cards(CardsCommand::Update(args)) => {
+            let card = get_card(&config, &CardId::from(args.card.as_str())).await?;
+            let templates = list_templates(&config).await?;
+            let template = templates
+                .iter()
+                .find(|t| Some(&t.id) == card.template_id.as_ref());
+            let patch = update_patch(&card, template, &args)?;
+            if args.dry_run {
+                let mut modified = card.clone();
+                patch.apply(&mut modified);
+                print!("{}", diff_cards(&card, &modified, &templates));
+                return Ok(ExitCode::SUCCESS);
+            }
+            let mut record = RunRecord::start("cards update").parameter("card", &card.id);
+            let result = patch_cards(&config, &[(card.id.clone(), patch)]).await;
+            record_run(&mut record, &result, &card.deck_id);
+            return Ok(exit_code(&result));
+        }
+        Command::Leeches(args) => {
+            let deck = resolve_deck(&config, args.deck.as_deref()).await?;
+            let leeches = find_leeches(&config, &deck.id, args.threshold).await?;
+            let templates = list_templates(&config).await?;
+            let rows = leeches
+                .iter()
+                .map(|c| {
+                    let mut row = card_row(c, &templates);
+                    row.insert("forgotten".to_string(), json!(c.times_forgotten()));
+                    row
+                })
+                .collect::<Vec<_>>();
+            write_rows(
+                &rows,
+                &["id", "name", "forgotten"],
+                &args.output,
+                &mut io::stdout().lock(),
+            )?;
+            let record = RunRecord::start("leeches")
+                .parameter("deck", &deck.id)
+                .parameter("threshold", args.threshold);
+            if args.tag {
+                let mut record = record.parameter("tag", true);
+                let result = tag_leeches(&config, &leeches).await;
+                record_run(&mut record, &result, &deck.id);
+                return Ok(exit_code(&result));
+            }
+            if args.archive {
+                let mut record = record.parameter("archive", true);
+                let result = archive_leeches(&config, &leeches).await;
+                record_run(&mut record, &result, &deck.id);
+                return Ok(exit_code(&result));
+            }
+        }
+        #[cfg(unix)]
+        Command::Daemon(args) => daemon(&config, &args).await?,
+        Command::Preview(args) => {
+            let deck = resolve_deck(&config, args.deck.as_deref()).await?;
+            let page = preview_deck_field(&config, &deck.id, &args.field).await?;
+            let server = PreviewServer::bind(("127.0.0.1", args.port)).await?;
+            eprintln!(
+                "previewing {} of {} at http://{}/, Ctrl-C to stop",
+                args.field,
+                deck.name,
+                server.local_addr()?
+            );
+            server.serve(page).await?;
+        }
+        Command::Stats { deck } => {
+            let deck = resolve_deck(&config, deck.as_deref()).await?;
+            print!("{}", deck_stats(&config, &deck.id).await?);
+        }
+        #[cfg(feature = "keyring")]
+        Command::Auth(_) => unreachable!("handled before the config is built"),
+        Command::History(_) => unreachable!("handled before the config is built"),
+        Command::Pitch(PitchCommand::Apply(args)) => {
+            let deck = resolve_deck(&config, args.deck.as_deref()).await?;
+            let cards = list_cards(&config, &deck.id, None).await?;
+            let templates = list_templates(&config).await?;
+            let mut transformer = PitchAccentTransformer::new(
+                AccentMap::global(),
+                &args.word_field,
+                &args.accent_field,
+            );
+            transformer.reading_field = args.reading_field.clone();
+            transformer.overwrite = match args.keep_existing {
+                true => OverwritePolicy::Never,
+                false => OverwritePolicy::IfDifferent,
+            };
+            let accents = AccentSettings::load(&config)?;
+            transformer.notation = accents.notation;
+            transformer.style = accents.style;
+            let pipeline = Pipeline::new().with(&transformer);
+            let run = pipeline.apply(&cards, &templates);
+            eprint!(
+                "{}",
+                CoverageReport::from_run(&run, &templates, &transformer)
+            );
+            if args.dry_run {
+                for diff in pipeline.preview(&cards, &templates) {
+                    print!("{}", diff);
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+            let mut record = RunRecord::start("pitch apply")
+                .parameter("deck", &deck.id)
+                .parameter("accent-field", &args.accent_field);
+            record.audit_file = args.journal.clone();
+            let result = match &args.journal {
+                Some(path) => {
+                    let description = format!("mochi pitch apply --deck {}", deck.id);
+                    patch_cards_with_journal(&config, &cards, &run.patches, path, &description)
+                        .await?
+                }
+                None => patch_cards(&config, &run.patches).await,
+            };
+            record_run(&mut record, &result, &deck.id);
+            if args.coverage && result.is_success() {
+                let fields = [(args.accent_field.as_str(), "pitch")];
+                refresh_coverage_card(&config, &deck.id, &fields).await?;
+            }
+            return Ok(exit_code(&result));
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    tracing_subscriber::fmt()
+        .with_env_filter(log_filter(cli.verbose))
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+    match run(cli).await {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_arguments() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "mochi",
+            "cards",
+            "update",
+            "c1",
+            "--field",
+            "Meaning=to eat",
+            "--archive",
+        ])
+        .unwrap();
+        let Command::Cards(CardsCommand::Update(args)) = cli.command else {
+            panic!("expected cards update");
+        };
+        assert_eq!(args.fields, [("Meaning".to_string(), "to eat".to_string())]);
+        assert!(parse_assignment("Meaning").is_err());
+        assert!(Cli::try_parse_from([
+            "mochi",
+            "cards",
+            "update",
+            "c1",
+            "--archive",
+            "--unarchive"
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use std::io::{self, Write};
+
+use clap::{Args, ValueEnum};
+use serde_json::{Map, Value};
+
+// Listing Output
+//
+// Listings are rows of JSON objects. JSON and NDJSON print them whole for
+// scripts; the table picks columns, by default the listing's own or those
+// given with `--fields`.
+
+// Cells longer than this are cut short in the table.
+const MAX_CELL_WIDTH: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Format {
+    #[default]
+    Table,
+    Json,
+    Ndjson,
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct OutputArgs {
+    #[arg(long, value_enum, default_value_t)]
+    pub format: Format,
+    /// Columns of the table, comma separated (e.g. id,Word,tags)
+    #[arg(long, value_delimiter = ',')]
+    pub fields: Vec<String>,
+}
+
+pub type Row = Map<String, Value>;
+
+// Terminal columns taken by the character: two for CJK and full-width forms.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6 => 2,
+        _ => 1,
+    }
+}
+
+fn width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+fn cell(value: Option<&Value>) -> String {
+    let text = match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|v| cell(Some(v)))
+            .collect::<Vec<_>>()
+            .join(" "),
+        Some(Value::Object(map)) => map
+            .iter()
+            .map(|(name, v)| format!("{}={}", name, cell(Some(v))))
+            .collect::<Vec<_>>()
+            .join(" "),
+        Some(other) => other.to_string(),
+    };
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if width(&text) <= MAX_CELL_WIDTH {
+        return text;
+    }
+    let mut cut = String::new();
+    for c in text.chars() {
+        if width(&cut) + char_width(c) > MAX_CELL_WIDTH - 1 {
+            break;
+        }
+        cut.push(c);
+    }
+    cut.push('…');
+    cut
+}
+
+fn write_table<W: Write>(rows: &[Row], columns: &[String], out: &mut W) -> io::Result<()> {
+    let cells = rows
+        .iter()
+        .map(|row| columns.iter().map(|c| cell(row.get(c))).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            cells
+                .iter()
+                .map(|row| width(&row[i]))
+                .chain([width(c)])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    let line = |values: &[String]| {
+        let padded = values
+            .iter()
+            .zip(widths.iter())
+            .map(|(v, w)| format!("{}{}", v, " ".repeat(w - width(v))))
+            .collect::<Vec<_>>();
+        padded.join("  ").trim_end().to_string()
+    };
+    writeln!(out, "{}", line(columns))?;
+    for row in cells.iter() {
+        writeln!(out, "{}", line(row))?;
+    }
+    Ok(())
+}
+
+pub fn write_rows<W: Write>(
+    rows: &[Row],
+    default_columns: &[&str],
+    args: &OutputArgs,
+    out: &mut W,
+) -> io::Result<()> {
+    match args.format {
+        Format::Table => {
+            let columns = match args.fields.is_empty() {
+                true => default_columns.iter().map(|c| c.to_string()).collect(),
+                false => args.fields.clone(),
+            };
+            write_table(rows, &columns, out)
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, rows)?;
+            writeln!(out)
+        }
+        Format::Ndjson => {
+            for row in rows {
+                writeln!(out, "{}", Value::Object(row.clone()))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_write_rows() {
+        let rows = [
+            json!({ "id": "c1", "Word": "箸", "tags": ["food", "n5"] }),
+            json!({ "id": "c22", "Word": "橋", "tags": [] }),
+        ]
+        .map(|v| v.as_object().unwrap().clone());
+
+        let mut out = vec![];
+        write_rows(&rows, &["id", "Word"], &OutputArgs::default(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "id   Word\nc1   箸\nc22  橋\n"
+        );
+
+        let mut out = vec![];
+        let args = OutputArgs {
+            format: Format::Table,
+            fields: vec!["Word".to_string(), "tags".to_string()],
+        };
+        write_rows(&rows, &["id"], &args, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Word  tags\n箸    food n5\n橋\n"
+        );
+
+        let mut out = vec![];
+        let run = json!({ "command": "leeches", "parameters": { "deck": "N3", "tag": "true" } });
+        let runs = [run.as_object().unwrap().clone()];
+        write_rows(
+            &runs,
+            &["command", "parameters"],
+            &OutputArgs::default(),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "command  parameters\nleeches  deck=N3 tag=true\n"
+        );
+
+        let mut out = vec![];
+        let args = OutputArgs {
+            format: Format::Ndjson,
+            ..OutputArgs::default()
+        };
+        write_rows(&rows[..1], &["id"], &args, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"Word\":\"箸\",\"id\":\"c1\",\"tags\":[\"food\",\"n5\"]}\n"
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use bincode::Options;
+
+// Bundled Dictionary
+//
+// Parses `resources/accents.txt` with the crate's own parser and saves it in
+// OUT_DIR the way `AccentMap::save_binary` does, for `load_accents` to
+// include. Decoding that at run time is much faster than parsing the text.
+
+#[allow(dead_code)]
+#[path = "src/pitch.rs"]
+mod pitch;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let source = Path::new("resources/accents.txt");
+    println!("cargo:rerun-if-changed={}", source.display());
+    println!("cargo:rerun-if-changed=src/pitch.rs");
+
+    let accents = pitch::parse_accents(&fs::read_to_string(source)?)?;
+    let out = Path::new(&env::var("OUT_DIR")?).join("accents.bin");
+    let writer = BufWriter::new(File::create(out)?);
+    bincode::DefaultOptions::new().serialize_into(writer, &accents)?;
+    Ok(())
+}
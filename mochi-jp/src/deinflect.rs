@@ -0,0 +1,321 @@
+use crate::{word_accents, Accent, AccentMap, AccentType, KanaString, Word, WordAccents};
+
+// Deinflection
+//
+// Conjugated card words like 食べた or 高くない are not in the accent
+// dictionary. Suffix rules reduce them to dictionary forms for the lookup, and
+// optionally the accent is shifted to the conjugated form using the usual
+// Tokyo rules, simplified:
+//
+// - ます forms fall on the ま, whatever the verb's accent.
+// - Otherwise heiban verbs and adjectives stay heiban, except the かった form
+//   of adjectives, which falls before the か.
+// - Accented verbs fall before ない, and in the た/て forms one mora earlier
+//   than the dictionary form for ichidan verbs and on the same mora for godan.
+// - Accented adjectives keep their accent.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordKind {
+    Ichidan,
+    Godan,
+    Suru,
+    Adjective,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inflection {
+    Past,
+    Te,
+    Negative,
+    NegativePast,
+    Polite,
+    PolitePast,
+    PoliteNegative,
+    Adverbial,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deinflection {
+    pub dictionary_form: String,
+    pub kind: WordKind,
+    pub inflection: Inflection,
+    // The conjugated ending that replaced `dictionary_ending`.
+    pub ending: &'static str,
+    pub dictionary_ending: &'static str,
+}
+
+// (conjugated ending, dictionary ending, kind, inflection), longest first
+// within each group so e.g. なかった is tried before た.
+const RULES: &[(&str, &str, WordKind, Inflection)] = {
+    use Inflection::*;
+    use WordKind::*;
+    &[
+        // Adjectives
+        ("くなかった", "い", Adjective, NegativePast),
+        ("くない", "い", Adjective, Negative),
+        ("かった", "い", Adjective, Past),
+        ("くて", "い", Adjective, Te),
+        ("く", "い", Adjective, Adverbial),
+        // する
+        ("しなかった", "する", Suru, NegativePast),
+        ("しました", "する", Suru, PolitePast),
+        ("しません", "する", Suru, PoliteNegative),
+        ("しない", "する", Suru, Negative),
+        ("します", "する", Suru, Polite),
+        ("した", "する", Suru, Past),
+        ("して", "する", Suru, Te),
+        // Ichidan
+        ("なかった", "る", Ichidan, NegativePast),
+        ("ました", "る", Ichidan, PolitePast),
+        ("ません", "る", Ichidan, PoliteNegative),
+        ("ない", "る", Ichidan, Negative),
+        ("ます", "る", Ichidan, Polite),
+        ("た", "る", Ichidan, Past),
+        ("て", "る", Ichidan, Te),
+        // Godan past and te forms
+        ("った", "う", Godan, Past),
+        ("った", "つ", Godan, Past),
+        ("った", "る", Godan, Past),
+        ("んだ", "む", Godan, Past),
+        ("んだ", "ぶ", Godan, Past),
+        ("んだ", "ぬ", Godan, Past),
+        ("いた", "く", Godan, Past),
+        ("いだ", "ぐ", Godan, Past),
+        ("した", "す", Godan, Past),
+        ("って", "う", Godan, Te),
+        ("って", "つ", Godan, Te),
+        ("って", "る", Godan, Te),
+        ("んで", "む", Godan, Te),
+        ("んで", "ぶ", Godan, Te),
+        ("んで", "ぬ", Godan, Te),
+        ("いて", "く", Godan, Te),
+        ("いで", "ぐ", Godan, Te),
+        ("して", "す", Godan, Te),
+        // Godan negative
+        ("わなかった", "う", Godan, NegativePast),
+        ("かなかった", "く", Godan, NegativePast),
+        ("がなかった", "ぐ", Godan, NegativePast),
+        ("さなかった", "す", Godan, NegativePast),
+        ("たなかった", "つ", Godan, NegativePast),
+        ("ななかった", "ぬ", Godan, NegativePast),
+        ("ばなかった", "ぶ", Godan, NegativePast),
+        ("まなかった", "む", Godan, NegativePast),
+        ("らなかった", "る", Godan, NegativePast),
+        ("わない", "う", Godan, Negative),
+        ("かない", "く", Godan, Negative),
+        ("がない", "ぐ", Godan, Negative),
+        ("さない", "す", Godan, Negative),
+        ("たない", "つ", Godan, Negative),
+        ("なない", "ぬ", Godan, Negative),
+        ("ばない", "ぶ", Godan, Negative),
+        ("まない", "む", Godan, Negative),
+        ("らない", "る", Godan, Negative),
+        // Godan polite
+        ("いました", "う", Godan, PolitePast),
+        ("きました", "く", Godan, PolitePast),
+        ("ぎました", "ぐ", Godan, PolitePast),
+        ("しました", "す", Godan, PolitePast),
+        ("ちました", "つ", Godan, PolitePast),
+        ("にました", "ぬ", Godan, PolitePast),
+        ("びました", "ぶ", Godan, PolitePast),
+        ("みました", "む", Godan, PolitePast),
+        ("りました", "る", Godan, PolitePast),
+        ("いません", "う", Godan, PoliteNegative),
+        ("きません", "く", Godan, PoliteNegative),
+        ("ぎません", "ぐ", Godan, PoliteNegative),
+        ("しません", "す", Godan, PoliteNegative),
+        ("ちません", "つ", Godan, PoliteNegative),
+        ("にません", "ぬ", Godan, PoliteNegative),
+        ("びません", "ぶ", Godan, PoliteNegative),
+        ("みません", "む", Godan, PoliteNegative),
+        ("りません", "る", Godan, PoliteNegative),
+        ("います", "う", Godan, Polite),
+        ("きます", "く", Godan, Polite),
+        ("ぎます", "ぐ", Godan, Polite),
+        ("します", "す", Godan, Polite),
+        ("ちます", "つ", Godan, Polite),
+        ("にます", "ぬ", Godan, Polite),
+        ("びます", "ぶ", Godan, Polite),
+        ("みます", "む", Godan, Polite),
+        ("ります", "る", Godan, Polite),
+    ]
+};
+
+// Every dictionary form the word could be a conjugation of, in rule order.
+// Most candidates are not real words; look them up to find out.
+pub fn deinflect(word: &str) -> Vec<Deinflection> {
+    RULES
+        .iter()
+        .filter_map(|(ending, dictionary_ending, kind, inflection)| {
+            let stem = word.strip_suffix(ending)?;
+            (!stem.is_empty()).then(|| Deinflection {
+                dictionary_form: format!("{}{}", stem, dictionary_ending),
+                kind: *kind,
+                inflection: *inflection,
+                ending,
+                dictionary_ending,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConjugatedForms {
+    // Only words in the dictionary get accents.
+    Ignore,
+    // Conjugated words get the accents of their dictionary form.
+    #[default]
+    DictionaryForm,
+    // Conjugated words get their own accents, shifted from the dictionary
+    // form's.
+    Shifted,
+}
+
+impl Deinflection {
+    // The conjugated kana of a dictionary form reading, e.g. たべた for たべる.
+    fn conjugate(&self, kana: &str) -> Option<String> {
+        let stem = kana.strip_suffix(self.dictionary_ending)?;
+        Some(format!("{}{}", stem, self.ending))
+    }
+
+    // The dictionary form reading of a conjugated one, e.g. たべる for たべた.
+    fn dictionary_reading(&self, kana: &str) -> Option<String> {
+        let stem = kana.strip_suffix(self.ending)?;
+        Some(format!("{}{}", stem, self.dictionary_ending))
+    }
+
+    fn shift(&self, accent_type: AccentType, dictionary_morae: usize, morae: usize) -> AccentType {
+        use Inflection::*;
+
+        let ending_morae = KanaString::from(self.ending.to_string()).mora_count();
+        let before_ending = morae.saturating_sub(ending_morae);
+        let downstep = accent_type.downstep(dictionary_morae);
+        let shifted = match (self.kind, self.inflection) {
+            // The ま or な is the first mora of the ending after the stem mora.
+            (WordKind::Godan | WordKind::Suru, Polite | PolitePast | PoliteNegative) => {
+                before_ending + 2
+            }
+            (_, Polite | PolitePast | PoliteNegative) => before_ending + 1,
+            (WordKind::Adjective, Past) if downstep == 0 => before_ending,
+            (_, _) if downstep == 0 => 0,
+            (WordKind::Adjective, _) => downstep,
+            (WordKind::Godan | WordKind::Suru, Negative | NegativePast) => before_ending + 1,
+            (_, Negative | NegativePast) => before_ending,
+            (WordKind::Ichidan, _) => downstep.saturating_sub(1).max(1),
+            (_, _) => downstep,
+        };
+        AccentType::from_downstep(shifted.min(morae), morae)
+    }
+}
+
+// The readings of the word, deinflecting it if it isn't in the dictionary.
+pub fn find_readings(
+    word: &Word,
+    reading: Option<&str>,
+    accent_map: &AccentMap,
+    conjugated: ConjugatedForms,
+) -> Vec<WordAccents> {
+    let readings = word_accents(word, reading, accent_map);
+    if !readings.is_empty() || conjugated == ConjugatedForms::Ignore {
+        return readings.into_iter().cloned().collect();
+    }
+
+    for deinflection in deinflect(word) {
+        let dictionary_reading = reading.and_then(|r| deinflection.dictionary_reading(r));
+        let readings = word_accents(
+            &deinflection.dictionary_form,
+            dictionary_reading.as_deref(),
+            accent_map,
+        );
+        if readings.is_empty() {
+            continue;
+        }
+        if conjugated == ConjugatedForms::DictionaryForm {
+            return readings.into_iter().cloned().collect();
+        }
+
+        return readings
+            .into_iter()
+            .filter_map(|wa| {
+                let kana = KanaString::from(deinflection.conjugate(&wa.kana.0)?);
+                let dictionary_morae = wa.kana.mora_count();
+                let morae = kana.mora_count();
+                let accents = wa
+                    .accents
+                    .iter()
+                    .map(|a| Accent {
+                        accent_type: deinflection.shift(a.accent_type, dictionary_morae, morae),
+                        note: a.note.clone(),
+                        // Positions past the stem no longer line up.
+                        devoiced: vec![],
+                        nasal: vec![],
+                    })
+                    .collect();
+                Some(WordAccents { kana, accents })
+            })
+            .collect();
+    }
+    vec![]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::load_accents;
+
+    #[test]
+    fn test_deinflect() {
+        let forms = |word: &str| {
+            deinflect(word)
+                .into_iter()
+                .map(|d| d.dictionary_form)
+                .collect::<Vec<_>>()
+        };
+        assert!(forms("食べた").contains(&"食べる".to_string()));
+        assert!(forms("書いた").contains(&"書く".to_string()));
+        assert!(forms("高くない").contains(&"高い".to_string()));
+        assert!(forms("飲みません").contains(&"飲む".to_string()));
+    }
+
+    #[test]
+    fn test_find_readings() {
+        let accents = load_accents();
+        let numeric = |word: &str, reading: Option<&str>, conjugated| {
+            find_readings(&word.to_string(), reading, &accents, conjugated)
+                .iter()
+                .map(|wa| crate::notation::numeric(&wa.kana, &wa.accents))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(numeric("食べた", None, ConjugatedForms::Ignore).is_empty());
+        assert_eq!(
+            numeric("食べた", None, ConjugatedForms::DictionaryForm),
+            vec!["たべる [2]"]
+        );
+        assert_eq!(
+            numeric("食べた", Some("たべた"), ConjugatedForms::Shifted),
+            vec!["たべた [1]"]
+        );
+        assert_eq!(
+            numeric("食べます", None, ConjugatedForms::Shifted),
+            vec!["たべます [3]"]
+        );
+        assert_eq!(
+            numeric("書かない", None, ConjugatedForms::Shifted),
+            vec!["かかない [2]"]
+        );
+        assert_eq!(
+            numeric("飲みます", None, ConjugatedForms::Shifted),
+            vec!["のみます [3]"]
+        );
+        assert_eq!(
+            numeric("高くない", None, ConjugatedForms::Shifted),
+            vec!["たかくない [2]"]
+        );
+        // Dictionary words are left alone.
+        assert_eq!(
+            numeric("箸", None, ConjugatedForms::Shifted),
+            vec!["はし [1]"]
+        );
+    }
+}
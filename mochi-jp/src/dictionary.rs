@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use bincode::Options;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    parse_accents, parse_word_accents, Accent, AccentMap, AccentType, KanaString, Word, WordAccents,
+};
+
+// Accent Dictionaries
+//
+// Readers for pitch-accent dictionaries other than the bundled one, so a
+// newer or preferred dictionary can be used without recompiling.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccentFormat {
+    // `word<TAB>kana<TAB>accents` lines, the format of the bundled dictionary.
+    Kanjium,
+    // A Yomitan/Yomichan pitch dictionary: the zip, its unpacked directory or a
+    // single `term_meta_bank_*.json`.
+    Yomitan,
+    // `word = [{ kana = "...", accents = "..." }]`, handy for overrides.
+    Toml,
+}
+
+impl AccentFormat {
+    // Yomitan for directories, `.zip` and `.json`, TOML for `.toml` and Kanjium
+    // for anything else.
+    pub fn from_extension(path: &Path) -> AccentFormat {
+        if path.is_dir() {
+            return AccentFormat::Yomitan;
+        }
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("zip") | Some("json") => AccentFormat::Yomitan,
+            Some("toml") => AccentFormat::Toml,
+            _ => AccentFormat::Kanjium,
+        }
+    }
+}
+
+impl AccentMap {
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+        format: AccentFormat,
+    ) -> Result<AccentMap, Box<dyn Error>> {
+        let path = path.as_ref();
+        match format {
+            AccentFormat::Kanjium => Ok(parse_accents(&std::fs::read_to_string(path)?)?),
+            AccentFormat::Toml => parse_toml_accents(&std::fs::read_to_string(path)?),
+            AccentFormat::Yomitan => {
+                let banks = read_yomitan_banks(path, "term_meta_bank_")?;
+                if banks.is_empty() && !path.is_dir() {
+                    return Err(format!("no term meta banks in {}", path.display()).into());
+                }
+                let mut accents = AccentMap::default();
+                for bank in banks {
+                    parse_yomitan_bank(&bank, &mut accents)?;
+                }
+                Ok(accents)
+            }
+        }
+    }
+}
+
+// Merging
+//
+// Sources are layered highest priority first, e.g. user overrides, then an
+// external Kanjium dump, then the bundled dictionary.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergeStrategy {
+    // A word takes all its readings from the first source that has it.
+    #[default]
+    FirstWins,
+    // Every reading from every source; accents of the same reading are
+    // combined, dropping duplicate accent types.
+    UnionOfAccents,
+    // Like first-wins, but from the first source whose accents of the word
+    // have notes, if any does.
+    PreferNoted,
+}
+
+fn has_notes(readings: &[WordAccents]) -> bool {
+    readings
+        .iter()
+        .any(|wa| wa.accents.iter().any(|a| a.note.is_some()))
+}
+
+impl AccentMap {
+    pub fn merge<I>(priority_list: I, strategy: MergeStrategy) -> AccentMap
+    where
+        I: IntoIterator<Item = AccentMap>,
+    {
+        let mut merged = AccentMap::default();
+        for source in priority_list {
+            for (word, readings) in source {
+                let existing = match merged.get_mut(&word) {
+                    Some(existing) => existing,
+                    None => {
+                        merged.insert(word, readings);
+                        continue;
+                    }
+                };
+
+                match strategy {
+                    MergeStrategy::FirstWins => {}
+                    MergeStrategy::PreferNoted => {
+                        if !has_notes(existing) && has_notes(&readings) {
+                            *existing = readings;
+                        }
+                    }
+                    MergeStrategy::UnionOfAccents => {
+                        for wa in readings {
+                            match existing.iter_mut().find(|e| e.kana == wa.kana) {
+                                Some(e) => {
+                                    for accent in wa.accents {
+                                        if !e
+                                            .accents
+                                            .iter()
+                                            .any(|a| a.accent_type == accent.accent_type)
+                                        {
+                                            e.accents.push(accent);
+                                        }
+                                    }
+                                }
+                                None => existing.push(wa),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        merged
+    }
+}
+
+// Binary
+//
+// build.rs saves `resources/accents.txt` this way into OUT_DIR at build time,
+// with the same bincode options.
+
+impl AccentMap {
+    pub fn save_binary<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        Ok(bincode::DefaultOptions::new().serialize_into(writer, self)?)
+    }
+
+    pub fn load_binary(bytes: &[u8]) -> Result<AccentMap, Box<dyn Error>> {
+        Ok(bincode::DefaultOptions::new().deserialize(bytes)?)
+    }
+}
+
+// Bundled
+
+// The bundled dictionary, decoded from the binary form of `accents.txt` built
+// into the crate, which is much faster than parsing the text.
+pub fn load_accents() -> AccentMap {
+    AccentMap::load_binary(include_bytes!(concat!(env!("OUT_DIR"), "/accents.bin"))).unwrap()
+}
+
+// The bundled dictionary with the entries of a user overrides file on top.
+// An overridden word loses all of its bundled readings. The format is picked
+// from the extension, see `AccentFormat::from_extension`.
+pub fn load_accents_with_overrides<P: AsRef<Path>>(path: P) -> Result<AccentMap, Box<dyn Error>> {
+    let path = path.as_ref();
+    let overrides = AccentMap::from_path(path, AccentFormat::from_extension(path))?;
+    Ok(AccentMap::merge(
+        [overrides, load_accents()],
+        MergeStrategy::FirstWins,
+    ))
+}
+
+impl AccentMap {
+    // The bundled dictionary, loaded on first use and shared after that.
+    pub fn global() -> &'static AccentMap {
+        static GLOBAL: OnceLock<AccentMap> = OnceLock::new();
+        GLOBAL.get_or_init(load_accents)
+    }
+}
+
+// TOML
+
+#[derive(Deserialize)]
+struct TomlReading {
+    // Defaults to the word itself.
+    #[serde(default)]
+    kana: String,
+    accents: String,
+}
+
+fn parse_toml_accents(raw: &str) -> Result<AccentMap, Box<dyn Error>> {
+    let table: HashMap<Word, Vec<TomlReading>> = toml::from_str(raw)?;
+    let mut words = AccentMap::default();
+    for (word, readings) in table {
+        let readings = readings
+            .iter()
+            .map(|r| parse_word_accents(&word, &r.kana, &r.accents))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("{}: {}", word, e))?;
+        words.insert(word, readings);
+    }
+    Ok(words)
+}
+
+// Yomitan
+
+fn is_bank(name: &str, prefix: &str) -> bool {
+    name.starts_with(prefix) && name.ends_with(".json")
+}
+
+// The contents of every bank of the dictionary whose name starts with the
+// prefix, e.g. `term_meta_bank_`.
+pub fn read_yomitan_banks(path: &Path, prefix: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    if path.is_dir() {
+        let mut names = std::fs::read_dir(path)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|n| is_bank(n, prefix))
+            .collect::<Vec<_>>();
+        names.sort();
+        return names
+            .iter()
+            .map(|n| Ok(std::fs::read_to_string(path.join(n))?))
+            .collect();
+    }
+    if path.extension().is_some_and(|e| e == "json") {
+        return Ok(vec![std::fs::read_to_string(path)?]);
+    }
+
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    let mut names = archive
+        .file_names()
+        .filter(|n| is_bank(n, prefix))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    names.sort();
+    let mut banks = vec![];
+    for name in names {
+        let mut bank = String::new();
+        archive.by_name(&name)?.read_to_string(&mut bank)?;
+        banks.push(bank);
+    }
+    Ok(banks)
+}
+
+// A 1-based mora position or a list of them, as 0-based indices.
+fn mora_positions(value: &Value) -> Vec<usize> {
+    let positions = match value {
+        Value::Array(values) => values.iter().filter_map(Value::as_u64).collect(),
+        _ => value.as_u64().into_iter().collect::<Vec<_>>(),
+    };
+    positions
+        .into_iter()
+        .filter(|p| *p > 0)
+        .map(|p| p as usize - 1)
+        .collect()
+}
+
+// Entries are `[term, "pitch", {"reading", "pitches": [{"position", "tags"}]}]`;
+// frequency and other entries are skipped, as are pattern positions like "LHL".
+pub fn parse_yomitan_bank(raw: &str, accents: &mut AccentMap) -> Result<(), Box<dyn Error>> {
+    let entries: Vec<Value> = serde_json::from_str(raw)?;
+    for entry in entries.iter() {
+        let (term, mode, data) = match (entry.get(0), entry.get(1), entry.get(2)) {
+            (Some(Value::String(term)), Some(mode), Some(data)) => (term, mode, data),
+            _ => return Err(format!("malformed entry {}", entry).into()),
+        };
+        if mode != "pitch" {
+            continue;
+        }
+
+        let kana = data["reading"].as_str().unwrap_or(term);
+        let kana = KanaString::from(kana.to_string());
+        let n_mora = kana.mora_count();
+        let pitches = data["pitches"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        let new_accents = pitches
+            .iter()
+            .filter_map(|pitch| {
+                let downstep = pitch["position"].as_u64()? as usize;
+                let tags = pitch["tags"]
+                    .as_array()
+                    .map(|tags| tags.iter().filter_map(Value::as_str).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                Some(Accent {
+                    accent_type: AccentType::from_downstep(downstep, n_mora),
+                    note: (!tags.is_empty()).then(|| tags.join(",")),
+                    devoiced: mora_positions(&pitch["devoice"]),
+                    nasal: mora_positions(&pitch["nasal"]),
+                })
+            })
+            .collect::<Vec<_>>();
+        if new_accents.is_empty() {
+            continue;
+        }
+
+        let readings = accents.entry(term.clone()).or_default();
+        match readings.iter_mut().find(|wa| wa.kana == kana) {
+            Some(wa) => wa.accents.extend(new_accents),
+            None => readings.push(WordAccents {
+                kana,
+                accents: new_accents,
+            }),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BANK: &str = r#"[
+        ["箸", "pitch", {"reading": "はし", "pitches": [{"position": 1}]}],
+        ["この後", "pitch", {"reading": "このあと", "pitches": [{"position": 3}]}],
+        ["この後", "pitch", {"reading": "こののち", "pitches": [{"position": 4}, {"position": 0, "tags": ["副"], "nasal": 3}]}],
+        ["汽車", "pitch", {"reading": "きしゃ", "pitches": [{"position": 2, "devoice": [1]}]}],
+        ["箸", "freq", 120]
+    ]"#;
+
+    #[test]
+    fn test_yomitan() {
+        let dir = std::env::temp_dir().join(format!("mochi-yomitan-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("unpacked")).unwrap();
+        std::fs::write(dir.join("unpacked/index.json"), "{}").unwrap();
+        std::fs::write(dir.join("unpacked/term_meta_bank_1.json"), BANK).unwrap();
+
+        let zip_path = dir.join("pitch.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        zip.start_file(
+            "term_meta_bank_1.json",
+            zip::write::SimpleFileOptions::default(),
+        )
+        .unwrap();
+        zip.write_all(BANK.as_bytes()).unwrap();
+        zip.finish().unwrap();
+
+        for path in [dir.join("unpacked"), zip_path] {
+            assert_eq!(AccentFormat::from_extension(&path), AccentFormat::Yomitan);
+            let accents = AccentMap::from_path(&path, AccentFormat::Yomitan).unwrap();
+            assert_eq!(accents.len(), 3);
+            assert_eq!(
+                accents["箸"][0].accents[0].accent_type,
+                AccentType::Atamadaka
+            );
+            let nochi = &accents["この後"][1];
+            assert_eq!(nochi.kana.0, "こののち");
+            assert_eq!(nochi.accents[0].accent_type, AccentType::Odaka);
+            assert_eq!(nochi.accents[1].note.as_deref(), Some("副"));
+            assert_eq!(nochi.accents[1].nasal, vec![2]);
+            assert_eq!(accents["汽車"][0].accents[0].devoiced, vec![0]);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_kanjium() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/accents.txt");
+        assert_eq!(AccentFormat::from_extension(&path), AccentFormat::Kanjium);
+        let accents = AccentMap::from_path(&path, AccentFormat::Kanjium).unwrap();
+        assert_eq!(accents.len(), crate::load_accents().len());
+    }
+
+    #[test]
+    fn test_merge() {
+        let reading = |kana: &str, accents: &[(usize, Option<&str>)]| {
+            let kana = KanaString::from(kana.to_string());
+            let n_mora = kana.mora_count();
+            let accents = accents
+                .iter()
+                .map(|(downstep, note)| Accent {
+                    accent_type: AccentType::from_downstep(*downstep, n_mora),
+                    note: note.map(str::to_string),
+                    devoiced: vec![],
+                    nasal: vec![],
+                })
+                .collect();
+            WordAccents { kana, accents }
+        };
+        let user = || AccentMap::from([("箸".to_string(), vec![reading("はし", &[(2, None)])])]);
+        let kanjium = || {
+            AccentMap::from([
+                (
+                    "箸".to_string(),
+                    vec![reading("はし", &[(1, Some("名")), (2, None)])],
+                ),
+                ("橋".to_string(), vec![reading("はし", &[(2, None)])]),
+            ])
+        };
+
+        let first = AccentMap::merge([user(), kanjium()], MergeStrategy::FirstWins);
+        assert_eq!(first.len(), 2);
+        assert_eq!(first["箸"][0].accents.len(), 1);
+
+        let union = AccentMap::merge([user(), kanjium()], MergeStrategy::UnionOfAccents);
+        let types = union["箸"][0]
+            .accents
+            .iter()
+            .map(|a| a.accent_type)
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec![AccentType::Odaka, AccentType::Atamadaka]);
+
+        let noted = AccentMap::merge([user(), kanjium()], MergeStrategy::PreferNoted);
+        assert_eq!(noted["箸"][0].accents[0].note.as_deref(), Some("名"));
+    }
+}
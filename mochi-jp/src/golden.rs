@@ -0,0 +1,52 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Golden Files
+//
+// Renderer output is compared against files under `tests/goldens`, one per
+// renderer, input and option combination. Run the tests with UPDATE_GOLDENS=1
+// to rewrite them after an intended change, then review the diff.
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("goldens")
+        .join(name)
+}
+
+fn first_difference(expected: &str, actual: &str) -> usize {
+    expected
+        .char_indices()
+        .zip(actual.chars())
+        .find(|((_, e), a)| e != a)
+        .map(|((i, _), _)| i)
+        .unwrap_or(expected.len().min(actual.len()))
+}
+
+// `name` is the path below `tests/goldens`, e.g. "html/ano_kata.html".
+pub(crate) fn assert_golden(name: &str, actual: &str) {
+    let path = golden_path(name);
+    if env::var_os("UPDATE_GOLDENS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "missing golden file {}, run with UPDATE_GOLDENS=1 to create it",
+            path.display()
+        )
+    });
+    if expected != actual {
+        let at = first_difference(&expected, actual);
+        panic!(
+            "output differs from {} at byte {}\n  expected: {}\n  actual:   {}\nrun with UPDATE_GOLDENS=1 if the change is intended",
+            path.display(),
+            at,
+            &expected[at..].chars().take(80).collect::<String>(),
+            &actual[at..].chars().take(80).collect::<String>(),
+        );
+    }
+}
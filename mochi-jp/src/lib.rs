@@ -0,0 +1,20 @@
+// Mochi Japanese
+//
+// Kana, pitch accents and their dictionaries, and the HTML and SVG written
+// to cards for them. Nothing here talks to the Mochi API, so it builds for
+// wasm32: `wasm-pack build -- --features wasm`.
+
+pub mod deinflect;
+pub mod dictionary;
+#[cfg(test)]
+mod golden;
+pub mod notation;
+pub mod phrase;
+pub mod pitch;
+pub mod romaji;
+pub mod svg;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use crate::dictionary::{load_accents, load_accents_with_overrides};
+pub use crate::pitch::*;
@@ -0,0 +1,135 @@
+use crate::svg::{generate_svg_for_readings, mora_heights};
+use crate::{
+    generate_html_for_readings, word_accents, Accent, AccentMap, KanaString, PitchHtmlStyle, Word,
+    WordAccents,
+};
+
+// Accent Notations
+//
+// The bordered HTML is the default, but some prefer a compact text notation:
+// the downstep number (`はし [1]`) or the low/high pattern with the following
+// particle in brackets (`HLL(L)` for はし).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccentNotation {
+    #[default]
+    Html,
+    Svg,
+    Numeric,
+    LowHigh,
+}
+
+impl AccentNotation {
+    pub fn by_name(name: &str) -> Option<AccentNotation> {
+        match name {
+            "html" => Some(AccentNotation::Html),
+            "svg" => Some(AccentNotation::Svg),
+            "numeric" => Some(AccentNotation::Numeric),
+            "low-high" => Some(AccentNotation::LowHigh),
+            _ => None,
+        }
+    }
+}
+
+fn with_note(text: String, accent: &Accent) -> String {
+    match &accent.note {
+        Some(note) => format!("{}({})", text, note),
+        None => text,
+    }
+}
+
+// `はし [1]`, with every accent of the reading in the brackets.
+pub fn numeric(kana: &KanaString, accents: &[Accent]) -> String {
+    let n_mora = kana.mora_count();
+    let downsteps = accents
+        .iter()
+        .map(|a| with_note(a.accent_type.downstep(n_mora).to_string(), a))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{} [{}]", kana.0, downsteps)
+}
+
+// `LHH(L)`: one letter per mora, then the particle.
+pub fn low_high(kana: &KanaString, accent: &Accent) -> String {
+    let letters = mora_heights(kana, accent)
+        .into_iter()
+        .map(|high| if high { 'H' } else { 'L' })
+        .collect::<Vec<_>>();
+    let (particle, morae) = letters.split_last().unwrap();
+    with_note(
+        format!("{}({})", morae.iter().collect::<String>(), particle),
+        accent,
+    )
+}
+
+// The word in the given notation. Readings are separated by `; `.
+pub fn render(
+    word: &Word,
+    reading: Option<&str>,
+    accent_map: &AccentMap,
+    notation: AccentNotation,
+    style: &PitchHtmlStyle,
+) -> String {
+    render_readings(&word_accents(word, reading, accent_map), notation, style)
+}
+
+pub fn render_readings(
+    readings: &[&WordAccents],
+    notation: AccentNotation,
+    style: &PitchHtmlStyle,
+) -> String {
+    let text_readings = |f: &dyn Fn(&KanaString, &[Accent]) -> String| {
+        readings
+            .iter()
+            .map(|wa| f(&wa.kana, &wa.accents))
+            .collect::<Vec<_>>()
+            .join("; ")
+    };
+
+    match notation {
+        AccentNotation::Html => generate_html_for_readings(readings, style),
+        AccentNotation::Svg => generate_svg_for_readings(readings),
+        AccentNotation::Numeric => text_readings(&numeric),
+        AccentNotation::LowHigh => text_readings(&|kana, accents| {
+            let patterns = accents
+                .iter()
+                .map(|a| low_high(kana, a))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{} {}", kana.0, patterns)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::load_accents;
+
+    #[test]
+    fn test_notations() {
+        let accents = load_accents();
+        let style = PitchHtmlStyle::default();
+        let text =
+            |word: &str, notation| render(&word.to_string(), None, &accents, notation, &style);
+
+        assert_eq!(text("箸", AccentNotation::Numeric), "はし [1]");
+        assert_eq!(text("箸", AccentNotation::LowHigh), "はし HL(L)");
+        assert_eq!(
+            text("この後", AccentNotation::Numeric),
+            "このあと [3]; こののち [4, 0]"
+        );
+        assert_eq!(
+            text("この後", AccentNotation::LowHigh),
+            "このあと LHHL(L); こののち LHHH(L), LHHH(H)"
+        );
+        assert_eq!(
+            text("箸", AccentNotation::Html),
+            crate::generate_html(&"箸".to_string(), &accents)
+        );
+        assert_eq!(
+            AccentNotation::by_name("low-high"),
+            Some(AccentNotation::LowHigh)
+        );
+    }
+}
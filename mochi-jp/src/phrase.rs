@@ -0,0 +1,197 @@
+use std::error::Error;
+
+use crate::deinflect::{find_readings, ConjugatedForms};
+use crate::notation::{self, AccentNotation};
+use crate::svg::generate_svg;
+use crate::{generate_html_for_accent, wrap_html, AccentMap, PitchHtmlStyle, WordAccents};
+
+// Phrases
+//
+// Fields like お茶を入れる are not in the accent dictionary as a whole. A
+// tokenizer splits them into words, each word gets its own accent, and the
+// accents are rendered one after the other. Words without accents (particles,
+// punctuation) are kept as plain text.
+//
+// With the `lindera` feature, `LinderaTokenizer` does the splitting using a
+// UniDic or IPADIC dictionary on disk.
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PhraseToken {
+    pub surface: String,
+    pub base_form: Option<String>,
+    // The reading from the tokenizer's dictionary, in any kana.
+    pub reading: Option<String>,
+}
+
+impl PhraseToken {
+    pub fn new(surface: &str) -> PhraseToken {
+        PhraseToken {
+            surface: surface.to_string(),
+            ..PhraseToken::default()
+        }
+    }
+}
+
+pub trait Tokenizer {
+    fn tokenize(&self, text: &str) -> Result<Vec<PhraseToken>, Box<dyn Error>>;
+}
+
+// Each token with the reading it was found under, if any.
+pub fn phrase_readings(
+    tokens: &[PhraseToken],
+    accent_map: &AccentMap,
+    conjugated: ConjugatedForms,
+) -> Vec<(String, Option<WordAccents>)> {
+    tokens
+        .iter()
+        .map(|token| {
+            let find = |word: &str, reading: Option<&str>| {
+                find_readings(&word.to_string(), reading, accent_map, conjugated)
+                    .into_iter()
+                    .next()
+            };
+            // The tokenizer's reading picks the right homograph, but its
+            // dictionary doesn't always agree with ours.
+            let found = find(&token.surface, token.reading.as_deref())
+                .or_else(|| find(&token.surface, None))
+                .or_else(|| match &token.base_form {
+                    Some(base) if conjugated != ConjugatedForms::Ignore => find(base, None),
+                    _ => None,
+                });
+            (token.surface.clone(), found)
+        })
+        .collect()
+}
+
+// The phrase in the given notation, using the first accent of each word.
+pub fn render_phrase(
+    parts: &[(String, Option<WordAccents>)],
+    notation: AccentNotation,
+    style: &PitchHtmlStyle,
+) -> String {
+    let render_part = |(surface, found): &(String, Option<WordAccents>)| {
+        let Some((wa, accent)) = found
+            .as_ref()
+            .and_then(|wa| Some((wa, wa.accents.first()?)))
+        else {
+            return surface.clone();
+        };
+        match notation {
+            AccentNotation::Html => generate_html_for_accent(&wa.kana, accent, style),
+            AccentNotation::Svg => generate_svg(&wa.kana, accent),
+            AccentNotation::Numeric => notation::numeric(&wa.kana, std::slice::from_ref(accent)),
+            AccentNotation::LowHigh => {
+                format!("{} {}", wa.kana.0, notation::low_high(&wa.kana, accent))
+            }
+        }
+    };
+
+    match notation {
+        AccentNotation::Html => {
+            wrap_html(&parts.iter().map(render_part).collect::<String>(), style)
+        }
+        AccentNotation::Svg => format!(
+            "<div style=\"text-align: center\">{}</div>",
+            parts.iter().map(render_part).collect::<String>()
+        ),
+        AccentNotation::Numeric | AccentNotation::LowHigh => {
+            parts.iter().map(render_part).collect::<Vec<_>>().join(" ")
+        }
+    }
+}
+
+// Whether any word of the phrase has an accent.
+pub fn has_accents(parts: &[(String, Option<WordAccents>)]) -> bool {
+    parts.iter().any(|(_, found)| found.is_some())
+}
+
+// Lindera
+
+#[cfg(feature = "lindera")]
+pub struct LinderaTokenizer {
+    segmenter: lindera::segmenter::Segmenter,
+}
+
+#[cfg(feature = "lindera")]
+impl LinderaTokenizer {
+    // Loads a compiled Lindera dictionary (e.g. UniDic) from a directory.
+    pub fn from_path(dictionary_dir: &str) -> Result<LinderaTokenizer, Box<dyn Error>> {
+        let dictionary =
+            lindera::dictionary::load_fs_dictionary(std::path::Path::new(dictionary_dir))
+                .map_err(|e| format!("could not load dictionary {}: {}", dictionary_dir, e))?;
+        Ok(LinderaTokenizer {
+            segmenter: lindera::segmenter::Segmenter::new(
+                lindera::mode::Mode::Normal,
+                dictionary,
+                None,
+            ),
+        })
+    }
+}
+
+#[cfg(feature = "lindera")]
+impl Tokenizer for LinderaTokenizer {
+    fn tokenize(&self, text: &str) -> Result<Vec<PhraseToken>, Box<dyn Error>> {
+        let mut tokens = self
+            .segmenter
+            .segment(std::borrow::Cow::Borrowed(text))
+            .map_err(|e| e.to_string())?;
+        // IPADIC and UniDic name their fields differently, and use `*` for
+        // unknown values.
+        let detail = |token: &mut lindera::token::Token, names: &[&str]| {
+            names.iter().find_map(|name| {
+                token
+                    .get(name)
+                    .filter(|value| !value.is_empty() && *value != "*")
+                    .map(|value| value.to_string())
+            })
+        };
+        Ok(tokens
+            .iter_mut()
+            .map(|token| PhraseToken {
+                surface: token.surface.to_string(),
+                base_form: detail(token, &["base_form", "orthographic_base_form", "lemma"]),
+                reading: detail(token, &["reading", "kana"]),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::load_accents;
+
+    // Splits on `|`, like a tokenizer would on word boundaries.
+    struct Pipes;
+
+    impl Tokenizer for Pipes {
+        fn tokenize(&self, text: &str) -> Result<Vec<PhraseToken>, Box<dyn Error>> {
+            Ok(text.split('|').map(PhraseToken::new).collect())
+        }
+    }
+
+    #[test]
+    fn test_render_phrase() {
+        let accents = load_accents();
+        let tokens = Pipes.tokenize("お茶|を|入れる").unwrap();
+        let parts = phrase_readings(&tokens, &accents, ConjugatedForms::default());
+        assert!(has_accents(&parts));
+        assert_eq!(
+            render_phrase(&parts, AccentNotation::Numeric, &PitchHtmlStyle::default()),
+            "おちゃ [0] を いれる [0]"
+        );
+
+        // Conjugated words are deinflected like whole fields.
+        let tokens = [PhraseToken {
+            surface: "入れた".to_string(),
+            base_form: Some("入れる".to_string()),
+            reading: Some("イレタ".to_string()),
+        }];
+        let parts = phrase_readings(&tokens, &accents, ConjugatedForms::Shifted);
+        assert_eq!(
+            render_phrase(&parts, AccentNotation::Numeric, &PitchHtmlStyle::default()),
+            "いれた [0]"
+        );
+    }
+}
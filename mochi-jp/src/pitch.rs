@@ -0,0 +1,881 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::ops::{Deref, DerefMut};
+use std::str::CharIndices;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+// Pitch Accent
+//
+// Kana strings and their morae, the accent dictionary and the HTML for
+// accents.
+
+// Japanese String
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct KanaString(pub String);
+
+impl KanaString {
+    pub fn iter_mora(&self) -> Morae<'_> {
+        Morae {
+            text: &self.0,
+            chars: self.0.char_indices().peekable(),
+        }
+    }
+
+    pub fn mora_count(&self) -> usize {
+        self.iter_mora().count()
+    }
+}
+
+// Small kana belong to the mora before them.
+fn is_small_kana(c: char) -> bool {
+    matches!(
+        c,
+        'ぁ' | 'ぃ'
+            | 'ぅ'
+            | 'ぇ'
+            | 'ぉ'
+            | 'っ'
+            | 'ゃ'
+            | 'ゅ'
+            | 'ょ'
+            | 'ァ'
+            | 'ィ'
+            | 'ゥ'
+            | 'ェ'
+            | 'ォ'
+            | 'ッ'
+            | 'ャ'
+            | 'ュ'
+            | 'ョ'
+            | 'ヮ'
+    )
+}
+
+// The morae of a `KanaString` as slices of it, e.g. サッ, カ, ー.
+pub struct Morae<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Iterator for Morae<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let (start, _) = self.chars.next()?;
+        while self.chars.next_if(|(_, c)| is_small_kana(*c)).is_some() {}
+        let end = self.chars.peek().map_or(self.text.len(), |(i, _)| *i);
+        Some(&self.text[start..end])
+    }
+}
+
+impl KanaString {
+    // Katakana as hiragana; anything else is kept, including ー.
+    pub fn to_hiragana(&self) -> KanaString {
+        KanaString(self.0.chars().map(katakana_to_hiragana).collect())
+    }
+
+    pub fn to_katakana(&self) -> KanaString {
+        KanaString(self.0.chars().map(hiragana_to_katakana).collect())
+    }
+}
+
+fn katakana_to_hiragana(c: char) -> char {
+    match c {
+        'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn hiragana_to_katakana(c: char) -> char {
+    match c {
+        'ぁ'..='ゖ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+// Composes decomposed dakuten (か + ◌゙ to が) and widens half-width katakana
+// (ｶﾞ to ガ), so text from OCR or other dictionaries splits into the right morae.
+pub fn normalize_kana(text: &str) -> String {
+    let widened = text
+        .chars()
+        .flat_map(|c| match c {
+            // NFKC maps ﾞ and ﾟ to the combining marks, composed below.
+            '\u{FF61}'..='\u{FF9F}' => c.to_string().nfkc().collect::<Vec<_>>(),
+            _ => vec![c],
+        })
+        .collect::<String>();
+    widened.nfc().collect()
+}
+
+impl From<String> for KanaString {
+    fn from(string: String) -> Self {
+        KanaString(normalize_kana(&string))
+    }
+}
+
+// Accents
+pub type Word = String;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AccentType {
+    Heiban,
+    Atamadaka,
+    Nakadaka(usize),
+    Odaka,
+}
+
+impl AccentType {
+    pub fn from_downstep(downstep: usize, n_mora: usize) -> AccentType {
+        match downstep {
+            0 => AccentType::Heiban,
+            1 => AccentType::Atamadaka,
+            _ if downstep == n_mora => AccentType::Odaka,
+            _ => AccentType::Nakadaka(downstep),
+        }
+    }
+
+    // The mora after which the pitch drops, 0 for none.
+    pub fn downstep(&self, n_mora: usize) -> usize {
+        match self {
+            AccentType::Heiban => 0,
+            AccentType::Atamadaka => 1,
+            AccentType::Nakadaka(i) => *i,
+            AccentType::Odaka => n_mora,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MoraEdges {
+    Top,
+    Bottom,
+    Left,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Accent {
+    pub accent_type: AccentType,
+    pub note: Option<String>,
+    // 0-based indices of devoiced morae, e.g. the き of きしゃ. Only Yomitan
+    // dictionaries have these: Kanjium lines (the bundled dictionary) and
+    // TOML overrides leave them empty, so their HTML has no marks.
+    pub devoiced: Vec<usize>,
+    // 0-based indices of nasalized が-row morae. Only from Yomitan, as above.
+    pub nasal: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WordAccents {
+    pub kana: KanaString,
+    pub accents: Vec<Accent>,
+}
+// `word<TAB>kana<TAB>accents` lines, kana empty if the word is kana already.
+pub fn parse_accents(raw: &str) -> Result<AccentMap, String> {
+    let lines = raw
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .collect::<Vec<_>>();
+    let mut words = AccentMap::default();
+    for (i, line) in lines.iter().enumerate() {
+        let mut splits = line.split('\t');
+        let (word, kana, accents) = match (splits.next(), splits.next(), splits.next()) {
+            (Some(word), Some(kana), Some(accents)) => (word, kana, accents),
+            _ => return Err(format!("line {}: expected 3 columns", i + 1)),
+        };
+        let word_accents = parse_word_accents(word, kana, accents)
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        words
+            .entry(word.to_string())
+            .or_default()
+            .push(word_accents);
+    }
+    Ok(words)
+}
+
+// One reading, with accents like `0,2(副)`.
+pub(crate) fn parse_word_accents(
+    word: &str,
+    kana: &str,
+    accents: &str,
+) -> Result<WordAccents, String> {
+    // Compiled once, the bundled dictionary has over 100k lines.
+    static REGEX_NOTE_EX: OnceLock<Regex> = OnceLock::new();
+    static REGEX_INDEX_EX: OnceLock<Regex> = OnceLock::new();
+    let regex_note_ex = REGEX_NOTE_EX.get_or_init(|| Regex::new(r"\(([\D]+)\)").unwrap());
+    let regex_index_ex = REGEX_INDEX_EX.get_or_init(|| Regex::new(r"(\d+)").unwrap());
+
+    let kana = KanaString::from(if kana.is_empty() { word } else { kana }.to_string());
+    let n_mora = kana.mora_count();
+
+    let accents = accents
+        .split(',')
+        .map(|s| {
+            let note = regex_note_ex
+                .captures(s)
+                .and_then(|c| c.get(1))
+                .map(|c| c.as_str().to_string());
+
+            let index = regex_index_ex
+                .captures(s)
+                .and_then(|c| c.get(1))
+                .and_then(|c| c.as_str().parse::<usize>().ok())
+                .ok_or(format!("no accent number in {:?}", s))?;
+
+            Ok(Accent {
+                accent_type: AccentType::from_downstep(index, n_mora),
+                note,
+                devoiced: vec![],
+                nasal: vec![],
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(WordAccents { kana, accents })
+}
+
+pub fn generate_html(word: &Word, accent_map: &AccentMap) -> String {
+    generate_html_for_reading(word, None, accent_map, &PitchHtmlStyle::default())
+}
+
+// The readings of the word, narrowed to the one written as `reading` if the
+// dictionary has it (e.g. 箸 for はし rather than every はし homograph entry).
+pub fn word_accents<'a>(
+    word: &Word,
+    reading: Option<&str>,
+    accent_map: &'a AccentMap,
+) -> Vec<&'a WordAccents> {
+    let all = accent_map
+        .lookup(word)
+        .map(|v| v.iter().collect::<Vec<_>>());
+    let all = all.unwrap_or_default();
+    let reading = reading.map(|r| KanaString::from(r.to_string()).to_hiragana());
+    let matching = all
+        .iter()
+        .filter(|wa| Some(wa.kana.to_hiragana()) == reading)
+        .copied()
+        .collect::<Vec<_>>();
+    if matching.is_empty() {
+        all
+    } else {
+        matching
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PitchHtmlStyle {
+    pub color: String,
+    // Any CSS border width, e.g. "medium" or "2px".
+    pub width: String,
+    // Between the accents of one reading.
+    pub separator: String,
+    // Text alignment of the wrapper div, None for no alignment.
+    pub align: Option<String>,
+    // Emit `pitch-*` classes instead of inline styles so a Mochi theme can
+    // style them. Colour, width and align are ignored.
+    pub use_css_classes: bool,
+}
+
+impl Default for PitchHtmlStyle {
+    fn default() -> Self {
+        PitchHtmlStyle {
+            color: "#FF6633".to_string(),
+            width: "medium".to_string(),
+            separator: "\u{30FB}".to_string(),
+            align: Some("center".to_string()),
+            use_css_classes: false,
+        }
+    }
+}
+
+pub fn generate_html_for_reading(
+    word: &Word,
+    reading: Option<&str>,
+    accent_map: &AccentMap,
+    style: &PitchHtmlStyle,
+) -> String {
+    generate_html_for_readings(&word_accents(word, reading, accent_map), style)
+}
+
+pub fn generate_html_for_readings(readings: &[&WordAccents], style: &PitchHtmlStyle) -> String {
+    let reading_break = if style.use_css_classes {
+        "<div class=\"pitch-break\"></div>"
+    } else {
+        "<div style=\"line-height:100%;\"><br></div>"
+    };
+    let inner = readings
+        .iter()
+        .map(|wa| {
+            wa.accents
+                .iter()
+                .map(|a| generate_html_for_accent(&wa.kana, a, style))
+                .collect::<Vec<_>>()
+                .join(&style.separator)
+        })
+        .collect::<Vec<_>>()
+        .join(reading_break);
+    wrap_html(&inner, style)
+}
+
+// The outer div of the generated HTML.
+pub(crate) fn wrap_html(inner: &str, style: &PitchHtmlStyle) -> String {
+    if style.use_css_classes {
+        format!("<div class=\"pitch-accent\">{}</div>", inner)
+    } else {
+        match &style.align {
+            Some(align) => format!("<div style=\"text-align: {}\">{}</div>", align, inner),
+            None => format!("<div>{}</div>", inner),
+        }
+    }
+}
+
+pub(crate) fn generate_html_for_accent(
+    kana_string: &KanaString,
+    accent: &Accent,
+    style: &PitchHtmlStyle,
+) -> String {
+    let mora_edges = generate_mora_edges(kana_string, &accent.accent_type);
+    let kana_with_final_whitespace =
+        KanaString::from(kana_string.0.chars().chain(['…']).collect::<String>());
+
+    let mora_html = kana_with_final_whitespace
+        .iter_mora()
+        .zip(mora_edges)
+        .enumerate()
+        .map(|(i, (mora, edges))| {
+            let mora = annotate_mora(mora, i, accent, style);
+            if style.use_css_classes {
+                let height = if edges.contains(&MoraEdges::Top) {
+                    "pitch-high"
+                } else {
+                    "pitch-low"
+                };
+                // A left edge marks a change from the previous mora.
+                let change = match (edges.contains(&MoraEdges::Left), height) {
+                    (false, _) => "",
+                    (true, "pitch-high") => " pitch-rise",
+                    (true, _) => " pitch-drop",
+                };
+                return format!("<span class=\"{}{}\">{}</span>", height, change, mora);
+            }
+
+            let border_style = format!(": {} {} solid;", style.color, style.width);
+            let border_css = edges
+                .iter()
+                .map(|e| match e {
+                    MoraEdges::Top => format!("BORDER-TOP{}", border_style),
+                    MoraEdges::Bottom => format!("BORDER-BOTTOM{}", border_style),
+                    MoraEdges::Left => format!("BORDER-LEFT{}", border_style),
+                })
+                .collect::<String>();
+
+            format!("<span style=\"{}\">{}</span>", border_css, mora)
+        })
+        .collect::<String>();
+
+    // If the accent has a note, prepend it to the html.
+    match &accent.note {
+        Some(note) if style.use_css_classes => {
+            format!("<span class=\"pitch-note\">{}: </span>{}", note, mora_html)
+        }
+        Some(note) => format!(
+            "<span style=\"font-weight:bold\">{}: </span>{}",
+            note, mora_html
+        ),
+        None => mora_html,
+    }
+}
+
+// Marks a devoiced mora with a dotted circle and a nasal one with ゜ (か゚).
+fn annotate_mora(mora: &str, i: usize, accent: &Accent, style: &PitchHtmlStyle) -> String {
+    let mut text = mora.to_string();
+    if accent.nasal.contains(&i) {
+        let mut chars = mora.chars();
+        text = chars
+            .next()
+            .map(|c| {
+                c.to_string()
+                    .nfd()
+                    .filter(|c| *c != '\u{3099}')
+                    .collect::<String>()
+            })
+            .unwrap_or_default();
+        text.push('\u{309A}');
+        text.extend(chars);
+    }
+    if accent.devoiced.contains(&i) {
+        text = if style.use_css_classes {
+            format!("<span class=\"pitch-devoiced\">{}</span>", text)
+        } else {
+            format!(
+                "<span style=\"border: 1px dotted {}; border-radius: 50%;\">{}</span>",
+                style.color, text
+            )
+        };
+    }
+    text
+}
+
+pub(crate) fn generate_mora_edges(
+    kana_string: &KanaString,
+    accent_type: &AccentType,
+) -> Vec<Vec<MoraEdges>> {
+    // Get the edges for the more itself.
+    let n_mora = kana_string.mora_count();
+    let mut mora_edges = kana_string
+        .iter_mora()
+        .enumerate()
+        .map(|(i, _)| match accent_type {
+            AccentType::Heiban => match i {
+                0 => vec![MoraEdges::Bottom],
+                1 => vec![MoraEdges::Left, MoraEdges::Top],
+                2.. => vec![MoraEdges::Top],
+            },
+            AccentType::Atamadaka => match i {
+                0 => vec![MoraEdges::Top],
+                1 => vec![MoraEdges::Left, MoraEdges::Bottom],
+                2.. => vec![MoraEdges::Bottom],
+            },
+            AccentType::Nakadaka(idx) => match i {
+                0 => vec![MoraEdges::Bottom],
+                1 => vec![MoraEdges::Left, MoraEdges::Top],
+                _ if i < *idx => vec![MoraEdges::Top],
+                _ if i == *idx => vec![MoraEdges::Left, MoraEdges::Bottom],
+                _ => vec![MoraEdges::Bottom],
+            },
+            AccentType::Odaka => match i {
+                0 => {
+                    if n_mora == 1 {
+                        vec![MoraEdges::Top]
+                    } else {
+                        vec![MoraEdges::Bottom]
+                    }
+                }
+                1 => vec![MoraEdges::Left, MoraEdges::Top],
+                _ => vec![MoraEdges::Top],
+            },
+        })
+        .collect::<Vec<Vec<MoraEdges>>>();
+
+    // Insert the edges for the particle following the word.
+    mora_edges.push(match accent_type {
+        AccentType::Heiban => vec![MoraEdges::Top],
+        AccentType::Atamadaka => vec![MoraEdges::Bottom],
+        AccentType::Nakadaka(_) => vec![MoraEdges::Bottom],
+        AccentType::Odaka => vec![MoraEdges::Left, MoraEdges::Bottom],
+    });
+
+    mora_edges
+}
+
+// Every reading of each word.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccentMap(HashMap<Word, Vec<WordAccents>>);
+
+impl AccentMap {
+    // The readings of the word, also trying its all-hiragana and all-katakana
+    // forms so サッカー and さっかー find the same entry.
+    pub fn lookup(&self, word: &str) -> Option<&Vec<WordAccents>> {
+        if let Some(readings) = self.get(word) {
+            return Some(readings);
+        }
+        let kana = KanaString::from(word.to_string());
+        if let Some(readings) = self.get(&kana.0) {
+            return Some(readings);
+        }
+        [kana.to_hiragana(), kana.to_katakana()]
+            .into_iter()
+            .find_map(|k| self.get(&k.0))
+    }
+}
+
+impl Deref for AccentMap {
+    type Target = HashMap<Word, Vec<WordAccents>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for AccentMap {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl FromIterator<(Word, Vec<WordAccents>)> for AccentMap {
+    fn from_iter<I: IntoIterator<Item = (Word, Vec<WordAccents>)>>(iter: I) -> Self {
+        AccentMap(HashMap::from_iter(iter))
+    }
+}
+
+impl<const N: usize> From<[(Word, Vec<WordAccents>); N]> for AccentMap {
+    fn from(entries: [(Word, Vec<WordAccents>); N]) -> Self {
+        AccentMap(HashMap::from(entries))
+    }
+}
+
+impl IntoIterator for AccentMap {
+    type Item = (Word, Vec<WordAccents>);
+    type IntoIter = std::collections::hash_map::IntoIter<Word, Vec<WordAccents>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{golden, load_accents, load_accents_with_overrides};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_accent_notes() {
+        let accents = load_accents();
+
+        let t1 = &accents[&"かちかち".to_string()][0].accents;
+        for accent in t1 {
+            match accent.accent_type {
+                AccentType::Heiban => {
+                    assert_eq!("形動".to_string(), accent.note.clone().unwrap_or_default())
+                }
+                AccentType::Atamadaka => {
+                    assert_eq!("副;名".to_string(), accent.note.clone().unwrap_or_default())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_accent_type() {
+        let accents = load_accents();
+
+        let trials = [
+            ("サッカー", "サッカー", vec![AccentType::Atamadaka]),
+            ("箸", "はし", vec![AccentType::Atamadaka]),
+            ("橋", "はし", vec![AccentType::Odaka]),
+            ("端", "はし", vec![AccentType::Heiban]),
+            ("鼻", "はな", vec![AccentType::Heiban]),
+            ("花", "はな", vec![AccentType::Odaka]),
+            (
+                "あの方",
+                "あのかた",
+                vec![AccentType::Nakadaka(3), AccentType::Odaka],
+            ),
+        ];
+        let trials = trials
+            .iter()
+            .map(|(w, k, v)| (w.to_string(), KanaString::from(k.to_string()), v))
+            .collect::<Vec<_>>();
+
+        for (word, kana, true_accents) in trials.iter() {
+            let test_accents = &accents[word]
+                .iter()
+                .filter(|w| w.kana == *kana)
+                .flat_map(|w| w.accents.clone())
+                .map(|a| a.accent_type)
+                .collect::<Vec<_>>();
+            let true_accents: HashSet<&AccentType> = true_accents.iter().collect();
+
+            assert_eq!(test_accents.len(), true_accents.len());
+            for test_accent in test_accents {
+                assert!(
+                    true_accents.contains(test_accent),
+                    "{:#?} in {:#?}",
+                    test_accent,
+                    true_accents
+                )
+            }
+        }
+    }
+
+    #[test]
+    fn test_iter_mora() {
+        // <-- actual test
+        let kana = KanaString::from("サッカー".to_string());
+        let s1 = kana.iter_mora().collect::<Vec<_>>();
+        assert_eq!(s1.len(), 3);
+        assert_eq!(s1[0], "サッ");
+        assert_eq!(s1[1], "カ");
+        assert_eq!(s1[2], "ー");
+
+        let kana = KanaString::from("れっしゃ".to_string());
+        let s2 = kana.iter_mora().collect::<Vec<_>>();
+        assert_eq!(s2.len(), 2);
+        assert_eq!(s2[0], "れっ");
+        assert_eq!(s2[1], "しゃ");
+
+        // A leading small kana is a mora of its own.
+        let s3 = KanaString::from("ッて".to_string());
+        assert_eq!(s3.iter_mora().collect::<Vec<_>>(), vec!["ッ", "て"]);
+        assert_eq!(s3.mora_count(), 2);
+    }
+
+    #[test]
+    fn test_kana_normalization() {
+        let kana = KanaString::from("サッカーとゔぁ".to_string());
+        assert_eq!(kana.to_hiragana().0, "さっかーとゔぁ");
+        assert_eq!(kana.to_katakana().0, "サッカートヴァ");
+
+        let accents = load_accents();
+        assert!(accents.get("さっかー").is_none());
+        let soccer = accents.lookup("さっかー").unwrap();
+        assert_eq!(soccer[0].kana.0, "サッカー");
+        // Readings match whatever kana they are written in.
+        let ato = word_accents(&"この後".to_string(), Some("コノアト"), &accents);
+        assert_eq!(ato.len(), 1);
+    }
+
+    #[test]
+    fn test_normalize_kana() {
+        // か + combining dakuten, and half-width ｶﾞｯｺｳ.
+        let decomposed = KanaString::from("か\u{3099}っこう".to_string());
+        assert_eq!(decomposed.0, "がっこう");
+        assert_eq!(decomposed.mora_count(), 3);
+        assert_eq!(KanaString::from("ｶﾞｯｺｳ ﾊﾟﾝ".to_string()).0, "ガッコウ パン");
+
+        let accents = load_accents();
+        assert!(accents.lookup("ｻｯｶｰ").is_some());
+        assert!(accents.lookup("学校").is_some());
+    }
+
+    #[test]
+    fn test_generate_mora_edges() {
+        let t = generate_mora_edges(&KanaString::from("き".to_string()), &AccentType::Odaka);
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].len(), 1);
+        assert_eq!(t[0][0], MoraEdges::Top);
+        assert_eq!(t[1].len(), 2);
+        assert_eq!(t[1][0], MoraEdges::Left);
+        assert_eq!(t[1][1], MoraEdges::Bottom);
+
+        let t = generate_mora_edges(&KanaString::from("かわ".to_string()), &AccentType::Odaka);
+        assert_eq!(t.len(), 3);
+        assert_eq!(t[0].len(), 1);
+        assert_eq!(t[0][0], MoraEdges::Bottom);
+        assert_eq!(t[1].len(), 2);
+        assert_eq!(t[1][0], MoraEdges::Left);
+        assert_eq!(t[1][1], MoraEdges::Top);
+        assert_eq!(t[2].len(), 2);
+        assert_eq!(t[2][0], MoraEdges::Left);
+        assert_eq!(t[2][1], MoraEdges::Bottom);
+
+        let t = generate_mora_edges(&KanaString::from("じかん".to_string()), &AccentType::Heiban);
+        assert_eq!(t.len(), 4);
+        assert_eq!(t[0].len(), 1);
+        assert_eq!(t[0][0], MoraEdges::Bottom);
+        assert_eq!(t[1].len(), 2);
+        assert_eq!(t[1][0], MoraEdges::Left);
+        assert_eq!(t[1][1], MoraEdges::Top);
+        assert_eq!(t[2].len(), 1);
+        assert_eq!(t[2][0], MoraEdges::Top);
+        assert_eq!(t[3].len(), 1);
+        assert_eq!(t[3][0], MoraEdges::Top);
+
+        let t = generate_mora_edges(
+            &KanaString::from("てんき".to_string()),
+            &AccentType::Atamadaka,
+        );
+        assert_eq!(t.len(), 4);
+        assert_eq!(t[0].len(), 1);
+        assert_eq!(t[0][0], MoraEdges::Top);
+        assert_eq!(t[1].len(), 2);
+        assert_eq!(t[1][0], MoraEdges::Left);
+        assert_eq!(t[1][1], MoraEdges::Bottom);
+        assert_eq!(t[2].len(), 1);
+        assert_eq!(t[2][0], MoraEdges::Bottom);
+        assert_eq!(t[3].len(), 1);
+        assert_eq!(t[3][0], MoraEdges::Bottom);
+
+        let t = generate_mora_edges(
+            &KanaString::from("ひとつ".to_string()),
+            &AccentType::Nakadaka(2),
+        );
+        assert_eq!(t.len(), 4);
+        assert_eq!(t[0].len(), 1);
+        assert_eq!(t[0][0], MoraEdges::Bottom);
+        assert_eq!(t[1].len(), 2);
+        assert_eq!(t[1][0], MoraEdges::Left);
+        assert_eq!(t[1][1], MoraEdges::Top);
+        assert_eq!(t[2].len(), 2);
+        assert_eq!(t[2][0], MoraEdges::Left);
+        assert_eq!(t[2][1], MoraEdges::Bottom);
+        assert_eq!(t[3].len(), 1);
+        assert_eq!(t[3][0], MoraEdges::Bottom);
+
+        let t = generate_mora_edges(
+            &KanaString::from("こうじょう".to_string()),
+            &AccentType::Nakadaka(3),
+        );
+        assert_eq!(t.len(), 5);
+        assert_eq!(t[0].len(), 1);
+        assert_eq!(t[0][0], MoraEdges::Bottom);
+        assert_eq!(t[1].len(), 2);
+        assert_eq!(t[1][0], MoraEdges::Left);
+        assert_eq!(t[1][1], MoraEdges::Top);
+        assert_eq!(t[2].len(), 1);
+        assert_eq!(t[2][0], MoraEdges::Top);
+        assert_eq!(t[3].len(), 2);
+        assert_eq!(t[3][0], MoraEdges::Left);
+        assert_eq!(t[3][1], MoraEdges::Bottom);
+        assert_eq!(t[4].len(), 1);
+        assert_eq!(t[3][1], MoraEdges::Bottom);
+    }
+
+    #[test]
+    fn test_generate_html_for_accent() {
+        let accents = load_accents();
+        let t1 = &accents[&"あの方".to_string()][0];
+        let r1 = generate_html_for_accent(
+            &t1.kana,
+            t1.accents
+                .iter()
+                .find(|a| a.accent_type == AccentType::Nakadaka(3))
+                .unwrap(),
+            &PitchHtmlStyle::default(),
+        );
+        golden::assert_golden("html/accent/ano_kata_nakadaka.html", &r1);
+
+        let t2 = &accents[&"かちかち".to_string()][0];
+        let r2 = generate_html_for_accent(
+            &t2.kana,
+            t2.accents
+                .iter()
+                .find(|a| a.accent_type == AccentType::Heiban)
+                .unwrap(),
+            &PitchHtmlStyle::default(),
+        );
+
+        golden::assert_golden("html/accent/kachikachi_heiban_note.html", &r2);
+    }
+
+    #[test]
+    fn test_devoiced_and_nasal() {
+        let kana = KanaString::from("きしゃがっこう".to_string());
+        let accent = Accent {
+            accent_type: AccentType::Heiban,
+            note: None,
+            devoiced: vec![0],
+            nasal: vec![2],
+        };
+        let html = generate_html_for_accent(&kana, &accent, &PitchHtmlStyle::default());
+        golden::assert_golden("html/accent/kisha_devoiced_nasal.html", &html);
+
+        let classes = PitchHtmlStyle {
+            use_css_classes: true,
+            ..Default::default()
+        };
+        let html = generate_html_for_accent(&kana, &accent, &classes);
+        assert!(html.contains("<span class=\"pitch-devoiced\">き</span>"));
+        assert!(html.contains("か\u{309A}っ"));
+
+        // The bundled dictionary has neither, so nothing is marked.
+        let accents = load_accents();
+        assert!(accents
+            .values()
+            .flatten()
+            .flat_map(|wa| wa.accents.iter())
+            .all(|a| a.devoiced.is_empty() && a.nasal.is_empty()));
+        let html = generate_html_for_reading(&"汽車".to_string(), None, &accents, &classes);
+        assert!(html.contains("き"));
+        assert!(!html.contains("pitch-devoiced"));
+        assert!(!html.contains('\u{309A}'));
+    }
+
+    #[test]
+    fn test_generate_html() {
+        let accents = load_accents();
+        let t1 = generate_html(&"あの方".to_string(), &accents);
+        golden::assert_golden("html/word/ano_kata.html", &t1);
+
+        let t2 = generate_html(&"この後".to_string(), &accents);
+        golden::assert_golden("html/word/kono_ato.html", &t2);
+    }
+
+    #[test]
+    fn test_generate_html_for_reading() {
+        let accents = load_accents();
+        let word = "この後".to_string();
+        assert_eq!(word_accents(&word, None, &accents).len(), 2);
+        let ato = word_accents(&word, Some("このあと"), &accents);
+        assert_eq!(ato.len(), 1);
+        assert_eq!(ato[0].kana.0, "このあと");
+        // An unknown reading falls back to every reading.
+        assert_eq!(word_accents(&word, Some("このご"), &accents).len(), 2);
+
+        let html = generate_html_for_reading(
+            &word,
+            Some("このあと"),
+            &accents,
+            &PitchHtmlStyle::default(),
+        );
+        assert!(html.contains(">あ<"));
+        assert!(!html.contains(">ち<"));
+    }
+
+    #[test]
+    fn test_pitch_html_style() {
+        let accents = load_accents();
+        let word = "この後".to_string();
+
+        let classes = PitchHtmlStyle {
+            use_css_classes: true,
+            ..Default::default()
+        };
+        let html = generate_html_for_reading(&word, None, &accents, &classes);
+        golden::assert_golden("html/word/kono_ato.css_classes.html", &html);
+
+        let custom = PitchHtmlStyle {
+            color: "#3366FF".to_string(),
+            width: "2px".to_string(),
+            separator: " / ".to_string(),
+            align: None,
+            use_css_classes: false,
+        };
+        let html = generate_html_for_reading(&word, None, &accents, &custom);
+        golden::assert_golden("html/word/kono_ato.custom_style.html", &html);
+    }
+
+    #[test]
+    fn test_load_accents_with_overrides() {
+        let dir = std::env::temp_dir().join(format!("mochi-overrides-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let tsv = dir.join("overrides.tsv");
+        std::fs::write(&tsv, "箸\tはし\t2\nぴえん\t\t1\n").unwrap();
+        let accents = load_accents_with_overrides(&tsv).unwrap();
+        assert_eq!(accents["箸"].len(), 1);
+        assert_eq!(accents["箸"][0].accents[0].accent_type, AccentType::Odaka);
+        assert_eq!(accents["ぴえん"][0].kana.0, "ぴえん");
+        assert!(accents.contains_key("この後"));
+
+        let toml = dir.join("overrides.toml");
+        std::fs::write(
+            &toml,
+            "\"この後\" = [{ kana = \"このあと\", accents = \"0,3(副)\" }]\n",
+        )
+        .unwrap();
+        let accents = load_accents_with_overrides(&toml).unwrap();
+        assert_eq!(accents["この後"].len(), 1);
+        assert_eq!(accents["この後"][0].accents.len(), 2);
+        assert_eq!(accents["この後"][0].accents[1].note.as_deref(), Some("副"));
+
+        std::fs::write(&tsv, "箸\tはし\n").unwrap();
+        let err = load_accents_with_overrides(&tsv).unwrap_err();
+        assert_eq!(err.to_string(), "line 1: expected 3 columns");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_global_accents() {
+        let accents = AccentMap::global();
+        assert!(std::ptr::eq(accents, AccentMap::global()));
+        assert!(accents.contains_key("箸"));
+    }
+}
@@ -0,0 +1,288 @@
+use crate::KanaString;
+
+// Romaji
+//
+// Kana to romaji in the three common systems, for beginner helper fields.
+// Long vowels written with ー, おう, おお or うう get a macron (Hepburn) or a
+// circumflex (Kunrei, Nihon). Without word boundaries おもう becomes omō too.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RomajiStyle {
+    #[default]
+    Hepburn,
+    Kunrei,
+    Nihon,
+}
+
+// Romaji of a single hiragana, in Hepburn, Kunrei and Nihon-shiki.
+fn base_romaji(c: char, style: RomajiStyle) -> Option<&'static str> {
+    let (hepburn, kunrei, nihon) = match c {
+        'あ' | 'ぁ' => ("a", "a", "a"),
+        'い' | 'ぃ' => ("i", "i", "i"),
+        'う' | 'ぅ' => ("u", "u", "u"),
+        'え' | 'ぇ' => ("e", "e", "e"),
+        'お' | 'ぉ' => ("o", "o", "o"),
+        'か' | 'ゕ' => ("ka", "ka", "ka"),
+        'き' => ("ki", "ki", "ki"),
+        'く' => ("ku", "ku", "ku"),
+        'け' | 'ゖ' => ("ke", "ke", "ke"),
+        'こ' => ("ko", "ko", "ko"),
+        'さ' => ("sa", "sa", "sa"),
+        'し' => ("shi", "si", "si"),
+        'す' => ("su", "su", "su"),
+        'せ' => ("se", "se", "se"),
+        'そ' => ("so", "so", "so"),
+        'た' => ("ta", "ta", "ta"),
+        'ち' => ("chi", "ti", "ti"),
+        'つ' => ("tsu", "tu", "tu"),
+        'て' => ("te", "te", "te"),
+        'と' => ("to", "to", "to"),
+        'な' => ("na", "na", "na"),
+        'に' => ("ni", "ni", "ni"),
+        'ぬ' => ("nu", "nu", "nu"),
+        'ね' => ("ne", "ne", "ne"),
+        'の' => ("no", "no", "no"),
+        'は' => ("ha", "ha", "ha"),
+        'ひ' => ("hi", "hi", "hi"),
+        'ふ' => ("fu", "hu", "hu"),
+        'へ' => ("he", "he", "he"),
+        'ほ' => ("ho", "ho", "ho"),
+        'ま' => ("ma", "ma", "ma"),
+        'み' => ("mi", "mi", "mi"),
+        'む' => ("mu", "mu", "mu"),
+        'め' => ("me", "me", "me"),
+        'も' => ("mo", "mo", "mo"),
+        'や' | 'ゃ' => ("ya", "ya", "ya"),
+        'ゆ' | 'ゅ' => ("yu", "yu", "yu"),
+        'よ' | 'ょ' => ("yo", "yo", "yo"),
+        'ら' => ("ra", "ra", "ra"),
+        'り' => ("ri", "ri", "ri"),
+        'る' => ("ru", "ru", "ru"),
+        'れ' => ("re", "re", "re"),
+        'ろ' => ("ro", "ro", "ro"),
+        'わ' | 'ゎ' => ("wa", "wa", "wa"),
+        'ゐ' => ("i", "i", "wi"),
+        'ゑ' => ("e", "e", "we"),
+        'を' => ("o", "o", "wo"),
+        'が' => ("ga", "ga", "ga"),
+        'ぎ' => ("gi", "gi", "gi"),
+        'ぐ' => ("gu", "gu", "gu"),
+        'げ' => ("ge", "ge", "ge"),
+        'ご' => ("go", "go", "go"),
+        'ざ' => ("za", "za", "za"),
+        'じ' => ("ji", "zi", "zi"),
+        'ず' => ("zu", "zu", "zu"),
+        'ぜ' => ("ze", "ze", "ze"),
+        'ぞ' => ("zo", "zo", "zo"),
+        'だ' => ("da", "da", "da"),
+        'ぢ' => ("ji", "zi", "di"),
+        'づ' => ("zu", "zu", "du"),
+        'で' => ("de", "de", "de"),
+        'ど' => ("do", "do", "do"),
+        'ば' => ("ba", "ba", "ba"),
+        'び' => ("bi", "bi", "bi"),
+        'ぶ' => ("bu", "bu", "bu"),
+        'べ' => ("be", "be", "be"),
+        'ぼ' => ("bo", "bo", "bo"),
+        'ぱ' => ("pa", "pa", "pa"),
+        'ぴ' => ("pi", "pi", "pi"),
+        'ぷ' => ("pu", "pu", "pu"),
+        'ぺ' => ("pe", "pe", "pe"),
+        'ぽ' => ("po", "po", "po"),
+        'ゔ' => ("vu", "vu", "vu"),
+        _ => return None,
+    };
+    Some(match style {
+        RomajiStyle::Hepburn => hepburn,
+        RomajiStyle::Kunrei => kunrei,
+        RomajiStyle::Nihon => nihon,
+    })
+}
+
+fn is_small_yoon(c: char) -> bool {
+    matches!(c, 'ゃ' | 'ゅ' | 'ょ')
+}
+
+fn is_small_vowel(c: char) -> bool {
+    matches!(c, 'ぁ' | 'ぃ' | 'ぅ' | 'ぇ' | 'ぉ' | 'ゎ')
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'i' | 'u' | 'e' | 'o')
+}
+
+pub fn is_kana(c: char) -> bool {
+    matches!(c, 'ぁ'..='ゖ' | 'ァ'..='ヺ' | 'ー')
+}
+
+fn long_vowel(c: char, style: RomajiStyle) -> Option<char> {
+    let (macron, circumflex) = match c {
+        'a' => ('ā', 'â'),
+        'i' => ('ī', 'î'),
+        'u' => ('ū', 'û'),
+        'e' => ('ē', 'ê'),
+        'o' => ('ō', 'ô'),
+        _ => return None,
+    };
+    Some(match style {
+        RomajiStyle::Hepburn => macron,
+        _ => circumflex,
+    })
+}
+
+enum Token {
+    Syllable(String),
+    Sokuon,
+    N,
+    Long,
+    Other(char),
+}
+
+// A mora with its small kana, e.g. しゃ or ふぁ.
+fn syllable(c: char, small: Option<char>, style: RomajiStyle) -> Option<String> {
+    let base = base_romaji(c, style)?;
+    let small = match small {
+        Some(small) => small,
+        None => return Some(base.to_string()),
+    };
+    let small_romaji = base_romaji(small, style)?;
+
+    if is_small_yoon(small) {
+        // き + ゃ = kya, but し + ゃ = sha in Hepburn.
+        let stem = base.strip_suffix('i').unwrap_or(base);
+        let vowel = &small_romaji[1..];
+        return Some(match stem {
+            "sh" | "ch" | "j" => format!("{}{}", stem, vowel),
+            _ => format!("{}y{}", stem, vowel),
+        });
+    }
+
+    // ふ + ぁ = fa, て + ぃ = ti, う + ぃ = wi.
+    let stem = base.trim_end_matches(is_vowel);
+    let stem = if stem.is_empty() && base == "u" {
+        "w"
+    } else {
+        stem
+    };
+    let vowel = small_romaji.trim_start_matches('w');
+    Some(format!("{}{}", stem, vowel))
+}
+
+fn tokenize(kana: &KanaString, style: RomajiStyle) -> Vec<Token> {
+    let hiragana = kana.to_hiragana();
+    let mut chars = hiragana.0.chars().peekable();
+    let mut tokens = vec![];
+    while let Some(c) = chars.next() {
+        let token = match c {
+            'っ' => Token::Sokuon,
+            'ん' => Token::N,
+            'ー' => Token::Long,
+            _ => {
+                let small = chars
+                    .next_if(|n| is_small_yoon(*n) || is_small_vowel(*n))
+                    .filter(|_| !is_small_vowel(c) && !is_small_yoon(c));
+                match syllable(c, small, style) {
+                    Some(romaji) => Token::Syllable(romaji),
+                    None => Token::Other(c),
+                }
+            }
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+// Lengthen the last vowel written, e.g. for ー or the う of おう.
+fn lengthen(romaji: &mut String, style: RomajiStyle) -> bool {
+    let last = romaji.chars().last();
+    match last.and_then(|c| long_vowel(c, style)) {
+        Some(long) => {
+            romaji.pop();
+            romaji.push(long);
+            true
+        }
+        None => false,
+    }
+}
+
+impl KanaString {
+    pub fn to_romaji(&self, style: RomajiStyle) -> String {
+        let tokens = tokenize(self, style);
+        let mut romaji = String::new();
+        for (i, token) in tokens.iter().enumerate() {
+            let next = match tokens.get(i + 1) {
+                Some(Token::Syllable(next)) => Some(next.as_str()),
+                _ => None,
+            };
+            match token {
+                Token::Syllable(syllable) => {
+                    // おう, おお and うう are long vowels.
+                    let previous = romaji.chars().last();
+                    let is_long = matches!(
+                        (previous, syllable.as_str()),
+                        (Some('o'), "u") | (Some('o'), "o") | (Some('u'), "u")
+                    );
+                    if !(is_long && lengthen(&mut romaji, style)) {
+                        romaji.push_str(syllable);
+                    }
+                }
+                // Doubles the next consonant, っち is tchi in Hepburn.
+                Token::Sokuon => match next {
+                    Some(next) if next.starts_with("ch") => romaji.push('t'),
+                    Some(next) if !next.starts_with(is_vowel) => {
+                        romaji.push(next.chars().next().unwrap())
+                    }
+                    _ => {}
+                },
+                // きんえん is kin'en, not kinen.
+                Token::N => {
+                    romaji.push('n');
+                    if next.is_some_and(|n| n.starts_with(is_vowel) || n.starts_with('y')) {
+                        romaji.push('\'');
+                    }
+                }
+                Token::Long => {
+                    lengthen(&mut romaji, style);
+                }
+                Token::Other(c) => romaji.push(*c),
+            }
+        }
+        romaji
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn romaji(kana: &str, style: RomajiStyle) -> String {
+        KanaString::from(kana.to_string()).to_romaji(style)
+    }
+
+    #[test]
+    fn test_to_romaji() {
+        let hepburn = |kana| romaji(kana, RomajiStyle::Hepburn);
+        assert_eq!(hepburn("とうきょう"), "tōkyō");
+        assert_eq!(hepburn("がっこう"), "gakkō");
+        assert_eq!(hepburn("まっちゃ"), "matcha");
+        assert_eq!(hepburn("しんぶん"), "shinbun");
+        assert_eq!(hepburn("きんえん"), "kin'en");
+        assert_eq!(hepburn("こんやく"), "kon'yaku");
+        assert_eq!(hepburn("サッカー"), "sakkā");
+        assert_eq!(hepburn("じゅう"), "jū");
+        assert_eq!(hepburn("ファイル"), "fairu");
+        assert_eq!(hepburn("ティー"), "tī");
+        assert_eq!(hepburn("おおきい"), "ōkii");
+
+        let kunrei = |kana| romaji(kana, RomajiStyle::Kunrei);
+        assert_eq!(kunrei("しゃしん"), "syasin");
+        assert_eq!(kunrei("まっちゃ"), "mattya");
+        assert_eq!(kunrei("とうきょう"), "tôkyô");
+        assert_eq!(kunrei("ちぢむ"), "tizimu");
+
+        let nihon = |kana| romaji(kana, RomajiStyle::Nihon);
+        assert_eq!(nihon("ちぢむ"), "tidimu");
+        assert_eq!(nihon("つづく"), "tuduku");
+        assert_eq!(nihon("を"), "wo");
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    generate_mora_edges, word_accents, Accent, AccentMap, KanaString, MoraEdges, Word, WordAccents,
+};
+
+// Pitch Accent Diagrams
+//
+// The dot-and-line contour diagrams known from OJAD and Migaku, as inline
+// `<svg>` strings that can be written into a field like the bordered HTML.
+// Filled dots are the morae of the word, the hollow dot the following particle.
+
+const COLOR: &str = "#FF6633";
+const STEP: usize = 30;
+const HIGH_Y: usize = 8;
+const LOW_Y: usize = 28;
+const TEXT_Y: usize = 52;
+const HEIGHT: usize = 60;
+
+// Whether each mora, followed by the particle, is high.
+pub(crate) fn mora_heights(kana: &KanaString, accent: &Accent) -> Vec<bool> {
+    generate_mora_edges(kana, &accent.accent_type)
+        .iter()
+        .map(|edges| edges.contains(&MoraEdges::Top))
+        .collect()
+}
+
+pub fn generate_svg(kana: &KanaString, accent: &Accent) -> String {
+    let morae = kana.iter_mora().collect::<Vec<_>>();
+    let heights = mora_heights(kana, accent);
+    let x = |i: usize| STEP / 2 + i * STEP;
+    let y = |high: bool| if high { HIGH_Y } else { LOW_Y };
+
+    let points = heights
+        .iter()
+        .enumerate()
+        .map(|(i, high)| format!("{},{}", x(i), y(*high)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let line = format!(
+        "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>",
+        points, COLOR
+    );
+
+    let dots = heights
+        .iter()
+        .enumerate()
+        .map(|(i, high)| {
+            let fill = if i < morae.len() { COLOR } else { "#FFFFFF" };
+            format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"4\" fill=\"{}\" stroke=\"{}\" stroke-width=\"2\"/>",
+                x(i),
+                y(*high),
+                fill,
+                COLOR
+            )
+        })
+        .collect::<String>();
+
+    let labels = morae
+        .iter()
+        .enumerate()
+        .map(|(i, mora)| {
+            format!(
+                "<text x=\"{}\" y=\"{}\" font-size=\"16\" text-anchor=\"middle\">{}</text>",
+                x(i),
+                TEXT_Y,
+                mora
+            )
+        })
+        .collect::<String>();
+
+    let width = heights.len() * STEP;
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\">{}{}{}</svg>",
+        line,
+        dots,
+        labels,
+        w = width,
+        h = HEIGHT
+    )
+}
+
+// Every accent of the word as diagrams, in the same layout as the HTML.
+pub fn generate_svg_for_reading(
+    word: &Word,
+    reading: Option<&str>,
+    accent_map: &AccentMap,
+) -> String {
+    generate_svg_for_readings(&word_accents(word, reading, accent_map))
+}
+
+pub fn generate_svg_for_readings(readings: &[&WordAccents]) -> String {
+    let inner = readings
+        .iter()
+        .flat_map(|wa| {
+            wa.accents.iter().map(|a| match &a.note {
+                Some(note) => format!(
+                    "<div><span style=\"font-weight:bold\">{}: </span>{}</div>",
+                    note,
+                    generate_svg(&wa.kana, a)
+                ),
+                None => format!("<div>{}</div>", generate_svg(&wa.kana, a)),
+            })
+        })
+        .collect::<String>();
+    format!("<div style=\"text-align: center\">{}</div>", inner)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{golden, load_accents, AccentType};
+
+    #[test]
+    fn test_mora_heights() {
+        let accents = load_accents();
+        let hashi = &accents["箸"][0];
+        // は-し, dropping after the first mora.
+        assert_eq!(
+            mora_heights(&hashi.kana, &hashi.accents[0]),
+            vec![true, false, false]
+        );
+        let kachikachi = &accents["かちかち"][0];
+        let heiban = kachikachi
+            .accents
+            .iter()
+            .find(|a| a.accent_type == AccentType::Heiban)
+            .unwrap();
+        assert_eq!(
+            mora_heights(&kachikachi.kana, heiban),
+            vec![false, true, true, true, true]
+        );
+    }
+
+    #[test]
+    fn test_generate_svg() {
+        let accents = load_accents();
+        let hashi = &accents["箸"][0];
+        let svg = generate_svg(&hashi.kana, &hashi.accents[0]);
+        golden::assert_golden("svg/accent/hashi_atamadaka.svg", &svg);
+
+        let html = generate_svg_for_reading(&"この後".to_string(), None, &accents);
+        golden::assert_golden("svg/word/kono_ato.html", &html);
+    }
+}
@@ -0,0 +1,69 @@
+use serde_json::json;
+use wasm_bindgen::prelude::*;
+
+use crate::svg::generate_svg_for_reading;
+use crate::{generate_html, AccentMap, AccentType};
+
+// WASM Bindings
+//
+// The accent engine for the browser, so a web preview renders exactly what
+// gets written to cards. Build with `wasm-pack build -- --features wasm`.
+
+// Every reading of the word with its accents as JSON, e.g.
+// `[{"kana":"はし","accents":[{"type":"odaka","downstep":2,"note":null}]}]`.
+#[wasm_bindgen]
+pub fn lookup(word: &str) -> String {
+    let readings = AccentMap::global()
+        .get(word)
+        .map(|readings| {
+            readings
+                .iter()
+                .map(|wa| {
+                    let accents = wa
+                        .accents
+                        .iter()
+                        .map(|a| {
+                            let name = match a.accent_type {
+                                AccentType::Heiban => "heiban",
+                                AccentType::Atamadaka => "atamadaka",
+                                AccentType::Nakadaka(_) => "nakadaka",
+                                AccentType::Odaka => "odaka",
+                            };
+                            let downstep = a.accent_type.downstep(wa.kana.mora_count());
+                            json!({ "type": name, "downstep": downstep, "note": a.note })
+                        })
+                        .collect::<Vec<_>>();
+                    json!({ "kana": wa.kana.0, "accents": accents })
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    serde_json::Value::from(readings).to_string()
+}
+
+#[wasm_bindgen(js_name = renderHtml)]
+pub fn render_html(word: &str) -> String {
+    generate_html(&word.to_string(), AccentMap::global())
+}
+
+#[wasm_bindgen(js_name = renderSvg)]
+pub fn render_svg(word: &str) -> String {
+    generate_svg_for_reading(&word.to_string(), None, AccentMap::global())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let readings: serde_json::Value = serde_json::from_str(&lookup("箸")).unwrap();
+        assert_eq!(readings[0]["kana"], "はし");
+        assert_eq!(readings[0]["accents"][0]["type"], "atamadaka");
+        assert_eq!(lookup("not a word"), "[]");
+        assert_eq!(
+            render_html("箸"),
+            generate_html(&"箸".to_string(), AccentMap::global())
+        );
+    }
+}
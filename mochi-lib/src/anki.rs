@@ -0,0 +1,1107 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use regex::Regex;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use zip::write::SimpleFileOptions;
+
+use crate::decks::DeckTree;
+use crate::markdown::{contains_html, markdown_to_html};
+use crate::models::{
+    Card, CardBuildError, CardBuilder, Deck, DeckId, FieldId, Template, TemplateField, TemplateId,
+};
+use crate::sanitize::strip_tags;
+use crate::{
+    add_attachment, create_card, create_deck, create_template, get_attachment, list_cards,
+    list_templates, Config,
+};
+
+// Anki Packages
+//
+// An `.apkg` is a zip of an SQLite collection (`collection.anki2`, the legacy
+// schema every Anki version imports), a `media` JSON index and the media
+// files, named by their index. Each Mochi template becomes a note type with
+// the template's fields and one card type made from its content, the sides
+// split at the `---` line. Cards without a template become notes of a
+// Front/Back note type. Notes keep the Mochi card id as their guid, so
+// importing a newer export of the same deck updates the notes.
+//
+// Markdown is converted to HTML, `![](@media/file)` to `<img>` or, for audio,
+// `[sound:file]`. Review history and reverse reviews are not exported.
+//
+// Importing goes the other way, from a package or live from Anki through
+// AnkiConnect. Only the first card type of each note type is kept, as Mochi
+// templates have one front and back.
+
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "ogg", "wav", "m4a", "opus", "flac"];
+
+const SCHEMA: &str = "
+CREATE TABLE col (id integer primary key, crt integer not null, mod integer not null,
+    scm integer not null, ver integer not null, dty integer not null, usn integer not null,
+    ls integer not null, conf text not null, models text not null, decks text not null,
+    dconf text not null, tags text not null);
+CREATE TABLE notes (id integer primary key, guid text not null, mid integer not null,
+    mod integer not null, usn integer not null, tags text not null, flds text not null,
+    sfld text not null, csum integer not null, flags integer not null, data text not null);
+CREATE TABLE cards (id integer primary key, nid integer not null, did integer not null,
+    ord integer not null, mod integer not null, usn integer not null, type integer not null,
+    queue integer not null, due integer not null, ivl integer not null, factor integer not null,
+    reps integer not null, lapses integer not null, left integer not null, odue integer not null,
+    odid integer not null, flags integer not null, data text not null);
+CREATE TABLE revlog (id integer primary key, cid integer not null, usn integer not null,
+    ease integer not null, ivl integer not null, lastIvl integer not null, factor integer not null,
+    time integer not null, type integer not null);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+#[derive(Debug, Default)]
+pub struct ApkgSummary {
+    pub notes: usize,
+    pub note_types: usize,
+    pub media: usize,
+    // Media the cards refer to that wasn't given.
+    pub missing_media: Vec<String>,
+}
+
+// A stable, positive id that stays exact as a JavaScript number.
+fn anki_id(key: &str) -> i64 {
+    let hash = Sha1::digest(key.as_bytes());
+    let id = i64::from_be_bytes(hash[..8].try_into().unwrap());
+    id & 0x1f_ffff_ffff_ffff
+}
+
+fn strip_html(html: &str) -> String {
+    strip_tags(html, "").trim().to_string()
+}
+
+// The first 8 hex digits of the SHA-1 of the stripped field, as Anki does for
+// its duplicate check.
+fn field_checksum(field: &str) -> i64 {
+    let hash = Sha1::digest(strip_html(field).as_bytes());
+    i64::from(u32::from_be_bytes(hash[..4].try_into().unwrap()))
+}
+
+fn media_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"!\[[^\]]*\]\(@media/([^)\s]+)\)").unwrap())
+}
+
+// The `@media/` files the text refers to.
+pub fn media_references(text: &str) -> Vec<String> {
+    media_regex()
+        .captures_iter(text)
+        .map(|c| c[1].to_string())
+        .collect()
+}
+
+// Field or template markdown as Anki HTML.
+pub fn anki_html(markdown: &str) -> String {
+    let text = media_regex().replace_all(markdown, |c: &regex::Captures| {
+        let file = &c[1];
+        let extension = file.rsplit('.').next().unwrap_or("").to_lowercase();
+        if AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+            format!("[sound:{}]", file)
+        } else {
+            format!("<img src=\"{}\">", file)
+        }
+    });
+    if contains_html(&text) || text.trim().is_empty() {
+        return text.trim().to_string();
+    }
+    let html = markdown_to_html(&text);
+    let html = html.trim_end();
+    // A single paragraph needs no wrapper.
+    match html
+        .strip_prefix("<p>")
+        .and_then(|h| h.strip_suffix("</p>"))
+    {
+        Some(inner) if !inner.contains("<p>") => inner.to_string(),
+        _ => html.to_string(),
+    }
+}
+
+// The two sides of a Mochi template or card, split at the first `---` line.
+fn sides(content: &str) -> (&str, &str) {
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return (&content[..offset], &content[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (content, "")
+}
+
+struct NoteType {
+    id: i64,
+    name: String,
+    fields: Vec<String>,
+    front: String,
+    back: String,
+}
+
+impl NoteType {
+    fn from_template(template: &Template) -> NoteType {
+        static FIELD: OnceLock<Regex> = OnceLock::new();
+        let field = FIELD.get_or_init(|| Regex::new(r"<<\s*(.+?)\s*>>").unwrap());
+        let mut fields = template
+            .fields
+            .iter()
+            .flat_map(|f| f.values())
+            .collect::<Vec<_>>();
+        fields.sort_by(|a, b| a.pos.cmp(&b.pos));
+        let content = field.replace_all(&template.content, "{{$1}}");
+        let (front, back) = sides(&content);
+        NoteType {
+            id: anki_id(&format!("template:{}", template.id)),
+            name: template.name.clone(),
+            fields: fields.iter().map(|f| f.name.clone()).collect(),
+            front: anki_html(front),
+            back: anki_html(back),
+        }
+    }
+
+    fn basic() -> NoteType {
+        NoteType {
+            id: anki_id("mochi-basic"),
+            name: "Mochi Basic".to_string(),
+            fields: vec!["Front".to_string(), "Back".to_string()],
+            front: "{{Front}}".to_string(),
+            back: "{{Back}}".to_string(),
+        }
+    }
+
+    fn to_json(&self, deck_id: i64, now: i64) -> Value {
+        let fields = self
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(ord, name)| {
+                json!({
+                    "name": name, "ord": ord, "sticky": false, "rtl": false,
+                    "font": "Arial", "size": 20, "media": [],
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "type": 0,
+            "mod": now,
+            "usn": -1,
+            "sortf": 0,
+            "did": deck_id,
+            "tmpls": [{
+                "name": "Card 1",
+                "ord": 0,
+                "qfmt": self.front,
+                "afmt": format!("{{{{FrontSide}}}}\n\n<hr id=answer>\n\n{}", self.back),
+                "did": null,
+                "bqfmt": "",
+                "bafmt": "",
+            }],
+            "flds": fields,
+            "css": ".card {\n font-family: arial;\n font-size: 20px;\n text-align: center;\n}\n",
+            "latexPre": "\\documentclass[12pt]{article}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "tags": [],
+            "vers": [],
+            "req": [[0, "any", [0]]],
+        })
+    }
+}
+
+fn deck_json(id: i64, name: &str, now: i64) -> Value {
+    json!({
+        "id": id, "name": name, "mod": now, "usn": -1, "desc": "", "dyn": 0, "conf": 1,
+        "collapsed": false, "extendNew": 10, "extendRev": 50,
+        "newToday": [0, 0], "revToday": [0, 0], "lrnToday": [0, 0], "timeToday": [0, 0],
+    })
+}
+
+fn deck_config_json() -> Value {
+    json!({
+        "1": {
+            "id": 1, "name": "Default", "mod": 0, "usn": 0, "maxTaken": 60, "autoplay": true,
+            "timer": 0, "replayq": true, "dyn": false,
+            "new": {
+                "delays": [1, 10], "ints": [1, 4, 7], "initialFactor": 2500, "order": 1,
+                "perDay": 20, "bury": true, "separate": true,
+            },
+            "rev": {
+                "perDay": 200, "ease4": 1.3, "fuzz": 0.05, "ivlFct": 1, "maxIvl": 36500,
+                "bury": true, "minSpace": 1,
+            },
+            "lapse": {
+                "delays": [10], "mult": 0, "minInt": 1, "leechFails": 8, "leechAction": 0,
+            },
+        }
+    })
+}
+
+fn write_collection(
+    path: &Path,
+    deck_name: &str,
+    cards: &[Card],
+    templates: &[Template],
+) -> Result<(usize, usize), Box<dyn Error>> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let (now_secs, now_millis) = (now.as_secs() as i64, now.as_millis() as i64);
+    let deck_id = anki_id(&format!("deck:{}", deck_name));
+
+    let mut note_types: BTreeMap<String, NoteType> = BTreeMap::new();
+    let mut notes = vec![];
+    for card in cards {
+        let template = card
+            .template_id
+            .as_ref()
+            .and_then(|id| templates.iter().find(|t| t.id == *id));
+        let (key, fields) = match template {
+            Some(template) => {
+                let note_type = note_types
+                    .entry(template.id.to_string())
+                    .or_insert_with(|| NoteType::from_template(template));
+                let fields = note_type
+                    .fields
+                    .iter()
+                    .map(|name| {
+                        let value = card.field_by_name(template, name);
+                        anki_html(value.map(|f| f.value.as_str()).unwrap_or(""))
+                    })
+                    .collect::<Vec<_>>();
+                (template.id.to_string(), fields)
+            }
+            None => {
+                note_types
+                    .entry(String::new())
+                    .or_insert_with(NoteType::basic);
+                let (front, back) = sides(&card.content);
+                (String::new(), vec![anki_html(front), anki_html(back)])
+            }
+        };
+        notes.push((card, note_types[&key].id, fields));
+    }
+
+    let _ = fs::remove_file(path);
+    let mut db = Connection::open(path)?;
+    db.execute_batch(SCHEMA)?;
+    let models = note_types
+        .values()
+        .map(|t| (t.id.to_string(), t.to_json(deck_id, now_secs)))
+        .collect::<serde_json::Map<_, _>>();
+    let decks = json!({
+        "1": deck_json(1, "Default", now_secs),
+        deck_id.to_string(): deck_json(deck_id, deck_name, now_secs),
+    });
+    let conf = json!({
+        "nextPos": notes.len() + 1, "estTimes": true, "activeDecks": [1], "sortType": "noteFld",
+        "timeLim": 0, "sortBackwards": false, "addToCur": true, "curDeck": 1, "newBu": true,
+        "newSpread": 0, "dueCounts": true, "curModel": null, "collapseTime": 1200,
+    });
+    db.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?3, 11, 0, 0, 0, ?4, ?5, ?6, ?7, '{}')",
+        params![
+            now_secs,
+            now_millis,
+            now_millis,
+            conf.to_string(),
+            Value::Object(models).to_string(),
+            decks.to_string(),
+            deck_config_json().to_string(),
+        ],
+    )?;
+
+    let tx = db.transaction()?;
+    for (i, (card, note_type_id, fields)) in notes.iter().enumerate() {
+        let note_id = now_millis + i as i64;
+        let tags = crate::tags::manual_tags(card)
+            .iter()
+            .map(|t| t.replace(' ', "_"))
+            .collect::<Vec<_>>();
+        let tags = if tags.is_empty() {
+            String::new()
+        } else {
+            format!(" {} ", tags.join(" "))
+        };
+        tx.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+            params![
+                note_id,
+                card.id.as_str(),
+                note_type_id,
+                now_secs,
+                tags,
+                fields.join("\x1f"),
+                strip_html(&fields[0]),
+                field_checksum(&fields[0]),
+            ],
+        )?;
+        // New cards, suspended (queue -1) if archived in Mochi.
+        let queue = if card.archived { -1 } else { 0 };
+        tx.execute(
+            "INSERT INTO cards VALUES (?1, ?2, ?3, 0, ?4, -1, 0, ?5, ?6, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+            params![note_id, note_id, deck_id, now_secs, queue, i as i64 + 1],
+        )?;
+    }
+    tx.commit()?;
+    db.close().map_err(|(_, err)| err)?;
+    Ok((notes.len(), note_types.len()))
+}
+
+// Writes the cards as an `.apkg` with a deck named `deck_name` (`::` separates
+// subdecks). `media` holds the attachments by filename.
+pub fn write_apkg(
+    path: &Path,
+    deck_name: &str,
+    cards: &[Card],
+    templates: &[Template],
+    media: &HashMap<String, Vec<u8>>,
+) -> Result<ApkgSummary, Box<dyn Error>> {
+    let collection = std::env::temp_dir().join(format!(
+        "mochi-apkg-{}-{}.anki2",
+        std::process::id(),
+        anki_id(&path.to_string_lossy())
+    ));
+    let written = write_collection(&collection, deck_name, cards, templates);
+    let bytes = written.as_ref().ok().map(|_| fs::read(&collection));
+    let _ = fs::remove_file(&collection);
+    let (notes, note_types) = written?;
+    let bytes = bytes.unwrap()?;
+
+    let mut referenced = BTreeSet::new();
+    for card in cards {
+        referenced.extend(media_references(&card.content));
+        for field in card.fields.iter().flat_map(|f| f.values()) {
+            referenced.extend(media_references(&field.value));
+        }
+    }
+
+    let mut zip = zip::ZipWriter::new(File::create(path)?);
+    zip.start_file("collection.anki2", SimpleFileOptions::default())?;
+    zip.write_all(&bytes)?;
+
+    let mut summary = ApkgSummary {
+        notes,
+        note_types,
+        ..ApkgSummary::default()
+    };
+    let mut index = serde_json::Map::new();
+    for filename in referenced {
+        let Some(data) = media.get(&filename) else {
+            summary.missing_media.push(filename);
+            continue;
+        };
+        let number = index.len().to_string();
+        zip.start_file(number.as_str(), SimpleFileOptions::default())?;
+        zip.write_all(data)?;
+        index.insert(number, Value::String(filename));
+    }
+    summary.media = index.len();
+    zip.start_file("media", SimpleFileOptions::default())?;
+    zip.write_all(Value::Object(index).to_string().as_bytes())?;
+    zip.finish()?;
+    Ok(summary)
+}
+
+// Exports the deck with its attachments. The Anki deck is named after the
+// deck's path, so `Japanese/N5` becomes `Japanese::N5`.
+pub async fn export_apkg(
+    config: &Config,
+    deck_id: &DeckId,
+    path: &Path,
+) -> Result<ApkgSummary, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    let cards = cards
+        .into_vec()
+        .into_iter()
+        .filter(|c| c.trashed.is_none())
+        .collect::<Vec<_>>();
+    let templates = list_templates(config).await?;
+    let tree = DeckTree::load(config).await?;
+    let deck_name = tree
+        .path(deck_id)
+        .unwrap_or(deck_id.to_string())
+        .replace('/', "::");
+
+    let mut media = HashMap::new();
+    for card in cards.iter() {
+        let texts = std::iter::once(&card.content).chain(
+            card.fields
+                .iter()
+                .flat_map(|f| f.values())
+                .map(|f| &f.value),
+        );
+        for filename in texts.flat_map(|t| media_references(t)) {
+            if media.contains_key(&filename) {
+                continue;
+            }
+            // Missing files are reported by write_apkg.
+            if let Ok(bytes) = get_attachment(config, &card.id, &filename).await {
+                media.insert(filename, bytes);
+            }
+        }
+    }
+
+    write_apkg(path, &deck_name, &cards, &templates, &media)
+}
+
+// Import
+
+// An Anki note type, with the templates of its first card type.
+#[derive(Debug, Clone)]
+pub struct AnkiNoteType {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub front: String,
+    pub back: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnkiNote {
+    pub guid: String,
+    pub note_type: String,
+    // Subdecks separated by `::`.
+    pub deck: String,
+    // In the order of the note type's fields.
+    pub fields: Vec<String>,
+    pub tags: Vec<String>,
+    pub suspended: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AnkiCollection {
+    pub note_types: Vec<AnkiNoteType>,
+    pub notes: Vec<AnkiNote>,
+    // Media files by filename.
+    pub media: HashMap<String, Vec<u8>>,
+}
+
+impl AnkiCollection {
+    pub fn note_type(&self, name: &str) -> Option<&AnkiNoteType> {
+        self.note_types.iter().find(|t| t.name == name)
+    }
+}
+
+// Note types, decks and notes from an Anki collection database.
+fn read_collection(db: &Connection) -> Result<AnkiCollection, Box<dyn Error>> {
+    let (models, decks): (String, String) =
+        db.query_row("SELECT models, decks FROM col", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    let models: HashMap<String, Value> = serde_json::from_str(&models)?;
+    let decks: HashMap<String, Value> = serde_json::from_str(&decks)?;
+
+    let mut collection = AnkiCollection::default();
+    let mut model_names = HashMap::new();
+    for (id, model) in models {
+        let mut fields = model["flds"].as_array().cloned().unwrap_or_default();
+        fields.sort_by_key(|f| f["ord"].as_i64());
+        let mut templates = model["tmpls"].as_array().cloned().unwrap_or_default();
+        templates.sort_by_key(|t| t["ord"].as_i64());
+        let template = templates.first().cloned().unwrap_or_default();
+        let name = model["name"].as_str().unwrap_or(&id).to_string();
+        collection.note_types.push(AnkiNoteType {
+            name: name.clone(),
+            fields: fields
+                .iter()
+                .filter_map(|f| Some(f["name"].as_str()?.to_string()))
+                .collect(),
+            front: template["qfmt"].as_str().unwrap_or("").to_string(),
+            back: template["afmt"].as_str().unwrap_or("").to_string(),
+        });
+        model_names.insert(id, name);
+    }
+    collection.note_types.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // The deck of each note's first card, and whether all its cards are
+    // suspended.
+    let mut note_decks: HashMap<i64, (String, bool)> = HashMap::new();
+    let mut cards = db.prepare("SELECT nid, did, queue FROM cards ORDER BY nid, ord")?;
+    let rows = cards.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, i64>(2)?,
+        ))
+    })?;
+    for row in rows {
+        let (note_id, deck_id, queue) = row?;
+        let deck = decks[&deck_id.to_string()]["name"]
+            .as_str()
+            .unwrap_or("Default")
+            .to_string();
+        note_decks
+            .entry(note_id)
+            .and_modify(|(_, suspended)| *suspended &= queue == -1)
+            .or_insert((deck, queue == -1));
+    }
+
+    let mut notes = db.prepare("SELECT id, guid, mid, tags, flds FROM notes ORDER BY id")?;
+    let rows = notes.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+        ))
+    })?;
+    for row in rows {
+        let (id, guid, model_id, tags, fields) = row?;
+        let (deck, suspended) = note_decks
+            .remove(&id)
+            .unwrap_or(("Default".to_string(), false));
+        collection.notes.push(AnkiNote {
+            guid,
+            note_type: model_names
+                .get(&model_id.to_string())
+                .cloned()
+                .unwrap_or_default(),
+            deck,
+            fields: fields.split('\x1f').map(str::to_string).collect(),
+            tags: tags.split_whitespace().map(str::to_string).collect(),
+            suspended,
+        });
+    }
+    Ok(collection)
+}
+
+// Reads a package exported by Anki. Packages from Anki 23.10 on must be
+// exported with "Support older Anki versions", as the newer format is
+// compressed with zstd.
+pub fn read_apkg(path: &Path) -> Result<AnkiCollection, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    // Packages with both hold a placeholder in the older collection.
+    let name = ["collection.anki21", "collection.anki2"]
+        .into_iter()
+        .find(|name| archive.index_for_name(name).is_some())
+        .ok_or("no collection in package; export it with \"Support older Anki versions\"")?;
+    let mut bytes = vec![];
+    archive.by_name(name)?.read_to_end(&mut bytes)?;
+
+    let collection_path = std::env::temp_dir().join(format!(
+        "mochi-apkg-{}-{}.anki2",
+        std::process::id(),
+        anki_id(&path.to_string_lossy())
+    ));
+    fs::write(&collection_path, bytes)?;
+    let read = Connection::open(&collection_path)
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|db| read_collection(&db));
+    let _ = fs::remove_file(&collection_path);
+    let mut collection = read?;
+
+    let mut index = String::new();
+    if let Ok(mut file) = archive.by_name("media") {
+        file.read_to_string(&mut index)?;
+    }
+    let index: HashMap<String, String> = if index.trim().is_empty() {
+        HashMap::new()
+    } else {
+        serde_json::from_str(&index).map_err(|_| "media index isn't JSON")?
+    };
+    for (number, filename) in index {
+        let mut bytes = vec![];
+        archive.by_name(&number)?.read_to_end(&mut bytes)?;
+        collection.media.insert(filename, bytes);
+    }
+    Ok(collection)
+}
+
+// Anki's local API, from the AnkiConnect add-on.
+#[derive(Debug, Clone)]
+pub struct AnkiConnect {
+    pub url: String,
+}
+
+impl Default for AnkiConnect {
+    fn default() -> AnkiConnect {
+        AnkiConnect {
+            url: "http://127.0.0.1:8765".to_string(),
+        }
+    }
+}
+
+impl AnkiConnect {
+    async fn invoke(&self, action: &str, params: Value) -> Result<Value, Box<dyn Error>> {
+        let body = json!({ "action": action, "version": 6, "params": params });
+        let resp = reqwest::Client::new()
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        if let Some(err) = resp["error"].as_str() {
+            return Err(format!("AnkiConnect {}: {}", action, err).into());
+        }
+        Ok(resp["result"].clone())
+    }
+
+    // The notes matching an Anki search, e.g. `deck:Japanese`, with their
+    // note types and media.
+    pub async fn fetch(&self, query: &str) -> Result<AnkiCollection, Box<dyn Error>> {
+        let ids = self.invoke("findNotes", json!({ "query": query })).await?;
+        let infos = self.invoke("notesInfo", json!({ "notes": ids })).await?;
+        let infos = infos.as_array().cloned().unwrap_or_default();
+
+        let card_ids = infos
+            .iter()
+            .flat_map(|n| n["cards"].as_array().cloned().unwrap_or_default())
+            .collect::<Vec<_>>();
+        let cards = self
+            .invoke("cardsInfo", json!({ "cards": card_ids }))
+            .await?;
+        let cards = cards
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|c| (c["cardId"].as_i64(), c))
+            .collect::<HashMap<_, _>>();
+
+        let mut collection = AnkiCollection::default();
+        for info in infos.iter() {
+            let mut fields = info["fields"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(name, f)| (f["order"].as_i64(), name.clone(), f["value"].as_str()))
+                .collect::<Vec<_>>();
+            fields.sort();
+            let note_cards = info["cards"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|id| cards.get(&id.as_i64()))
+                .collect::<Vec<_>>();
+            let note_type = info["modelName"].as_str().unwrap_or("").to_string();
+
+            if collection.note_type(&note_type).is_none() {
+                let templates = self
+                    .invoke("modelTemplates", json!({ "modelName": note_type }))
+                    .await?;
+                let template = templates
+                    .as_object()
+                    .and_then(|t| t.values().next().cloned())
+                    .unwrap_or_default();
+                collection.note_types.push(AnkiNoteType {
+                    name: note_type.clone(),
+                    fields: fields.iter().map(|(_, name, _)| name.clone()).collect(),
+                    front: template["Front"].as_str().unwrap_or("").to_string(),
+                    back: template["Back"].as_str().unwrap_or("").to_string(),
+                });
+            }
+            collection.notes.push(AnkiNote {
+                guid: info["noteId"].to_string(),
+                note_type,
+                deck: note_cards
+                    .first()
+                    .and_then(|c| c["deckName"].as_str())
+                    .unwrap_or("Default")
+                    .to_string(),
+                fields: fields
+                    .iter()
+                    .map(|(_, _, value)| value.unwrap_or("").to_string())
+                    .collect(),
+                tags: info["tags"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|t| Some(t.as_str()?.to_string()))
+                    .collect(),
+                suspended: !note_cards.is_empty()
+                    && note_cards.iter().all(|c| c["queue"].as_i64() == Some(-1)),
+            });
+        }
+
+        let filenames = collection
+            .notes
+            .iter()
+            .flat_map(|n| n.fields.iter())
+            .flat_map(|f| media_references(&mochi_markdown(f)))
+            .collect::<BTreeSet<_>>();
+        for filename in filenames {
+            let data = self
+                .invoke("retrieveMediaFile", json!({ "filename": filename }))
+                .await?;
+            // false for files not in the media folder.
+            if let Some(data) = data.as_str() {
+                let bytes = base64::engine::general_purpose::STANDARD.decode(data)?;
+                collection.media.insert(filename, bytes);
+            }
+        }
+        Ok(collection)
+    }
+}
+
+// Anki field HTML as Mochi markdown: media as attachments, clozes
+// renumbered the Mochi way and ruby as `漢字[かんじ]` furigana. Other HTML is
+// kept.
+pub fn mochi_markdown(html: &str) -> String {
+    static PATTERNS: OnceLock<[Regex; 6]> = OnceLock::new();
+    let [sound, image, cloze, ruby, line_break, div] = PATTERNS.get_or_init(|| {
+        [
+            Regex::new(r"\[sound:([^\]]+)\]").unwrap(),
+            Regex::new(r#"<img[^>]*?src=["']?([^"' >]+)["']?[^>]*>"#).unwrap(),
+            Regex::new(r"\{\{c(\d+)::").unwrap(),
+            Regex::new(r"<ruby>(?:<rb>)?(.*?)(?:</rb>)?<rt>(.*?)</rt></ruby>").unwrap(),
+            Regex::new(r"(?i)<br\s*/?>").unwrap(),
+            Regex::new(r"(?i)<div>(.*?)</div>").unwrap(),
+        ]
+    });
+    let text = sound.replace_all(html, "![](@media/$1)");
+    let text = image.replace_all(&text, |c: &regex::Captures| {
+        let src = &c[1];
+        if src.contains("://") {
+            format!("![]({})", src)
+        } else {
+            format!("![](@media/{})", src)
+        }
+    });
+    let text = cloze.replace_all(&text, "{{$1::");
+    let text = ruby.replace_all(&text, " $1[$2]");
+    let text = line_break.replace_all(&text, "\n");
+    let text = div.replace_all(&text, "\n$1");
+    text.replace("&nbsp;", " ").trim().to_string()
+}
+
+// The note type's first card type as Mochi template content, its fields as
+// `<< Field >>` whatever filters Anki applied.
+pub fn mochi_template_content(note_type: &AnkiNoteType) -> String {
+    static PATTERNS: OnceLock<[Regex; 3]> = OnceLock::new();
+    let [front_side, section, field] = PATTERNS.get_or_init(|| {
+        [
+            Regex::new(r#"\{\{FrontSide\}\}|<hr id=["']?answer["']?>"#).unwrap(),
+            Regex::new(r"\{\{[#^/][^}]*\}\}").unwrap(),
+            Regex::new(r"\{\{\s*(?:[^}:]+:)*([^}]+?)\s*\}\}").unwrap(),
+        ]
+    });
+    let side = |template: &str| {
+        let text = front_side.replace_all(template, "");
+        let text = section.replace_all(&text, "");
+        let text = field.replace_all(&text, "<< $1 >>");
+        mochi_markdown(&text)
+    };
+    let (front, back) = (side(&note_type.front), side(&note_type.back));
+    if back.is_empty() {
+        front
+    } else {
+        format!("{}\n---\n{}", front, back)
+    }
+}
+
+// A Mochi template for the note type. The first field is the card's name.
+pub fn note_type_template(note_type: &AnkiNoteType) -> Template {
+    let mut fields = HashMap::new();
+    for (i, name) in note_type.fields.iter().enumerate() {
+        let slug = name
+            .to_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        let id = match i {
+            0 => "name".to_string(),
+            _ if slug.is_empty()
+                || slug == "name"
+                || fields.contains_key(&FieldId::from(slug.as_str())) =>
+            {
+                format!("field-{}", i)
+            }
+            _ => slug,
+        };
+        let field = TemplateField {
+            id: FieldId::from(id.as_str()),
+            name: name.clone(),
+            // Mochi orders fields by their `pos` string.
+            pos: format!("{:04}", i),
+            options: None,
+        };
+        fields.insert(field.id.clone(), field);
+    }
+    Template {
+        name: note_type.name.clone(),
+        content: mochi_template_content(note_type),
+        fields: Some(fields),
+        pos: None,
+        id: TemplateId::default(),
+        extra: HashMap::new(),
+    }
+}
+
+// The note as a card of the note type's template. Anki's `::` tag hierarchy
+// becomes `/`.
+pub fn note_card(
+    note: &AnkiNote,
+    note_type: &AnkiNoteType,
+    template: &Template,
+    deck_id: &DeckId,
+) -> Result<Card, CardBuildError> {
+    let mut builder = CardBuilder::new(deck_id.clone())
+        .template(template)
+        .archived(note.suspended);
+    for (name, value) in note_type.fields.iter().zip(note.fields.iter()) {
+        builder = builder.field(name, &mochi_markdown(value));
+    }
+    for tag in note.tags.iter() {
+        builder = builder.tag(&tag.replace("::", "/"));
+    }
+    builder.build()
+}
+
+#[derive(Debug, Default)]
+pub struct AnkiImportSummary {
+    pub decks_created: usize,
+    pub templates_created: usize,
+    pub cards_created: usize,
+    pub attachments: usize,
+    // Media the notes refer to that the collection doesn't have.
+    pub missing_media: Vec<String>,
+    // Notes that couldn't be created, by guid.
+    pub failed: Vec<(String, Box<dyn Error>)>,
+}
+
+// Creates the collection's decks, templates and cards, then uploads the
+// media each card refers to. Decks and templates already in the account
+// under the same path or name are reused.
+pub async fn import_anki(
+    config: &Config,
+    collection: &AnkiCollection,
+) -> Result<AnkiImportSummary, Box<dyn Error>> {
+    let mut summary = AnkiImportSummary::default();
+
+    let existing = list_templates(config).await?;
+    let mut templates = HashMap::new();
+    for note_type in collection.note_types.iter() {
+        let fits = |t: &&Template| {
+            t.name == note_type.name
+                && note_type
+                    .fields
+                    .iter()
+                    .all(|f| t.field_by_name(f).is_some())
+        };
+        let template = match existing.iter().find(fits) {
+            Some(template) => template.clone(),
+            None => {
+                summary.templates_created += 1;
+                create_template(config, &note_type_template(note_type)).await?
+            }
+        };
+        templates.insert(note_type.name.as_str(), template);
+    }
+
+    let tree = DeckTree::load(config).await?;
+    let mut deck_ids: HashMap<String, DeckId> = HashMap::new();
+    for note in collection.notes.iter() {
+        let Some(note_type) = collection.note_type(&note.note_type) else {
+            summary.failed.push((
+                note.guid.clone(),
+                format!("no note type {}", note.note_type).into(),
+            ));
+            continue;
+        };
+
+        // Each level of the deck path, created if missing.
+        let mut path = String::new();
+        let mut parent: Option<DeckId> = None;
+        for name in note.deck.split("::") {
+            path = if path.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}/{}", path, name)
+            };
+            let deck_id = match deck_ids.get(&path) {
+                Some(id) => id.clone(),
+                None => {
+                    let id = match tree.find_by_path(&path) {
+                        Some(deck) => deck.id.clone(),
+                        None => {
+                            let deck = Deck {
+                                name: name.to_string(),
+                                parent_id: parent.clone(),
+                                template_id: None,
+                                archived: false,
+                                sort: None,
+                                trashed: None,
+                                id: DeckId::default(),
+                                extra: HashMap::new(),
+                            };
+                            summary.decks_created += 1;
+                            create_deck(config, &deck).await?.id
+                        }
+                    };
+                    deck_ids.insert(path.clone(), id.clone());
+                    id
+                }
+            };
+            parent = Some(deck_id);
+        }
+        let deck_id = parent.unwrap_or_default();
+
+        let template = &templates[note_type.name.as_str()];
+        let card = match note_card(note, note_type, template, &deck_id) {
+            Ok(card) => card,
+            Err(err) => {
+                summary.failed.push((note.guid.clone(), err.into()));
+                continue;
+            }
+        };
+        let created = match create_card(config, &card).await {
+            Ok(created) => created,
+            Err(err) => {
+                summary.failed.push((note.guid.clone(), err.into()));
+                continue;
+            }
+        };
+        summary.cards_created += 1;
+
+        let filenames = card
+            .fields
+            .iter()
+            .flat_map(|f| f.values())
+            .flat_map(|f| media_references(&f.value))
+            .collect::<BTreeSet<_>>();
+        for filename in filenames {
+            let Some(bytes) = collection.media.get(&filename) else {
+                summary.missing_media.push(filename);
+                continue;
+            };
+            match add_attachment(config, &created.id, &filename, bytes.clone()).await {
+                Ok(()) => summary.attachments += 1,
+                Err(err) => summary.failed.push((note.guid.clone(), err.into())),
+            }
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+
+    #[test]
+    fn test_write_apkg() {
+        let template = template_fixture(
+            "vocab",
+            "# << Word >>\n---\n<< Meaning >>",
+            &[("word", "Word"), ("meaning", "Meaning")],
+        );
+        let cards = [
+            CardFixture::new("c1", "n5")
+                .template("vocab")
+                .field("word", "**箸** ![](@media/hashi.mp3)")
+                .field("meaning", "chopsticks")
+                .tags(&["food", "n5"])
+                .build(),
+            CardFixture::new("c2", "n5")
+                .content("橋\n---\nbridge ![](@media/bridge.png)")
+                .build(),
+        ];
+        assert_eq!(
+            anki_html("**箸** ![](@media/hashi.mp3)"),
+            "<strong>箸</strong> [sound:hashi.mp3]"
+        );
+
+        let dir = std::env::temp_dir().join(format!("mochi-apkg-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("n5.apkg");
+        let media = HashMap::from([("hashi.mp3".to_string(), b"ID3".to_vec())]);
+        let summary = write_apkg(&path, "Japanese::N5", &cards, &[template], &media).unwrap();
+        assert_eq!(
+            (summary.notes, summary.note_types, summary.media),
+            (2, 2, 1)
+        );
+        assert_eq!(summary.missing_media, ["bridge.png"]);
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut index = String::new();
+        archive
+            .by_name("media")
+            .unwrap()
+            .read_to_string(&mut index)
+            .unwrap();
+        assert_eq!(index, r#"{"0":"hashi.mp3"}"#);
+        let collection = dir.join("collection.anki2");
+        let mut bytes = vec![];
+        archive
+            .by_name("collection.anki2")
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        fs::write(&collection, bytes).unwrap();
+
+        let db = Connection::open(&collection).unwrap();
+        let (flds, tags): (String, String) = db
+            .query_row(
+                "SELECT flds, tags FROM notes WHERE guid = 'c1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(flds, "<strong>箸</strong> [sound:hashi.mp3]\x1fchopsticks");
+        assert_eq!(tags, " food n5 ");
+        let models: String = db
+            .query_row("SELECT models FROM col", [], |row| row.get(0))
+            .unwrap();
+        assert!(models.contains(r#""qfmt":"<h1>{{Word}}</h1>""#));
+        drop(db);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_apkg() {
+        let cards = [CardFixture::new("c1", "n5")
+            .content("橋\n---\nbridge ![](@media/bridge.png)")
+            .build()];
+        let path = std::env::temp_dir().join(format!("mochi-read-{}.apkg", std::process::id()));
+        let media = HashMap::from([("bridge.png".to_string(), b"PNG".to_vec())]);
+        write_apkg(&path, "Japanese::N5", &cards, &[], &media).unwrap();
+        let collection = read_apkg(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(collection.media, media);
+        let note = &collection.notes[0];
+        assert_eq!(
+            (note.guid.as_str(), note.deck.as_str()),
+            ("c1", "Japanese::N5")
+        );
+        assert_eq!(note.fields, ["橋", "bridge <img src=\"bridge.png\">"]);
+        let note_type = collection.note_type(&note.note_type).unwrap();
+        assert_eq!(
+            mochi_template_content(note_type),
+            "<< Front >>\n---\n<< Back >>"
+        );
+
+        let template = note_type_template(note_type);
+        let card = note_card(note, note_type, &template, &DeckId::from("n5")).unwrap();
+        let back = template.field_by_name("Back").unwrap();
+        assert_eq!(
+            card.fields.unwrap()[&back.id].value,
+            "bridge ![](@media/bridge.png)"
+        );
+
+        assert_eq!(
+            mochi_markdown(
+                "{{c1::箸::chopsticks}}<br><ruby>橋<rt>はし</rt></ruby>[sound:hashi.mp3]"
+            ),
+            "{{1::箸::chopsticks}}\n 橋[はし]![](@media/hashi.mp3)"
+        );
+    }
+}
@@ -0,0 +1,482 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use base64::Engine;
+use futures::future::LocalBoxFuture;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::models::{CardId, ResolvedCard};
+use crate::pipeline::{CardTransformer, TransformOutcome};
+use crate::{add_attachment, Config};
+
+// Pronunciation Audio
+//
+// Audio for a field's text from a speech backend (a synthesizer, or native
+// recordings from Forvo), attached to the card and referenced from another
+// field. Transformers run synchronously, so this
+// happens in two steps: the transformer writes the reference and queues the
+// text, then `upload` synthesizes and attaches the queued audio. Upload
+// before pushing the pipeline's patches so the references never dangle:
+//
+//     let run = Pipeline::new().with(&audio).apply(&cards, &templates);
+//     audio.upload(&config).await;
+//     patch_cards(&config, &run.patches).await;
+//
+// File names are a hash of the backend and the text, so re-running the
+// pipeline leaves cards that already have their audio alone.
+
+pub trait SpeechBackend {
+    // Part of the file names, so changing voices regenerates the audio.
+    fn name(&self) -> String;
+
+    // `wav`, `mp3`, ...
+    fn extension(&self) -> &str;
+
+    fn synthesize<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Vec<u8>, Box<dyn Error>>>;
+}
+
+// espeak-ng (or espeak) on the PATH, writing WAV to stdout.
+pub struct Espeak {
+    pub program: String,
+    pub voice: String,
+}
+
+impl Default for Espeak {
+    fn default() -> Espeak {
+        Espeak {
+            program: "espeak-ng".to_string(),
+            voice: "ja".to_string(),
+        }
+    }
+}
+
+impl SpeechBackend for Espeak {
+    fn name(&self) -> String {
+        format!("espeak:{}", self.voice)
+    }
+
+    fn extension(&self) -> &str {
+        "wav"
+    }
+
+    fn synthesize<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Vec<u8>, Box<dyn Error>>> {
+        Box::pin(async move {
+            let output = tokio::process::Command::new(&self.program)
+                .args(["-v", &self.voice, "--stdout", text])
+                .output()
+                .await?;
+            if !output.status.success() {
+                return Err(format!(
+                    "{} failed: {}",
+                    self.program,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )
+                .into());
+            }
+            Ok(output.stdout)
+        })
+    }
+}
+
+// A VOICEVOX engine, by default the local one.
+pub struct Voicevox {
+    pub base_url: String,
+    pub speaker: u32,
+}
+
+impl Default for Voicevox {
+    fn default() -> Voicevox {
+        Voicevox {
+            base_url: "http://127.0.0.1:50021".to_string(),
+            speaker: 1,
+        }
+    }
+}
+
+impl SpeechBackend for Voicevox {
+    fn name(&self) -> String {
+        format!("voicevox:{}", self.speaker)
+    }
+
+    fn extension(&self) -> &str {
+        "wav"
+    }
+
+    fn synthesize<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Vec<u8>, Box<dyn Error>>> {
+        Box::pin(async move {
+            let client = reqwest::Client::new();
+            let speaker = self.speaker.to_string();
+            let query = client
+                .post(format!("{}/audio_query", self.base_url))
+                .query(&[("text", text), ("speaker", &speaker)])
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Value>()
+                .await?;
+            let audio = client
+                .post(format!("{}/synthesis", self.base_url))
+                .query(&[("speaker", &speaker)])
+                .json(&query)
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+            Ok(audio.to_vec())
+        })
+    }
+}
+
+// Google Cloud Text-to-Speech with an API key.
+pub struct GoogleTts {
+    pub api_key: String,
+    // e.g. `ja-JP-Neural2-B`
+    pub voice: String,
+}
+
+impl SpeechBackend for GoogleTts {
+    fn name(&self) -> String {
+        format!("google:{}", self.voice)
+    }
+
+    fn extension(&self) -> &str {
+        "mp3"
+    }
+
+    fn synthesize<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Vec<u8>, Box<dyn Error>>> {
+        Box::pin(async move {
+            let body = json!({
+                "input": { "text": text },
+                "voice": { "languageCode": "ja-JP", "name": self.voice },
+                "audioConfig": { "audioEncoding": "MP3" },
+            });
+            let resp = reqwest::Client::new()
+                .post("https://texttospeech.googleapis.com/v1/text:synthesize")
+                .query(&[("key", &self.api_key)])
+                .json(&body)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Value>()
+                .await?;
+            let content = resp["audioContent"]
+                .as_str()
+                .ok_or("no audioContent in response")?;
+            Ok(base64::engine::general_purpose::STANDARD.decode(content)?)
+        })
+    }
+}
+
+// Native recordings from Forvo, the best rated first. Words without
+// recordings are an error, so put Forvo first in a `Fallback`.
+pub struct Forvo {
+    pub api_key: String,
+}
+
+impl SpeechBackend for Forvo {
+    fn name(&self) -> String {
+        "forvo".to_string()
+    }
+
+    fn extension(&self) -> &str {
+        "mp3"
+    }
+
+    fn synthesize<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Vec<u8>, Box<dyn Error>>> {
+        Box::pin(async move {
+            let client = reqwest::Client::new();
+            let mut url = reqwest::Url::parse("https://apifree.forvo.com/")?;
+            url.path_segments_mut()
+                .map_err(|_| "invalid Forvo URL")?
+                .pop_if_empty()
+                .extend(["key", &self.api_key, "format", "json"])
+                .extend(["action", "word-pronunciations", "word", text])
+                .extend(["language", "ja", "order", "rate-desc"]);
+            let resp = client
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Value>()
+                .await?;
+            let best = resp["items"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .max_by_key(|item| item["rate"].as_i64().unwrap_or(0))
+                .and_then(|item| item["pathmp3"].as_str())
+                .ok_or_else(|| format!("no Forvo pronunciations for {}", text))?;
+            let audio = client
+                .get(best)
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+            Ok(audio.to_vec())
+        })
+    }
+}
+
+// The first backend, or the second if the first fails, e.g. native audio with
+// synthesized audio for words nobody recorded. The file extension is the
+// first backend's.
+pub struct Fallback<'a> {
+    pub primary: &'a dyn SpeechBackend,
+    pub fallback: &'a dyn SpeechBackend,
+}
+
+impl SpeechBackend for Fallback<'_> {
+    fn name(&self) -> String {
+        format!("{}|{}", self.primary.name(), self.fallback.name())
+    }
+
+    fn extension(&self) -> &str {
+        self.primary.extension()
+    }
+
+    fn synthesize<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Vec<u8>, Box<dyn Error>>> {
+        Box::pin(async move {
+            match self.primary.synthesize(text).await {
+                Ok(audio) => Ok(audio),
+                Err(_) => self.fallback.synthesize(text).await,
+            }
+        })
+    }
+}
+
+// Keeps the backend's audio in a directory, so re-runs don't download or
+// synthesize it again.
+pub struct Cached<'a> {
+    pub backend: &'a dyn SpeechBackend,
+    pub dir: PathBuf,
+}
+
+impl SpeechBackend for Cached<'_> {
+    fn name(&self) -> String {
+        self.backend.name()
+    }
+
+    fn extension(&self) -> &str {
+        self.backend.extension()
+    }
+
+    fn synthesize<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Vec<u8>, Box<dyn Error>>> {
+        Box::pin(async move {
+            let path = self.dir.join(audio_filename(self.backend, text));
+            if let Ok(audio) = fs::read(&path) {
+                return Ok(audio);
+            }
+            let audio = self.backend.synthesize(text).await?;
+            fs::create_dir_all(&self.dir)?;
+            fs::write(&path, &audio)?;
+            Ok(audio)
+        })
+    }
+}
+
+pub fn audio_filename(backend: &dyn SpeechBackend, text: &str) -> String {
+    let hash = Sha256::digest(format!("{}\n{}", backend.name(), text).as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!("tts-{}.{}", hash, backend.extension())
+}
+
+pub fn audio_reference(filename: &str) -> String {
+    format!("![](@media/{})", filename)
+}
+
+// Transformer
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingAudio {
+    pub card_id: CardId,
+    pub filename: String,
+    pub text: String,
+}
+
+pub struct AudioTransformer<'a> {
+    pub backend: &'a dyn SpeechBackend,
+    // The word or the example sentence.
+    pub text_field: String,
+    pub audio_field: String,
+    pending: Mutex<Vec<PendingAudio>>,
+}
+
+impl<'a> AudioTransformer<'a> {
+    pub fn new(
+        backend: &'a dyn SpeechBackend,
+        text_field: &str,
+        audio_field: &str,
+    ) -> AudioTransformer<'a> {
+        AudioTransformer {
+            backend,
+            text_field: text_field.to_string(),
+            audio_field: audio_field.to_string(),
+            pending: Mutex::new(vec![]),
+        }
+    }
+
+    // The audio referenced by transformed cards but not uploaded yet.
+    pub fn pending(&self) -> Vec<PendingAudio> {
+        self.pending.lock().unwrap().clone()
+    }
+
+    // Synthesizes and attaches the pending audio, in order. Failed uploads
+    // stay pending.
+    pub async fn upload(&self, config: &Config) -> Vec<(CardId, Result<(), String>)> {
+        let mut results = vec![];
+        for audio in self.pending() {
+            let result = async {
+                let bytes = self.backend.synthesize(&audio.text).await?;
+                add_attachment(config, &audio.card_id, &audio.filename, bytes).await?;
+                Ok::<(), Box<dyn Error>>(())
+            }
+            .await
+            .map_err(|e| e.to_string());
+            if result.is_ok() {
+                self.pending.lock().unwrap().retain(|p| *p != audio);
+            }
+            results.push((audio.card_id, result));
+        }
+        results
+    }
+}
+
+impl CardTransformer for AudioTransformer<'_> {
+    fn name(&self) -> &str {
+        "audio"
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        if !card.has_field(&self.audio_field) {
+            return TransformOutcome::Skipped(format!("no {} field", self.audio_field));
+        }
+        let text = match card.field(&self.text_field).map(str::trim) {
+            Some(text) if !text.is_empty() => text.to_string(),
+            _ => return TransformOutcome::Skipped(format!("no {} value", self.text_field)),
+        };
+
+        let filename = audio_filename(self.backend, &text);
+        let reference = audio_reference(&filename);
+        if card.field(&self.audio_field) == Some(reference.as_str()) {
+            return TransformOutcome::Skipped("unchanged".to_string());
+        }
+        card.set_field(&self.audio_field, &reference);
+        let audio = PendingAudio {
+            card_id: card.card.id.clone(),
+            filename,
+            text,
+        };
+        let mut pending = self.pending.lock().unwrap();
+        if !pending.contains(&audio) {
+            pending.push(audio);
+        }
+        TransformOutcome::Changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+    use crate::pipeline::Pipeline;
+
+    #[test]
+    fn test_audio_transformer() {
+        let template = template_fixture("vocab", "", &[("name", "Word"), ("audio", "Audio")]);
+        let card = CardFixture::new("c1", "deck")
+            .template("vocab")
+            .field("name", "箸")
+            .build();
+
+        let backend = Espeak::default();
+        let audio = AudioTransformer::new(&backend, "Word", "Audio");
+        let run = Pipeline::new()
+            .with(&audio)
+            .apply(&[card], std::slice::from_ref(&template));
+        let filename = audio_filename(&backend, "箸");
+        assert!(filename.starts_with("tts-") && filename.ends_with(".wav"));
+        assert_eq!(
+            run.patches[0].1.fields["audio"].value,
+            format!("![](@media/{})", filename)
+        );
+        assert_eq!(audio.pending()[0].text, "箸");
+
+        // Cards that have their audio are left alone.
+        let run = Pipeline::new().with(&audio).apply(&run.cards, &[template]);
+        assert!(run.patches.is_empty());
+        assert_eq!(audio.pending().len(), 1);
+    }
+
+    struct Fixed(Option<&'static [u8]>);
+
+    impl SpeechBackend for Fixed {
+        fn name(&self) -> String {
+            "fixed".to_string()
+        }
+
+        fn extension(&self) -> &str {
+            "mp3"
+        }
+
+        fn synthesize<'a>(
+            &'a self,
+            _text: &'a str,
+        ) -> LocalBoxFuture<'a, Result<Vec<u8>, Box<dyn Error>>> {
+            Box::pin(async move { Ok(self.0.ok_or("no audio")?.to_vec()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback_and_cache() {
+        let native = Fixed(None);
+        let synthesized = Fixed(Some(b"synthesized"));
+        let fallback = Fallback {
+            primary: &native,
+            fallback: &synthesized,
+        };
+        assert_eq!(fallback.synthesize("箸").await.unwrap(), b"synthesized");
+
+        let dir = std::env::temp_dir().join("mochi-audio-test");
+        let _ = fs::remove_dir_all(&dir);
+        let cached = Cached {
+            backend: &synthesized,
+            dir: dir.clone(),
+        };
+        cached.synthesize("箸").await.unwrap();
+        let missing = Cached {
+            backend: &Fixed(None),
+            dir,
+        };
+        // Same name, so the file written for the other backend is found.
+        assert_eq!(missing.synthesize("箸").await.unwrap(), b"synthesized");
+    }
+}
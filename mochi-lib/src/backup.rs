@@ -0,0 +1,555 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::{
+    Card, CardField, CardId, CardPatch, Deck, DeckId, FieldId, Template, TemplateId,
+};
+use crate::references::remap_references;
+use crate::{
+    add_attachment, create_card, create_deck, create_template, get_attachment, list,
+    list_all_cards, list_decks, list_templates, unix_now, update_card_fields, update_deck, Config,
+    MochiError,
+};
+
+// Account Backup
+//
+// Decks, templates and cards are kept as the API returns them, so nothing the
+// typed models don't know about is lost. A backup directory holds:
+//
+//   manifest.json          version, time and card count per deck
+//   decks.json             every deck
+//   templates.json         every template
+//   cards/<deck-id>.ndjson one card per line
+//   attachments/<card-id>/<filename>
+//
+// The API can't count a deck's cards without listing them, so every backup
+// lists the whole account. Incremental backups then only rewrite the decks
+// whose card counts changed and only download their attachments.
+//
+// Restoring recreates what's missing and remaps the ids in everything that
+// refers to it: parent decks, templates, field ids and `@[[card-id]]` links.
+
+pub const BACKUP_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub version: u32,
+    // Seconds since the unix epoch.
+    pub created: u64,
+    // Card count per deck.
+    pub decks: BTreeMap<DeckId, usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BackupOptions {
+    pub attachments: bool,
+    // Keeps the files of decks whose card counts match the last backup in
+    // the directory.
+    pub incremental: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct BackupSummary {
+    pub decks: usize,
+    pub templates: usize,
+    pub cards: usize,
+    // Decks whose cards were (re)written.
+    pub written: Vec<DeckId>,
+    pub attachments: usize,
+    // Attachments that could not be downloaded, with the reason.
+    pub failed_attachments: Vec<(CardId, String, String)>,
+}
+
+pub fn cards_path(dir: &Path, deck_id: &DeckId) -> PathBuf {
+    dir.join("cards").join(format!("{}.ndjson", deck_id))
+}
+
+pub fn read_manifest(dir: &Path) -> io::Result<BackupManifest> {
+    let file = File::open(dir.join("manifest.json"))?;
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+pub fn read_cards(dir: &Path, deck_id: &DeckId) -> io::Result<Vec<Value>> {
+    let file = File::open(cards_path(dir, deck_id))?;
+    BufReader::new(file)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+// The filenames of a card's attachments, keyed by filename in the API.
+pub fn attachment_names(card: &Value) -> Vec<String> {
+    match card.get("attachments") {
+        Some(Value::Object(attachments)) => attachments.keys().cloned().collect(),
+        Some(Value::Array(attachments)) => attachments
+            .iter()
+            .filter_map(|a| a.as_str().or_else(|| a.get("file-name")?.as_str()))
+            .map(String::from)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn write_json(path: &Path, value: &impl Serialize) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut out, value)?;
+    out.write_all(b"\n")?;
+    out.flush()
+}
+
+// Writes the listings into the directory and returns the decks whose cards
+// were written. `previous` is the manifest already in the directory, if any.
+pub fn write_backup(
+    dir: &Path,
+    decks: &[Value],
+    templates: &[Value],
+    cards: &[Value],
+    previous: Option<&BackupManifest>,
+) -> io::Result<(BackupManifest, Vec<DeckId>)> {
+    fs::create_dir_all(dir.join("cards"))?;
+    write_json(&dir.join("decks.json"), &decks)?;
+    write_json(&dir.join("templates.json"), &templates)?;
+
+    let mut by_deck: BTreeMap<DeckId, Vec<&Value>> = BTreeMap::new();
+    for deck_id in decks.iter().filter_map(|d| d.get("id")?.as_str()) {
+        by_deck.entry(DeckId::from(deck_id)).or_default();
+    }
+    for card in cards {
+        let deck_id = card.get("deck-id").and_then(Value::as_str).unwrap_or("");
+        by_deck.entry(DeckId::from(deck_id)).or_default().push(card);
+    }
+
+    let mut written = vec![];
+    for (deck_id, cards) in by_deck.iter() {
+        let path = cards_path(dir, deck_id);
+        let unchanged = previous.is_some_and(|p| p.decks.get(deck_id) == Some(&cards.len()));
+        if unchanged && path.exists() {
+            continue;
+        }
+        let mut out = BufWriter::new(File::create(&path)?);
+        for card in cards {
+            serde_json::to_writer(&mut out, card)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+        written.push(deck_id.clone());
+    }
+    // Decks deleted since the last backup.
+    for entry in fs::read_dir(dir.join("cards"))? {
+        let path = entry?.path();
+        let deck_id = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        if !by_deck.contains_key(&DeckId::from(deck_id)) {
+            fs::remove_file(path)?;
+        }
+    }
+
+    let manifest = BackupManifest {
+        version: BACKUP_VERSION,
+        created: unix_now(),
+        decks: by_deck
+            .iter()
+            .map(|(id, cards)| (id.clone(), cards.len()))
+            .collect(),
+    };
+    write_json(&dir.join("manifest.json"), &manifest)?;
+    Ok((manifest, written))
+}
+
+pub async fn backup_account(
+    config: &Config,
+    dir: &Path,
+    options: &BackupOptions,
+) -> Result<BackupSummary, Box<dyn Error>> {
+    let previous = if options.incremental {
+        read_manifest(dir)
+            .ok()
+            .filter(|m| m.version == BACKUP_VERSION)
+    } else {
+        None
+    };
+
+    let decks: Box<[Value]> = list("decks".to_string(), &Default::default(), config, None).await?;
+    let templates: Box<[Value]> =
+        list("templates".to_string(), &Default::default(), config, None).await?;
+    let args = [("limit".to_string(), serde_json::to_value(100)?)].into();
+    let cards: Box<[Value]> = list("cards".to_string(), &args, config, None).await?;
+
+    let (_, written) = write_backup(dir, &decks, &templates, &cards, previous.as_ref())?;
+    let mut summary = BackupSummary {
+        decks: decks.len(),
+        templates: templates.len(),
+        cards: cards.len(),
+        ..BackupSummary::default()
+    };
+
+    if options.attachments {
+        let changed = cards.iter().filter(|card| {
+            let deck_id = card.get("deck-id").and_then(Value::as_str).unwrap_or("");
+            written.contains(&DeckId::from(deck_id))
+        });
+        for card in changed {
+            let Some(card_id) = card.get("id").and_then(Value::as_str) else {
+                continue;
+            };
+            let card_id = CardId::from(card_id);
+            // Names come from the API; don't let one escape the directory.
+            let filenames = attachment_names(card)
+                .into_iter()
+                .filter(|f| !f.contains(['/', '\\']) && f != "..");
+            for filename in filenames {
+                let path = dir
+                    .join("attachments")
+                    .join(card_id.as_str())
+                    .join(&filename);
+                if options.incremental && path.exists() {
+                    continue;
+                }
+                match get_attachment(config, &card_id, &filename).await {
+                    Ok(bytes) => {
+                        fs::create_dir_all(path.parent().unwrap())?;
+                        fs::write(&path, bytes)?;
+                        summary.attachments += 1;
+                    }
+                    Err(err) => summary.failed_attachments.push((
+                        card_id.clone(),
+                        filename,
+                        err.to_string(),
+                    )),
+                }
+            }
+        }
+    }
+
+    summary.written = written;
+    Ok(summary)
+}
+
+// Restore
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestoreTarget {
+    // Recreate everything as new decks, templates and cards next to what's
+    // in the account.
+    #[default]
+    NewDecks,
+    // Update the decks and cards that still exist in place and recreate the
+    // rest. Templates that still exist are kept as they are.
+    Overwrite,
+}
+
+// Backup ids to account ids. Objects restored in place map to themselves.
+#[derive(Debug, Clone, Default)]
+pub struct IdMap {
+    pub decks: HashMap<DeckId, DeckId>,
+    pub templates: HashMap<TemplateId, TemplateId>,
+    // Per backup template, by field name.
+    pub fields: HashMap<TemplateId, HashMap<FieldId, FieldId>>,
+    pub cards: HashMap<CardId, CardId>,
+}
+
+#[derive(Debug, Default)]
+pub struct RestoreSummary {
+    pub ids: IdMap,
+    pub decks_created: usize,
+    pub templates_created: usize,
+    pub cards_created: usize,
+    pub cards_updated: usize,
+    pub attachments: usize,
+    pub failed: Vec<(CardId, MochiError)>,
+}
+
+// Every file of the backup, parsed.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub manifest: BackupManifest,
+    pub decks: Vec<Deck>,
+    pub templates: Vec<Template>,
+    pub cards: Vec<Card>,
+}
+
+pub fn read_backup(dir: &Path) -> Result<Backup, Box<dyn Error>> {
+    let manifest = read_manifest(dir)?;
+    if manifest.version > BACKUP_VERSION {
+        return Err(format!("backup version {} is not supported", manifest.version).into());
+    }
+    let decks = serde_json::from_reader(BufReader::new(File::open(dir.join("decks.json"))?))?;
+    let templates =
+        serde_json::from_reader(BufReader::new(File::open(dir.join("templates.json"))?))?;
+    let mut cards = vec![];
+    for deck_id in manifest.decks.keys() {
+        for card in read_cards(dir, deck_id)? {
+            cards.push(serde_json::from_value(card)?);
+        }
+    }
+    Ok(Backup {
+        manifest,
+        decks,
+        templates,
+        cards,
+    })
+}
+
+// Parents before their children, so parent ids can be remapped as the decks
+// are created.
+pub fn deck_creation_order(decks: &[Deck]) -> Vec<&Deck> {
+    let mut ordered: Vec<&Deck> = vec![];
+    let mut remaining = decks.iter().collect::<Vec<_>>();
+    while !remaining.is_empty() {
+        let (ready, rest): (Vec<_>, Vec<_>) = remaining.into_iter().partition(|deck| {
+            deck.parent_id.as_ref().is_none_or(|parent| {
+                ordered.iter().any(|d| d.id == *parent) || !decks.iter().any(|d| d.id == *parent)
+            })
+        });
+        // A parent cycle; the rest go in as they are.
+        if ready.is_empty() {
+            ordered.extend(rest);
+            break;
+        }
+        ordered.extend(ready);
+        remaining = rest;
+    }
+    ordered
+}
+
+// Field ids of the backup template to those of the account template with the
+// same field names.
+pub fn field_map(old: &Template, new: &Template) -> HashMap<FieldId, FieldId> {
+    old.fields
+        .iter()
+        .flat_map(|f| f.values())
+        .filter_map(|field| Some((field.id.clone(), new.field_by_name(&field.name)?.id.clone())))
+        .collect()
+}
+
+// The backup card with its deck, template and fields remapped. References to
+// other cards are remapped once every card has an id.
+pub fn remap_card(card: &Card, ids: &IdMap) -> Card {
+    let mut card = card.clone();
+    if let Some(deck_id) = ids.decks.get(&card.deck_id) {
+        card.deck_id = deck_id.clone();
+    }
+    if let Some(template_id) = card.template_id.clone() {
+        if let Some(fields) = ids.fields.get(&template_id) {
+            card.fields = card.fields.map(|values| {
+                values
+                    .into_values()
+                    .filter_map(|field| {
+                        let id = fields.get(&field.id)?.clone();
+                        Some((id.clone(), CardField { id, ..field }))
+                    })
+                    .collect()
+            });
+        }
+        card.template_id = ids
+            .templates
+            .get(&template_id)
+            .cloned()
+            .or(Some(template_id));
+    }
+    card
+}
+
+fn full_patch(card: &Card) -> CardPatch {
+    CardPatch {
+        content: Some(card.content.clone()),
+        deck_id: Some(card.deck_id.clone()),
+        template_id: card.template_id.clone(),
+        fields: card.fields.clone().unwrap_or_default(),
+        archived: Some(card.archived),
+        review_reverse: Some(card.review_reverse),
+        manual_tags: card.manual_tags.clone(),
+        trashed: None,
+    }
+}
+
+pub async fn restore_account(
+    config: &Config,
+    dir: &Path,
+    target: RestoreTarget,
+) -> Result<RestoreSummary, Box<dyn Error>> {
+    let backup = read_backup(dir)?;
+    let mut summary = RestoreSummary::default();
+    let overwrite = target == RestoreTarget::Overwrite;
+
+    let current_templates = list_templates(config).await?;
+    for template in backup.templates.iter() {
+        let existing = current_templates.iter().find(|t| t.id == template.id);
+        let restored = match existing {
+            Some(existing) if overwrite => existing.clone(),
+            _ => {
+                summary.templates_created += 1;
+                create_template(config, template).await?
+            }
+        };
+        let ids = &mut summary.ids;
+        ids.fields
+            .insert(template.id.clone(), field_map(template, &restored));
+        ids.templates.insert(template.id.clone(), restored.id);
+    }
+
+    let current_decks = list_decks(config).await?;
+    for deck in deck_creation_order(&backup.decks) {
+        let mut restored = deck.clone();
+        restored.parent_id = deck
+            .parent_id
+            .as_ref()
+            .map(|p| summary.ids.decks.get(p).cloned().unwrap_or(p.clone()));
+        restored.template_id = deck
+            .template_id
+            .as_ref()
+            .map(|t| summary.ids.templates.get(t).cloned().unwrap_or(t.clone()));
+        let restored = if overwrite && current_decks.iter().any(|d| d.id == deck.id) {
+            update_deck(config, &restored).await?
+        } else {
+            summary.decks_created += 1;
+            create_deck(config, &restored).await?
+        };
+        summary.ids.decks.insert(deck.id.clone(), restored.id);
+    }
+
+    let current_cards = if overwrite {
+        list_all_cards(config).await?
+    } else {
+        Box::new([])
+    };
+    let mut restored_cards = vec![];
+    // Trashed cards stay in the backup only.
+    for card in backup.cards.iter().filter(|c| c.trashed.is_none()) {
+        let remapped = remap_card(card, &summary.ids);
+        let result = if current_cards.iter().any(|c| c.card.id == card.id) {
+            summary.cards_updated += 1;
+            update_card_fields(config, &card.id, &full_patch(&remapped)).await
+        } else {
+            summary.cards_created += 1;
+            create_card(config, &remapped).await
+        };
+        match result {
+            Ok(restored) => {
+                summary
+                    .ids
+                    .cards
+                    .insert(card.id.clone(), restored.id.clone());
+                restored_cards.push((card, restored.id));
+            }
+            Err(err) => summary.failed.push((card.id.clone(), err)),
+        }
+    }
+
+    // Links between restored cards, now that they all have ids.
+    for (card, id) in restored_cards.iter() {
+        let remapped = remap_card(card, &summary.ids);
+        let mut patch = CardPatch::new();
+        let content = remap_references(&remapped.content, &summary.ids.cards);
+        if content != remapped.content {
+            patch = patch.content(&content);
+        }
+        for field in remapped.fields.iter().flat_map(|f| f.values()) {
+            let value = remap_references(&field.value, &summary.ids.cards);
+            if value != field.value {
+                patch = patch.field(field.id.clone(), &value);
+            }
+        }
+        if !patch.is_empty() {
+            if let Err(err) = update_card_fields(config, id, &patch).await {
+                summary.failed.push((card.id.clone(), err));
+            }
+        }
+    }
+
+    for (card, id) in restored_cards.iter() {
+        let card_dir = dir.join("attachments").join(card.id.as_str());
+        let Ok(entries) = fs::read_dir(&card_dir) else {
+            continue;
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let filename = path.file_name().and_then(|f| f.to_str()).unwrap_or("");
+            match add_attachment(config, id, filename, fs::read(&path)?).await {
+                Ok(()) => summary.attachments += 1,
+                Err(err) => summary.failed.push((card.id.clone(), err)),
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+    use crate::models::TemplateField;
+    use serde_json::json;
+
+    #[test]
+    fn test_write_backup() {
+        let dir = std::env::temp_dir().join(format!("mochi-backup-{}", std::process::id()));
+        let decks = [
+            json!({ "id": "n5", "name": "N5" }),
+            json!({ "id": "n4", "name": "N4" }),
+        ];
+        let card = |id: &str, deck: &str| json!({ "id": id, "deck-id": deck, "content": id });
+        let mut cards = vec![card("c1", "n5"), card("c2", "n4")];
+
+        let (manifest, written) = write_backup(&dir, &decks, &[], &cards, None).unwrap();
+        assert_eq!(written, [DeckId::from("n4"), DeckId::from("n5")]);
+        assert_eq!(read_manifest(&dir).unwrap(), manifest);
+        assert_eq!(
+            read_cards(&dir, &DeckId::from("n5")).unwrap(),
+            [card("c1", "n5")]
+        );
+
+        cards.push(card("c3", "n5"));
+        let (_, written) = write_backup(&dir, &decks, &[], &cards, Some(&manifest)).unwrap();
+        assert_eq!(written, [DeckId::from("n5")]);
+        assert_eq!(read_cards(&dir, &DeckId::from("n5")).unwrap().len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_remap() {
+        let decks: Vec<Deck> = serde_json::from_value(json!([
+            { "id": "kanji", "name": "Kanji", "parent-id": "jp" },
+            { "id": "jp", "name": "Japanese", "parent-id": "languages" },
+        ]))
+        .unwrap();
+        let order = deck_creation_order(&decks);
+        assert_eq!(order[0].id, DeckId::from("jp"));
+
+        let old = template_fixture("old", "", &[("a", "Word")]);
+        let new = Template {
+            id: TemplateId::from("new"),
+            fields: Some(HashMap::from([(
+                FieldId::from("b"),
+                TemplateField {
+                    id: FieldId::from("b"),
+                    ..old.fields.as_ref().unwrap()[&FieldId::from("a")].clone()
+                },
+            )])),
+            ..old.clone()
+        };
+        let ids = IdMap {
+            decks: HashMap::from([(DeckId::from("jp"), DeckId::from("jp2"))]),
+            templates: HashMap::from([(TemplateId::from("old"), TemplateId::from("new"))]),
+            fields: HashMap::from([(TemplateId::from("old"), field_map(&old, &new))]),
+            cards: HashMap::new(),
+        };
+        let card = CardFixture::new("c1", "jp")
+            .template("old")
+            .field("a", "箸")
+            .build();
+
+        let card = remap_card(&card, &ids);
+        assert_eq!(card.deck_id, DeckId::from("jp2"));
+        assert_eq!(card.template_id, Some(TemplateId::from("new")));
+        assert_eq!(card.field_by_name(&new, "Word").unwrap().value, "箸");
+    }
+}
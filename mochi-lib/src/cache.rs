@@ -0,0 +1,459 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::stream::{self, StreamExt};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::models::{Card, CardId, Deck, DeckId, Template};
+use crate::search::{SearchIndex, SearchQuery};
+use crate::{card_args, list, unix_secs, Config};
+
+// Local Cache
+//
+// Decks, templates and cards in a SQLite file, kept as the API returns them
+// like backups are. The API has no "changed since" filter and can't count a
+// deck's cards without listing them, so a refresh always lists the decks and
+// templates (a page or two) but only re-lists the cards of decks that are new,
+// were marked stale or were listed longer than `max_age` ago. Scripts that
+// change cards mark their decks stale so the next refresh picks them up; the
+// CLI, the daemon and offline replay all do. Edits made in the Mochi app mark
+// nothing, so every refresh takes a `max_age` after which a deck is listed
+// again anyway.
+
+// A day, the daemon's default.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS decks (id TEXT PRIMARY KEY, json TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS templates (id TEXT PRIMARY KEY, json TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS cards (
+        id TEXT PRIMARY KEY,
+        deck_id TEXT NOT NULL,
+        json TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS cards_deck ON cards (deck_id);
+    CREATE TABLE IF NOT EXISTS listings (
+        deck_id TEXT PRIMARY KEY,
+        -- Seconds since the unix epoch.
+        listed_at INTEGER NOT NULL,
+        stale INTEGER NOT NULL DEFAULT 0
+    );
+";
+
+pub struct Cache {
+    pub(crate) db: Connection,
+}
+
+fn id_of(object: &Value) -> &str {
+    object.get("id").and_then(Value::as_str).unwrap_or("")
+}
+
+// The card as the API returns it, with the retrieval only values that
+// serializing a `Card` leaves out.
+pub(crate) fn card_json(card: &Card) -> Value {
+    let mut json = serde_json::to_value(card).unwrap_or_default();
+    if let Value::Object(object) = &mut json {
+        object.insert("id".to_string(), Value::from(card.id.as_str()));
+        object.insert("tags".to_string(), Value::from(card.tags.clone()));
+        object.insert(
+            "references".to_string(),
+            Value::from(card.references.clone()),
+        );
+        if let Some(attachments) = &card.attachments {
+            object.insert("attachments".to_string(), attachments.clone());
+        }
+        if let Some(trashed) = &card.trashed {
+            object.insert("trashed?".to_string(), trashed.clone());
+        }
+        if let Some(name) = &card.name {
+            object.insert("name".to_string(), Value::from(name.as_str()));
+        }
+        for (key, timestamp) in [
+            ("created-at", &card.created_at),
+            ("updated-at", &card.updated_at),
+        ] {
+            if let Some(timestamp) = timestamp {
+                let timestamp = serde_json::to_value(timestamp).unwrap_or_default();
+                object.insert(key.to_string(), timestamp);
+            }
+        }
+        object.insert("new?".to_string(), Value::from(card.new));
+        object.insert(
+            "reviews".to_string(),
+            serde_json::to_value(&card.reviews).unwrap_or_default(),
+        );
+    }
+    json
+}
+
+impl Cache {
+    pub fn open(path: &Path) -> Result<Cache, Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let db = Connection::open(path)?;
+        db.execute_batch(SCHEMA)?;
+        Ok(Cache { db })
+    }
+
+    pub fn open_in_memory() -> Result<Cache, Box<dyn Error>> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(SCHEMA)?;
+        Ok(Cache { db })
+    }
+
+    // `cache.db` in the user's cache directory.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("mochi-utils").join("cache.db"))
+    }
+
+    fn objects<T>(&self, sql: &str, args: &[&dyn rusqlite::ToSql]) -> Result<Vec<T>, Box<dyn Error>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let mut query = self.db.prepare(sql)?;
+        let rows = query.query_map(args, |row| row.get::<_, String>(0))?;
+        let mut objects = vec![];
+        for json in rows {
+            objects.push(serde_json::from_str(&json?)?);
+        }
+        Ok(objects)
+    }
+
+    pub fn decks(&self) -> Result<Vec<Deck>, Box<dyn Error>> {
+        self.objects("SELECT json FROM decks ORDER BY rowid", &[])
+    }
+
+    pub fn templates(&self) -> Result<Vec<Template>, Box<dyn Error>> {
+        self.objects("SELECT json FROM templates ORDER BY rowid", &[])
+    }
+
+    pub fn cards(&self, deck_id: &DeckId) -> Result<Vec<Card>, Box<dyn Error>> {
+        self.objects(
+            "SELECT json FROM cards WHERE deck_id = ? ORDER BY rowid",
+            &[&deck_id.to_string()],
+        )
+    }
+
+    pub fn all_cards(&self) -> Result<Vec<Card>, Box<dyn Error>> {
+        self.objects("SELECT json FROM cards ORDER BY rowid", &[])
+    }
+
+    pub fn card(&self, card_id: &CardId) -> Result<Option<Card>, Box<dyn Error>> {
+        let cards = self.objects(
+            "SELECT json FROM cards WHERE id = ?",
+            &[&card_id.to_string()],
+        )?;
+        Ok(cards.into_iter().next())
+    }
+
+    // Adds or replaces a single card, e.g. one just created or updated,
+    // without counting as a listing of its deck.
+    pub fn put_card(&self, card: &Card) -> Result<(), Box<dyn Error>> {
+        self.db.execute(
+            "INSERT OR REPLACE INTO cards (id, deck_id, json) VALUES (?, ?, ?)",
+            params![
+                card.id.to_string(),
+                card.deck_id.to_string(),
+                card_json(card).to_string()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_card(&self, card_id: &CardId) -> Result<(), Box<dyn Error>> {
+        self.db
+            .execute("DELETE FROM cards WHERE id = ?", [card_id.to_string()])?;
+        Ok(())
+    }
+
+    // Replaces every deck.
+    pub fn store_decks(&mut self, decks: &[Value]) -> Result<(), Box<dyn Error>> {
+        self.replace_all("decks", decks)
+    }
+
+    // Replaces every template.
+    pub fn store_templates(&mut self, templates: &[Value]) -> Result<(), Box<dyn Error>> {
+        self.replace_all("templates", templates)
+    }
+
+    fn replace_all(&mut self, table: &str, objects: &[Value]) -> Result<(), Box<dyn Error>> {
+        let tx = self.db.transaction()?;
+        tx.execute(&format!("DELETE FROM {}", table), [])?;
+        for object in objects {
+            tx.execute(
+                &format!("INSERT OR REPLACE INTO {} (id, json) VALUES (?, ?)", table),
+                params![id_of(object), object.to_string()],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Replaces the deck's cards with a fresh listing of it.
+    pub fn store_cards(
+        &mut self,
+        deck_id: &DeckId,
+        cards: &[Value],
+        listed_at: SystemTime,
+    ) -> Result<(), Box<dyn Error>> {
+        let deck_id = deck_id.to_string();
+        let tx = self.db.transaction()?;
+        tx.execute("DELETE FROM cards WHERE deck_id = ?", [&deck_id])?;
+        for card in cards {
+            tx.execute(
+                "INSERT OR REPLACE INTO cards (id, deck_id, json) VALUES (?, ?, ?)",
+                params![id_of(card), deck_id, card.to_string()],
+            )?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO listings (deck_id, listed_at, stale) VALUES (?, ?, 0)",
+            params![deck_id, unix_secs(listed_at) as i64],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // The deck's cards are re-listed on the next refresh.
+    pub fn mark_stale(&self, deck_id: &DeckId) -> Result<(), Box<dyn Error>> {
+        self.db.execute(
+            "UPDATE listings SET stale = 1 WHERE deck_id = ?",
+            [deck_id.to_string()],
+        )?;
+        Ok(())
+    }
+
+    // When the deck's cards were last listed, if they ever were.
+    pub fn listed_at(&self, deck_id: &DeckId) -> Result<Option<SystemTime>, Box<dyn Error>> {
+        let secs: Option<i64> = self
+            .db
+            .query_row(
+                "SELECT listed_at FROM listings WHERE deck_id = ?",
+                [deck_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(secs.map(|s| UNIX_EPOCH + Duration::from_secs(s.max(0) as u64)))
+    }
+
+    // The decks whose cards need listing: never listed, marked stale or, with
+    // a `max_age`, listed at least that long ago. Listings are kept to the
+    // second, so a zero `max_age` lists every deck.
+    pub fn decks_to_refresh(
+        &self,
+        deck_ids: &[DeckId],
+        max_age: Option<Duration>,
+        now: SystemTime,
+    ) -> Result<Vec<DeckId>, Box<dyn Error>> {
+        let oldest = max_age.map(|age| unix_secs(now) as i64 - age.as_secs() as i64);
+        let mut refresh = vec![];
+        for deck_id in deck_ids {
+            let listing: Option<(i64, bool)> = self
+                .db
+                .query_row(
+                    "SELECT listed_at, stale FROM listings WHERE deck_id = ?",
+                    [deck_id.to_string()],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            let needed = match listing {
+                None => true,
+                Some((_, true)) => true,
+                Some((listed_at, false)) => oldest.is_some_and(|oldest| listed_at <= oldest),
+            };
+            if needed {
+                refresh.push(deck_id.clone());
+            }
+        }
+        Ok(refresh)
+    }
+
+    // Drops the cards of every deck not in `deck_ids` (e.g. deleted decks),
+    // returning those decks.
+    pub fn retain_decks(&mut self, deck_ids: &[DeckId]) -> Result<Vec<DeckId>, Box<dyn Error>> {
+        let keep = deck_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<HashSet<_>>();
+        let cached = {
+            let mut query = self
+                .db
+                .prepare("SELECT deck_id FROM listings UNION SELECT deck_id FROM cards")?;
+            let rows = query.query_map([], |row| row.get::<_, String>(0))?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+        let tx = self.db.transaction()?;
+        let mut removed = vec![];
+        for deck_id in cached.into_iter().filter(|id| !keep.contains(id)) {
+            tx.execute("DELETE FROM cards WHERE deck_id = ?", [&deck_id])?;
+            tx.execute("DELETE FROM listings WHERE deck_id = ?", [&deck_id])?;
+            removed.push(DeckId::from(deck_id.as_str()));
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+}
+
+impl SearchIndex for Cache {
+    fn candidates(&self, query: &SearchQuery) -> Option<Vec<Card>> {
+        if query.decks.is_empty() {
+            return self.all_cards().ok();
+        }
+        let mut cards = vec![];
+        for deck_id in query.decks.iter() {
+            // Never listed, so an empty result would be a guess.
+            self.listed_at(deck_id).ok()??;
+            cards.extend(self.cards(deck_id).ok()?);
+        }
+        Some(cards)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RefreshSummary {
+    pub decks: usize,
+    pub templates: usize,
+    // Decks whose cards were listed again.
+    pub refreshed: Vec<DeckId>,
+    // Decks no longer in the account.
+    pub removed: Vec<DeckId>,
+    // Cards downloaded.
+    pub cards: usize,
+}
+
+// Brings the cache up to date with the account, listing only the cards of
+// the decks `decks_to_refresh` picks.
+pub async fn refresh(
+    config: &Config,
+    cache: &mut Cache,
+    max_age: Duration,
+) -> Result<RefreshSummary, Box<dyn Error>> {
+    let decks: Box<[Value]> = list("decks".to_string(), &Default::default(), config, None).await?;
+    let templates: Box<[Value]> =
+        list("templates".to_string(), &Default::default(), config, None).await?;
+    cache.store_decks(&decks)?;
+    cache.store_templates(&templates)?;
+
+    let deck_ids = decks
+        .iter()
+        .map(|d| DeckId::from(id_of(d)))
+        .collect::<Vec<_>>();
+    let mut summary = RefreshSummary {
+        decks: decks.len(),
+        templates: templates.len(),
+        removed: cache.retain_decks(&deck_ids)?,
+        ..RefreshSummary::default()
+    };
+
+    let stale = cache.decks_to_refresh(&deck_ids, Some(max_age), SystemTime::now())?;
+    let mut listings = stream::iter(stale.iter())
+        .map(|deck_id| async move {
+            let listed_at = SystemTime::now();
+            let args = card_args(deck_id, None);
+            let cards: Result<Box<[Value]>, _> =
+                list("cards".to_string(), &args, config, None).await;
+            (deck_id, listed_at, cards)
+        })
+        .buffer_unordered(config.listing_concurrency);
+    while let Some((deck_id, listed_at, cards)) = listings.next().await {
+        let cards = cards?;
+        cache.store_cards(deck_id, &cards, listed_at)?;
+        summary.cards += cards.len();
+        summary.refreshed.push(deck_id.clone());
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{self, CardFixture};
+    use crate::models::CardPatch;
+    use crate::patch_cards;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_refresh_after_changes() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        let mut cache = Cache::open_in_memory().unwrap();
+        let n3 = DeckId::from("MK5LCEAL");
+
+        let summary = refresh(&config, &mut cache, DEFAULT_MAX_AGE).await.unwrap();
+        assert!(summary.refreshed.contains(&n3));
+        let listed = cache.cards(&n3).unwrap().len();
+
+        // Changed on the server, e.g. in the app: unseen until stale or old.
+        server.add_card(json!({
+            "id": "added",
+            "content": "新しい",
+            "deck-id": "MK5LCEAL",
+        }));
+        let summary = refresh(&config, &mut cache, DEFAULT_MAX_AGE).await.unwrap();
+        assert!(summary.refreshed.is_empty());
+        assert_eq!(cache.cards(&n3).unwrap().len(), listed);
+
+        let summary = refresh(&config, &mut cache, Duration::ZERO).await.unwrap();
+        assert!(summary.refreshed.contains(&n3));
+        assert_eq!(cache.cards(&n3).unwrap().len(), listed + 1);
+
+        // Changed by this tool, which marks the deck stale.
+        let patch = CardPatch::new().content("新しい new");
+        let result = patch_cards(&config, &[(CardId::from("added"), patch)]).await;
+        assert!(result.is_success());
+        cache.mark_stale(&n3).unwrap();
+        let summary = refresh(&config, &mut cache, DEFAULT_MAX_AGE).await.unwrap();
+        assert_eq!(summary.refreshed, [n3]);
+        let added = cache.card(&CardId::from("added")).unwrap().unwrap();
+        assert_eq!(added.content, "新しい new");
+    }
+
+    #[test]
+    fn test_cache_refresh_plan() {
+        let mut cache = Cache::open_in_memory().unwrap();
+        cache
+            .store_decks(&[
+                json!({ "id": "n5", "name": "N5" }),
+                json!({ "id": "n4", "name": "N4" }),
+            ])
+            .unwrap();
+        let card = CardFixture::new("c1", "n5")
+            .content("犬")
+            .set("updated-at", json!({ "date": "2024-05-01T12:00:00Z" }))
+            .json();
+        let now = UNIX_EPOCH + Duration::from_secs(100_000);
+        let hour_ago = now - Duration::from_secs(3600);
+        cache
+            .store_cards(&DeckId::from("n5"), &[card], hour_ago)
+            .unwrap();
+        cache
+            .store_cards(&DeckId::from("gone"), &[], hour_ago)
+            .unwrap();
+
+        assert_eq!(cache.decks().unwrap()[1].name, "N4");
+        assert_eq!(cache.cards(&DeckId::from("n5")).unwrap()[0].content, "犬");
+        assert_eq!(
+            cache.listed_at(&DeckId::from("n5")).unwrap(),
+            Some(hour_ago)
+        );
+
+        let decks = [DeckId::from("n5"), DeckId::from("n4")];
+        let minute = Some(Duration::from_secs(60));
+        let day = Some(Duration::from_secs(86400));
+        assert_eq!(
+            cache.decks_to_refresh(&decks, day, now).unwrap(),
+            [DeckId::from("n4")]
+        );
+        assert_eq!(cache.decks_to_refresh(&decks, minute, now).unwrap(), decks);
+        cache.mark_stale(&DeckId::from("n5")).unwrap();
+        assert_eq!(cache.decks_to_refresh(&decks, None, now).unwrap(), decks);
+
+        assert_eq!(cache.retain_decks(&decks).unwrap(), [DeckId::from("gone")]);
+        assert_eq!(cache.all_cards().unwrap().len(), 1);
+    }
+}
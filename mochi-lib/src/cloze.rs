@@ -0,0 +1,158 @@
+use crate::models::ResolvedCard;
+use crate::pipeline::{CardTransformer, TransformOutcome};
+use crate::romaji::is_kana;
+
+// Cloze Deletions
+//
+// Writes the sentence into the card content with the card's words hidden as
+// Mochi clozes, `{{word}}`. With several clozes each can get its own number,
+// `{{1::word}}`, so Mochi makes one card per cloze instead of hiding them all
+// at once. Hints go after the word: `{{1::word::hint}}`.
+//
+// Conjugated words are found by their stem, so 食べる hides the 食べ of
+// 食べた.
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ClozeHint {
+    #[default]
+    None,
+    // The value of another field, e.g. the reading or the meaning.
+    Field(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct ClozeTransformer {
+    pub sentence_field: String,
+    // One or more words, separated by `,` or `、`.
+    pub word_field: String,
+    pub hint: ClozeHint,
+    // Numbers each cloze so each becomes its own card.
+    pub separate_cards: bool,
+}
+
+impl ClozeTransformer {
+    pub fn new(sentence_field: &str, word_field: &str) -> ClozeTransformer {
+        ClozeTransformer {
+            sentence_field: sentence_field.to_string(),
+            word_field: word_field.to_string(),
+            hint: ClozeHint::default(),
+            separate_cards: false,
+        }
+    }
+}
+
+// The word itself, or for conjugated words the stem before the okurigana.
+fn find_word(sentence: &str, word: &str) -> Option<(usize, usize)> {
+    if let Some(start) = sentence.find(word) {
+        return Some((start, start + word.len()));
+    }
+    // Only words written with kanji and okurigana conjugate this way.
+    let kanji = word.trim_end_matches(is_kana);
+    if kanji.is_empty() || kanji == word {
+        return None;
+    }
+    // Keep okurigana that doesn't conjugate, e.g. the べ of 食べる.
+    let (last, _) = word.char_indices().last()?;
+    let stem = &word[..last.max(kanji.len())];
+    let start = sentence.find(stem)?;
+    Some((start, start + stem.len()))
+}
+
+// The sentence with every occurrence of the words hidden. None if none of the
+// words are in the sentence.
+pub fn cloze(sentence: &str, words: &[&str], hint: Option<&str>, numbered: bool) -> Option<String> {
+    // (start, end, cloze number), by position.
+    let mut spans = vec![];
+    for (i, word) in words.iter().enumerate() {
+        let mut offset = 0;
+        while let Some((start, end)) = find_word(&sentence[offset..], word) {
+            let (start, end) = (start + offset, end + offset);
+            if !spans.iter().any(|(s, e, _)| start < *e && *s < end) {
+                spans.push((start, end, i + 1));
+            }
+            offset = end;
+        }
+    }
+    if spans.is_empty() {
+        return None;
+    }
+    spans.sort();
+
+    let mut clozed = String::with_capacity(sentence.len() + spans.len() * 8);
+    let mut offset = 0;
+    for (start, end, number) in spans {
+        clozed.push_str(&sentence[offset..start]);
+        clozed.push_str("{{");
+        if numbered {
+            clozed.push_str(&format!("{}::", number));
+        }
+        clozed.push_str(&sentence[start..end]);
+        if let Some(hint) = hint {
+            clozed.push_str(&format!("::{}", hint));
+        }
+        clozed.push_str("}}");
+        offset = end;
+    }
+    clozed.push_str(&sentence[offset..]);
+    Some(clozed)
+}
+
+impl CardTransformer for ClozeTransformer {
+    fn name(&self) -> &str {
+        "cloze"
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        let sentence = match card.field(&self.sentence_field).map(str::trim) {
+            Some(sentence) if !sentence.is_empty() => sentence.to_string(),
+            _ => return TransformOutcome::Skipped(format!("no {} value", self.sentence_field)),
+        };
+        let words = card.field(&self.word_field).unwrap_or("").to_string();
+        let words = words
+            .split([',', '、'])
+            .map(str::trim)
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>();
+        if words.is_empty() {
+            return TransformOutcome::Skipped(format!("no {} value", self.word_field));
+        }
+        let hint = match &self.hint {
+            ClozeHint::None => None,
+            ClozeHint::Field(field) => card
+                .field(field)
+                .map(|h| h.trim().to_string())
+                .filter(|h| !h.is_empty()),
+        };
+
+        let Some(content) = cloze(&sentence, &words, hint.as_deref(), self.separate_cards) else {
+            return TransformOutcome::Skipped("word not in sentence".to_string());
+        };
+        if card.card.content == content {
+            return TransformOutcome::Skipped("unchanged".to_string());
+        }
+        card.card.content = content;
+        TransformOutcome::Changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cloze() {
+        assert_eq!(
+            cloze("箸で食べた。", &["箸"], None, false),
+            Some("{{箸}}で食べた。".to_string())
+        );
+        assert_eq!(
+            cloze("箸で食べた。", &["食べる"], Some("たべる"), false),
+            Some("箸で{{食べ::たべる}}た。".to_string())
+        );
+        assert_eq!(
+            cloze("猫が猫を見た。", &["猫", "見る"], None, true),
+            Some("{{1::猫}}が{{1::猫}}を{{2::見}}た。".to_string())
+        );
+        assert_eq!(cloze("箸で食べた。", &["犬"], None, false), None);
+    }
+}
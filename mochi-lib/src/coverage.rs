@@ -0,0 +1,391 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::models::{Card, CardBuilder, CardId, CardPatch, DeckId, ResolvedCard, Template};
+use crate::pipeline::{
+    CardTransformer, OverwritePolicy, Pipeline, PipelineRun, PitchAccentTransformer,
+    TransformOutcome,
+};
+use crate::sanitize::strip_tags;
+use crate::{
+    create_card, list_cards, list_templates, update_card_fields, AccentMap, ChangedCard, Config,
+};
+
+// Enrichment Coverage
+//
+// Summaries like "pitch: 93% covered, 41 missing", written into a stats card
+// inside the deck so the state of automated enrichment is visible in Mochi.
+// The stats card is found by the marker at the start of its content.
+
+pub const STATS_CARD_MARKER: &str = "<!-- mochi-utils:stats -->";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldCoverage {
+    pub label: String,
+    pub covered: usize,
+    pub total: usize,
+}
+
+impl FieldCoverage {
+    pub fn missing(&self) -> usize {
+        self.total - self.covered
+    }
+
+    pub fn percent(&self) -> usize {
+        if self.total == 0 {
+            return 100;
+        }
+        self.covered * 100 / self.total
+    }
+}
+
+impl fmt::Display for FieldCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}% covered, {} missing",
+            self.label,
+            self.percent(),
+            self.missing()
+        )
+    }
+}
+
+// Whether field HTML has no visible text, e.g. the empty wrapper div written
+// for words missing from the accent dictionary.
+pub fn is_blank_html(html: &str) -> bool {
+    strip_tags(html, "").replace("&nbsp;", "").trim().is_empty()
+}
+
+pub fn is_stats_card(card: &Card) -> bool {
+    card.content.starts_with(STATS_CARD_MARKER)
+}
+
+// Coverage of the named field over the cards whose template has that field.
+pub fn field_coverage(
+    cards: &[Card],
+    templates: &[Template],
+    field_name: &str,
+    label: &str,
+) -> FieldCoverage {
+    let mut coverage = FieldCoverage {
+        label: label.to_string(),
+        covered: 0,
+        total: 0,
+    };
+
+    let resolved = ResolvedCard::resolve(cards, templates);
+    for card in resolved.iter().filter(|c| !is_stats_card(&c.card)) {
+        if !card.has_field(field_name) {
+            continue;
+        }
+
+        coverage.total += 1;
+        if !is_blank_html(card.field(field_name).unwrap_or("")) {
+            coverage.covered += 1;
+        }
+    }
+
+    coverage
+}
+
+pub fn stats_content(coverages: &[FieldCoverage]) -> String {
+    let lines = coverages
+        .iter()
+        .map(|c| format!("- {}", c))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("{}\n# Enrichment coverage\n{}", STATS_CARD_MARKER, lines)
+}
+
+// Pitch Accent Coverage
+//
+// Which cards a pitch accent run could not fill in properly, so the deck or
+// the overrides can be fixed instead of finding blank fields later.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    // (card, word) with no dictionary entry.
+    pub missing: Vec<(CardId, String)>,
+    // (card, word, number of readings) for homographs.
+    pub ambiguous: Vec<(CardId, String, usize)>,
+    // (card, reason) for cards the transformer skipped.
+    pub skipped: Vec<(CardId, String)>,
+}
+
+impl CoverageReport {
+    pub fn from_run(
+        run: &PipelineRun,
+        templates: &[Template],
+        transformer: &PitchAccentTransformer,
+    ) -> CoverageReport {
+        let mut report = CoverageReport::default();
+        let resolved = ResolvedCard::resolve(&run.cards, templates);
+        for (card, outcome) in resolved.iter().zip(run.outcomes.iter()) {
+            let pitch_outcome = outcome
+                .outcomes
+                .iter()
+                .find(|(name, _)| name == transformer.name())
+                .map(|(_, o)| o);
+            match pitch_outcome {
+                Some(TransformOutcome::Skipped(reason)) if reason != "unchanged" => {
+                    report.skipped.push((card.card.id.clone(), reason.clone()));
+                    continue;
+                }
+                Some(TransformOutcome::Error(reason)) => {
+                    report.skipped.push((card.card.id.clone(), reason.clone()));
+                    continue;
+                }
+                None => continue,
+                _ => {}
+            }
+
+            let word = card
+                .field(&transformer.word_field)
+                .unwrap_or("")
+                .to_string();
+            match transformer.readings(card).len() {
+                0 => report.missing.push((card.card.id.clone(), word)),
+                1 => {}
+                readings => report
+                    .ambiguous
+                    .push((card.card.id.clone(), word, readings)),
+            }
+        }
+        report
+    }
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} missing, {} ambiguous, {} skipped",
+            self.missing.len(),
+            self.ambiguous.len(),
+            self.skipped.len()
+        )?;
+        for (card_id, word) in self.missing.iter() {
+            writeln!(f, "  missing {} ({})", word, card_id)?;
+        }
+        for (card_id, word, readings) in self.ambiguous.iter() {
+            writeln!(f, "  {} readings for {} ({})", readings, word, card_id)?;
+        }
+        for (card_id, reason) in self.skipped.iter() {
+            writeln!(f, "  skipped {}: {}", card_id, reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct PitchAccentResult {
+    pub changed: Box<[ChangedCard]>,
+    pub report: CoverageReport,
+}
+
+// Templates are passed in so a caller looping over decks lists them once.
+pub fn add_pitch_accent_to_cards(
+    cards: &[Card],
+    templates: &[Template],
+    word_field_name: &str,
+    reading_field_name: Option<&str>,
+    pitch_accent_field_name: &str,
+    overwrite: OverwritePolicy,
+) -> PitchAccentResult {
+    let mut transformer = PitchAccentTransformer::new(
+        AccentMap::global(),
+        word_field_name,
+        pitch_accent_field_name,
+    );
+    transformer.reading_field = reading_field_name.map(str::to_string);
+    transformer.overwrite = overwrite;
+    let run = Pipeline::new()
+        .with(transformer.clone())
+        .apply(cards, templates);
+
+    PitchAccentResult {
+        changed: run.changed_cards().into_boxed_slice(),
+        report: CoverageReport::from_run(&run, templates, &transformer),
+    }
+}
+
+// Compute the coverage of each (field name, label) pair over the deck and
+// write it into the deck's stats card, creating the card on the first run.
+pub async fn refresh_coverage_card(
+    config: &Config,
+    deck_id: &DeckId,
+    fields: &[(&str, &str)],
+) -> Result<Vec<FieldCoverage>, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    let templates = list_templates(config).await?;
+
+    let coverages = fields
+        .iter()
+        .map(|(name, label)| field_coverage(&cards, &templates, name, label))
+        .collect::<Vec<_>>();
+    let content = stats_content(&coverages);
+
+    match cards.iter().find(|c| is_stats_card(c)) {
+        Some(card) if card.content == content => {}
+        Some(card) => {
+            update_card_fields(config, &card.id, &CardPatch::new().content(&content)).await?;
+        }
+        None => {
+            let card = CardBuilder::new(deck_id).content(&content).build()?;
+            create_card(config, &card).await?;
+        }
+    }
+
+    Ok(coverages)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{self, template_fixture, CardFixture};
+    use crate::{list_decks, update_changed_cards, ProgressHook};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_field_coverage() {
+        let template = template_fixture("vocab", "", &[("pitch", "PitchAccent")]);
+        let card = |value: &str| {
+            CardFixture::new("card", "deck")
+                .template("vocab")
+                .field("pitch", value)
+                .build()
+        };
+        let cards = [
+            card("<div style=\"text-align: center\"><span>は</span></div>"),
+            card("<div style=\"text-align: center\"></div>"),
+            card("<span>は</span>"),
+            card("<span>し</span>"),
+            // Neither the stats card nor cards of other templates count.
+            CardFixture::new("stats", "deck")
+                .content(STATS_CARD_MARKER)
+                .build(),
+            CardFixture::new("other", "deck").template("other").build(),
+        ];
+        let templates = [template];
+
+        let coverage = field_coverage(&cards, &templates, "PitchAccent", "pitch");
+        assert_eq!(coverage.to_string(), "pitch: 75% covered, 1 missing");
+        assert_eq!(
+            stats_content(&[coverage]),
+            format!(
+                "{}\n# Enrichment coverage\n- pitch: 75% covered, 1 missing",
+                STATS_CARD_MARKER
+            )
+        );
+
+        let none = field_coverage(&cards[4..], &templates, "PitchAccent", "pitch");
+        assert_eq!(none.to_string(), "pitch: 100% covered, 0 missing");
+        assert!(is_blank_html("<div>&nbsp; </div>"));
+    }
+
+    #[test]
+    fn test_coverage_report() {
+        use crate::pipeline::Pipeline;
+        use crate::{load_accents, AccentMap};
+
+        let template = template_fixture("vocab", "", &[("name", "Word"), ("pitch", "PitchAccent")]);
+        let card = |id: &str, word: &str| {
+            CardFixture::new(id, "deck")
+                .template("vocab")
+                .field("name", word)
+                .build()
+        };
+        let cards = [
+            card("c1", "箸"),
+            card("c2", "ぴえん"),
+            card("c3", "方"),
+            CardFixture::new("c4", "deck").template("vocab").build(),
+        ];
+
+        let all = load_accents();
+        let accents = ["箸", "方"]
+            .iter()
+            .map(|w| (w.to_string(), all[*w].clone()))
+            .collect::<AccentMap>();
+        let transformer = PitchAccentTransformer::new(&accents, "Word", "PitchAccent");
+        let templates = [template];
+        let run = Pipeline::new()
+            .with(transformer.clone())
+            .apply(&cards, &templates);
+
+        let report = CoverageReport::from_run(&run, &templates, &transformer);
+        assert_eq!(report.missing, vec![("c2".into(), "ぴえん".to_string())]);
+        assert_eq!(report.ambiguous.len(), 1);
+        assert_eq!(report.ambiguous[0].1, "方");
+        assert_eq!(
+            report.skipped,
+            vec![("c4".into(), "no Word value".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_pitch_accent_to_cards() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        let decks = list_decks(&config).await.unwrap();
+        let n3_deck = decks.iter().find(|d| d.id == "MK5LCEAL");
+
+        let cards = list_cards(&config, &n3_deck.unwrap().id, Some(10))
+            .await
+            .unwrap();
+        let templates = list_templates(&config).await.unwrap();
+        let pitch = add_pitch_accent_to_cards(
+            &cards,
+            &templates,
+            "Word",
+            Some("Reading"),
+            "PitchAccent",
+            OverwritePolicy::IfDifferent,
+        );
+        // Every fixture word is in the dictionary, with its reading.
+        assert!(pitch.report.missing.is_empty());
+        assert!(pitch.report.ambiguous.is_empty());
+        assert!(pitch.report.skipped.is_empty());
+        assert!(!pitch.changed.is_empty());
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let total = pitch.changed.len();
+        let progress = ProgressHook::Callback(Box::new(move |p| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(p.total, total);
+        }));
+        let result = update_changed_cards(&config, &pitch.changed, Some(&progress)).await;
+        assert!(result.is_success());
+        assert_eq!(result.succeeded.len(), pitch.changed.len());
+        assert_eq!(calls.load(Ordering::SeqCst), pitch.changed.len());
+        let updated = server.card(result.succeeded[0].as_str()).unwrap();
+        assert!(updated.fields.unwrap()["pitch"].value.contains("span"));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_coverage_card() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        let deck_id = DeckId::from("N5DECK");
+        let fields = [("PitchAccent", "pitch")];
+
+        let first = refresh_coverage_card(&config, &deck_id, &fields)
+            .await
+            .unwrap();
+        server.fail_next(400, json!({ "error": "bad request" }));
+        assert!(refresh_coverage_card(&config, &deck_id, &fields)
+            .await
+            .is_err());
+        // The second run finds the stats card instead of adding another.
+        let second = refresh_coverage_card(&config, &deck_id, &fields)
+            .await
+            .unwrap();
+        assert_eq!(first, second);
+        let cards = list_cards(&config, &deck_id, None).await.unwrap();
+        assert_eq!(cards.iter().filter(|c| is_stats_card(c)).count(), 1);
+    }
+}
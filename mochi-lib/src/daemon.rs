@@ -0,0 +1,371 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::LocalBoxFuture;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixListener;
+use tokio::time::{sleep, Instant};
+
+use crate::cache::{refresh, Cache};
+use crate::coverage::refresh_coverage_card;
+use crate::decks::DeckTree;
+use crate::find::NameMatch;
+use crate::offline::OfflineClient;
+use crate::pipeline::{Pipeline, PitchAccentTransformer};
+use crate::profiles::{AccentSettings, ScheduledPipeline};
+use crate::romaji::{RomajiStyle, RomajiTransformer};
+use crate::{list_decks, unix_now, AccentMap, Config, MochiError};
+
+// Background Daemon
+//
+// Runs scheduled jobs one at a time, never closer together than `min_gap`, so
+// heavy syncing trickles along at a gentle request rate. The current status is
+// written as JSON to anyone connecting to the status socket.
+//
+// `mochi daemon` registers the built-in jobs below: sending the offline
+// outbox, refreshing the local cache and the profile's scheduled pipelines.
+
+type JobFn = Box<dyn Fn(Arc<Config>) -> LocalBoxFuture<'static, Result<(), Box<dyn Error>>>>;
+
+pub struct Job {
+    pub name: String,
+    pub interval: Duration,
+    run: JobFn,
+}
+
+impl Job {
+    pub fn new<F>(name: &str, interval: Duration, run: F) -> Job
+    where
+        F: Fn(Arc<Config>) -> LocalBoxFuture<'static, Result<(), Box<dyn Error>>> + 'static,
+    {
+        Job {
+            name: name.to_string(),
+            interval,
+            run: Box::new(run),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DaemonOptions {
+    pub socket_path: PathBuf,
+    // The minimum pause between two job runs.
+    pub min_gap: Duration,
+}
+
+impl DaemonOptions {
+    // `daemon.sock` in the user's cache directory, next to the cache.
+    pub fn default_socket_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("mochi-utils").join("daemon.sock"))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub runs: u64,
+    pub failures: u64,
+    // Seconds since the unix epoch.
+    pub last_run: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DaemonStatus {
+    pub started: u64,
+    pub running: Option<String>,
+    pub jobs: Vec<JobStatus>,
+}
+
+pub struct Daemon {
+    config: Arc<Config>,
+    options: DaemonOptions,
+    jobs: Vec<Job>,
+    status: Arc<Mutex<DaemonStatus>>,
+}
+
+impl Daemon {
+    pub fn new(config: &Config, options: DaemonOptions) -> Daemon {
+        Daemon {
+            config: Arc::new(config.clone()),
+            options,
+            jobs: vec![],
+            status: Arc::new(Mutex::new(DaemonStatus {
+                started: unix_now(),
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn add_job(&mut self, job: Job) {
+        self.status.lock().unwrap().jobs.push(JobStatus {
+            name: job.name.clone(),
+            ..Default::default()
+        });
+        self.jobs.push(job);
+    }
+
+    pub fn status(&self) -> DaemonStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    // Runs forever. Every job runs once at startup and then on its interval.
+    pub async fn run(&self) -> Result<(), Box<dyn Error>> {
+        let _ = std::fs::remove_file(&self.options.socket_path);
+        let listener = UnixListener::bind(&self.options.socket_path)?;
+        let status = Arc::clone(&self.status);
+        let server = tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let json = serde_json::to_string(&*status.lock().unwrap()).unwrap_or_default();
+                let _ = socket.write_all(json.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        let mut next_due = vec![Instant::now(); self.jobs.len()];
+        while let Some((index, due)) = next_due.iter().copied().enumerate().min_by_key(|(_, d)| *d)
+        {
+            tokio::time::sleep_until(due).await;
+
+            let job = &self.jobs[index];
+            self.status.lock().unwrap().running = Some(job.name.clone());
+            let result = (job.run)(Arc::clone(&self.config)).await;
+            {
+                let mut status = self.status.lock().unwrap();
+                status.running = None;
+                let job_status = &mut status.jobs[index];
+                job_status.runs += 1;
+                job_status.last_run = Some(unix_now());
+                if let Err(err) = result {
+                    job_status.failures += 1;
+                    job_status.last_error = Some(err.to_string());
+                }
+            }
+
+            next_due[index] = Instant::now() + job.interval;
+            sleep(self.options.min_gap).await;
+        }
+
+        server.abort();
+        Ok(())
+    }
+}
+
+// Built-in Jobs
+
+// Sends the changes queued while offline. Register it before the cache
+// refresh: a refresh replaces cached cards, queued changes included.
+pub fn outbox_job(cache_path: &Path, interval: Duration) -> Job {
+    let cache_path = cache_path.to_path_buf();
+    Job::new("outbox", interval, move |config| {
+        let cache_path = cache_path.clone();
+        Box::pin(async move {
+            let mut client = OfflineClient::new(Cache::open(&cache_path)?)?;
+            let result = client.replay(&config).await?;
+            match result.interrupted {
+                Some(err) => Err(err.into()),
+                None => Ok(()),
+            }
+        })
+    })
+}
+
+pub fn cache_refresh_job(cache_path: &Path, interval: Duration, max_age: Duration) -> Job {
+    let cache_path = cache_path.to_path_buf();
+    Job::new("cache refresh", interval, move |config| {
+        let cache_path = cache_path.clone();
+        Box::pin(async move {
+            let mut cache = Cache::open(&cache_path)?;
+            refresh(&config, &mut cache, max_age).await?;
+            Ok(())
+        })
+    })
+}
+
+// A pipeline from the profile's `schedule`. Errors for pipelines this
+// daemon doesn't know, so a typo is caught at startup.
+pub fn pipeline_job(
+    cache_path: &Path,
+    schedule: &ScheduledPipeline,
+    accents: &AccentSettings,
+) -> Result<Job, String> {
+    if !["pitch", "romaji"].contains(&schedule.pipeline.as_str()) {
+        return Err(format!("unknown pipeline {}", schedule.pipeline));
+    }
+    let name = format!("{} on {}", schedule.pipeline, schedule.deck);
+    let interval = Duration::from_secs(schedule.every_mins.max(1) * 60);
+    let cache_path = cache_path.to_path_buf();
+    let schedule = schedule.clone();
+    let accents = accents.clone();
+    Ok(Job::new(&name, interval, move |config| {
+        let cache_path = cache_path.clone();
+        let schedule = schedule.clone();
+        let accents = accents.clone();
+        Box::pin(async move { run_scheduled(&config, &cache_path, &schedule, &accents).await })
+    }))
+}
+
+async fn run_scheduled(
+    config: &Config,
+    cache_path: &Path,
+    schedule: &ScheduledPipeline,
+    accents: &AccentSettings,
+) -> Result<(), Box<dyn Error>> {
+    let decks = list_decks(config).await?;
+    let deck = match decks.iter().find(|d| d.id == schedule.deck.as_str()) {
+        Some(deck) => deck.clone(),
+        None => DeckTree::new(&decks)
+            .find_by_path_matching(&schedule.deck, NameMatch::CaseInsensitive)
+            .cloned()
+            .ok_or(MochiError::NotFound {
+                kind: "deck",
+                name: schedule.deck.clone(),
+            })?,
+    };
+    let field =
+        |name: &Option<String>, default: &str| name.clone().unwrap_or_else(|| default.to_string());
+    let output_field = match schedule.pipeline.as_str() {
+        "pitch" => field(&schedule.accent_field, "PitchAccent"),
+        _ => field(&schedule.romaji_field, "Romaji"),
+    };
+    let pipeline = match schedule.pipeline.as_str() {
+        "pitch" => {
+            let mut transformer = PitchAccentTransformer::new(
+                AccentMap::global(),
+                &field(&schedule.word_field, "Word"),
+                &output_field,
+            );
+            transformer.reading_field = schedule.reading_field.clone();
+            transformer.notation = accents.notation;
+            transformer.style = accents.style.clone();
+            Pipeline::new().with(transformer)
+        }
+        _ => Pipeline::new().with(RomajiTransformer {
+            kana_field: field(&schedule.reading_field, "Reading"),
+            romaji_field: output_field.clone(),
+            style: RomajiStyle::default(),
+        }),
+    };
+    let run = pipeline.run(config, &deck.id, false).await?;
+    // The next cache refresh lists the deck again, with the changes.
+    if run.updates.is_some() {
+        Cache::open(cache_path)?.mark_stale(&deck.id)?;
+    }
+    if let Some(updates) = run.updates.filter(|u| !u.is_success()) {
+        return Err(format!("{} cards failed to update", updates.failed.len()).into());
+    }
+    if schedule.coverage {
+        let fields = [(output_field.as_str(), schedule.pipeline.as_str())];
+        refresh_coverage_card(config, &deck.id, &fields).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::DEFAULT_MAX_AGE;
+    use crate::coverage::STATS_CARD_MARKER;
+    use crate::mock;
+    use crate::models::DeckId;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_daemon_status() {
+        let config = Config::default();
+        let socket_path = std::env::temp_dir().join("mochi-daemon-test.sock");
+        let mut daemon = Daemon::new(
+            &config,
+            DaemonOptions {
+                socket_path: socket_path.clone(),
+                min_gap: Duration::from_millis(10),
+            },
+        );
+        daemon.add_job(Job::new("ok", Duration::from_secs(60), |_| {
+            Box::pin(async { Ok(()) })
+        }));
+        daemon.add_job(Job::new("fails", Duration::from_secs(60), |_| {
+            Box::pin(async { Err("offline".into()) })
+        }));
+
+        let run = daemon.run();
+        let query = async {
+            sleep(Duration::from_millis(200)).await;
+            let mut socket = UnixStream::connect(&socket_path).await.unwrap();
+            let mut json = String::new();
+            socket.read_to_string(&mut json).await.unwrap();
+            json
+        };
+        let json = tokio::select! {
+            _ = run => panic!("daemon stopped"),
+            json = query => json,
+        };
+
+        let status: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(status["jobs"][0]["runs"], 1);
+        assert_eq!(status["jobs"][1]["failures"], 1);
+        assert_eq!(status["jobs"][1]["last_error"], "offline");
+    }
+
+    #[tokio::test]
+    async fn test_builtin_jobs() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = Arc::new(server.config());
+        let cache_path = std::env::temp_dir().join("mochi-daemon-test.db");
+        let _ = std::fs::remove_file(&cache_path);
+
+        let schedule = ScheduledPipeline {
+            pipeline: "pitch".to_string(),
+            deck: "Japanese/N3".to_string(),
+            every_mins: 60,
+            reading_field: Some("Reading".to_string()),
+            coverage: true,
+            ..ScheduledPipeline::default()
+        };
+        let accents = AccentSettings::default();
+        let jobs = [
+            outbox_job(&cache_path, Duration::from_secs(60)),
+            cache_refresh_job(&cache_path, Duration::from_secs(60), DEFAULT_MAX_AGE),
+            pipeline_job(&cache_path, &schedule, &accents).unwrap(),
+        ];
+        for job in jobs.iter() {
+            (job.run)(Arc::clone(&config)).await.unwrap();
+        }
+        assert_eq!(jobs[2].name, "pitch on Japanese/N3");
+
+        let cache = Cache::open(&cache_path).unwrap();
+        assert_eq!(cache.decks().unwrap().len(), 3);
+        let n3 = [DeckId::from("MK5LCEAL")];
+        let now = std::time::SystemTime::now();
+        assert_eq!(cache.decks_to_refresh(&n3, None, now).unwrap(), n3);
+        let patched = server
+            .requests()
+            .iter()
+            .filter(|r| r.method == "POST" && r.path.starts_with("cards/"))
+            .count();
+        assert!(patched > 0);
+        let stats = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "POST" && r.path == "cards")
+            .filter_map(|r| r.body)
+            .find(|body| {
+                body["content"]
+                    .as_str()
+                    .unwrap_or("")
+                    .starts_with(STATS_CARD_MARKER)
+            });
+        assert!(stats.is_some());
+
+        let typo = ScheduledPipeline {
+            pipeline: "pitchh".to_string(),
+            ..schedule
+        };
+        assert!(pipeline_job(&cache_path, &typo, &accents).is_err());
+        let _ = std::fs::remove_file(&cache_path);
+    }
+}
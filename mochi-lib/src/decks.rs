@@ -0,0 +1,682 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use crate::find::{find_by_name, NameMatch};
+use crate::models::{Card, CardId, CardPatch, Deck, DeckId, FieldId};
+use crate::{
+    create_deck, list_cards, list_cards_for_decks, list_decks, patch_cards, update_cards,
+    update_deck, BulkResult, Config,
+};
+
+// Merge Decks
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DuplicateKey {
+    // Cards with the same trimmed content are duplicates.
+    Content,
+    // Cards with the same trimmed value for this field id are duplicates.
+    Field(FieldId),
+}
+
+impl DuplicateKey {
+    fn key(&self, card: &Card) -> Option<String> {
+        let key = match self {
+            DuplicateKey::Content => card.content.trim().to_string(),
+            DuplicateKey::Field(field_id) => card
+                .fields
+                .as_ref()
+                .and_then(|f| f.get(field_id))
+                .map(|f| f.value.trim().to_string())
+                .unwrap_or_default(),
+        };
+
+        if key.is_empty() {
+            None
+        } else {
+            Some(key)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OnDuplicate {
+    // Leave the source card behind. The source deck then stays unarchived.
+    Skip,
+    // Fill the target card's empty fields from the source card. Only applies
+    // when both cards share a template, otherwise the source card is skipped.
+    MergeFields,
+    // Move the source card anyway and tag both copies.
+    KeepBothTagged(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct MergePolicy {
+    pub key: DuplicateKey,
+    pub on_duplicate: OnDuplicate,
+}
+
+#[derive(Debug, Default)]
+pub struct MergePlan {
+    // Cards to upload, both moved source cards and modified target cards.
+    pub updates: Vec<Card>,
+    pub moved: Vec<CardId>,
+    pub skipped: Vec<CardId>,
+    // Pairs of (source, target) card ids.
+    pub merged: Vec<(CardId, CardId)>,
+}
+
+#[derive(Debug)]
+pub struct MergeResult {
+    pub plan: MergePlan,
+    pub updates: BulkResult,
+    pub source_archived: bool,
+}
+
+fn with_tag(card: &Card, tag: &str) -> Card {
+    let mut card = card.clone();
+    let mut tags = card.manual_tags.clone().unwrap_or(card.tags.clone());
+    if !tags.iter().any(|t| t == tag) {
+        tags.push(tag.to_string());
+    }
+    card.manual_tags = Some(tags);
+    card
+}
+
+// Returns true if any field of the target was filled in.
+fn merge_fields(target: &mut Card, source: &Card) -> bool {
+    let source_fields = match &source.fields {
+        Some(fields) => fields,
+        None => return false,
+    };
+    let target_fields = target.fields.get_or_insert_with(HashMap::new);
+
+    let mut changed = false;
+    for (id, field) in source_fields.iter() {
+        if field.value.trim().is_empty() {
+            continue;
+        }
+        let empty = target_fields
+            .get(id)
+            .map(|f| f.value.trim().is_empty())
+            .unwrap_or(true);
+        if empty {
+            target_fields.insert(id.clone(), field.clone());
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+pub fn plan_merge(
+    source_cards: &[Card],
+    target_cards: &[Card],
+    target_deck_id: &DeckId,
+    policy: &MergePolicy,
+) -> MergePlan {
+    let mut plan = MergePlan::default();
+
+    let mut targets: HashMap<String, Card> = HashMap::new();
+    for card in target_cards.iter() {
+        if let Some(key) = policy.key.key(card) {
+            targets.entry(key).or_insert(card.clone());
+        }
+    }
+    let mut changed_targets: HashMap<CardId, Card> = HashMap::new();
+
+    for card in source_cards.iter() {
+        let duplicate = policy.key.key(card).and_then(|k| targets.get(&k));
+
+        let mut moved = match (duplicate, &policy.on_duplicate) {
+            (None, _) => card.clone(),
+            (Some(_), OnDuplicate::Skip) => {
+                plan.skipped.push(card.id.clone());
+                continue;
+            }
+            (Some(target), OnDuplicate::MergeFields) => {
+                if target.template_id != card.template_id {
+                    plan.skipped.push(card.id.clone());
+                    continue;
+                }
+                let mut merged = changed_targets
+                    .get(&target.id)
+                    .cloned()
+                    .unwrap_or(target.clone());
+                if merge_fields(&mut merged, card) {
+                    changed_targets.insert(target.id.clone(), merged);
+                }
+                plan.merged.push((card.id.clone(), target.id.clone()));
+                continue;
+            }
+            (Some(target), OnDuplicate::KeepBothTagged(tag)) => {
+                changed_targets
+                    .entry(target.id.clone())
+                    .or_insert(with_tag(target, tag));
+                with_tag(card, tag)
+            }
+        };
+
+        moved.deck_id = target_deck_id.clone();
+        plan.moved.push(moved.id.clone());
+        plan.updates.push(moved);
+    }
+
+    plan.updates.extend(changed_targets.into_values());
+    plan
+}
+
+// Move every card of `source` into `target`, then archive the source deck.
+// The source deck is only archived if no card was skipped and all uploads
+// succeeded, so no card is left behind in an archived deck.
+pub async fn merge_decks(
+    config: &Config,
+    source: &DeckId,
+    target: &DeckId,
+    policy: &MergePolicy,
+) -> Result<MergeResult, Box<dyn Error>> {
+    if source == target {
+        return Err(format!("cannot merge deck {} into itself", source).into());
+    }
+    let source_cards = list_cards(config, source, None).await?;
+    let target_cards = list_cards(config, target, None).await?;
+
+    let plan = plan_merge(&source_cards, &target_cards, target, policy);
+    let updates = update_cards(config, &plan.updates).await;
+
+    let mut source_archived = false;
+    if plan.skipped.is_empty() && updates.is_success() {
+        let decks = list_decks(config).await?;
+        if let Some(deck) = decks.iter().find(|d| d.id.eq(source)) {
+            let mut deck = deck.clone();
+            deck.archived = true;
+            update_deck(config, &deck).await?;
+            source_archived = true;
+        }
+    }
+
+    Ok(MergeResult {
+        plan,
+        updates,
+        source_archived,
+    })
+}
+
+// Split Decks
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplitPredicate {
+    Tag(String),
+    FieldEquals { field_id: FieldId, value: String },
+}
+
+impl SplitPredicate {
+    fn matches(&self, card: &Card) -> bool {
+        match self {
+            SplitPredicate::Tag(tag) => card.tags.iter().any(|t| t == tag),
+            SplitPredicate::FieldEquals { field_id, value } => card
+                .fields
+                .as_ref()
+                .and_then(|f| f.get(field_id))
+                .map(|f| f.value.trim() == value.trim())
+                .unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SplitTarget {
+    pub deck_name: String,
+    pub predicate: SplitPredicate,
+}
+
+#[derive(Debug, Default)]
+pub struct SplitPlan {
+    // The card ids going to each target deck, in the order of the targets.
+    pub assignments: Vec<(String, Vec<CardId>)>,
+    // Cards matching no target stay in the original deck.
+    pub unmatched: Vec<CardId>,
+}
+
+#[derive(Debug)]
+pub struct SplitResult {
+    pub plan: SplitPlan,
+    pub created_decks: Vec<Deck>,
+    // None for a dry run.
+    pub updates: Option<BulkResult>,
+}
+
+// Each card goes to the first target whose predicate it matches.
+pub fn plan_split(cards: &[Card], targets: &[SplitTarget]) -> SplitPlan {
+    let mut plan = SplitPlan {
+        assignments: targets
+            .iter()
+            .map(|t| (t.deck_name.clone(), vec![]))
+            .collect(),
+        unmatched: vec![],
+    };
+
+    for card in cards.iter() {
+        match targets.iter().position(|t| t.predicate.matches(card)) {
+            Some(i) => plan.assignments[i].1.push(card.id.clone()),
+            None => plan.unmatched.push(card.id.clone()),
+        }
+    }
+
+    plan
+}
+
+// Split a deck into new decks created under `parent_id`. With `dry_run` only
+// the plan is returned and nothing is created or moved.
+pub async fn split_deck(
+    config: &Config,
+    deck_id: &DeckId,
+    parent_id: Option<&DeckId>,
+    targets: &[SplitTarget],
+    dry_run: bool,
+) -> Result<SplitResult, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    let plan = plan_split(&cards, targets);
+    if dry_run {
+        return Ok(SplitResult {
+            plan,
+            created_decks: vec![],
+            updates: None,
+        });
+    }
+
+    let mut created_decks = vec![];
+    let mut updates = BulkResult::default();
+    for (deck_name, card_ids) in plan.assignments.iter() {
+        if card_ids.is_empty() {
+            continue;
+        }
+
+        let deck = Deck {
+            name: deck_name.clone(),
+            parent_id: parent_id.cloned(),
+            template_id: None,
+            archived: false,
+            sort: None,
+            trashed: None,
+            id: DeckId::default(),
+            extra: HashMap::new(),
+        };
+        let deck = create_deck(config, &deck).await?;
+
+        let moved = move_cards(config, card_ids, &deck.id, false).await;
+        if let Some(moved) = moved.updates {
+            updates.succeeded.extend(moved.succeeded);
+            updates.failed.extend(moved.failed);
+        }
+        created_decks.push(deck);
+    }
+
+    Ok(SplitResult {
+        plan,
+        created_decks,
+        updates: Some(updates),
+    })
+}
+
+// Move Cards
+
+#[derive(Debug)]
+pub struct MoveResult {
+    pub planned: Vec<(CardId, CardPatch)>,
+    // None for a dry run.
+    pub updates: Option<BulkResult>,
+}
+
+// Set the deck of each card, throttled to the bulk concurrency limit. With
+// `dry_run` only the planned patches are returned.
+pub async fn move_cards(
+    config: &Config,
+    card_ids: &[CardId],
+    target_deck_id: &DeckId,
+    dry_run: bool,
+) -> MoveResult {
+    let planned = card_ids
+        .iter()
+        .map(|id| (id.clone(), CardPatch::new().deck_id(target_deck_id)))
+        .collect::<Vec<_>>();
+
+    let updates = if dry_run {
+        None
+    } else {
+        Some(patch_cards(config, &planned).await)
+    };
+    MoveResult { planned, updates }
+}
+
+// Deck Tree
+
+#[derive(Debug, Clone)]
+pub struct DeckTree {
+    decks: HashMap<DeckId, Deck>,
+    children: HashMap<DeckId, Vec<DeckId>>,
+    roots: Vec<DeckId>,
+}
+
+impl DeckTree {
+    pub fn new(decks: &[Deck]) -> DeckTree {
+        let decks = decks
+            .iter()
+            .map(|d| (d.id.clone(), d.clone()))
+            .collect::<HashMap<_, _>>();
+
+        let mut children: HashMap<DeckId, Vec<DeckId>> = HashMap::new();
+        let mut roots = vec![];
+        for deck in decks.values() {
+            // Decks whose parent is missing from the listing become roots.
+            match deck.parent_id.as_ref().filter(|p| decks.contains_key(*p)) {
+                Some(parent_id) => children
+                    .entry(parent_id.clone())
+                    .or_default()
+                    .push(deck.id.clone()),
+                None => roots.push(deck.id.clone()),
+            }
+        }
+
+        let by_name = |a: &DeckId, b: &DeckId| decks[a].name.cmp(&decks[b].name);
+        roots.sort_by(by_name);
+        for ids in children.values_mut() {
+            ids.sort_by(by_name);
+        }
+
+        DeckTree {
+            decks,
+            children,
+            roots,
+        }
+    }
+
+    pub async fn load(config: &Config) -> Result<DeckTree, Box<dyn Error>> {
+        Ok(DeckTree::new(&list_decks(config).await?))
+    }
+
+    pub fn get(&self, deck_id: &DeckId) -> Option<&Deck> {
+        self.decks.get(deck_id)
+    }
+
+    pub fn roots(&self) -> Vec<&Deck> {
+        self.roots.iter().map(|id| &self.decks[id]).collect()
+    }
+
+    pub fn children(&self, deck_id: &DeckId) -> Vec<&Deck> {
+        self.children
+            .get(deck_id)
+            .map(|ids| ids.iter().map(|id| &self.decks[id]).collect())
+            .unwrap_or_default()
+    }
+
+    // All decks below the deck, depth first. A deck in a parent cycle is
+    // listed once.
+    pub fn descendants(&self, deck_id: &DeckId) -> Vec<&Deck> {
+        let mut descendants = vec![];
+        let mut visited = self
+            .decks
+            .get_key_value(deck_id)
+            .map(|(id, _)| id)
+            .into_iter()
+            .collect();
+        self.collect_descendants(deck_id, &mut visited, &mut descendants);
+        descendants
+    }
+
+    fn collect_descendants<'a>(
+        &'a self,
+        deck_id: &DeckId,
+        visited: &mut HashSet<&'a DeckId>,
+        descendants: &mut Vec<&'a Deck>,
+    ) {
+        for child in self.children(deck_id) {
+            if visited.insert(&child.id) {
+                descendants.push(child);
+                self.collect_descendants(&child.id, visited, descendants);
+            }
+        }
+    }
+
+    // Deck names from the root down, joined with "/", e.g. "Japanese/N3/Vocab".
+    pub fn path(&self, deck_id: &DeckId) -> Option<String> {
+        let mut names = vec![];
+        let mut current = self.decks.get(deck_id);
+        while let Some(deck) = current {
+            // Guard against parent cycles.
+            if names.len() > self.decks.len() {
+                break;
+            }
+            names.push(deck.name.as_str());
+            current = deck.parent_id.as_ref().and_then(|p| self.decks.get(p));
+        }
+        if names.is_empty() {
+            return None;
+        }
+        names.reverse();
+        Some(names.join("/"))
+    }
+
+    pub fn find_by_path(&self, path: &str) -> Option<&Deck> {
+        self.find_by_path_matching(path, NameMatch::Exact)
+    }
+
+    pub fn find_by_path_matching(&self, path: &str, mode: NameMatch) -> Option<&Deck> {
+        let mut candidates = self.roots();
+        let mut found = None;
+        for name in path.split('/').filter(|n| !n.is_empty()) {
+            let deck = *find_by_name(&candidates, |d| &d.name, name, mode)?;
+            candidates = self.children(&deck.id);
+            found = Some(deck);
+        }
+        found
+    }
+}
+
+// List the cards of the deck at the path and of every deck below it.
+pub async fn list_cards_under(
+    config: &Config,
+    tree: &DeckTree,
+    path: &str,
+) -> Result<HashMap<DeckId, Box<[Card]>>, Box<dyn Error>> {
+    let deck = tree
+        .find_by_path(path)
+        .ok_or(format!("no deck at {}", path))?;
+    let mut deck_ids = vec![deck.id.clone()];
+    deck_ids.extend(tree.descendants(&deck.id).iter().map(|d| d.id.clone()));
+    list_cards_for_decks(config, &deck_ids, None).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{self, CardFixture};
+    use serde_json::json;
+
+    fn fixture(id: &str, deck_id: &str, word: &str, note: &str) -> CardFixture {
+        CardFixture::new(id, deck_id)
+            .template("template")
+            .field("word", word)
+            .field("note", note)
+    }
+
+    fn card(id: &str, deck_id: &str, word: &str, note: &str) -> Card {
+        fixture(id, deck_id, word, note).build()
+    }
+
+    #[test]
+    fn test_plan_merge() {
+        let source = [
+            card("s1", "source", "犬", "dog"),
+            card("s2", "source", "猫", "cat"),
+        ];
+        let target = [card("t1", "target", "犬", "")];
+        let target_id = DeckId::from("target");
+
+        let skip = MergePolicy {
+            key: DuplicateKey::Field("word".into()),
+            on_duplicate: OnDuplicate::Skip,
+        };
+        let plan = plan_merge(&source, &target, &target_id, &skip);
+        assert_eq!(plan.moved, vec![CardId::from("s2")]);
+        assert_eq!(plan.skipped, vec![CardId::from("s1")]);
+        assert_eq!(plan.updates.len(), 1);
+        assert_eq!(plan.updates[0].deck_id, "target");
+
+        let merge = MergePolicy {
+            key: DuplicateKey::Field("word".into()),
+            on_duplicate: OnDuplicate::MergeFields,
+        };
+        let plan = plan_merge(&source, &target, &target_id, &merge);
+        assert_eq!(plan.merged, vec![("s1".into(), "t1".into())]);
+        let t1 = plan.updates.iter().find(|c| c.id == "t1").unwrap();
+        assert_eq!(t1.fields.as_ref().unwrap()["note"].value, "dog");
+
+        let keep = MergePolicy {
+            key: DuplicateKey::Field("word".into()),
+            on_duplicate: OnDuplicate::KeepBothTagged("dupe".to_string()),
+        };
+        let plan = plan_merge(&source, &target, &target_id, &keep);
+        assert_eq!(plan.moved.len(), 2);
+        assert_eq!(plan.updates.len(), 3);
+        let tagged = plan
+            .updates
+            .iter()
+            .filter(|c| c.manual_tags == Some(vec!["dupe".to_string()]))
+            .count();
+        assert_eq!(tagged, 2);
+    }
+
+    #[test]
+    fn test_plan_split() {
+        let mut verb = card("c1", "deck", "食べる", "verb");
+        verb.tags = vec!["jlpt-n5".to_string()];
+        let noun = card("c2", "deck", "犬", "noun");
+        let other = card("c3", "deck", "とても", "adverb");
+
+        let targets = [
+            SplitTarget {
+                deck_name: "N5".to_string(),
+                predicate: SplitPredicate::Tag("jlpt-n5".to_string()),
+            },
+            SplitTarget {
+                deck_name: "Nouns".to_string(),
+                predicate: SplitPredicate::FieldEquals {
+                    field_id: "note".into(),
+                    value: "noun".to_string(),
+                },
+            },
+        ];
+        let plan = plan_split(&[verb, noun, other], &targets);
+        assert_eq!(
+            plan.assignments[0],
+            ("N5".to_string(), vec![CardId::from("c1")])
+        );
+        assert_eq!(
+            plan.assignments[1],
+            ("Nouns".to_string(), vec![CardId::from("c2")])
+        );
+        assert_eq!(plan.unmatched, vec![CardId::from("c3")]);
+    }
+
+    #[tokio::test]
+    async fn test_merge_decks() {
+        let server = mock::MockMochiServer::start().await.unwrap();
+        let config = server.config();
+        server.add_deck(json!({ "id": "source", "name": "Source" }));
+        server.add_deck(json!({ "id": "target", "name": "Target" }));
+        for card in [
+            fixture("s1", "source", "犬", "dog"),
+            fixture("s2", "source", "猫", "cat"),
+            fixture("t1", "target", "犬", ""),
+        ] {
+            server.add_card(card.json());
+        }
+        let archived = |decks: &[Deck], id: &str| decks.iter().any(|d| d.id == id && d.archived);
+        let (source, target) = (DeckId::from("source"), DeckId::from("target"));
+
+        let skip = MergePolicy {
+            key: DuplicateKey::Field("word".into()),
+            on_duplicate: OnDuplicate::Skip,
+        };
+        assert!(merge_decks(&config, &target, &target, &skip).await.is_err());
+        assert!(server.requests().is_empty());
+
+        // s1 is left behind, so the source deck stays.
+        let result = merge_decks(&config, &source, &target, &skip).await.unwrap();
+        assert_eq!(result.plan.skipped, vec![CardId::from("s1")]);
+        assert!(!result.source_archived);
+        assert_eq!(server.card("s2").unwrap().deck_id, "target");
+        assert!(!archived(&list_decks(&config).await.unwrap(), "source"));
+
+        let merge = MergePolicy {
+            key: DuplicateKey::Field("word".into()),
+            on_duplicate: OnDuplicate::MergeFields,
+        };
+        let result = merge_decks(&config, &source, &target, &merge)
+            .await
+            .unwrap();
+        assert_eq!(result.plan.merged, vec![("s1".into(), "t1".into())]);
+        assert!(result.source_archived);
+        let decks = list_decks(&config).await.unwrap();
+        assert!(archived(&decks, "source"));
+        assert!(!archived(&decks, "target"));
+    }
+
+    #[tokio::test]
+    async fn test_move_cards_dry_run() {
+        let config = Config::default();
+        let card_ids = vec![CardId::from("a"), CardId::from("b")];
+
+        let result = move_cards(&config, &card_ids, &DeckId::from("target"), true).await;
+        assert!(result.updates.is_none());
+        assert_eq!(result.planned.len(), 2);
+        assert_eq!(result.planned[0].1, CardPatch::new().deck_id("target"));
+    }
+
+    #[test]
+    fn test_deck_tree() {
+        let deck = |id: &str, name: &str, parent_id: Option<&str>| -> Deck {
+            serde_json::from_value(json!({
+                "id": id,
+                "name": name,
+                "parent-id": parent_id,
+            }))
+            .unwrap()
+        };
+        let tree = DeckTree::new(&[
+            deck("jp", "Japanese", None),
+            deck("n3", "N3", Some("jp")),
+            deck("vocab", "Vocab", Some("n3")),
+            deck("kanji", "Kanji", Some("n3")),
+            deck("n2", "N2", Some("jp")),
+            deck("fr", "French", None),
+        ]);
+
+        let names = |decks: Vec<&Deck>| decks.iter().map(|d| d.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(tree.roots()), vec!["French", "Japanese"]);
+        assert_eq!(
+            names(tree.children(&DeckId::from("n3"))),
+            vec!["Kanji", "Vocab"]
+        );
+        assert_eq!(
+            names(tree.descendants(&DeckId::from("jp"))),
+            vec!["N2", "N3", "Kanji", "Vocab"]
+        );
+        assert_eq!(
+            tree.path(&DeckId::from("vocab")).as_deref(),
+            Some("Japanese/N3/Vocab")
+        );
+
+        let cycle = DeckTree::new(&[deck("a", "A", Some("b")), deck("b", "B", Some("a"))]);
+        assert_eq!(names(cycle.descendants(&DeckId::from("a"))), vec!["B"]);
+        assert!(cycle.path(&DeckId::from("a")).is_some());
+        assert_eq!(tree.find_by_path("Japanese/N3/Vocab").unwrap().id, "vocab");
+        assert!(tree.find_by_path("Japanese/N4").is_none());
+        assert_eq!(
+            tree.find_by_path_matching("japanese/n3", NameMatch::CaseInsensitive)
+                .unwrap()
+                .id,
+            "n3"
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt;
+
+use crate::models::{Card, CardId, Template};
+use crate::preview::escape_html;
+use crate::tags::manual_tags;
+
+// Card Diffs
+//
+// What an edit would change on a card, field by field with names from the
+// template, for reviewing a dry run before pushing it. Values are compared
+// line by line; the text form reads like a unified diff and the HTML form
+// fits the preview server's card frames.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+// The lines of `after` as edits of `before`, by longest common subsequence.
+pub fn diff_lines<'a>(before: &'a str, after: &'a str) -> Vec<DiffLine<'a>> {
+    let a = before.lines().collect::<Vec<_>>();
+    let b = after.lines().collect::<Vec<_>>();
+    // common[i][j]: common lines of a[i..] and b[j..].
+    let mut common = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = match a[i] == b[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            lines.push(DiffLine::Same(a[i]));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            lines.push(DiffLine::Removed(a[i]));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(b[j]));
+            j += 1;
+        }
+    }
+    lines.extend(a[i..].iter().map(|l| DiffLine::Removed(l)));
+    lines.extend(b[j..].iter().map(|l| DiffLine::Added(l)));
+    lines
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    // The field's name, or `content`, `deck`, `template`, `tags`,
+    // `archived` or `review-reverse`.
+    pub name: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl FieldChange {
+    pub fn lines(&self) -> Vec<DiffLine<'_>> {
+        diff_lines(&self.before, &self.after)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardDiff {
+    pub card_id: CardId,
+    pub changes: Vec<FieldChange>,
+}
+
+impl CardDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        for change in self.changes.iter() {
+            html.push_str(&format!(
+                "<div class=\"diff\"><div class=\"label\">{}</div>",
+                escape_html(&change.name)
+            ));
+            for line in change.lines() {
+                let (tag, line) = match line {
+                    DiffLine::Same(l) => ("span", l),
+                    DiffLine::Removed(l) => ("del", l),
+                    DiffLine::Added(l) => ("ins", l),
+                };
+                html.push_str(&format!("<{tag}>{}</{tag}><br>", escape_html(line)));
+            }
+            html.push_str("</div>");
+        }
+        html
+    }
+}
+
+impl fmt::Display for CardDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "card {}", self.card_id)?;
+        for change in self.changes.iter() {
+            writeln!(f, "  {}:", change.name)?;
+            for line in change.lines() {
+                match line {
+                    DiffLine::Same(l) => writeln!(f, "      {}", l)?,
+                    DiffLine::Removed(l) => writeln!(f, "    - {}", l)?,
+                    DiffLine::Added(l) => writeln!(f, "    + {}", l)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn change(changes: &mut Vec<FieldChange>, name: &str, before: String, after: String) {
+    if before != after {
+        changes.push(FieldChange {
+            name: name.to_string(),
+            before,
+            after,
+        });
+    }
+}
+
+// Field changes are in the order of the modified card's template; fields it
+// doesn't name are shown by id.
+pub fn diff_cards(original: &Card, modified: &Card, templates: &[Template]) -> CardDiff {
+    let mut changes = vec![];
+    change(
+        &mut changes,
+        "content",
+        original.content.clone(),
+        modified.content.clone(),
+    );
+
+    let template = templates
+        .iter()
+        .find(|t| Some(&t.id) == modified.template_id.as_ref());
+    let mut field_ids = original
+        .fields
+        .iter()
+        .chain(modified.fields.iter())
+        .flat_map(|f| f.keys())
+        .collect::<Vec<_>>();
+    field_ids.sort_by_key(|id| {
+        let field = template.and_then(|t| t.fields.as_ref()?.get(*id));
+        (field.map(|f| f.pos.clone()), (*id).clone())
+    });
+    field_ids.dedup();
+    for id in field_ids {
+        let value = |card: &Card| {
+            card.fields
+                .as_ref()
+                .and_then(|f| f.get(id))
+                .map(|f| f.value.clone())
+                .unwrap_or_default()
+        };
+        let name = template
+            .and_then(|t| t.fields.as_ref()?.get(id))
+            .map(|f| f.name.as_str())
+            .unwrap_or(id.as_str());
+        change(&mut changes, name, value(original), value(modified));
+    }
+
+    change(
+        &mut changes,
+        "deck",
+        original.deck_id.to_string(),
+        modified.deck_id.to_string(),
+    );
+    let template_id = |card: &Card| card.template_id.as_ref().map(|t| t.to_string());
+    change(
+        &mut changes,
+        "template",
+        template_id(original).unwrap_or_default(),
+        template_id(modified).unwrap_or_default(),
+    );
+    change(
+        &mut changes,
+        "tags",
+        manual_tags(original).join(" "),
+        manual_tags(modified).join(" "),
+    );
+    change(
+        &mut changes,
+        "archived",
+        original.archived.to_string(),
+        modified.archived.to_string(),
+    );
+    change(
+        &mut changes,
+        "review-reverse",
+        original.review_reverse.to_string(),
+        modified.review_reverse.to_string(),
+    );
+
+    CardDiff {
+        card_id: original.id.clone(),
+        changes,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+
+    #[test]
+    fn test_diff_cards() {
+        let template = template_fixture("vocab", "", &[("name", "Word"), ("meaning", "Meaning")]);
+        let original = CardFixture::new("c1", "n5")
+            .template("vocab")
+            .field("name", "箸")
+            .field("meaning", "chopsticks\n(noun)")
+            .build();
+        let mut modified = original.clone();
+        modified.set_field_by_name(&template, "Meaning", "CHOPSTICKS\n(noun)");
+        modified.archived = true;
+
+        let diff = diff_cards(&original, &modified, &[template]);
+        assert_eq!(diff.changes.len(), 2);
+        assert_eq!(
+            diff.to_string(),
+            "card c1\n  Meaning:\n    - chopsticks\n    + CHOPSTICKS\n      (noun)\n  \
+             archived:\n    - false\n    + true\n"
+        );
+        assert!(diff.to_html().contains("<del>chopsticks</del>"));
+        assert!(diff_cards(&original, &original, &[]).is_empty());
+    }
+}
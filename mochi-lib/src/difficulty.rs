@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::models::ResolvedCard;
+use crate::pipeline::{CardTransformer, TransformOutcome};
+use crate::tags::manual_tags;
+
+// Difficulty Tagging
+//
+// JLPT levels and corpus frequency ranks from word lists on disk, as tags
+// (`jlpt-n3`, `freq-top5k`) and optionally as field values. The lists are
+// plain text with one word per line and tab or comma separated columns:
+//
+// - JLPT: `word, level`, the level written `N3`, `n3` or `3`.
+// - Frequency: the word in the first column, most frequent first, as in the
+//   BCCWJ and Netflix lists. The rank is the line number.
+//
+// Empty lines and lines starting with `#` are skipped.
+
+const JLPT_TAG_PREFIX: &str = "jlpt-";
+const FREQUENCY_TAG_PREFIX: &str = "freq-";
+
+fn columns(raw: &str) -> impl Iterator<Item = Vec<&str>> {
+    raw.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.split(['\t', ',']).map(str::trim).collect())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct JlptLevels(HashMap<String, u8>);
+
+impl JlptLevels {
+    pub fn from_path(path: &str) -> Result<JlptLevels, Box<dyn Error>> {
+        Ok(JlptLevels::parse(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn parse(raw: &str) -> Result<JlptLevels, String> {
+        let mut levels = HashMap::new();
+        for columns in columns(raw) {
+            let (word, level) = match columns.as_slice() {
+                [word, level, ..] => (word, level),
+                _ => return Err(format!("no JLPT level for {}", columns[0])),
+            };
+            let level = level
+                .trim_start_matches(['N', 'n'])
+                .parse::<u8>()
+                .ok()
+                .filter(|l| (1..=5).contains(l))
+                .ok_or_else(|| format!("invalid JLPT level {} for {}", level, word))?;
+            // Words listed at several levels belong to the easiest one.
+            let entry = levels.entry(word.to_string()).or_insert(level);
+            *entry = (*entry).max(level);
+        }
+        Ok(JlptLevels(levels))
+    }
+
+    // 5 for N5 down to 1 for N1.
+    pub fn level(&self, word: &str) -> Option<u8> {
+        self.0.get(word).copied()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrequencyList(HashMap<String, usize>);
+
+impl FrequencyList {
+    pub fn from_path(path: &str) -> Result<FrequencyList, Box<dyn Error>> {
+        Ok(FrequencyList::parse(&std::fs::read_to_string(path)?))
+    }
+
+    pub fn parse(raw: &str) -> FrequencyList {
+        let mut ranks = HashMap::new();
+        for (i, columns) in columns(raw).enumerate() {
+            ranks.entry(columns[0].to_string()).or_insert(i + 1);
+        }
+        FrequencyList(ranks)
+    }
+
+    // 1 for the most frequent word.
+    pub fn rank(&self, word: &str) -> Option<usize> {
+        self.0.get(word).copied()
+    }
+}
+
+// `freq-top5k` for the smallest bucket the rank fits in.
+pub fn frequency_tag(rank: usize, buckets: &[usize]) -> Option<String> {
+    let bucket = buckets.iter().filter(|b| rank <= **b).min()?;
+    Some(if bucket % 1000 == 0 {
+        format!("{}top{}k", FREQUENCY_TAG_PREFIX, bucket / 1000)
+    } else {
+        format!("{}top{}", FREQUENCY_TAG_PREFIX, bucket)
+    })
+}
+
+// Transformer
+
+#[derive(Debug, Clone)]
+pub struct DifficultyTransformer<'a> {
+    pub jlpt: Option<&'a JlptLevels>,
+    pub frequency: Option<&'a FrequencyList>,
+    pub word_field: String,
+    // Replaces the card's `jlpt-` and `freq-` tags.
+    pub add_tags: bool,
+    // `N3`
+    pub jlpt_field: Option<String>,
+    // The rank, e.g. `4210`.
+    pub frequency_field: Option<String>,
+    pub frequency_buckets: Vec<usize>,
+}
+
+impl<'a> DifficultyTransformer<'a> {
+    pub fn new(word_field: &str) -> DifficultyTransformer<'a> {
+        DifficultyTransformer {
+            jlpt: None,
+            frequency: None,
+            word_field: word_field.to_string(),
+            add_tags: true,
+            jlpt_field: None,
+            frequency_field: None,
+            frequency_buckets: vec![1000, 2000, 5000, 10000, 20000, 50000],
+        }
+    }
+}
+
+impl CardTransformer for DifficultyTransformer<'_> {
+    fn name(&self) -> &str {
+        "difficulty"
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        let word = match card.field(&self.word_field).map(str::trim) {
+            Some(word) if !word.is_empty() => word.to_string(),
+            _ => return TransformOutcome::Skipped(format!("no {} value", self.word_field)),
+        };
+        let level = self.jlpt.and_then(|jlpt| jlpt.level(&word));
+        let rank = self.frequency.and_then(|frequency| frequency.rank(&word));
+
+        let mut changed = false;
+        if self.add_tags {
+            let mut tags = manual_tags(&card.card);
+            let replaced = |t: &String| {
+                (self.jlpt.is_some() && t.starts_with(JLPT_TAG_PREFIX))
+                    || (self.frequency.is_some() && t.starts_with(FREQUENCY_TAG_PREFIX))
+            };
+            tags.retain(|t| !replaced(t));
+            tags.extend(level.map(|l| format!("{}n{}", JLPT_TAG_PREFIX, l)));
+            tags.extend(rank.and_then(|r| frequency_tag(r, &self.frequency_buckets)));
+            if tags != manual_tags(&card.card) {
+                card.card.manual_tags = Some(tags);
+                changed = true;
+            }
+        }
+        let fields = [
+            (&self.jlpt_field, level.map(|l| format!("N{}", l))),
+            (&self.frequency_field, rank.map(|r| r.to_string())),
+        ];
+        for (field, value) in fields {
+            if let (Some(field), Some(value)) = (field, value) {
+                if card.field(field).is_some_and(|v| v != value) {
+                    changed |= card.set_field(field, &value);
+                }
+            }
+        }
+
+        if changed {
+            TransformOutcome::Changed
+        } else if level.is_none() && rank.is_none() {
+            TransformOutcome::Skipped("not in any list".to_string())
+        } else {
+            TransformOutcome::Skipped("unchanged".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+
+    #[test]
+    fn test_difficulty_tags() {
+        let jlpt = JlptLevels::parse("# word, level\n箸,N4\n猫\tn5\n猫\t3\n").unwrap();
+        assert_eq!(jlpt.level("猫"), Some(5));
+        assert!(JlptLevels::parse("箸,N6").is_err());
+        let frequency = FrequencyList::parse("の\nに\n箸\t123\n");
+        assert_eq!(frequency.rank("箸"), Some(3));
+        assert_eq!(
+            frequency_tag(4210, &[1000, 5000]),
+            Some("freq-top5k".to_string())
+        );
+        assert_eq!(frequency_tag(6000, &[1000, 5000]), None);
+
+        let card = |word: &str, tags: &[&str]| {
+            CardFixture::new("c1", "deck")
+                .template("vocab")
+                .field("name", word)
+                .tags(tags)
+                .build()
+        };
+        let templates = [template_fixture("vocab", "", &[("name", "Word")])];
+        let mut resolved =
+            ResolvedCard::resolve(&[card("箸", &["jlpt-n1", "food"])], &templates).remove(0);
+        let transformer = DifficultyTransformer {
+            jlpt: Some(&jlpt),
+            frequency: Some(&frequency),
+            ..DifficultyTransformer::new("Word")
+        };
+        assert_eq!(
+            transformer.transform(&mut resolved),
+            TransformOutcome::Changed
+        );
+        assert_eq!(
+            resolved.card.manual_tags,
+            Some(vec![
+                "food".to_string(),
+                "jlpt-n4".to_string(),
+                "freq-top1k".to_string()
+            ])
+        );
+        assert_eq!(
+            transformer.transform(&mut resolved),
+            TransformOutcome::Skipped("unchanged".to_string())
+        );
+
+        let mut unknown = ResolvedCard::resolve(&[card("犬", &["food"])], &templates).remove(0);
+        assert_eq!(
+            transformer.transform(&mut unknown),
+            TransformOutcome::Skipped("not in any list".to_string())
+        );
+        let mut blank = ResolvedCard::resolve(&[card(" ", &[])], &templates).remove(0);
+        assert_eq!(
+            transformer.transform(&mut blank),
+            TransformOutcome::Skipped("no Word value".to_string())
+        );
+    }
+}
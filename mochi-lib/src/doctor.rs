@@ -0,0 +1,257 @@
+use std::collections::{BTreeSet, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use reqwest::StatusCode;
+
+use crate::coverage::is_blank_html;
+use crate::models::{Card, CardId, DeckId, FieldId, Template, TemplateId};
+use crate::references::card_references;
+use crate::sanitize::tag_problems;
+use crate::{get_card, list_cards, list_templates, Config, MochiError};
+
+// Deck Validation
+//
+// Checks a deck for the problems bulk operations tend to leave behind or
+// trip over. A field counts as required when the template content shows it,
+// `<< Name >>`. Run before and after a bulk operation, `new_issues` tells
+// what the operation broke.
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Issue {
+    MissingTemplate(TemplateId),
+    // A field shown by the template with no value, by name.
+    MissingField(String),
+    // Every field of the card is empty.
+    EmptyFields,
+    // An `@[[card-id]]` reference to a card that doesn't exist.
+    BrokenReference(CardId),
+    // Where (`content` or a field name) and what, e.g. `unclosed <b>`.
+    MalformedHtml { location: String, problem: String },
+    // Two fields with the same id, or a field stored under another's id.
+    DuplicateFieldId(FieldId),
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::MissingTemplate(id) => write!(f, "template {} does not exist", id),
+            Issue::MissingField(name) => write!(f, "required field {} is empty", name),
+            Issue::EmptyFields => write!(f, "every field is empty"),
+            Issue::BrokenReference(id) => write!(f, "reference to missing card {}", id),
+            Issue::MalformedHtml { location, problem } => {
+                write!(f, "malformed HTML in {}: {}", location, problem)
+            }
+            Issue::DuplicateFieldId(id) => write!(f, "duplicate field id {}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CardIssue {
+    pub card_id: CardId,
+    pub issue: Issue,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub cards_checked: usize,
+    // By card, then by issue.
+    pub issues: Vec<CardIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    // Issues that weren't in the earlier report.
+    pub fn new_issues(&self, before: &ValidationReport) -> Vec<&CardIssue> {
+        let before = before.issues.iter().collect::<HashSet<_>>();
+        self.issues.iter().filter(|i| !before.contains(i)).collect()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} cards checked, {} issues",
+            self.cards_checked,
+            self.issues.len()
+        )?;
+        for issue in self.issues.iter() {
+            write!(f, "\n{}: {}", issue.card_id, issue.issue)?;
+        }
+        Ok(())
+    }
+}
+
+// `existing` is every card id known to exist; references to other ids are
+// reported as broken.
+pub fn validate_cards(
+    cards: &[Card],
+    templates: &[Template],
+    existing: &HashSet<CardId>,
+) -> ValidationReport {
+    let mut issues = vec![];
+    for card in cards {
+        let mut card_issues = BTreeSet::new();
+        let template = match &card.template_id {
+            Some(id) => match templates.iter().find(|t| t.id == *id) {
+                Some(template) => Some(template),
+                None => {
+                    card_issues.insert(Issue::MissingTemplate(id.clone()));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let fields = card.fields.iter().flatten().collect::<Vec<_>>();
+        let mut ids = HashSet::new();
+        for (id, field) in fields.iter() {
+            if field.id != **id || !ids.insert(&field.id) {
+                card_issues.insert(Issue::DuplicateFieldId(field.id.clone()));
+            }
+        }
+        if !fields.is_empty() && fields.iter().all(|(_, f)| is_blank_html(&f.value)) {
+            card_issues.insert(Issue::EmptyFields);
+        }
+        if let Some(template) = template {
+            for field in template.fields.iter().flat_map(|f| f.values()) {
+                let shown = template.content.contains(&format!("<< {} >>", field.name));
+                let value = card.field_by_name(template, &field.name);
+                if shown && value.is_none_or(|v| is_blank_html(&v.value)) {
+                    card_issues.insert(Issue::MissingField(field.name.clone()));
+                }
+            }
+        }
+
+        let mut html = vec![("content".to_string(), &card.content)];
+        for (id, field) in fields.iter() {
+            let name = template
+                .and_then(|t| t.fields.as_ref()?.get(*id))
+                .map(|f| f.name.clone())
+                .unwrap_or_else(|| id.to_string());
+            html.push((name, &field.value));
+        }
+        for (location, text) in html {
+            for problem in tag_problems(text) {
+                card_issues.insert(Issue::MalformedHtml {
+                    location: location.clone(),
+                    problem,
+                });
+            }
+        }
+
+        for reference in card_references(card) {
+            if !existing.contains(&reference) {
+                card_issues.insert(Issue::BrokenReference(reference));
+            }
+        }
+
+        issues.extend(card_issues.into_iter().map(|issue| CardIssue {
+            card_id: card.id.clone(),
+            issue,
+        }));
+    }
+
+    ValidationReport {
+        cards_checked: cards.len(),
+        issues,
+    }
+}
+
+// References to cards outside the deck are looked up one by one.
+pub async fn validate_deck(
+    config: &Config,
+    deck_id: &DeckId,
+) -> Result<ValidationReport, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    let templates = list_templates(config).await?;
+
+    let mut existing = cards.iter().map(|c| c.id.clone()).collect::<HashSet<_>>();
+    let outside = cards
+        .iter()
+        .flat_map(card_references)
+        .filter(|id| !existing.contains(id))
+        .collect::<BTreeSet<_>>();
+    for card_id in outside {
+        match get_card(config, &card_id).await {
+            Ok(card) if card.trashed.is_none() => {
+                existing.insert(card_id);
+            }
+            Ok(_) => {}
+            Err(MochiError::Api { status, .. }) if status == StatusCode::NOT_FOUND => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(validate_cards(&cards, &templates, &existing))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_cards() {
+        let template = template_fixture(
+            "vocab",
+            "# << Word >>\n---\n<< Meaning >>",
+            &[("word", "Word"), ("meaning", "Meaning"), ("notes", "Notes")],
+        );
+        let cards = [
+            CardFixture::new("c1", "deck")
+                .content("See @[[c2]] and @[[gone]]")
+                .template("vocab")
+                .field("word", "<b>箸")
+                .field("meaning", "chopsticks")
+                .build(),
+            // The notes field claims the word field's id.
+            CardFixture::new("c2", "deck")
+                .template("vocab")
+                .set(
+                    "fields",
+                    json!({
+                        "word": { "id": "word", "value": "" },
+                        "notes": { "id": "word", "value": "<div></div>" },
+                    }),
+                )
+                .build(),
+            CardFixture::new("c3", "deck")
+                .content("橋")
+                .template("deleted")
+                .build(),
+        ];
+        let existing = HashSet::from([CardId::from("c1"), CardId::from("c2")]);
+
+        let report = validate_cards(&cards, &[template], &existing);
+        let issues = report
+            .issues
+            .iter()
+            .map(|i| format!("{}: {}", i.card_id, i.issue))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            [
+                "c1: reference to missing card gone",
+                "c1: malformed HTML in Word: unclosed <b>",
+                "c2: required field Meaning is empty",
+                "c2: required field Word is empty",
+                "c2: every field is empty",
+                "c2: duplicate field id word",
+                "c3: template deleted does not exist",
+            ]
+        );
+
+        let before = ValidationReport {
+            cards_checked: 3,
+            issues: report.issues[1..].to_vec(),
+        };
+        assert_eq!(report.new_issues(&before), [&report.issues[0]]);
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::models::{Card, CardId, CardPatch, DeckId, ResolvedCard, Template};
+use crate::sanitize::strip_tags;
+use crate::tags::manual_tags;
+use crate::{list_cards_for_decks, list_templates, patch_cards, trash_cards, BulkResult, Config};
+
+// Duplicate Cards
+//
+// Cards are duplicates when their keys, a field value or the content, are the
+// same after normalization. Merging keeps the first card of a cluster, gives
+// it the tags of the others and fills its empty fields from theirs, then
+// trashes the others.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DuplicateKey {
+    // The value of the field with this name.
+    Field(String),
+    Content,
+}
+
+// The default normalizer: NFKC, without HTML tags, lowercase, with whitespace
+// collapsed.
+pub fn normalize_key(text: &str) -> String {
+    let text = strip_tags(text, " ").nfkc().collect::<String>();
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[derive(Debug, Clone)]
+pub struct DuplicateCluster {
+    // The normalized key the cards share.
+    pub key: String,
+    // In listing order, at least two.
+    pub cards: Vec<Card>,
+}
+
+// Clusters in order of their first card. Cards without a key are ignored.
+pub fn find_duplicate_clusters<F>(
+    cards: &[Card],
+    templates: &[Template],
+    key: &DuplicateKey,
+    normalizer: F,
+) -> Vec<DuplicateCluster>
+where
+    F: Fn(&str) -> String,
+{
+    let mut clusters: Vec<DuplicateCluster> = vec![];
+    let mut by_key: HashMap<String, usize> = HashMap::new();
+    for card in ResolvedCard::resolve(cards, templates) {
+        let value = match key {
+            DuplicateKey::Field(name) => card.field(name).unwrap_or(""),
+            DuplicateKey::Content => card.card.content.as_str(),
+        };
+        let value = normalizer(value);
+        if value.is_empty() {
+            continue;
+        }
+        match by_key.get(&value) {
+            Some(&i) => clusters[i].cards.push(card.card),
+            None => {
+                by_key.insert(value.clone(), clusters.len());
+                clusters.push(DuplicateCluster {
+                    key: value,
+                    cards: vec![card.card],
+                });
+            }
+        }
+    }
+    clusters.retain(|c| c.cards.len() > 1);
+    clusters
+}
+
+pub async fn find_duplicates<F>(
+    config: &Config,
+    deck_ids: &[DeckId],
+    key: &DuplicateKey,
+    normalizer: F,
+) -> Result<Vec<DuplicateCluster>, Box<dyn Error>>
+where
+    F: Fn(&str) -> String,
+{
+    let mut cards_by_deck = list_cards_for_decks(config, deck_ids, None).await?;
+    // Listings finish in any order; keep the order the decks were given in.
+    let cards = deck_ids
+        .iter()
+        .filter_map(|id| cards_by_deck.remove(id))
+        .flat_map(|cards| cards.into_vec())
+        .collect::<Vec<_>>();
+    let templates = match key {
+        DuplicateKey::Field(_) => list_templates(config).await?,
+        DuplicateKey::Content => Box::new([]),
+    };
+    Ok(find_duplicate_clusters(&cards, &templates, key, normalizer))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergePlan {
+    pub keep: CardId,
+    // Empty if the kept card already has everything.
+    pub patch: CardPatch,
+    pub trash: Vec<CardId>,
+}
+
+pub fn plan_merge(cluster: &DuplicateCluster) -> MergePlan {
+    let (keep, others) = cluster.cards.split_first().expect("empty cluster");
+    let mut merged = keep.clone();
+
+    let mut tags = manual_tags(keep);
+    for tag in others.iter().flat_map(manual_tags) {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    if tags != manual_tags(keep) {
+        merged.manual_tags = Some(tags);
+    }
+
+    if merged.content.trim().is_empty() {
+        if let Some(other) = others.iter().find(|c| !c.content.trim().is_empty()) {
+            merged.content = other.content.clone();
+        }
+    }
+    // Field ids are only comparable within a template.
+    for other in others.iter().filter(|c| c.template_id == keep.template_id) {
+        for (id, field) in other.fields.iter().flatten() {
+            if field.value.trim().is_empty() {
+                continue;
+            }
+            let fields = merged.fields.get_or_insert_with(HashMap::new);
+            if fields.get(id).is_none_or(|f| f.value.trim().is_empty()) {
+                fields.insert(id.clone(), field.clone());
+            }
+        }
+    }
+
+    MergePlan {
+        keep: keep.id.clone(),
+        patch: CardPatch::between(keep, &merged),
+        trash: others.iter().map(|c| c.id.clone()).collect(),
+    }
+}
+
+#[derive(Debug)]
+pub struct MergeResult {
+    pub plans: Vec<MergePlan>,
+    // Both None for a dry run.
+    pub updates: Option<BulkResult>,
+    pub trashed: Option<BulkResult>,
+}
+
+// Duplicates are only trashed once their kept card has been updated.
+pub async fn merge_duplicates(
+    config: &Config,
+    clusters: &[DuplicateCluster],
+    dry_run: bool,
+) -> MergeResult {
+    let plans = clusters.iter().map(plan_merge).collect::<Vec<_>>();
+    if dry_run {
+        return MergeResult {
+            plans,
+            updates: None,
+            trashed: None,
+        };
+    }
+
+    let patches = plans
+        .iter()
+        .filter(|p| !p.patch.is_empty())
+        .map(|p| (p.keep.clone(), p.patch.clone()))
+        .collect::<Vec<_>>();
+    let updates = patch_cards(config, &patches).await;
+    let failed = updates.failed_ids();
+    let trash = plans
+        .iter()
+        .filter(|p| !failed.contains(&p.keep))
+        .flat_map(|p| p.trash.iter().cloned())
+        .collect::<Vec<_>>();
+    let trashed = trash_cards(config, &trash).await;
+
+    MergeResult {
+        plans,
+        updates: Some(updates),
+        trashed: Some(trashed),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+
+    fn card(id: &str, word: &str, meaning: &str, tags: &[&str]) -> Card {
+        CardFixture::new(id, "deck")
+            .template("vocab")
+            .field("word", word)
+            .field("meaning", meaning)
+            .tags(tags)
+            .build()
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let template = template_fixture("vocab", "", &[("word", "Word"), ("meaning", "Meaning")]);
+        let cards = [
+            card("c1", "箸", "", &["n5"]),
+            card("c2", "橋", "bridge", &[]),
+            card("c3", "<b>箸</b> ", "chopsticks", &["food", "n5"]),
+        ];
+
+        let clusters = find_duplicate_clusters(
+            &cards,
+            std::slice::from_ref(&template),
+            &DuplicateKey::Field("Word".to_string()),
+            normalize_key,
+        );
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].key, "箸");
+
+        assert_eq!(
+            plan_merge(&clusters[0]),
+            MergePlan {
+                keep: CardId::from("c1"),
+                patch: CardPatch::new()
+                    .manual_tags(&["n5".to_string(), "food".to_string()])
+                    .field("meaning", "chopsticks"),
+                trash: vec![CardId::from("c3")],
+            }
+        );
+
+        // Cards without a word aren't duplicates of each other.
+        let blank = [card("c4", "", "", &[]), card("c5", "<br>", "", &[])];
+        let clusters = find_duplicate_clusters(
+            &blank,
+            &[template],
+            &DuplicateKey::Field("Word".to_string()),
+            normalize_key,
+        );
+        assert!(clusters.is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+use encoding_rs::{Encoding, EUC_JP, SHIFT_JIS, UTF_8, WINDOWS_1252};
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+// Encoding Detection and Repair
+//
+// Old Japanese word lists are frequently Shift-JIS or EUC-JP, or UTF-8 that
+// went through a Latin-1 round trip somewhere. Importers decode through here
+// and get a report of which rows had to be repaired.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repair {
+    // UTF-8 bytes that had been decoded as Windows-1252 / Latin-1.
+    Mojibake,
+    // HTML entities escaped twice, e.g. `&amp;lt;`.
+    DoubleEncodedEntities,
+    // U+FFFD replacement characters. The original bytes are lost, so these
+    // rows are only reported.
+    Unrepairable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairedRow {
+    // Zero-based line index.
+    pub line: usize,
+    pub repairs: Vec<Repair>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EncodingReport {
+    pub encoding: &'static str,
+    pub rows: Vec<RepairedRow>,
+}
+
+// Decode raw bytes, detecting UTF-8 (with or without BOM), Shift-JIS and EUC-JP.
+pub fn decode(bytes: &[u8]) -> (String, &'static Encoding) {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        return (text.into_owned(), encoding);
+    }
+
+    for encoding in [UTF_8, SHIFT_JIS, EUC_JP] {
+        if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+            return (text.into_owned(), encoding);
+        }
+    }
+
+    // Nothing decodes cleanly, keep the replacement characters so that the
+    // affected rows show up in the report.
+    let (text, _) = UTF_8.decode_without_bom_handling(bytes);
+    (text.into_owned(), UTF_8)
+}
+
+fn repair_mojibake(line: &str) -> Option<String> {
+    if !line.chars().any(|c| ('\u{80}'..='\u{FF}').contains(&c)) {
+        return None;
+    }
+
+    let (bytes, _, unmappable) = WINDOWS_1252.encode(line);
+    if unmappable {
+        return None;
+    }
+    match std::str::from_utf8(&bytes) {
+        Ok(repaired) if repaired != line => Some(repaired.to_string()),
+        _ => None,
+    }
+}
+
+// Entities escaped twice, e.g. `&amp;amp;`.
+fn entity_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"&amp;(#\d+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap())
+}
+
+fn repair_entities(line: &str) -> Option<String> {
+    let regex = entity_regex();
+    let mut repaired = line.to_string();
+    while regex.is_match(&repaired) {
+        repaired = regex.replace_all(&repaired, "&$1;").to_string();
+    }
+
+    if repaired != line {
+        Some(repaired)
+    } else {
+        None
+    }
+}
+
+// Repair each line of already decoded text.
+pub fn repair(text: &str) -> (String, Vec<RepairedRow>) {
+    let mut rows = vec![];
+    let lines = text
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| {
+            let mut line = line.to_string();
+            let mut repairs = vec![];
+            if let Some(repaired) = repair_mojibake(&line) {
+                line = repaired;
+                repairs.push(Repair::Mojibake);
+            }
+            if let Some(repaired) = repair_entities(&line) {
+                line = repaired;
+                repairs.push(Repair::DoubleEncodedEntities);
+            }
+            if line.contains('\u{FFFD}') {
+                repairs.push(Repair::Unrepairable);
+            }
+            if !repairs.is_empty() {
+                rows.push(RepairedRow { line: i, repairs });
+            }
+            line
+        })
+        .collect::<Vec<_>>();
+
+    (lines.join("\n"), rows)
+}
+
+pub fn decode_and_repair(bytes: &[u8]) -> (String, EncodingReport) {
+    let (text, encoding) = decode(bytes);
+    let (text, rows) = repair(&text);
+    let report = EncodingReport {
+        encoding: encoding.name(),
+        rows,
+    };
+    (text, report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let (bytes, _, _) = SHIFT_JIS.encode("箸\tはし\t1");
+        let (text, encoding) = decode(&bytes);
+        assert_eq!(text, "箸\tはし\t1");
+        assert_eq!(encoding, SHIFT_JIS);
+
+        let (text, encoding) = decode("\u{FEFF}犬".as_bytes());
+        assert_eq!(text, "犬");
+        assert_eq!(encoding, UTF_8);
+    }
+
+    #[test]
+    fn test_repair() {
+        let (mojibake, _) = WINDOWS_1252.decode_without_bom_handling("はし".as_bytes());
+        let text = format!("café\n{}\n&amp;amp;lt;b&amp;gt;\n\u{FFFD}", mojibake);
+
+        let (text, rows) = repair(&text);
+        assert_eq!(text, "café\nはし\n&lt;b&gt;\n\u{FFFD}");
+        assert_eq!(
+            rows,
+            vec![
+                RepairedRow {
+                    line: 1,
+                    repairs: vec![Repair::Mojibake]
+                },
+                RepairedRow {
+                    line: 2,
+                    repairs: vec![Repair::DoubleEncodedEntities]
+                },
+                RepairedRow {
+                    line: 3,
+                    repairs: vec![Repair::Unrepairable]
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use futures::future::LocalBoxFuture;
+use futures::stream::{self, StreamExt};
+use sha2::{Digest, Sha256};
+
+// Enrichers
+//
+// An enricher looks something up for an input (a word, a sentence) and
+// returns field content. Each declares how many lookups may run at once, since
+// TTS is slow while an accent lookup is instant, and expensive lookups are
+// cached by input so re-running a job is nearly free.
+
+pub trait Enricher {
+    fn name(&self) -> &str;
+
+    fn concurrency(&self) -> usize {
+        1
+    }
+
+    // The cache key for an input, None to never cache this enricher.
+    fn cache_key(&self, input: &str) -> Option<String> {
+        Some(input.to_string())
+    }
+
+    fn lookup<'a>(&'a self, input: &'a str) -> LocalBoxFuture<'a, Result<String, Box<dyn Error>>>;
+}
+
+// Cached lookups, kept in memory and, if a directory is given, on disk as one
+// file per enricher and key.
+pub struct LookupCache {
+    dir: Option<PathBuf>,
+    memory: Mutex<HashMap<(String, String), String>>,
+}
+
+impl LookupCache {
+    pub fn in_memory() -> LookupCache {
+        LookupCache {
+            dir: None,
+            memory: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn on_disk(dir: PathBuf) -> LookupCache {
+        LookupCache {
+            dir: Some(dir),
+            memory: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn path(&self, enricher: &str, key: &str) -> Option<PathBuf> {
+        let hash = Sha256::digest(key.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        self.dir.as_ref().map(|d| d.join(enricher).join(hash))
+    }
+
+    pub fn get(&self, enricher: &str, key: &str) -> Option<String> {
+        let memory_key = (enricher.to_string(), key.to_string());
+        if let Some(value) = self.memory.lock().unwrap().get(&memory_key) {
+            return Some(value.clone());
+        }
+
+        let value = fs::read_to_string(self.path(enricher, key)?).ok()?;
+        self.memory
+            .lock()
+            .unwrap()
+            .insert(memory_key, value.clone());
+        Some(value)
+    }
+
+    pub fn put(&self, enricher: &str, key: &str, value: &str) -> std::io::Result<()> {
+        if let Some(path) = self.path(enricher, key) {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, value)?;
+        }
+        self.memory
+            .lock()
+            .unwrap()
+            .insert((enricher.to_string(), key.to_string()), value.to_string());
+        Ok(())
+    }
+}
+
+// Look up every input, in order, with the enricher's own concurrency.
+pub async fn run_enricher(
+    enricher: &dyn Enricher,
+    inputs: &[String],
+    cache: Option<&LookupCache>,
+) -> Vec<Result<String, String>> {
+    stream::iter(inputs.iter())
+        .map(|input| async move {
+            let key = enricher.cache_key(input);
+            if let (Some(cache), Some(key)) = (cache, &key) {
+                if let Some(value) = cache.get(enricher.name(), key) {
+                    return Ok(value);
+                }
+            }
+
+            let value = enricher.lookup(input).await.map_err(|e| e.to_string())?;
+            if let (Some(cache), Some(key)) = (cache, &key) {
+                cache
+                    .put(enricher.name(), key, &value)
+                    .map_err(|e| e.to_string())?;
+            }
+            Ok(value)
+        })
+        .buffered(enricher.concurrency().max(1))
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+
+    struct Upper {
+        lookups: Cell<usize>,
+    }
+
+    impl Enricher for Upper {
+        fn name(&self) -> &str {
+            "upper"
+        }
+
+        fn concurrency(&self) -> usize {
+            4
+        }
+
+        fn lookup<'a>(
+            &'a self,
+            input: &'a str,
+        ) -> LocalBoxFuture<'a, Result<String, Box<dyn Error>>> {
+            Box::pin(async move {
+                self.lookups.set(self.lookups.get() + 1);
+                if input.is_empty() {
+                    return Err("empty input".into());
+                }
+                Ok(input.to_uppercase())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_enricher() {
+        let enricher = Upper {
+            lookups: Cell::new(0),
+        };
+        let dir = std::env::temp_dir().join("mochi-enrich-test");
+        let _ = fs::remove_dir_all(&dir);
+        let cache = LookupCache::on_disk(dir.clone());
+        let inputs = vec!["a".to_string(), "b".to_string(), "".to_string()];
+
+        let results = run_enricher(&enricher, &inputs, Some(&cache)).await;
+        assert_eq!(results[0], Ok("A".to_string()));
+        assert_eq!(results[1], Ok("B".to_string()));
+        assert!(results[2].is_err());
+        assert_eq!(enricher.lookups.get(), 3);
+
+        // A fresh cache over the same directory reads the results from disk.
+        let cache = LookupCache::on_disk(dir);
+        let results = run_enricher(&enricher, &inputs[..2], Some(&cache)).await;
+        assert_eq!(results, vec![Ok("A".to_string()), Ok("B".to_string())]);
+        assert_eq!(enricher.lookups.get(), 3);
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::difficulty::JlptLevels;
+use crate::models::ResolvedCard;
+use crate::phrase::Tokenizer;
+use crate::pipeline::{CardTransformer, TransformOutcome};
+
+// Example Sentences
+//
+// Japanese sentences with their English translations from a Tatoeba
+// sentence pairs dump, the TSV the download page exports for a language pair:
+// `jpn id, Japanese, eng id, English`. Sentences match a word when one of their
+// tokens is the word, so 日 doesn't pull in every sentence with 日本. Without
+// a tokenizer, sentences are matched by substring.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentencePair {
+    pub japanese: String,
+    pub english: String,
+    // Surface and base forms of the tokens, empty until tokenized.
+    pub words: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Tatoeba {
+    pairs: Vec<SentencePair>,
+    // Words to pair indices, once tokenized.
+    index: Option<HashMap<String, Vec<usize>>>,
+}
+
+impl Tatoeba {
+    pub fn from_path(path: &str) -> Result<Tatoeba, Box<dyn Error>> {
+        Ok(Tatoeba::parse(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn parse(raw: &str) -> Result<Tatoeba, String> {
+        let mut pairs: Vec<SentencePair> = vec![];
+        for (i, line) in raw.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let columns = line.split('\t').collect::<Vec<_>>();
+            let [_, japanese, _, english] = columns.as_slice() else {
+                return Err(format!("line {}: expected 4 columns", i + 1));
+            };
+            // A sentence with several translations comes once per translation;
+            // keep the first.
+            if pairs.last().is_some_and(|p| p.japanese == *japanese) {
+                continue;
+            }
+            pairs.push(SentencePair {
+                japanese: japanese.to_string(),
+                english: english.to_string(),
+                words: vec![],
+            });
+        }
+        Ok(Tatoeba { pairs, index: None })
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    // Splits every sentence into words and indexes them. Slow for a full
+    // dump, so it's done once up front rather than per card.
+    pub fn tokenize(&mut self, tokenizer: &dyn Tokenizer) -> Result<(), Box<dyn Error>> {
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, pair) in self.pairs.iter_mut().enumerate() {
+            pair.words = tokenizer
+                .tokenize(&pair.japanese)?
+                .into_iter()
+                .flat_map(|token| std::iter::once(token.surface).chain(token.base_form))
+                .collect();
+            pair.words.sort();
+            pair.words.dedup();
+            for word in pair.words.iter() {
+                index.entry(word.clone()).or_default().push(i);
+            }
+        }
+        self.index = Some(index);
+        Ok(())
+    }
+
+    // The sentences containing the word, shortest first.
+    pub fn examples(&self, word: &str) -> Vec<&SentencePair> {
+        let mut pairs = match &self.index {
+            Some(index) => index
+                .get(word)
+                .into_iter()
+                .flatten()
+                .map(|i| &self.pairs[*i])
+                .collect(),
+            None => self
+                .pairs
+                .iter()
+                .filter(|p| p.japanese.contains(word))
+                .collect::<Vec<_>>(),
+        };
+        pairs.sort_by_key(|p| p.japanese.chars().count());
+        pairs
+    }
+}
+
+// Whether every word of the sentence on the JLPT list is at `level` or easier.
+// Words not on the list (names, particles) don't count.
+fn within_jlpt(pair: &SentencePair, jlpt: &JlptLevels, level: u8) -> bool {
+    pair.words
+        .iter()
+        .filter_map(|word| jlpt.level(word))
+        .all(|l| l >= level)
+}
+
+pub fn examples_html(pairs: &[&SentencePair]) -> String {
+    pairs
+        .iter()
+        .map(|p| format!("{}<br>{}", p.japanese, p.english))
+        .collect::<Vec<_>>()
+        .join("<br><br>")
+}
+
+// Transformer
+
+#[derive(Clone)]
+pub struct ExampleTransformer<'a> {
+    pub tatoeba: &'a Tatoeba,
+    pub word_field: String,
+    pub example_field: String,
+    // How many sentences to fill in.
+    pub count: usize,
+    // In characters of the Japanese sentence.
+    pub max_length: Option<usize>,
+    // Only sentences at this level (5 for N5) or easier. Needs a tokenized
+    // corpus.
+    pub jlpt: Option<(&'a JlptLevels, u8)>,
+}
+
+impl<'a> ExampleTransformer<'a> {
+    pub fn new(
+        tatoeba: &'a Tatoeba,
+        word_field: &str,
+        example_field: &str,
+    ) -> ExampleTransformer<'a> {
+        ExampleTransformer {
+            tatoeba,
+            word_field: word_field.to_string(),
+            example_field: example_field.to_string(),
+            count: 2,
+            max_length: None,
+            jlpt: None,
+        }
+    }
+}
+
+impl CardTransformer for ExampleTransformer<'_> {
+    fn name(&self) -> &str {
+        "examples"
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        if !card.has_field(&self.example_field) {
+            return TransformOutcome::Skipped(format!("no {} field", self.example_field));
+        }
+        let word = match card.field(&self.word_field).map(str::trim) {
+            Some(word) if !word.is_empty() => word.to_string(),
+            _ => return TransformOutcome::Skipped(format!("no {} value", self.word_field)),
+        };
+
+        let pairs = self
+            .tatoeba
+            .examples(&word)
+            .into_iter()
+            .filter(|p| {
+                self.max_length
+                    .is_none_or(|max| p.japanese.chars().count() <= max)
+            })
+            .filter(|p| match self.jlpt {
+                Some((jlpt, level)) => within_jlpt(p, jlpt, level),
+                None => true,
+            })
+            .take(self.count)
+            .collect::<Vec<_>>();
+        if pairs.is_empty() {
+            return TransformOutcome::Skipped("no example sentences".to_string());
+        }
+        let html = examples_html(&pairs);
+        if card.field(&self.example_field) == Some(html.as_str()) {
+            return TransformOutcome::Skipped("unchanged".to_string());
+        }
+        card.set_field(&self.example_field, &html);
+        TransformOutcome::Changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::phrase::PhraseToken;
+
+    // Splits on spaces, like a tokenizer would on word boundaries.
+    struct Spaces;
+
+    impl Tokenizer for Spaces {
+        fn tokenize(&self, text: &str) -> Result<Vec<PhraseToken>, Box<dyn Error>> {
+            Ok(text.split(' ').map(PhraseToken::new).collect())
+        }
+    }
+
+    #[test]
+    fn test_examples() {
+        let raw = "1\t日本 に 行く\t2\tI go to Japan.\n\
+                   1\t日本 に 行く\t3\tI'm going to Japan.\n\
+                   4\t日 が 昇る\t5\tThe sun rises.\n\
+                   6\t毎日 日 を 見る\t7\tI see the sun every day.\n";
+        let mut tatoeba = Tatoeba::parse(raw).unwrap();
+        assert_eq!(tatoeba.len(), 3);
+        assert_eq!(tatoeba.examples("日").len(), 3);
+
+        tatoeba.tokenize(&Spaces).unwrap();
+        assert_eq!(
+            examples_html(&tatoeba.examples("日")),
+            "日 が 昇る<br>The sun rises.<br><br>毎日 日 を 見る<br>I see the sun every day."
+        );
+
+        let jlpt = JlptLevels::parse("日,5\n毎日,5\n昇る,2").unwrap();
+        let easy = tatoeba
+            .examples("日")
+            .into_iter()
+            .filter(|p| within_jlpt(p, &jlpt, 4))
+            .collect::<Vec<_>>();
+        assert_eq!(easy.len(), 1);
+        assert!(Tatoeba::parse("1\t日本").is_err());
+    }
+}
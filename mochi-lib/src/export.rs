@@ -0,0 +1,149 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use crate::models::{Card, DeckId, ResolvedCard, Template};
+use crate::tags::manual_tags;
+use crate::{list_cards, list_templates, Config};
+
+// CSV Export
+//
+// One row per card with its id, the selected fields by name, its manual tags
+// and whether it's archived. The headers are the ones `import_csv` maps by
+// name, so an edited sheet can go straight back in. Cards without a template
+// get their content in a `content` column.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum FieldSelection {
+    // Every field of the cards' templates, in template order.
+    #[default]
+    All,
+    Names(Vec<String>),
+}
+
+// The field names to export, in the order of the templates' fields.
+fn field_columns(cards: &[ResolvedCard], selection: &FieldSelection) -> Vec<String> {
+    if let FieldSelection::Names(names) = selection {
+        return names.clone();
+    }
+    let mut columns: Vec<String> = vec![];
+    for template in cards.iter().filter_map(|c| c.template.as_ref()) {
+        let mut fields = template
+            .fields
+            .iter()
+            .flat_map(|f| f.values())
+            .collect::<Vec<_>>();
+        fields.sort_by(|a, b| a.pos.cmp(&b.pos));
+        for field in fields {
+            if !columns.contains(&field.name) {
+                columns.push(field.name.clone());
+            }
+        }
+    }
+    columns
+}
+
+pub fn write_csv<W: Write>(
+    cards: &[Card],
+    templates: &[Template],
+    selection: &FieldSelection,
+    delimiter: u8,
+    writer: W,
+) -> Result<usize, Box<dyn Error>> {
+    let cards = ResolvedCard::resolve(cards, templates);
+    let columns = field_columns(&cards, selection);
+    let with_content = cards.iter().any(|c| c.template.is_none());
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+    let mut header = vec!["card_id"];
+    if with_content {
+        header.push("content");
+    }
+    header.extend(columns.iter().map(|c| c.as_str()));
+    header.extend(["tags", "archived"]);
+    writer.write_record(&header)?;
+
+    for card in cards.iter() {
+        let mut row = vec![card.card.id.to_string()];
+        if with_content {
+            let content = match card.template {
+                Some(_) => "",
+                None => card.card.content.as_str(),
+            };
+            row.push(content.to_string());
+        }
+        row.extend(
+            columns
+                .iter()
+                .map(|name| card.field(name).unwrap_or("").to_string()),
+        );
+        row.push(manual_tags(&card.card).join(" "));
+        row.push(card.card.archived.to_string());
+        writer.write_record(&row)?;
+    }
+
+    writer.flush()?;
+    Ok(cards.len())
+}
+
+// Writes the deck's cards to the path, tab-separated for `.tsv` files.
+pub async fn export_csv(
+    config: &Config,
+    deck_id: &DeckId,
+    path: &Path,
+    selection: &FieldSelection,
+) -> Result<usize, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    let templates = list_templates(config).await?;
+    let is_tsv = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("tsv"));
+    let delimiter = if is_tsv { b'\t' } else { b',' };
+    write_csv(
+        &cards,
+        &templates,
+        selection,
+        delimiter,
+        File::create(path)?,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+
+    #[test]
+    fn test_write_csv() {
+        let template = template_fixture(
+            "vocab",
+            "<< Word >>\n---\n<< Meaning >>",
+            &[("name", "Word"), ("meaning", "Meaning")],
+        );
+        let cards = [
+            CardFixture::new("c1", "n5")
+                .template("vocab")
+                .field("name", "箸")
+                .field("meaning", "chopsticks, \"hashi\"")
+                .manual_tags(&["food", "n5"])
+                .build(),
+            CardFixture::new("c2", "n5")
+                .content("橋\n---\nbridge")
+                .archived(true)
+                .build(),
+        ];
+
+        let mut out = vec![];
+        let written = write_csv(&cards, &[template], &FieldSelection::All, b',', &mut out).unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "card_id,content,Word,Meaning,tags,archived\n\
+             c1,,箸,\"chopsticks, \"\"hashi\"\"\",food n5,false\n\
+             c2,\"橋\n---\nbridge\",,,,true\n"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use crate::decks::DeckTree;
+use crate::models::{Deck, Template};
+use crate::{list, Config, MochiError};
+
+// Find By Name
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NameMatch {
+    #[default]
+    Exact,
+    CaseInsensitive,
+    // Case-insensitive, ignoring whitespace and punctuation, and allowing a
+    // few typos. The closest name wins.
+    Fuzzy,
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(row[j + 1])
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+pub fn find_by_name<'a, T>(
+    items: &'a [T],
+    name_of: impl Fn(&T) -> &str,
+    name: &str,
+    mode: NameMatch,
+) -> Option<&'a T> {
+    match mode {
+        NameMatch::Exact => items.iter().find(|i| name_of(i) == name),
+        NameMatch::CaseInsensitive => {
+            let name = name.to_lowercase();
+            items.iter().find(|i| name_of(i).to_lowercase() == name)
+        }
+        NameMatch::Fuzzy => {
+            let name = normalize(name);
+            // Roughly one typo per four characters.
+            let max_distance = name.chars().count() / 4;
+            items
+                .iter()
+                .map(|i| (edit_distance(&normalize(name_of(i)), &name), i))
+                .filter(|(distance, _)| *distance <= max_distance)
+                .min_by_key(|(distance, _)| *distance)
+                .map(|(_, i)| i)
+        }
+    }
+}
+
+pub async fn find_deck_by_name(
+    config: &Config,
+    name: &str,
+    mode: NameMatch,
+) -> Result<Deck, MochiError> {
+    let decks = list::<Deck>("decks".to_string(), &HashMap::new(), config, None)
+        .await
+        .map_err(|e| e.source)?;
+    find_by_name(&decks, |d| &d.name, name, mode)
+        .cloned()
+        .ok_or(MochiError::NotFound {
+            kind: "deck",
+            name: name.to_string(),
+        })
+}
+
+// Find a deck by its "Japanese/N3/Vocab" path, matching each segment by mode.
+pub async fn find_deck_by_path(
+    config: &Config,
+    path: &str,
+    mode: NameMatch,
+) -> Result<Deck, MochiError> {
+    let decks = list::<Deck>("decks".to_string(), &HashMap::new(), config, None)
+        .await
+        .map_err(|e| e.source)?;
+    DeckTree::new(&decks)
+        .find_by_path_matching(path, mode)
+        .cloned()
+        .ok_or(MochiError::NotFound {
+            kind: "deck",
+            name: path.to_string(),
+        })
+}
+
+pub async fn find_template_by_name(
+    config: &Config,
+    name: &str,
+    mode: NameMatch,
+) -> Result<Template, MochiError> {
+    let templates = list::<Template>("templates".to_string(), &HashMap::new(), config, None)
+        .await
+        .map_err(|e| e.source)?;
+    find_by_name(&templates, |t| &t.name, name, mode)
+        .cloned()
+        .ok_or(MochiError::NotFound {
+            kind: "template",
+            name: name.to_string(),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find_by_name() {
+        let names = ["N3 Vocab", "N2 Vocab", "Kanji"];
+        let find = |name, mode| find_by_name(&names, |n| n, name, mode).copied();
+
+        assert_eq!(find("N3 Vocab", NameMatch::Exact), Some("N3 Vocab"));
+        assert_eq!(find("n3 vocab", NameMatch::Exact), None);
+        assert_eq!(
+            find("n3 vocab", NameMatch::CaseInsensitive),
+            Some("N3 Vocab")
+        );
+        assert_eq!(find("n3-vocab", NameMatch::CaseInsensitive), None);
+        assert_eq!(find("n3-vocab", NameMatch::Fuzzy), Some("N3 Vocab"));
+        assert_eq!(find("n3 vocob", NameMatch::Fuzzy), Some("N3 Vocab"));
+        assert_eq!(find("kanjii", NameMatch::Fuzzy), Some("Kanji"));
+        assert_eq!(find("grammar", NameMatch::Fuzzy), None);
+
+        assert_eq!(
+            MochiError::NotFound {
+                kind: "deck",
+                name: "N4".to_string()
+            }
+            .to_string(),
+            "no deck named N4"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use crate::models::ResolvedCard;
+use crate::pipeline::{CardTransformer, TransformOutcome};
+use crate::romaji::is_kana;
+use crate::{word_accents, AccentMap, KanaString};
+
+// Furigana
+//
+// Ruby markup in the `漢字[かんじ]` form, with a space before every annotated
+// run that doesn't start the word so the reading only covers the kanji:
+// `お 茶[ちゃ]`. The reading is aligned to the word by matching its kana
+// (okurigana and the like) literally; whatever is left between them is the
+// reading of the kanji in between.
+
+// Runs of the word that are read as written, or not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Kana(String),
+    Kanji { text: String, reading: String },
+}
+
+fn runs(word: &str) -> Vec<(String, bool)> {
+    let mut runs: Vec<(String, bool)> = vec![];
+    for c in word.chars() {
+        let literal = is_kana(c) || !is_kanji(c);
+        match runs.last_mut() {
+            Some((run, l)) if *l == literal => run.push(c),
+            _ => runs.push((c.to_string(), literal)),
+        }
+    }
+    runs
+}
+
+pub(crate) fn is_kanji(c: char) -> bool {
+    matches!(c, '一'..='鿿' | '㐀'..='䶿' | '々' | '〆' | 'ヵ' | 'ヶ')
+}
+
+fn hiragana(text: &str) -> Vec<char> {
+    KanaString(text.to_string())
+        .to_hiragana()
+        .0
+        .chars()
+        .collect()
+}
+
+// Where the reading of each remaining kanji run ends, shortest readings first.
+fn align_runs(runs: &[(String, bool)], reading: &[char], offset: usize) -> Option<Vec<usize>> {
+    let Some(((run, literal), rest)) = runs.split_first() else {
+        return (offset == reading.len()).then(Vec::new);
+    };
+    if *literal {
+        let run = hiragana(run);
+        let end = offset + run.len();
+        if reading.get(offset..end)? != run.as_slice() {
+            return None;
+        }
+        return align_runs(rest, reading, end);
+    }
+    (offset + 1..=reading.len()).find_map(|end| {
+        let mut ends = align_runs(rest, reading, end)?;
+        ends.insert(0, end);
+        Some(ends)
+    })
+}
+
+// The word split into kana and kanji with their readings, or None if the
+// reading doesn't fit the word.
+pub fn align(word: &str, reading: &str) -> Option<Vec<Segment>> {
+    let runs = runs(word);
+    let reading_chars = reading.chars().collect::<Vec<_>>();
+    let mut ends = align_runs(&runs, &hiragana(reading), 0)?.into_iter();
+
+    let mut offset = 0;
+    let segments = runs
+        .into_iter()
+        .map(|(run, literal)| {
+            if literal {
+                offset += run.chars().count();
+                return Segment::Kana(run);
+            }
+            let end = ends.next().unwrap();
+            let reading = reading_chars[offset..end].iter().collect();
+            offset = end;
+            Segment::Kanji { text: run, reading }
+        })
+        .collect();
+    Some(segments)
+}
+
+// `入[い]れる`. Words the reading can't be aligned to get it as a whole.
+pub fn furigana(word: &str, reading: &str) -> String {
+    let Some(segments) = align(word, reading) else {
+        return format!("{}[{}]", word, reading);
+    };
+    let mut markup = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Kana(text) => markup.push_str(&text),
+            Segment::Kanji { text, reading } => {
+                if !markup.is_empty() {
+                    markup.push(' ');
+                }
+                markup.push_str(&format!("{}[{}]", text, reading));
+            }
+        }
+    }
+    markup
+}
+
+// Transformer
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FuriganaTarget {
+    Field(String),
+    // Annotates the word where it appears in the card's content.
+    Content,
+}
+
+#[derive(Clone)]
+pub struct FuriganaTransformer<'a> {
+    // Readings for cards without a reading field.
+    pub accents: &'a AccentMap,
+    pub word_field: String,
+    pub reading_field: Option<String>,
+    pub target: FuriganaTarget,
+}
+
+impl<'a> FuriganaTransformer<'a> {
+    pub fn new(
+        accents: &'a AccentMap,
+        word_field: &str,
+        target: FuriganaTarget,
+    ) -> FuriganaTransformer<'a> {
+        FuriganaTransformer {
+            accents,
+            word_field: word_field.to_string(),
+            reading_field: None,
+            target,
+        }
+    }
+
+    fn reading(&self, card: &ResolvedCard, word: &str) -> Option<String> {
+        let field = self
+            .reading_field
+            .as_ref()
+            .and_then(|name| card.field(name))
+            .map(str::trim)
+            .filter(|r| !r.is_empty());
+        match field {
+            Some(reading) => Some(reading.to_string()),
+            // Homographs are ambiguous; go with the first reading.
+            None => word_accents(&word.to_string(), None, self.accents)
+                .first()
+                .map(|wa| wa.kana.0.clone()),
+        }
+    }
+}
+
+impl CardTransformer for FuriganaTransformer<'_> {
+    fn name(&self) -> &str {
+        "furigana"
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        if let FuriganaTarget::Field(field) = &self.target {
+            if !card.has_field(field) {
+                return TransformOutcome::Skipped(format!("no {} field", field));
+            }
+        }
+        let word = match card.field(&self.word_field).map(str::trim) {
+            Some(word) if !word.is_empty() => word.to_string(),
+            _ => return TransformOutcome::Skipped(format!("no {} value", self.word_field)),
+        };
+        if !word.chars().any(is_kanji) {
+            return TransformOutcome::Skipped("no kanji".to_string());
+        }
+        let Some(reading) = self.reading(card, &word) else {
+            return TransformOutcome::Skipped("no reading".to_string());
+        };
+
+        let markup = furigana(&word, &reading);
+        match &self.target {
+            FuriganaTarget::Field(field) => {
+                if card.field(field) == Some(markup.as_str()) {
+                    return TransformOutcome::Skipped("unchanged".to_string());
+                }
+                card.set_field(field, &markup);
+            }
+            FuriganaTarget::Content => {
+                if card.card.content.contains(&markup) {
+                    return TransformOutcome::Skipped("unchanged".to_string());
+                }
+                if !card.card.content.contains(&word) {
+                    return TransformOutcome::Skipped("word not in content".to_string());
+                }
+                card.card.content = card.card.content.replace(&word, &markup);
+            }
+        }
+        TransformOutcome::Changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_furigana() {
+        assert_eq!(furigana("漢字", "かんじ"), "漢字[かんじ]");
+        assert_eq!(furigana("入れる", "いれる"), "入[い]れる");
+        assert_eq!(furigana("お茶", "おちゃ"), "お 茶[ちゃ]");
+        assert_eq!(furigana("引き出し", "ひきだし"), "引[ひ]き 出[だ]し");
+        assert_eq!(
+            furigana("取り扱う", "とりあつかう"),
+            "取[と]り 扱[あつか]う"
+        );
+        // Katakana readings still match hiragana okurigana.
+        assert_eq!(furigana("食べる", "タベル"), "食[タ]べる");
+        // Readings that don't fit cover the whole word.
+        assert_eq!(furigana("今日は", "きょうわ"), "今日は[きょうわ]");
+    }
+}
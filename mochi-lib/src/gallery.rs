@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use crate::models::{FieldId, Template, TemplateField, TemplateId};
+use crate::profiles::ConfigFile;
+use crate::{create_template, list_templates, Config};
+
+// Template Gallery
+//
+// Ready-made Japanese templates. Installing one creates it in the account and
+// records its id in the config under the gallery key, so pipelines can find it
+// without hardcoding ids.
+
+#[derive(Debug, Clone, Copy)]
+pub struct GalleryTemplate {
+    pub key: &'static str,
+    pub name: &'static str,
+    pub content: &'static str,
+    // (field id, field name) in display order.
+    pub fields: &'static [(&'static str, &'static str)],
+}
+
+pub const JAPANESE_VOCAB: GalleryTemplate = GalleryTemplate {
+    key: "japanese-vocab",
+    name: "Japanese Vocab",
+    content: "# << Word >>\n---\n<< Reading >>\n\n<< PitchAccent >>\n\n<< Meaning >>",
+    fields: &[
+        ("name", "Word"),
+        ("reading", "Reading"),
+        ("meaning", "Meaning"),
+        ("pitch-accent", "PitchAccent"),
+    ],
+};
+
+pub const JAPANESE_SENTENCE: GalleryTemplate = GalleryTemplate {
+    key: "japanese-sentence",
+    name: "Japanese Sentence",
+    content: "<< Sentence >>\n---\n<< Translation >>\n\n<< Notes >>",
+    fields: &[
+        ("name", "Sentence"),
+        ("translation", "Translation"),
+        ("notes", "Notes"),
+    ],
+};
+
+pub const JAPANESE_KANJI: GalleryTemplate = GalleryTemplate {
+    key: "japanese-kanji",
+    name: "Japanese Kanji",
+    content: "# << Kanji >>\n---\n<< Meanings >>\n\nOn: << Onyomi >>\nKun: << Kunyomi >>\n\n<< Examples >>",
+    fields: &[
+        ("name", "Kanji"),
+        ("meanings", "Meanings"),
+        ("onyomi", "Onyomi"),
+        ("kunyomi", "Kunyomi"),
+        ("examples", "Examples"),
+    ],
+};
+
+pub fn gallery() -> &'static [GalleryTemplate] {
+    &[JAPANESE_VOCAB, JAPANESE_SENTENCE, JAPANESE_KANJI]
+}
+
+pub fn find_gallery_template(key: &str) -> Option<&'static GalleryTemplate> {
+    gallery().iter().find(|t| t.key == key)
+}
+
+impl GalleryTemplate {
+    pub fn to_template(&self) -> Template {
+        let fields = self
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, (id, name))| {
+                let field = TemplateField {
+                    id: FieldId::from(*id),
+                    name: name.to_string(),
+                    // Mochi orders fields by their `pos` string.
+                    pos: ((b'a' + i as u8) as char).to_string(),
+                    options: None,
+                };
+                (field.id.clone(), field)
+            })
+            .collect::<HashMap<_, _>>();
+
+        Template {
+            name: self.name.to_string(),
+            content: self.content.to_string(),
+            fields: Some(fields),
+            pos: None,
+            id: TemplateId::default(),
+            extra: HashMap::new(),
+        }
+    }
+}
+
+// Create the gallery template in the account, reusing an existing template of
+// the same name, and record its id in the config. With a config profile the id
+// is also saved to the profile's `templates` in config.toml, for later runs.
+pub async fn install_template(
+    config: &mut Config,
+    key: &str,
+) -> Result<Template, Box<dyn std::error::Error>> {
+    let gallery_template =
+        find_gallery_template(key).ok_or(format!("no gallery template named {}", key))?;
+
+    let existing = list_templates(config).await?;
+    let template = match existing.iter().find(|t| t.name == gallery_template.name) {
+        Some(template) => template.clone(),
+        None => create_template(config, &gallery_template.to_template()).await?,
+    };
+
+    config
+        .templates
+        .insert(key.to_string(), template.id.clone());
+    if let (Some(profile), Some(path)) = (&config.profile, ConfigFile::default_path()) {
+        ConfigFile::save_template(&path, profile, key, &template.id)?;
+    }
+    Ok(template)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gallery_templates() {
+        for gallery_template in gallery() {
+            let template = gallery_template.to_template();
+            let fields = template.fields.unwrap();
+            assert_eq!(fields.len(), gallery_template.fields.len());
+            // Every field is used by the template content.
+            for field in fields.values() {
+                assert!(
+                    template.content.contains(&format!("<< {} >>", field.name)),
+                    "{} in {}",
+                    field.name,
+                    gallery_template.key
+                );
+            }
+        }
+
+        let vocab = find_gallery_template("japanese-vocab").unwrap();
+        assert_eq!(vocab.to_template().fields.unwrap()["reading"].pos, "b");
+        assert!(find_gallery_template("klingon-vocab").is_none());
+    }
+}
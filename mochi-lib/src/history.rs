@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::Cache;
+use crate::{unix_now, BulkResult};
+
+// Run History
+//
+// One row per tool run in the local cache, so "what did this change last
+// Tuesday?" can be answered without digging through shell history.
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunRecord {
+    // Seconds since the unix epoch.
+    pub started: u64,
+    pub finished: u64,
+    pub command: String,
+    #[serde(default)]
+    pub parameters: BTreeMap<String, String>,
+    // e.g. "updated" => 120, "failed" => 2
+    #[serde(default)]
+    pub counts: BTreeMap<String, usize>,
+    // The audit or import log written by the run, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_file: Option<PathBuf>,
+}
+
+impl RunRecord {
+    pub fn start(command: &str) -> RunRecord {
+        let now = unix_now();
+        RunRecord {
+            started: now,
+            finished: now,
+            command: command.to_string(),
+            parameters: BTreeMap::new(),
+            counts: BTreeMap::new(),
+            audit_file: None,
+        }
+    }
+
+    pub fn parameter(mut self, name: &str, value: impl ToString) -> RunRecord {
+        self.parameters.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn count(&mut self, name: &str, count: usize) {
+        *self.counts.entry(name.to_string()).or_default() += count;
+    }
+
+    // The run's "updated" and "failed" counts.
+    pub fn count_result(&mut self, result: &BulkResult) {
+        self.count("updated", result.succeeded.len());
+        self.count("failed", result.failed.len());
+    }
+
+    pub fn finish(&mut self) {
+        self.finished = unix_now();
+    }
+}
+
+impl fmt::Display for RunRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.started, self.command)?;
+        for (name, value) in self.parameters.iter() {
+            write!(f, " --{} {}", name, value)?;
+        }
+        let counts = self
+            .counts
+            .iter()
+            .map(|(name, count)| format!("{} {}", count, name))
+            .collect::<Vec<_>>();
+        if !counts.is_empty() {
+            write!(f, " ({})", counts.join(", "))?;
+        }
+        if let Some(audit_file) = &self.audit_file {
+            write!(f, " -> {}", audit_file.display())?;
+        }
+        Ok(())
+    }
+}
+
+const HISTORY_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        -- Seconds since the unix epoch.
+        started INTEGER NOT NULL,
+        json TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS runs_started ON runs (started);
+";
+
+pub struct RunHistory {
+    cache: Cache,
+}
+
+impl RunHistory {
+    pub fn new(cache: Cache) -> Result<RunHistory, Box<dyn Error>> {
+        cache.db.execute_batch(HISTORY_SCHEMA)?;
+        Ok(RunHistory { cache })
+    }
+
+    // The history in the default cache, `None` without a cache directory.
+    pub fn open_default() -> Result<Option<RunHistory>, Box<dyn Error>> {
+        match Cache::default_path() {
+            Some(path) => Ok(Some(RunHistory::new(Cache::open(&path)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn into_cache(self) -> Cache {
+        self.cache
+    }
+
+    pub fn append(&self, record: &RunRecord) -> Result<(), Box<dyn Error>> {
+        self.cache.db.execute(
+            "INSERT INTO runs (started, json) VALUES (?, ?)",
+            params![record.started as i64, serde_json::to_string(record)?],
+        )?;
+        Ok(())
+    }
+
+    // Oldest first.
+    pub fn runs(&self) -> Result<Vec<RunRecord>, Box<dyn Error>> {
+        self.runs_between(0, i64::MAX as u64)
+    }
+
+    // Runs started within [from, to), as unix seconds.
+    pub fn runs_between(&self, from: u64, to: u64) -> Result<Vec<RunRecord>, Box<dyn Error>> {
+        let mut query = self.cache.db.prepare(
+            "SELECT json FROM runs WHERE started >= ? AND started < ? ORDER BY started, seq",
+        )?;
+        let rows = query.query_map(params![from as i64, to as i64], |row| {
+            row.get::<_, String>(0)
+        })?;
+        let mut runs = vec![];
+        for json in rows {
+            runs.push(serde_json::from_str(&json?)?);
+        }
+        Ok(runs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_run_history() {
+        let history = RunHistory::new(Cache::open_in_memory().unwrap()).unwrap();
+        assert!(history.runs().unwrap().is_empty());
+
+        let mut record = RunRecord::start("pitch-accent").parameter("deck", "N3");
+        record.started = 100;
+        record.count("updated", 120);
+        record.count("failed", 2);
+        record.audit_file = Some(PathBuf::from("audit.ndjson"));
+        history.append(&record).unwrap();
+
+        let mut other = RunRecord::start("retag");
+        other.started = 200;
+        history.append(&other).unwrap();
+
+        let runs = history.runs().unwrap();
+        assert_eq!(runs, vec![record.clone(), other]);
+        assert_eq!(history.runs_between(0, 150).unwrap(), vec![record.clone()]);
+        assert_eq!(
+            record.to_string(),
+            "100 pitch-accent --deck N3 (2 failed, 120 updated) -> audit.ndjson"
+        );
+    }
+}
@@ -0,0 +1,733 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::duplicates::{normalize_key, DuplicateKey};
+use crate::encoding;
+use crate::models::{Card, CardBuilder, CardId, DeckId, Template, TemplateId};
+use crate::{create_card, list_cards, list_templates, Config, MochiError};
+
+// Column Inference
+//
+// Guess what each column of a word list holds from its content, so headerless
+// files can be previewed and corrected before any card is created.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ColumnRole {
+    Word,
+    Reading,
+    Meaning,
+    Tags,
+    Ignore,
+}
+
+#[derive(Debug, Clone)]
+pub struct ColumnGuess {
+    pub index: usize,
+    pub role: ColumnRole,
+    // Share of the sampled cells that support the guess, 1.0 for a header.
+    pub confidence: f32,
+    pub samples: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MappingPreview {
+    pub has_header: bool,
+    pub columns: Vec<ColumnGuess>,
+}
+
+const SAMPLE_SIZE: usize = 3;
+
+fn is_kanji(c: char) -> bool {
+    ('\u{4E00}'..='\u{9FFF}').contains(&c) || ('\u{3400}'..='\u{4DBF}').contains(&c) || c == '々'
+}
+
+fn is_kana(c: char) -> bool {
+    ('\u{3041}'..='\u{309F}').contains(&c) || ('\u{30A0}'..='\u{30FF}').contains(&c)
+}
+
+fn looks_like_reading(cell: &str) -> bool {
+    !cell.is_empty() && cell.chars().all(is_kana)
+}
+
+fn looks_like_meaning(cell: &str) -> bool {
+    let letters = cell.chars().filter(|c| c.is_ascii_alphabetic()).count();
+    letters > 0 && cell.chars().all(|c| c.is_ascii() || c.is_whitespace())
+}
+
+fn looks_like_tags(cell: &str) -> bool {
+    let tokens = cell
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>();
+    !tokens.is_empty()
+        && tokens.iter().all(|t| {
+            t.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        })
+        && tokens
+            .iter()
+            .any(|t| t.contains('-') || t.contains('_') || t.chars().any(|c| c.is_ascii_digit()))
+}
+
+fn header_role(cell: &str) -> Option<ColumnRole> {
+    match cell.trim().to_lowercase().as_str() {
+        "word" | "expression" | "vocab" | "kanji" | "term" => Some(ColumnRole::Word),
+        "reading" | "kana" | "furigana" => Some(ColumnRole::Reading),
+        "meaning" | "english" | "definition" | "gloss" => Some(ColumnRole::Meaning),
+        "tags" | "tag" => Some(ColumnRole::Tags),
+        _ => None,
+    }
+}
+
+fn fraction(cells: &[&str], predicate: impl Fn(&str) -> bool) -> f32 {
+    let non_empty = cells
+        .iter()
+        .filter(|c| !c.trim().is_empty())
+        .collect::<Vec<_>>();
+    if non_empty.is_empty() {
+        return 0.0;
+    }
+    non_empty.iter().filter(|c| predicate(c.trim())).count() as f32 / non_empty.len() as f32
+}
+
+// Pick the best remaining column for a role, if any clears the threshold.
+fn best_column(
+    scores: &[f32],
+    assigned: &HashMap<usize, (ColumnRole, f32)>,
+    threshold: f32,
+) -> Option<(usize, f32)> {
+    scores
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(i, score)| !assigned.contains_key(i) && *score >= threshold)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+pub fn infer_columns(rows: &[Vec<String>]) -> MappingPreview {
+    let n_columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let header = rows
+        .first()
+        .map(|r| r.iter().map(|c| header_role(c)).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let has_header = header.iter().any(|r| r.is_some());
+    let body = if has_header { &rows[1..] } else { rows };
+
+    let columns = (0..n_columns)
+        .map(|i| {
+            body.iter()
+                .map(|r| r.get(i).map(|c| c.as_str()).unwrap_or(""))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut assigned: HashMap<usize, (ColumnRole, f32)> = HashMap::new();
+    if has_header {
+        for (i, role) in header.iter().enumerate() {
+            assigned.insert(i, (role.unwrap_or(ColumnRole::Ignore), 1.0));
+        }
+    } else {
+        let kanji = columns
+            .iter()
+            .map(|c| fraction(c, |s| s.chars().any(is_kanji)))
+            .collect::<Vec<_>>();
+        let japanese = columns
+            .iter()
+            .map(|c| fraction(c, |s| s.chars().any(|ch| is_kanji(ch) || is_kana(ch))))
+            .collect::<Vec<_>>();
+        let reading = columns
+            .iter()
+            .map(|c| fraction(c, looks_like_reading))
+            .collect::<Vec<_>>();
+        let meaning = columns
+            .iter()
+            .map(|c| fraction(c, looks_like_meaning))
+            .collect::<Vec<_>>();
+        let tags = columns
+            .iter()
+            .map(|c| fraction(c, looks_like_tags))
+            .collect::<Vec<_>>();
+
+        // The word is the column with the most kanji, or failing that the
+        // most Japanese-looking column (kana-only word lists).
+        let word =
+            best_column(&kanji, &assigned, 0.3).or_else(|| best_column(&japanese, &assigned, 0.5));
+        if let Some((i, score)) = word {
+            assigned.insert(i, (ColumnRole::Word, score));
+        }
+        if let Some((i, score)) = best_column(&reading, &assigned, 0.5) {
+            assigned.insert(i, (ColumnRole::Reading, score));
+        }
+        if let Some((i, score)) = best_column(&tags, &assigned, 0.8) {
+            assigned.insert(i, (ColumnRole::Tags, score));
+        }
+        if let Some((i, score)) = best_column(&meaning, &assigned, 0.5) {
+            assigned.insert(i, (ColumnRole::Meaning, score));
+        }
+    }
+
+    let columns = columns
+        .iter()
+        .enumerate()
+        .map(|(i, cells)| {
+            let (role, confidence) = assigned
+                .get(&i)
+                .copied()
+                .unwrap_or((ColumnRole::Ignore, 0.0));
+            ColumnGuess {
+                index: i,
+                role,
+                confidence,
+                samples: cells
+                    .iter()
+                    .filter(|c| !c.trim().is_empty())
+                    .take(SAMPLE_SIZE)
+                    .map(|c| c.to_string())
+                    .collect(),
+            }
+        })
+        .collect();
+
+    MappingPreview {
+        has_header,
+        columns,
+    }
+}
+
+impl MappingPreview {
+    // Override a guess. Any other column holding the role is set to Ignore.
+    pub fn set_role(&mut self, index: usize, role: ColumnRole) {
+        for column in self.columns.iter_mut() {
+            if column.index == index {
+                column.role = role;
+                column.confidence = 1.0;
+            } else if role != ColumnRole::Ignore && column.role == role {
+                column.role = ColumnRole::Ignore;
+                column.confidence = 0.0;
+            }
+        }
+    }
+
+    pub fn column_for(&self, role: ColumnRole) -> Option<usize> {
+        self.columns
+            .iter()
+            .find(|c| c.role == role)
+            .map(|c| c.index)
+    }
+
+    // The confirmed mapping from role to column index.
+    pub fn mapping(&self) -> HashMap<ColumnRole, usize> {
+        self.columns
+            .iter()
+            .filter(|c| c.role != ColumnRole::Ignore)
+            .map(|c| (c.role, c.index))
+            .collect()
+    }
+}
+
+impl fmt::Display for MappingPreview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for column in self.columns.iter() {
+            writeln!(
+                f,
+                "column {}: {:?} ({:.0}%) e.g. {}",
+                column.index,
+                column.role,
+                column.confidence * 100.0,
+                column.samples.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Import Log
+//
+// Every input row's outcome as NDJSON, plus the failed rows written back out
+// in their original format so they can be fixed and imported again.
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum RowStatus {
+    Created { card_id: CardId },
+    Skipped { reason: String },
+    Failed { reason: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowOutcome {
+    // One-based line number in the input file.
+    pub line: usize,
+    #[serde(flatten)]
+    pub status: RowStatus,
+    #[serde(skip)]
+    pub raw: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportLog {
+    pub rows: Vec<RowOutcome>,
+}
+
+impl ImportLog {
+    pub fn record(&mut self, line: usize, raw: &str, status: RowStatus) {
+        self.rows.push(RowOutcome {
+            line,
+            status,
+            raw: raw.to_string(),
+        });
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &RowOutcome> {
+        self.rows
+            .iter()
+            .filter(|r| matches!(r.status, RowStatus::Failed { .. }))
+    }
+
+    // (created, skipped, failed)
+    pub fn counts(&self) -> (usize, usize, usize) {
+        self.rows
+            .iter()
+            .fold((0, 0, 0), |(c, s, f), r| match r.status {
+                RowStatus::Created { .. } => (c + 1, s, f),
+                RowStatus::Skipped { .. } => (c, s + 1, f),
+                RowStatus::Failed { .. } => (c, s, f + 1),
+            })
+    }
+
+    pub fn write_log<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for row in self.rows.iter() {
+            serde_json::to_writer(&mut writer, row)?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    // The header (if any) and each failed row verbatim, preceded by a `#`
+    // comment line holding the reason. Importers skip `#` lines, so the file
+    // can be fed straight back in once fixed.
+    pub fn write_failures<W: Write>(&self, mut writer: W, header: Option<&str>) -> io::Result<()> {
+        if let Some(header) = header {
+            writeln!(writer, "{}", header)?;
+        }
+        for row in self.failures() {
+            if let RowStatus::Failed { reason } = &row.status {
+                writeln!(writer, "# line {}: {}", row.line, reason.replace('\n', " "))?;
+            }
+            writeln!(writer, "{}", row.raw)?;
+        }
+        Ok(())
+    }
+
+    pub fn save(
+        &self,
+        log_path: &Path,
+        failures_path: &Path,
+        header: Option<&str>,
+    ) -> io::Result<()> {
+        self.write_log(BufWriter::new(File::create(log_path)?))?;
+        if self.failures().next().is_some() {
+            self.write_failures(BufWriter::new(File::create(failures_path)?), header)?;
+        }
+        Ok(())
+    }
+}
+
+// CSV Import
+//
+// Rows become cards of one template. Columns map to fields by index, or by
+// header name when no fields are given; `tags` and `archived` header columns
+// are then taken as such. Rows whose dedupe key matches a card already in
+// the deck, or an earlier row, are skipped.
+
+#[derive(Debug, Clone, Default)]
+pub struct CsvMapping {
+    // (column, field name). Empty to map by header name.
+    pub fields: Vec<(usize, String)>,
+    // Columns of tags separated by spaces or commas.
+    pub tag_columns: Vec<usize>,
+    pub has_header: bool,
+    // Tab for `.tsv` files and comma otherwise, if None.
+    pub delimiter: Option<u8>,
+}
+
+impl CsvMapping {
+    pub fn from_header() -> CsvMapping {
+        CsvMapping {
+            has_header: true,
+            ..CsvMapping::default()
+        }
+    }
+
+    // The columns of the confirmed preview, with the field name for each
+    // role. Roles without a name are left out.
+    pub fn from_preview(
+        preview: &MappingPreview,
+        names: &HashMap<ColumnRole, String>,
+    ) -> CsvMapping {
+        let mut mapping = CsvMapping {
+            has_header: preview.has_header,
+            ..CsvMapping::default()
+        };
+        for (role, column) in preview.mapping() {
+            match (role, names.get(&role)) {
+                (ColumnRole::Tags, _) => mapping.tag_columns.push(column),
+                (_, Some(name)) => mapping.fields.push((column, name.clone())),
+                (_, None) => {}
+            }
+        }
+        mapping.fields.sort();
+        mapping
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvRow {
+    pub line: usize,
+    pub raw: String,
+    pub card: Card,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CsvImportPlan {
+    // The rows to create.
+    pub rows: Vec<CsvRow>,
+    // Skipped and failed rows, and created ones once imported.
+    pub log: ImportLog,
+}
+
+// Cards for the rows of the text. `existing` are the cards already in the
+// deck, for deduplication.
+pub fn plan_csv_import(
+    text: &str,
+    delimiter: u8,
+    deck_id: &DeckId,
+    template: &Template,
+    mapping: &CsvMapping,
+    dedupe_key: Option<&DuplicateKey>,
+    existing: &[Card],
+) -> Result<CsvImportPlan, Box<dyn Error>> {
+    // `#` lines are blanked, so line numbers stay those of the file.
+    let text = text
+        .split('\n')
+        .map(|line| if line.starts_with('#') { "" } else { line })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(mapping.has_header)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+
+    let mut fields = mapping.fields.clone();
+    let mut tag_columns = mapping.tag_columns.clone();
+    let mut archived_column = None;
+    if fields.is_empty() && mapping.has_header {
+        for (i, name) in reader.headers()?.iter().enumerate() {
+            let name = name.trim();
+            if let Some(field) = template
+                .fields
+                .iter()
+                .flat_map(|f| f.values())
+                .find(|f| f.name.eq_ignore_ascii_case(name))
+            {
+                fields.push((i, field.name.clone()));
+            } else if header_role(name) == Some(ColumnRole::Tags) {
+                tag_columns.push(i);
+            } else if name.eq_ignore_ascii_case("archived") {
+                archived_column = Some(i);
+            }
+        }
+    }
+    if let Some((_, name)) = fields
+        .iter()
+        .find(|(_, name)| template.field_by_name(name).is_none())
+    {
+        return Err(MochiError::NotFound {
+            kind: "field",
+            name: name.clone(),
+        }
+        .into());
+    }
+
+    let key_of = |card: &Card| -> Option<String> {
+        let value = match dedupe_key? {
+            DuplicateKey::Field(name) => card.field_by_name(template, name)?.value.as_str(),
+            DuplicateKey::Content => card.content.as_str(),
+        };
+        Some(normalize_key(value)).filter(|k| !k.is_empty())
+    };
+    let mut seen = existing.iter().filter_map(key_of).collect::<HashSet<_>>();
+
+    let separator = (delimiter as char).to_string();
+    let mut plan = CsvImportPlan::default();
+    for record in reader.records() {
+        let record = record?;
+        // The reader's position is where it started skipping blank lines.
+        let position = record
+            .position()
+            .cloned()
+            .unwrap_or_else(csv::Position::new);
+        let skipped = text[position.byte() as usize..]
+            .chars()
+            .take_while(|c| matches!(c, '\n' | '\r'))
+            .filter(|c| *c == '\n')
+            .count();
+        let line = position.line() as usize + skipped;
+        let raw = record.iter().collect::<Vec<_>>().join(&separator);
+        if record.iter().all(|cell| cell.trim().is_empty()) {
+            plan.log.record(
+                line,
+                &raw,
+                RowStatus::Skipped {
+                    reason: "empty row".to_string(),
+                },
+            );
+            continue;
+        }
+
+        let mut builder = CardBuilder::new(deck_id.clone()).template(template);
+        for (column, name) in fields.iter() {
+            let value = record.get(*column).unwrap_or("").trim();
+            if !value.is_empty() {
+                builder = builder.field(name, value);
+            }
+        }
+        for column in tag_columns.iter() {
+            let tags = record.get(*column).unwrap_or("");
+            for tag in tags.split(|c: char| c.is_whitespace() || c == ',') {
+                if !tag.is_empty() {
+                    builder = builder.tag(tag);
+                }
+            }
+        }
+        if let Some(column) = archived_column {
+            let archived = record.get(column).unwrap_or("").trim();
+            builder = builder.archived(matches!(archived, "true" | "1" | "yes"));
+        }
+        let card = match builder.build() {
+            Ok(card) => card,
+            Err(err) => {
+                let reason = err.to_string();
+                plan.log.record(line, &raw, RowStatus::Failed { reason });
+                continue;
+            }
+        };
+        if let Some(key) = key_of(&card) {
+            if !seen.insert(key.clone()) {
+                let reason = format!("duplicate of {}", key);
+                plan.log.record(line, &raw, RowStatus::Skipped { reason });
+                continue;
+            }
+        }
+        plan.rows.push(CsvRow { line, raw, card });
+    }
+    Ok(plan)
+}
+
+// Creates cards of the template in the deck from a CSV or TSV file, in any
+// encoding `encoding::decode` detects. With `preview`, nothing is created and
+// the plan holds only the first cards.
+pub async fn import_csv(
+    config: &Config,
+    path: &Path,
+    deck_id: &DeckId,
+    template_id: &TemplateId,
+    mapping: &CsvMapping,
+    dedupe_key: Option<&DuplicateKey>,
+    preview: Option<usize>,
+) -> Result<CsvImportPlan, Box<dyn Error>> {
+    let (text, _) = encoding::decode(&fs::read(path)?);
+    let is_tsv = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("tsv"));
+    let delimiter = mapping
+        .delimiter
+        .unwrap_or(if is_tsv { b'\t' } else { b',' });
+
+    let templates = list_templates(config).await?;
+    let template = templates
+        .iter()
+        .find(|t| t.id == *template_id)
+        .ok_or_else(|| MochiError::NotFound {
+            kind: "template",
+            name: template_id.to_string(),
+        })?;
+    let existing = match dedupe_key {
+        Some(_) => list_cards(config, deck_id, None).await?.into_vec(),
+        None => vec![],
+    };
+    let mut plan = plan_csv_import(
+        &text, delimiter, deck_id, template, mapping, dedupe_key, &existing,
+    )?;
+
+    if let Some(n) = preview {
+        plan.rows.truncate(n);
+        return Ok(plan);
+    }
+    for row in plan.rows.iter() {
+        let status = match create_card(config, &row.card).await {
+            Ok(created) => RowStatus::Created {
+                card_id: created.id,
+            },
+            Err(err) => RowStatus::Failed {
+                reason: err.to_string(),
+            },
+        };
+        plan.log.record(row.line, &row.raw, status);
+    }
+    plan.log.rows.sort_by_key(|r| r.line);
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::template_fixture;
+
+    fn rows(raw: &[&[&str]]) -> Vec<Vec<String>> {
+        raw.iter()
+            .map(|r| r.iter().map(|c| c.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_infer_columns() {
+        let rows = rows(&[
+            &["dog", "犬", "いぬ", "jlpt-n5 noun"],
+            &["cat", "猫", "ねこ", "jlpt-n5"],
+            &["to eat", "食べる", "たべる", "jlpt-n5 verb"],
+        ]);
+        let preview = infer_columns(&rows);
+        assert!(!preview.has_header);
+        assert_eq!(preview.column_for(ColumnRole::Word), Some(1));
+        assert_eq!(preview.column_for(ColumnRole::Reading), Some(2));
+        assert_eq!(preview.column_for(ColumnRole::Meaning), Some(0));
+        assert_eq!(preview.column_for(ColumnRole::Tags), Some(3));
+        assert_eq!(preview.columns[1].samples, vec!["犬", "猫", "食べる"]);
+    }
+
+    #[test]
+    fn test_infer_columns_with_header() {
+        let rows = rows(&[&["Reading", "Word", "Notes"], &["いぬ", "犬", "?"]]);
+        let mut preview = infer_columns(&rows);
+        assert!(preview.has_header);
+        assert_eq!(preview.column_for(ColumnRole::Word), Some(1));
+        assert_eq!(preview.column_for(ColumnRole::Reading), Some(0));
+
+        preview.set_role(2, ColumnRole::Word);
+        assert_eq!(preview.column_for(ColumnRole::Word), Some(2));
+        assert_eq!(preview.columns[1].role, ColumnRole::Ignore);
+        assert_eq!(preview.mapping().len(), 2);
+    }
+
+    #[test]
+    fn test_import_log() {
+        let mut log = ImportLog::default();
+        log.record(
+            2,
+            "犬\tいぬ",
+            RowStatus::Created {
+                card_id: "abc".into(),
+            },
+        );
+        log.record(
+            3,
+            "猫\tねこ",
+            RowStatus::Failed {
+                reason: "API error".to_string(),
+            },
+        );
+        log.record(
+            4,
+            "",
+            RowStatus::Skipped {
+                reason: "empty".to_string(),
+            },
+        );
+        assert_eq!(log.counts(), (1, 1, 1));
+
+        let mut ndjson = vec![];
+        log.write_log(&mut ndjson).unwrap();
+        let first = String::from_utf8(ndjson).unwrap();
+        let first = first.lines().next().unwrap();
+        assert_eq!(first, r#"{"line":2,"status":"created","card_id":"abc"}"#);
+
+        let mut failures = vec![];
+        log.write_failures(&mut failures, Some("word\treading"))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(failures).unwrap(),
+            "word\treading\n# line 3: API error\n猫\tねこ\n"
+        );
+    }
+
+    #[test]
+    fn test_plan_csv_import() {
+        let template = template_fixture(
+            "vocab",
+            "<< Word >>\n---\n<< Meaning >>",
+            &[("name", "Word"), ("meaning", "Meaning")],
+        );
+        let existing = CardBuilder::new("n5")
+            .template(&template)
+            .field("Word", "犬")
+            .build()
+            .unwrap();
+        let text = "word\tmeaning\ttags\n犬\tdog\tn5\n箸\tchopsticks\tn5 food\n# fixed\n\tbridge\t\n箸\tedge\t\n";
+        let plan = plan_csv_import(
+            text,
+            b'\t',
+            &DeckId::from("n5"),
+            &template,
+            &CsvMapping::from_header(),
+            Some(&DuplicateKey::Field("Word".to_string())),
+            &[existing],
+        )
+        .unwrap();
+
+        assert_eq!(plan.rows.len(), 1);
+        let row = &plan.rows[0];
+        assert_eq!((row.line, row.raw.as_str()), (3, "箸\tchopsticks\tn5 food"));
+        assert_eq!(
+            row.card.field_by_name(&template, "Meaning").unwrap().value,
+            "chopsticks"
+        );
+        assert_eq!(row.card.manual_tags, Some(vec!["n5".into(), "food".into()]));
+        let statuses = plan
+            .log
+            .rows
+            .iter()
+            .map(|r| (r.line, r.status.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            [
+                (
+                    2,
+                    RowStatus::Skipped {
+                        reason: "duplicate of 犬".to_string()
+                    }
+                ),
+                (
+                    5,
+                    RowStatus::Failed {
+                        reason: "required field Word is empty".to_string()
+                    }
+                ),
+                (
+                    6,
+                    RowStatus::Skipped {
+                        reason: "duplicate of 箸".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Deserialize;
+
+use crate::models::ResolvedCard;
+use crate::pipeline::{CardTransformer, TransformOutcome};
+use crate::KanaString;
+
+// JMdict Definitions
+//
+// Glosses for card words from a user-supplied JMdict, either the official
+// JMdict_e XML or the JSON of jmdict-simplified. Parts of speech are kept as
+// the short JMdict codes (`n`, `v1`, `adj-i`, ...).
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JmdictSense {
+    pub parts_of_speech: Vec<String>,
+    pub glosses: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JmdictEntry {
+    pub kanji: Vec<String>,
+    pub readings: Vec<String>,
+    // Marked as common in any of its spellings.
+    pub common: bool,
+    pub senses: Vec<JmdictSense>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Jmdict {
+    entries: Vec<JmdictEntry>,
+    // Kanji and kana spellings to entry indices.
+    index: HashMap<String, Vec<usize>>,
+}
+
+// The priority codes JMdict counts as common.
+const COMMON_PRIORITIES: &[&str] = &["news1", "ichi1", "spec1", "spec2", "gai1"];
+
+impl Jmdict {
+    pub fn new(entries: Vec<JmdictEntry>) -> Jmdict {
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            for spelling in entry.kanji.iter().chain(entry.readings.iter()) {
+                let indices = index.entry(spelling.clone()).or_default();
+                if indices.last() != Some(&i) {
+                    indices.push(i);
+                }
+            }
+        }
+        Jmdict { entries, index }
+    }
+
+    // XML for `.xml` files, jmdict-simplified JSON otherwise.
+    pub fn from_path(path: &str) -> Result<Jmdict, Box<dyn Error>> {
+        let raw = std::fs::read_to_string(path)?;
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("xml") => Jmdict::parse_xml(&raw),
+            _ => Jmdict::parse_json(&raw),
+        }
+    }
+
+    pub fn parse_xml(raw: &str) -> Result<Jmdict, Box<dyn Error>> {
+        let mut reader = Reader::from_str(raw);
+        reader.config_mut().trim_text(true);
+
+        let mut entries = vec![];
+        let mut entry = JmdictEntry::default();
+        let mut sense = JmdictSense::default();
+        let mut element = String::new();
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) => {
+                    element = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                    match element.as_str() {
+                        "entry" => entry = JmdictEntry::default(),
+                        "sense" => sense = JmdictSense::default(),
+                        _ => {}
+                    }
+                }
+                Event::Text(text) => match element.as_str() {
+                    "keb" => entry.kanji.push(text.unescape()?.to_string()),
+                    "reb" => entry.readings.push(text.unescape()?.to_string()),
+                    "ke_pri" | "re_pri" => {
+                        let priority = text.unescape()?;
+                        entry.common |= COMMON_PRIORITIES.contains(&priority.as_ref());
+                    }
+                    // `&n;`, an entity declared in the DTD. Keep its name.
+                    "pos" => {
+                        let raw = String::from_utf8_lossy(&text);
+                        let code = raw.trim_start_matches('&').trim_end_matches(';');
+                        sense.parts_of_speech.push(code.to_string());
+                    }
+                    "gloss" => sense.glosses.push(text.unescape()?.to_string()),
+                    _ => {}
+                },
+                Event::End(e) => {
+                    match e.local_name().as_ref() {
+                        b"sense" => entry.senses.push(std::mem::take(&mut sense)),
+                        b"entry" => entries.push(std::mem::take(&mut entry)),
+                        _ => {}
+                    }
+                    element.clear();
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        for entry in entries.iter_mut() {
+            inherit_parts_of_speech(&mut entry.senses);
+        }
+        Ok(Jmdict::new(entries))
+    }
+
+    pub fn parse_json(raw: &str) -> Result<Jmdict, Box<dyn Error>> {
+        let simplified: SimplifiedJmdict = serde_json::from_str(raw)?;
+        let entries = simplified
+            .words
+            .into_iter()
+            .map(|word| {
+                let mut senses = word
+                    .sense
+                    .into_iter()
+                    .map(|s| JmdictSense {
+                        parts_of_speech: s.part_of_speech,
+                        glosses: s.gloss.into_iter().map(|g| g.text).collect(),
+                    })
+                    .collect::<Vec<_>>();
+                inherit_parts_of_speech(&mut senses);
+                JmdictEntry {
+                    common: word.kanji.iter().chain(word.kana.iter()).any(|s| s.common),
+                    kanji: word.kanji.into_iter().map(|s| s.text).collect(),
+                    readings: word.kana.into_iter().map(|s| s.text).collect(),
+                    senses,
+                }
+            })
+            .collect();
+        Ok(Jmdict::new(entries))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // The entries spelled `word`, narrowed to those read `reading` if given.
+    // Common entries come first.
+    pub fn lookup(&self, word: &str, reading: Option<&str>) -> Vec<&JmdictEntry> {
+        let hiragana = |kana: &str| KanaString(kana.to_string()).to_hiragana();
+        let mut entries = self
+            .index
+            .get(word)
+            .into_iter()
+            .flatten()
+            .map(|i| &self.entries[*i])
+            .filter(|entry| match reading {
+                Some(reading) => entry
+                    .readings
+                    .iter()
+                    .any(|r| hiragana(r) == hiragana(reading)),
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|entry| !entry.common);
+        entries
+    }
+}
+
+// Senses without parts of speech share those of the sense before.
+fn inherit_parts_of_speech(senses: &mut [JmdictSense]) {
+    for i in 1..senses.len() {
+        if senses[i].parts_of_speech.is_empty() {
+            senses[i].parts_of_speech = senses[i - 1].parts_of_speech.clone();
+        }
+    }
+}
+
+// jmdict-simplified
+
+#[derive(Deserialize)]
+struct SimplifiedJmdict {
+    words: Vec<SimplifiedWord>,
+}
+
+#[derive(Deserialize)]
+struct SimplifiedWord {
+    #[serde(default)]
+    kanji: Vec<SimplifiedSpelling>,
+    #[serde(default)]
+    kana: Vec<SimplifiedSpelling>,
+    #[serde(default)]
+    sense: Vec<SimplifiedSense>,
+}
+
+#[derive(Deserialize)]
+struct SimplifiedSpelling {
+    text: String,
+    #[serde(default)]
+    common: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SimplifiedSense {
+    #[serde(default)]
+    part_of_speech: Vec<String>,
+    #[serde(default)]
+    gloss: Vec<SimplifiedGloss>,
+}
+
+#[derive(Deserialize)]
+struct SimplifiedGloss {
+    text: String,
+}
+
+// Definition HTML
+
+// `(common) 1. (n) chopsticks<br>2. ...`, at most `max_senses` senses per
+// entry. Entries are separated by `<hr>`.
+pub fn definition_html(entries: &[&JmdictEntry], max_senses: Option<usize>) -> String {
+    entries
+        .iter()
+        .map(|entry| {
+            let senses = entry
+                .senses
+                .iter()
+                .take(max_senses.unwrap_or(usize::MAX))
+                .enumerate()
+                .map(|(i, sense)| {
+                    let glosses = sense.glosses.join("; ");
+                    if sense.parts_of_speech.is_empty() {
+                        format!("{}. {}", i + 1, glosses)
+                    } else {
+                        format!(
+                            "{}. ({}) {}",
+                            i + 1,
+                            sense.parts_of_speech.join(", "),
+                            glosses
+                        )
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("<br>");
+            if entry.common {
+                format!("(common) {}", senses)
+            } else {
+                senses
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("<hr>")
+}
+
+// Transformer
+
+#[derive(Clone)]
+pub struct DefinitionTransformer<'a> {
+    pub jmdict: &'a Jmdict,
+    pub word_field: String,
+    // Narrows homographs to the reading on the card, if set.
+    pub reading_field: Option<String>,
+    pub definition_field: String,
+    pub max_senses: Option<usize>,
+}
+
+impl<'a> DefinitionTransformer<'a> {
+    pub fn new(
+        jmdict: &'a Jmdict,
+        word_field: &str,
+        definition_field: &str,
+    ) -> DefinitionTransformer<'a> {
+        DefinitionTransformer {
+            jmdict,
+            word_field: word_field.to_string(),
+            reading_field: None,
+            definition_field: definition_field.to_string(),
+            max_senses: None,
+        }
+    }
+}
+
+impl CardTransformer for DefinitionTransformer<'_> {
+    fn name(&self) -> &str {
+        "definition"
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        if !card.has_field(&self.definition_field) {
+            return TransformOutcome::Skipped(format!("no {} field", self.definition_field));
+        }
+        let word = match card.field(&self.word_field).map(str::trim) {
+            Some(word) if !word.is_empty() => word.to_string(),
+            _ => return TransformOutcome::Skipped(format!("no {} value", self.word_field)),
+        };
+        let reading = self
+            .reading_field
+            .as_ref()
+            .and_then(|name| card.field(name))
+            .map(str::trim)
+            .filter(|r| !r.is_empty());
+
+        let entries = self.jmdict.lookup(&word, reading);
+        if entries.is_empty() {
+            return TransformOutcome::Skipped("not in JMdict".to_string());
+        }
+        let html = definition_html(&entries, self.max_senses);
+        if card.field(&self.definition_field) == Some(html.as_str()) {
+            return TransformOutcome::Skipped("unchanged".to_string());
+        }
+        card.set_field(&self.definition_field, &html);
+        TransformOutcome::Changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE JMdict [
+<!ENTITY n "noun (common) (futsuumeishi)">
+<!ENTITY v1 "Ichidan verb">
+<!ENTITY vt "transitive verb">
+]>
+<JMdict>
+<entry>
+<ent_seq>1601110</ent_seq>
+<k_ele><keb>箸</keb><ke_pri>ichi1</ke_pri></k_ele>
+<r_ele><reb>はし</reb><re_pri>ichi1</re_pri></r_ele>
+<sense><pos>&n;</pos><gloss>chopsticks</gloss></sense>
+</entry>
+<entry>
+<ent_seq>1465590</ent_seq>
+<k_ele><keb>入れる</keb></k_ele>
+<r_ele><reb>いれる</reb></r_ele>
+<sense><pos>&v1;</pos><pos>&vt;</pos><gloss>to put in</gloss><gloss>to let in</gloss></sense>
+<sense><gloss>to make (tea, coffee, etc.)</gloss></sense>
+</entry>
+</JMdict>
+"#;
+
+    #[test]
+    fn test_jmdict() {
+        let jmdict = Jmdict::parse_xml(XML).unwrap();
+        assert_eq!(jmdict.len(), 2);
+        assert_eq!(
+            definition_html(&jmdict.lookup("箸", None), None),
+            "(common) 1. (n) chopsticks"
+        );
+        assert_eq!(
+            definition_html(&jmdict.lookup("入れる", Some("イレル")), None),
+            "1. (v1, vt) to put in; to let in<br>2. (v1, vt) to make (tea, coffee, etc.)"
+        );
+        assert!(jmdict.lookup("入れる", Some("はいる")).is_empty());
+
+        let json = r#"{"words": [{
+            "id": "1601110",
+            "kanji": [{"common": true, "text": "箸", "tags": []}],
+            "kana": [{"common": true, "text": "はし", "tags": [], "appliesToKanji": ["*"]}],
+            "sense": [{"partOfSpeech": ["n"], "gloss": [{"lang": "eng", "text": "chopsticks"}]}]
+        }]}"#;
+        let simplified = Jmdict::parse_json(json).unwrap();
+        assert_eq!(simplified.lookup("はし", None), jmdict.lookup("箸", None));
+    }
+}
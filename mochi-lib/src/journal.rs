@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::card_json;
+use crate::models::{Card, CardId, CardPatch};
+use crate::{patch_cards, unix_now, BulkResult, Config};
+
+// Undo Journal
+//
+// Before a bulk update is pushed, the cards it touches are written as they
+// were to a journal file: a header line, then one card per line as the API
+// returned it. `rollback` patches every card back to its journaled state.
+// Cards created or trashed by the update aren't covered.
+
+pub const JOURNAL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalHeader {
+    pub version: u32,
+    // Seconds since the unix epoch.
+    pub created: u64,
+    // What the update was, e.g. the command line.
+    pub description: String,
+}
+
+// `journals/<seconds>.ndjson` in the user's data directory.
+pub fn default_journal_path() -> Option<PathBuf> {
+    let secs = unix_now();
+    dirs::data_dir().map(|dir| {
+        dir.join("mochi-utils")
+            .join("journals")
+            .join(format!("{}.ndjson", secs))
+    })
+}
+
+pub fn write_journal(path: &Path, description: &str, originals: &[Card]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let header = JournalHeader {
+        version: JOURNAL_VERSION,
+        created: unix_now(),
+        description: description.to_string(),
+    };
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "{}", serde_json::to_string(&header)?)?;
+    for card in originals {
+        writeln!(out, "{}", card_json(card))?;
+    }
+    out.flush()
+}
+
+pub fn read_journal(path: &Path) -> Result<(JournalHeader, Vec<Card>), Box<dyn Error>> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header: JournalHeader = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => return Err(format!("{} is empty", path.display()).into()),
+    };
+    if header.version != JOURNAL_VERSION {
+        return Err(format!("unsupported journal version {}", header.version).into());
+    }
+    let mut cards = vec![];
+    for line in lines {
+        let line = line?;
+        if !line.trim().is_empty() {
+            cards.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok((header, cards))
+}
+
+// Everything a patch can set, as it was on the card.
+pub fn restore_patch(card: &Card) -> CardPatch {
+    let mut patch = CardPatch::new()
+        .content(&card.content)
+        .deck_id(&card.deck_id)
+        .archived(card.archived)
+        .review_reverse(card.review_reverse)
+        .manual_tags(card.manual_tags.as_deref().unwrap_or_default());
+    patch.template_id = card.template_id.clone();
+    for field in card.fields.iter().flat_map(|f| f.values()) {
+        patch = patch.field(&field.id, &field.value);
+    }
+    patch
+}
+
+// Journals the originals of the patched cards, then pushes the patches. Nothing
+// is pushed if the journal can't be written.
+pub async fn patch_cards_with_journal(
+    config: &Config,
+    originals: &[Card],
+    patches: &[(CardId, CardPatch)],
+    journal: &Path,
+    description: &str,
+) -> Result<BulkResult, Box<dyn Error>> {
+    let patched = patches.iter().map(|(id, _)| id).collect::<HashSet<_>>();
+    let originals = originals
+        .iter()
+        .filter(|c| patched.contains(&c.id))
+        .cloned()
+        .collect::<Vec<_>>();
+    write_journal(journal, description, &originals)?;
+    Ok(patch_cards(config, patches).await)
+}
+
+// Puts every journaled card back the way it was.
+pub async fn rollback(config: &Config, journal: &Path) -> Result<BulkResult, Box<dyn Error>> {
+    let (_, cards) = read_journal(journal)?;
+    let patches = cards
+        .iter()
+        .map(|card| (card.id.clone(), restore_patch(card)))
+        .collect::<Vec<_>>();
+    Ok(patch_cards(config, &patches).await)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::CardFixture;
+    use serde_json::json;
+
+    #[test]
+    fn test_journal() {
+        let card = CardFixture::new("c1", "n5")
+            .template("vocab")
+            .field("name", "箸")
+            .manual_tags(&["food"])
+            .build();
+        let path =
+            std::env::temp_dir().join(format!("mochi-journal-{}.ndjson", std::process::id()));
+        write_journal(&path, "replace --field Word", std::slice::from_ref(&card)).unwrap();
+        let (header, cards) = read_journal(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(header.description, "replace --field Word");
+        assert_eq!(cards[0].id, "c1");
+        assert_eq!(
+            restore_patch(&cards[0]),
+            CardPatch::new()
+                .content("")
+                .deck_id("n5")
+                .template_id("vocab")
+                .field("name", "箸")
+                .archived(false)
+                .review_reverse(false)
+                .manual_tags(&["food".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_read_journal_errors() {
+        let path = std::env::temp_dir().join(format!(
+            "mochi-journal-errors-{}.ndjson",
+            std::process::id()
+        ));
+        std::fs::write(&path, "").unwrap();
+        assert!(read_journal(&path).is_err());
+        let header = json!({ "version": JOURNAL_VERSION + 1, "created": 0, "description": "" });
+        std::fs::write(&path, format!("{}\n", header)).unwrap();
+        let err = read_journal(&path).unwrap_err();
+        assert!(err.to_string().contains("unsupported journal version"));
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_journal(&path).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::encoding;
+use crate::furigana::is_kanji;
+use crate::models::ResolvedCard;
+use crate::pipeline::{CardTransformer, TransformOutcome};
+
+// Kanji Information
+//
+// Per-character meanings, readings, grade and stroke count from KANJIDIC2.
+// KANJIDIC2 has no components, so those come from a KRADFILE (`漢 : 氵 口 ...`
+// per line, EUC-JP or UTF-8) if one is given. Stroke order diagrams are the
+// KanjiVG SVGs, one file per kanji named after its code point (`06f22.svg`).
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KanjiInfo {
+    pub literal: char,
+    pub meanings: Vec<String>,
+    pub on_readings: Vec<String>,
+    pub kun_readings: Vec<String>,
+    pub grade: Option<u8>,
+    pub stroke_count: Option<u8>,
+    pub components: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Kanjidic(HashMap<char, KanjiInfo>);
+
+impl Kanjidic {
+    pub fn from_path(path: &str) -> Result<Kanjidic, Box<dyn Error>> {
+        Kanjidic::parse_xml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse_xml(raw: &str) -> Result<Kanjidic, Box<dyn Error>> {
+        let mut reader = Reader::from_str(raw);
+        reader.config_mut().trim_text(true);
+
+        let mut kanji = HashMap::new();
+        let mut info = KanjiInfo::default();
+        let mut element = String::new();
+        // The r_type of a <reading>, or whether a <meaning> is English.
+        let mut reading_type = String::new();
+        let mut english = false;
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) => {
+                    element = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                    let attribute = |name: &[u8]| -> Option<String> {
+                        let value = e.try_get_attribute(name).ok()??.unescape_value().ok()?;
+                        Some(value.to_string())
+                    };
+                    match element.as_str() {
+                        "character" => info = KanjiInfo::default(),
+                        "reading" => reading_type = attribute(b"r_type").unwrap_or_default(),
+                        "meaning" => english = attribute(b"m_lang").is_none(),
+                        _ => {}
+                    }
+                }
+                Event::Text(text) => {
+                    let text = text.unescape()?.to_string();
+                    match element.as_str() {
+                        "literal" => info.literal = text.chars().next().unwrap_or_default(),
+                        "grade" => info.grade = text.parse().ok(),
+                        // The first stroke count is the accepted one.
+                        "stroke_count" if info.stroke_count.is_none() => {
+                            info.stroke_count = text.parse().ok()
+                        }
+                        "reading" if reading_type == "ja_on" => info.on_readings.push(text),
+                        "reading" if reading_type == "ja_kun" => info.kun_readings.push(text),
+                        "meaning" if english => info.meanings.push(text),
+                        _ => {}
+                    }
+                }
+                Event::End(e) => {
+                    if e.local_name().as_ref() == b"character" {
+                        let info = std::mem::take(&mut info);
+                        kanji.insert(info.literal, info);
+                    }
+                    element.clear();
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(Kanjidic(kanji))
+    }
+
+    // Adds the components of a KRADFILE to the kanji.
+    pub fn add_components(&mut self, kradfile: &[u8]) {
+        let (raw, _) = encoding::decode(kradfile);
+        for line in raw.lines().filter(|l| !l.starts_with('#')) {
+            let Some((literal, components)) = line.split_once(':') else {
+                continue;
+            };
+            let Some(literal) = literal.trim().chars().next() else {
+                continue;
+            };
+            if let Some(info) = self.0.get_mut(&literal) {
+                info.components = components.split_whitespace().map(String::from).collect();
+            }
+        }
+    }
+
+    pub fn get(&self, literal: char) -> Option<&KanjiInfo> {
+        self.0.get(&literal)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// The KanjiVG diagram of the kanji, without the XML prologue so it can be
+// inlined into a field.
+pub fn stroke_order_svg(kanjivg_dir: &Path, literal: char) -> Option<String> {
+    let path = kanjivg_dir.join(format!("{:05x}.svg", literal as u32));
+    let svg = std::fs::read_to_string(path).ok()?;
+    Some(svg[svg.find("<svg")?..].trim_end().to_string())
+}
+
+pub fn kanji_html(info: &KanjiInfo, stroke_order: Option<&str>) -> String {
+    let mut lines = vec![format!(
+        "<b>{}</b> {}",
+        info.literal,
+        info.meanings.join(", ")
+    )];
+    if !info.on_readings.is_empty() {
+        lines.push(format!("On: {}", info.on_readings.join("、")));
+    }
+    if !info.kun_readings.is_empty() {
+        lines.push(format!("Kun: {}", info.kun_readings.join("、")));
+    }
+    let counts = [
+        info.grade.map(|g| format!("Grade {}", g)),
+        info.stroke_count.map(|s| format!("{} strokes", s)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    if !counts.is_empty() {
+        lines.push(counts.join(", "));
+    }
+    if !info.components.is_empty() {
+        lines.push(format!("Components: {}", info.components.join(" ")));
+    }
+    if let Some(svg) = stroke_order {
+        lines.push(svg.to_string());
+    }
+    format!("<div>{}</div>", lines.join("<br>"))
+}
+
+// Transformer
+
+#[derive(Debug, Clone)]
+pub struct KanjiTransformer<'a> {
+    pub kanjidic: &'a Kanjidic,
+    pub word_field: String,
+    pub kanji_field: String,
+    // Embeds stroke order diagrams from this KanjiVG directory, if set.
+    pub kanjivg_dir: Option<PathBuf>,
+}
+
+impl<'a> KanjiTransformer<'a> {
+    pub fn new(
+        kanjidic: &'a Kanjidic,
+        word_field: &str,
+        kanji_field: &str,
+    ) -> KanjiTransformer<'a> {
+        KanjiTransformer {
+            kanjidic,
+            word_field: word_field.to_string(),
+            kanji_field: kanji_field.to_string(),
+            kanjivg_dir: None,
+        }
+    }
+}
+
+impl CardTransformer for KanjiTransformer<'_> {
+    fn name(&self) -> &str {
+        "kanji"
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        if !card.has_field(&self.kanji_field) {
+            return TransformOutcome::Skipped(format!("no {} field", self.kanji_field));
+        }
+        let word = match card.field(&self.word_field) {
+            Some(word) => word.to_string(),
+            None => return TransformOutcome::Skipped(format!("no {} value", self.word_field)),
+        };
+
+        let mut seen = vec![];
+        let html = word
+            .chars()
+            .filter(|c| is_kanji(*c))
+            .filter(|c| {
+                let new = !seen.contains(c);
+                seen.push(*c);
+                new
+            })
+            .filter_map(|c| self.kanjidic.get(c))
+            .map(|info| {
+                let svg = self
+                    .kanjivg_dir
+                    .as_ref()
+                    .and_then(|dir| stroke_order_svg(dir, info.literal));
+                kanji_html(info, svg.as_deref())
+            })
+            .collect::<String>();
+        if html.is_empty() {
+            return TransformOutcome::Skipped("no kanji in KANJIDIC2".to_string());
+        }
+        if card.field(&self.kanji_field) == Some(html.as_str()) {
+            return TransformOutcome::Skipped("unchanged".to_string());
+        }
+        card.set_field(&self.kanji_field, &html);
+        TransformOutcome::Changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<kanjidic2>
+<character>
+<literal>漢</literal>
+<misc><grade>3</grade><stroke_count>13</stroke_count><stroke_count>14</stroke_count></misc>
+<reading_meaning><rmgroup>
+<reading r_type="pinyin">han4</reading>
+<reading r_type="ja_on">カン</reading>
+<meaning>Sino-</meaning>
+<meaning>China</meaning>
+<meaning m_lang="fr">chinois</meaning>
+</rmgroup></reading_meaning>
+</character>
+</kanjidic2>
+"#;
+
+    #[test]
+    fn test_kanji_html() {
+        let mut kanjidic = Kanjidic::parse_xml(XML).unwrap();
+        kanjidic.add_components("# KRADFILE\n漢 : 氵 口 二 大 廾\n".as_bytes());
+        let info = kanjidic.get('漢').unwrap();
+        assert_eq!(info.stroke_count, Some(13));
+        assert_eq!(
+            kanji_html(info, None),
+            "<div><b>漢</b> Sino-, China<br>On: カン<br>Grade 3, 13 strokes<br>\
+             Components: 氵 口 二 大 廾</div>"
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::Path;
+
+use rusqlite::{Connection, OpenFlags};
+
+use crate::duplicates::normalize_key;
+use crate::models::{Card, CardBuildError, CardBuilder, DeckId, Template};
+use crate::{create_card, list_cards, Config};
+
+// Kindle Vocabulary Builder
+//
+// Kindle keeps every dictionary lookup in `vocab.db` on the device, with the
+// sentence the word was read in and the book. Lookups of the same word (by
+// its dictionary form) make one card, with the first sentence and every book
+// it came up in. Words already in the deck are left out.
+
+pub const KINDLE_TAG: &str = "kindle";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KindleLookup {
+    // As it appeared in the book.
+    pub word: String,
+    // The dictionary form, or the word if Kindle has none.
+    pub stem: String,
+    pub lang: String,
+    pub usage: String,
+    pub book: String,
+    // Milliseconds since the epoch.
+    pub timestamp: i64,
+}
+
+// Every lookup, oldest first, optionally only those in the language (e.g.
+// `ja`).
+pub fn read_vocab_db(path: &Path, lang: Option<&str>) -> Result<Vec<KindleLookup>, Box<dyn Error>> {
+    let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut query = db.prepare(
+        "SELECT w.word, w.stem, w.lang, l.usage, b.title, l.timestamp
+         FROM LOOKUPS l
+         JOIN WORDS w ON w.id = l.word_key
+         LEFT JOIN BOOK_INFO b ON b.id = l.book_key
+         ORDER BY l.timestamp",
+    )?;
+    let rows = query.query_map([], |row| {
+        let word: String = row.get(0)?;
+        let stem: Option<String> = row.get(1)?;
+        Ok(KindleLookup {
+            stem: stem.filter(|s| !s.is_empty()).unwrap_or(word.clone()),
+            word,
+            lang: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            usage: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+            book: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+            timestamp: row.get::<_, Option<i64>>(5)?.unwrap_or(0),
+        })
+    })?;
+    let mut lookups = vec![];
+    for lookup in rows {
+        let lookup = lookup?;
+        if lang.is_none_or(|lang| lookup.lang == lang) {
+            lookups.push(lookup);
+        }
+    }
+    Ok(lookups)
+}
+
+// The template fields the importer fills. Fields the template lacks are left
+// out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KindleFields {
+    pub word: String,
+    pub sentence: String,
+    pub book: String,
+}
+
+impl Default for KindleFields {
+    fn default() -> KindleFields {
+        KindleFields {
+            word: "Word".to_string(),
+            sentence: "Sentence".to_string(),
+            book: "Book".to_string(),
+        }
+    }
+}
+
+// One word's lookups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KindleWord {
+    pub stem: String,
+    pub lookups: Vec<KindleLookup>,
+}
+
+impl KindleWord {
+    // The first sentence, with the word in bold.
+    pub fn sentence(&self) -> String {
+        let Some(first) = self.lookups.first() else {
+            return String::new();
+        };
+        let usage = first.usage.trim();
+        match first.word.is_empty() {
+            true => usage.to_string(),
+            false => usage.replacen(&first.word, &format!("**{}**", first.word), 1),
+        }
+    }
+
+    // Every book the word was looked up in, in order.
+    pub fn books(&self) -> Vec<&str> {
+        let mut books: Vec<&str> = vec![];
+        for lookup in self.lookups.iter() {
+            if !lookup.book.is_empty() && !books.contains(&lookup.book.as_str()) {
+                books.push(&lookup.book);
+            }
+        }
+        books
+    }
+
+    pub fn card(
+        &self,
+        deck_id: &DeckId,
+        template: &Template,
+        fields: &KindleFields,
+    ) -> Result<Card, CardBuildError> {
+        let sentence = self.sentence();
+        let books = self.books().join(", ");
+        let mut builder = CardBuilder::new(deck_id.clone())
+            .template(template)
+            .field(&fields.word, &self.stem)
+            .tag(KINDLE_TAG);
+        for (name, value) in [(&fields.sentence, sentence), (&fields.book, books)] {
+            if template.field_by_name(name).is_some() && !value.is_empty() {
+                builder = builder.field(name, &value);
+            }
+        }
+        builder.build()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KindlePlan {
+    pub words: Vec<KindleWord>,
+    // Words already in the deck.
+    pub duplicates: Vec<String>,
+}
+
+// The lookups grouped by word, in order of the first lookup, without the
+// words whose normalized value is in `existing`.
+pub fn plan_kindle_import(lookups: &[KindleLookup], existing: &HashSet<String>) -> KindlePlan {
+    let mut plan = KindlePlan::default();
+    for lookup in lookups {
+        let key = normalize_key(&lookup.stem);
+        if existing.contains(&key) {
+            if !plan.duplicates.contains(&lookup.stem) {
+                plan.duplicates.push(lookup.stem.clone());
+            }
+            continue;
+        }
+        match plan
+            .words
+            .iter_mut()
+            .find(|w| normalize_key(&w.stem) == key)
+        {
+            Some(word) => word.lookups.push(lookup.clone()),
+            None => plan.words.push(KindleWord {
+                stem: lookup.stem.clone(),
+                lookups: vec![lookup.clone()],
+            }),
+        }
+    }
+    plan
+}
+
+#[derive(Debug, Default)]
+pub struct KindleImport {
+    pub plan: KindlePlan,
+    pub created: Vec<Card>,
+    // Words whose cards couldn't be built or created.
+    pub failed: Vec<(String, Box<dyn Error>)>,
+}
+
+// Creates a card per new word of the `vocab.db` in the deck, unless
+// `dry_run`.
+pub async fn import_kindle(
+    config: &Config,
+    path: &Path,
+    lang: Option<&str>,
+    deck_id: &DeckId,
+    template: &Template,
+    fields: &KindleFields,
+    dry_run: bool,
+) -> Result<KindleImport, Box<dyn Error>> {
+    let lookups = read_vocab_db(path, lang)?;
+    let cards = list_cards(config, deck_id, None).await?;
+    let existing = cards
+        .iter()
+        .filter_map(|c| c.field_by_name(template, &fields.word))
+        .map(|f| normalize_key(&f.value))
+        .collect::<HashSet<_>>();
+    let mut import = KindleImport {
+        plan: plan_kindle_import(&lookups, &existing),
+        ..KindleImport::default()
+    };
+    if dry_run {
+        return Ok(import);
+    }
+    for word in import.plan.words.iter() {
+        let created = match word.card(deck_id, template, fields) {
+            Ok(card) => create_card(config, &card).await.map_err(Into::into),
+            Err(err) => Err(err.into()),
+        };
+        match created {
+            Ok(card) => import.created.push(card),
+            Err(err) => import.failed.push((word.stem.clone(), err)),
+        }
+    }
+    Ok(import)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::template_fixture;
+
+    #[test]
+    fn test_plan_kindle_import() {
+        let path = std::env::temp_dir().join(format!("mochi-vocab-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db = Connection::open(&path).unwrap();
+        db.execute_batch(
+            "CREATE TABLE WORDS (id TEXT PRIMARY KEY, word TEXT, stem TEXT, lang TEXT,
+                category INTEGER, timestamp INTEGER, profileid TEXT);
+             CREATE TABLE LOOKUPS (id TEXT PRIMARY KEY, word_key TEXT, book_key TEXT,
+                dict_key TEXT, pos TEXT, usage TEXT, timestamp INTEGER);
+             CREATE TABLE BOOK_INFO (id TEXT PRIMARY KEY, asin TEXT, guid TEXT, lang TEXT,
+                title TEXT, authors TEXT);
+             INSERT INTO WORDS VALUES ('ja:食べた', '食べた', '食べる', 'ja', 0, 0, '');
+             INSERT INTO WORDS VALUES ('ja:犬', '犬', '犬', 'ja', 0, 0, '');
+             INSERT INTO WORDS VALUES ('en:dog', 'dogs', 'dog', 'en', 0, 0, '');
+             INSERT INTO BOOK_INFO VALUES ('b1', '', '', 'ja', '吾輩は猫である', '');
+             INSERT INTO BOOK_INFO VALUES ('b2', '', '', 'ja', 'こころ', '');
+             INSERT INTO LOOKUPS VALUES ('l1', 'ja:食べた', 'b1', '', '', '魚を食べた。', 1);
+             INSERT INTO LOOKUPS VALUES ('l2', 'ja:犬', 'b1', '', '', '犬がいる。', 2);
+             INSERT INTO LOOKUPS VALUES ('l3', 'ja:食べた', 'b2', '', '', '飯を食べた。', 3);
+             INSERT INTO LOOKUPS VALUES ('l4', 'en:dog', 'b2', '', '', 'Two dogs.', 4);",
+        )
+        .unwrap();
+        drop(db);
+        let lookups = read_vocab_db(&path, Some("ja")).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(lookups.len(), 3);
+
+        let plan = plan_kindle_import(&lookups, &HashSet::from(["犬".to_string()]));
+        assert_eq!(plan.duplicates, ["犬"]);
+        let taberu = &plan.words[0];
+        assert_eq!(taberu.sentence(), "魚を**食べた**。");
+        assert_eq!(taberu.books(), ["吾輩は猫である", "こころ"]);
+
+        let template = template_fixture(
+            "vocab",
+            "<< Word >>\n---\n<< Sentence >>",
+            &[("name", "Word"), ("sentence", "Sentence")],
+        );
+        let card = taberu
+            .card(&DeckId::from("books"), &template, &KindleFields::default())
+            .unwrap();
+        assert_eq!(
+            card.field_by_name(&template, "Word").unwrap().value,
+            "食べる"
+        );
+        assert_eq!(card.manual_tags, Some(vec![KINDLE_TAG.to_string()]));
+    }
+}
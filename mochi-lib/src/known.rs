@@ -0,0 +1,341 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+use futures::future::LocalBoxFuture;
+use serde_json::{json, Value};
+
+use crate::models::{CardId, CardPatch, DeckId, ResolvedCard};
+use crate::tags::add_tags_patch;
+use crate::{list_cards_for_decks, list_templates, patch_cards, BulkResult, Config};
+
+// Known Words
+//
+// Word lists from other SRS tools, so Mochi cards for words already known
+// there can be tagged or archived, and imports can skip them. Sources only
+// read; nothing is written back to them.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KnownWords {
+    pub known: HashSet<String>,
+    // In review but not known yet.
+    pub learning: HashSet<String>,
+}
+
+impl KnownWords {
+    pub fn is_known(&self, word: &str) -> bool {
+        self.known.contains(word.trim())
+    }
+
+    pub fn is_learning(&self, word: &str) -> bool {
+        let word = word.trim();
+        !self.known.contains(word) && self.learning.contains(word)
+    }
+
+    // The words of an import word list that aren't known yet.
+    pub fn unknown<'w, T>(&self, words: &'w [(String, T)]) -> Vec<&'w (String, T)> {
+        words.iter().filter(|(w, _)| !self.is_known(w)).collect()
+    }
+}
+
+pub trait KnownWordSource {
+    fn name(&self) -> String;
+
+    fn fetch(&self) -> LocalBoxFuture<'_, Result<KnownWords, Box<dyn Error>>>;
+}
+
+// jpdb.io, with the API key from the settings page. Words from every deck
+// count; never-forget and known cards are known, the rest learning.
+pub struct Jpdb {
+    pub api_key: String,
+}
+
+impl Jpdb {
+    async fn call(&self, endpoint: &str, body: Value) -> Result<Value, Box<dyn Error>> {
+        let resp = reqwest::Client::new()
+            .post(format!("https://jpdb.io/api/v1/{}", endpoint))
+            .bearer_auth(&self.api_key)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        Ok(resp)
+    }
+}
+
+impl KnownWordSource for Jpdb {
+    fn name(&self) -> String {
+        "jpdb".to_string()
+    }
+
+    fn fetch(&self) -> LocalBoxFuture<'_, Result<KnownWords, Box<dyn Error>>> {
+        Box::pin(async move {
+            let decks = self
+                .call("list-user-decks", json!({ "fields": ["id"] }))
+                .await?;
+            let mut vocabulary = vec![];
+            for deck in decks["decks"].as_array().into_iter().flatten() {
+                let resp = self
+                    .call("deck/list-vocabulary", json!({ "id": deck[0] }))
+                    .await?;
+                vocabulary.extend(resp["vocabulary"].as_array().cloned().unwrap_or_default());
+            }
+
+            let mut words = KnownWords::default();
+            for chunk in vocabulary.chunks(1000) {
+                let body = json!({ "list": chunk, "fields": ["spelling", "card_state"] });
+                let resp = self.call("lookup-vocabulary", body).await?;
+                for info in resp["vocabulary_info"].as_array().into_iter().flatten() {
+                    let Some(spelling) = info[0].as_str() else {
+                        continue;
+                    };
+                    let states = info[1].as_array().cloned().unwrap_or_default();
+                    let known = states.iter().any(|s| s == "known" || s == "never-forget");
+                    match known {
+                        true => words.known.insert(spelling.to_string()),
+                        false => words.learning.insert(spelling.to_string()),
+                    };
+                }
+            }
+            Ok(words)
+        })
+    }
+}
+
+// WaniKani, with a read-only API token. Vocabulary at `known_stage` (5,
+// Guru, by default) or above is known; started vocabulary below it is
+// learning.
+pub struct WaniKani {
+    pub api_token: String,
+    pub known_stage: u64,
+}
+
+impl WaniKani {
+    pub fn new(api_token: &str) -> WaniKani {
+        WaniKani {
+            api_token: api_token.to_string(),
+            known_stage: 5,
+        }
+    }
+
+    // Every item of the collection, following `pages.next_url`.
+    async fn collection(&self, url: String) -> Result<Vec<Value>, Box<dyn Error>> {
+        let client = reqwest::Client::new();
+        let mut items = vec![];
+        let mut next = Some(url);
+        while let Some(url) = next {
+            let resp = client
+                .get(url)
+                .bearer_auth(&self.api_token)
+                .header("Wanikani-Revision", "20170710")
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Value>()
+                .await?;
+            items.extend(resp["data"].as_array().cloned().unwrap_or_default());
+            next = resp["pages"]["next_url"].as_str().map(str::to_string);
+        }
+        Ok(items)
+    }
+}
+
+impl KnownWordSource for WaniKani {
+    fn name(&self) -> String {
+        "wanikani".to_string()
+    }
+
+    fn fetch(&self) -> LocalBoxFuture<'_, Result<KnownWords, Box<dyn Error>>> {
+        Box::pin(async move {
+            let base = "https://api.wanikani.com/v2";
+            let assignments = self
+                .collection(format!(
+                    "{}/assignments?subject_types=vocabulary,kana_vocabulary&started=true",
+                    base
+                ))
+                .await?;
+            let stages = assignments
+                .iter()
+                .filter_map(|a| {
+                    Some((
+                        a["data"]["subject_id"].as_u64()?,
+                        a["data"]["srs_stage"].as_u64()?,
+                    ))
+                })
+                .collect::<Vec<_>>();
+
+            let mut words = KnownWords::default();
+            for chunk in stages.chunks(500) {
+                let ids = chunk
+                    .iter()
+                    .map(|(id, _)| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                for subject in self
+                    .collection(format!("{}/subjects?ids={}", base, ids))
+                    .await?
+                {
+                    let Some(characters) = subject["data"]["characters"].as_str() else {
+                        continue;
+                    };
+                    let id = subject["id"].as_u64();
+                    let stage = chunk
+                        .iter()
+                        .find(|(s, _)| Some(*s) == id)
+                        .map(|(_, stage)| *stage);
+                    match stage {
+                        Some(stage) if stage >= self.known_stage => {
+                            words.known.insert(characters.to_string())
+                        }
+                        _ => words.learning.insert(characters.to_string()),
+                    };
+                }
+            }
+            Ok(words)
+        })
+    }
+}
+
+// What to do with cards for known and learning words.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownWordActions {
+    pub known_tag: Option<String>,
+    pub learning_tag: Option<String>,
+    pub archive_known: bool,
+}
+
+impl Default for KnownWordActions {
+    fn default() -> KnownWordActions {
+        KnownWordActions {
+            known_tag: Some("known".to_string()),
+            learning_tag: None,
+            archive_known: false,
+        }
+    }
+}
+
+// Patches for the cards whose word field is a known or learning word.
+pub fn plan_known_sync(
+    cards: &[ResolvedCard],
+    word_field: &str,
+    words: &KnownWords,
+    actions: &KnownWordActions,
+) -> Vec<(CardId, CardPatch)> {
+    let mut patches = vec![];
+    for card in cards {
+        let Some(word) = card.field(word_field) else {
+            continue;
+        };
+        let (tag, archive) = if words.is_known(word) {
+            (actions.known_tag.as_ref(), actions.archive_known)
+        } else if words.is_learning(word) {
+            (actions.learning_tag.as_ref(), false)
+        } else {
+            continue;
+        };
+        let mut patch = tag
+            .and_then(|tag| add_tags_patch(&card.card, std::slice::from_ref(tag)))
+            .unwrap_or_default();
+        if archive && !card.card.archived {
+            patch = patch.archived(true);
+        }
+        if !patch.is_empty() {
+            patches.push((card.card.id.clone(), patch));
+        }
+    }
+    patches
+}
+
+#[derive(Debug)]
+pub struct KnownSyncResult {
+    pub words: KnownWords,
+    pub patches: Vec<(CardId, CardPatch)>,
+    // None on a dry run.
+    pub updates: Option<BulkResult>,
+}
+
+// Fetches the source's words and applies the actions to the decks' cards.
+pub async fn sync_known_words(
+    config: &Config,
+    source: &dyn KnownWordSource,
+    deck_ids: &[DeckId],
+    word_field: &str,
+    actions: &KnownWordActions,
+    dry_run: bool,
+) -> Result<KnownSyncResult, Box<dyn Error>> {
+    let words = source.fetch().await?;
+    let mut cards_by_deck = list_cards_for_decks(config, deck_ids, None).await?;
+    let cards = deck_ids
+        .iter()
+        .filter_map(|id| cards_by_deck.remove(id))
+        .flat_map(|cards| cards.into_vec())
+        .collect::<Vec<_>>();
+    let templates = list_templates(config).await?;
+    let resolved = ResolvedCard::resolve(&cards, &templates);
+    let patches = plan_known_sync(&resolved, word_field, &words, actions);
+    let updates = match dry_run {
+        true => None,
+        false => Some(patch_cards(config, &patches).await),
+    };
+    Ok(KnownSyncResult {
+        words,
+        patches,
+        updates,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+
+    #[test]
+    fn test_plan_known_sync() {
+        let templates = [template_fixture("vocab", "<< Word >>", &[("name", "Word")])];
+        let card = |id: &str, word: &str| {
+            CardFixture::new(id, "n5")
+                .template("vocab")
+                .field("name", word)
+        };
+        let cards = [card("c1", "犬"), card("c2", "猫"), card("c3", "鳥")].map(CardFixture::build);
+        let resolved = ResolvedCard::resolve(&cards, &templates);
+        let words = KnownWords {
+            known: HashSet::from(["犬".to_string()]),
+            learning: HashSet::from(["猫".to_string(), "犬".to_string()]),
+        };
+        let actions = KnownWordActions {
+            learning_tag: Some("learning".to_string()),
+            archive_known: true,
+            ..KnownWordActions::default()
+        };
+
+        let patches = plan_known_sync(&resolved, "Word", &words, &actions);
+        assert_eq!(
+            patches,
+            [
+                (
+                    CardId::from("c1"),
+                    CardPatch::new()
+                        .manual_tags(&["known".to_string()])
+                        .archived(true)
+                ),
+                (
+                    CardId::from("c2"),
+                    CardPatch::new().manual_tags(&["learning".to_string()])
+                ),
+            ]
+        );
+
+        // Cards already tagged and archived are left alone.
+        let done = card("c4", "犬")
+            .manual_tags(&["known"])
+            .archived(true)
+            .build();
+        let resolved = ResolvedCard::resolve(&[done], &templates);
+        assert!(plan_known_sync(&resolved, "Word", &words, &actions).is_empty());
+
+        let list = [("犬".to_string(), ()), ("鳥".to_string(), ())];
+        assert_eq!(words.unknown(&list), [&("鳥".to_string(), ())]);
+    }
+}
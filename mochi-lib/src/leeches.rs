@@ -0,0 +1,110 @@
+use std::error::Error;
+
+use crate::models::{Card, DeckId};
+use crate::tags::add_tags_patch;
+use crate::{archive_cards, list_cards, patch_cards, BulkResult, Config};
+
+// Leeches
+//
+// Cards forgotten again and again take review time without sticking. Find
+// them in a deck, then tag them `leech` to rework later, archive them, or
+// just list them.
+
+// Forgotten this many times, a card is a leech.
+pub const LEECH_THRESHOLD: usize = 8;
+
+pub const LEECH_TAG: &str = "leech";
+
+pub fn is_leech(card: &Card, threshold: usize) -> bool {
+    card.times_forgotten() >= threshold
+}
+
+// The unarchived leeches among the cards, most forgotten first.
+pub fn leeches(cards: &[Card], threshold: usize) -> Vec<Card> {
+    let mut leeches = cards
+        .iter()
+        .filter(|c| !c.archived && c.trashed.is_none() && is_leech(c, threshold))
+        .cloned()
+        .collect::<Vec<_>>();
+    leeches.sort_by_key(|c| std::cmp::Reverse(c.times_forgotten()));
+    leeches
+}
+
+pub async fn find_leeches(
+    config: &Config,
+    deck_id: &DeckId,
+    threshold: usize,
+) -> Result<Vec<Card>, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    Ok(leeches(&cards, threshold))
+}
+
+// Cards already tagged are left alone.
+pub async fn tag_leeches(config: &Config, leeches: &[Card]) -> BulkResult {
+    let tags = [LEECH_TAG.to_string()];
+    let patches = leeches
+        .iter()
+        .filter_map(|c| add_tags_patch(c, &tags).map(|p| (c.id.clone(), p)))
+        .collect::<Vec<_>>();
+    patch_cards(config, &patches).await
+}
+
+pub async fn archive_leeches(config: &Config, leeches: &[Card]) -> BulkResult {
+    let card_ids = leeches.iter().map(|c| c.id.clone()).collect::<Vec<_>>();
+    archive_cards(config, &card_ids).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock;
+    use crate::tags::has_tag;
+    use serde_json::json;
+
+    fn card(id: &str, forgotten: usize) -> serde_json::Value {
+        let review = json!({
+            "date": { "date": "2024-03-01T08:00:00Z" },
+            "due": { "date": "2024-03-02T08:00:00Z" },
+            "remembered?": false,
+        });
+        json!({
+            "id": id,
+            "content": id,
+            "deck-id": "MK5LCEAL",
+            "reviews": vec![review; forgotten],
+        })
+    }
+
+    #[test]
+    fn test_leeches() {
+        let cards = [card("ok", 1), card("leech", 3), card("worse", 5)]
+            .into_iter()
+            .map(|c| serde_json::from_value::<Card>(c).unwrap())
+            .collect::<Vec<_>>();
+        let found = leeches(&cards, 3);
+        let ids = found.iter().map(|c| c.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["worse", "leech"]);
+    }
+
+    #[tokio::test]
+    async fn test_tag_and_archive_leeches() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        server.add_card(card("leech", 9));
+        let deck_id = DeckId::from("MK5LCEAL");
+
+        let found = find_leeches(&config, &deck_id, LEECH_THRESHOLD)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(tag_leeches(&config, &found).await.is_success());
+        assert!(has_tag(&server.card("leech").unwrap(), LEECH_TAG));
+
+        assert!(archive_leeches(&config, &found).await.is_success());
+        assert!(server.card("leech").unwrap().archived);
+        assert!(find_leeches(&config, &deck_id, LEECH_THRESHOLD)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
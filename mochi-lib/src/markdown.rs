@@ -0,0 +1,208 @@
+use std::sync::OnceLock;
+
+use pulldown_cmark::{html, Parser};
+use regex::Regex;
+
+use crate::models::ResolvedCard;
+use crate::pipeline::{CardTransformer, TransformOutcome};
+use crate::sanitize::{parse, Attributes, Node};
+
+// Markdown and HTML
+//
+// Mochi renders card content as markdown, but fields imported from Anki are
+// full of HTML. `html_to_markdown` covers the markup those fields use:
+// emphasis, links, images, headings, lists and `<div>`/`<br>` line breaks.
+// Other elements are replaced by their text.
+
+pub fn markdown_to_html(markdown: &str) -> String {
+    let mut out = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut out, Parser::new(markdown));
+    out
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn attribute<'a>(attributes: &'a Attributes, name: &str) -> &'a str {
+    attributes
+        .iter()
+        .find(|(a, _)| a == name)
+        .and_then(|(_, value)| value.as_deref())
+        .map(|value| value.trim_matches(['"', '\'']))
+        .unwrap_or("")
+}
+
+// `list` is the next item number of the enclosing list, 0 if unordered.
+fn write_markdown(nodes: &[Node], out: &mut String, mut list: Option<&mut usize>) {
+    for node in nodes {
+        let (name, attributes, children) = match node {
+            Node::Text(text) => {
+                // Source line breaks are whitespace in HTML.
+                out.push_str(&decode_entities(&text.replace('\n', " ")));
+                continue;
+            }
+            Node::Element {
+                name,
+                attributes,
+                children,
+            } => (name.as_str(), attributes, children),
+        };
+        let inline = |out: &mut String, marker: &str| {
+            out.push_str(marker);
+            write_markdown(children, out, None);
+            out.push_str(marker);
+        };
+        match name {
+            "b" | "strong" => inline(out, "**"),
+            "i" | "em" => inline(out, "*"),
+            "s" | "del" | "strike" => inline(out, "~~"),
+            "code" => inline(out, "`"),
+            "br" => out.push('\n'),
+            "hr" => out.push_str("\n\n---\n\n"),
+            "a" => {
+                out.push('[');
+                write_markdown(children, out, None);
+                out.push_str(&format!("]({})", attribute(attributes, "href")));
+            }
+            "img" => out.push_str(&format!(
+                "![{}]({})",
+                attribute(attributes, "alt"),
+                attribute(attributes, "src")
+            )),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse::<usize>().unwrap();
+                out.push_str(&format!("\n\n{} ", "#".repeat(level)));
+                write_markdown(children, out, None);
+                out.push_str("\n\n");
+            }
+            "p" | "blockquote" | "ul" | "ol" => {
+                out.push_str("\n\n");
+                let mut item = 0;
+                match name {
+                    "blockquote" => {
+                        let mut quote = String::new();
+                        write_markdown(children, &mut quote, None);
+                        for line in quote.trim().lines() {
+                            out.push_str(&format!("> {}\n", line));
+                        }
+                    }
+                    "ol" => {
+                        item = 1;
+                        write_markdown(children, out, Some(&mut item));
+                    }
+                    "ul" => write_markdown(children, out, Some(&mut item)),
+                    _ => write_markdown(children, out, None),
+                }
+                out.push_str("\n\n");
+            }
+            "li" => {
+                match list.as_deref_mut() {
+                    Some(item) if *item > 0 => {
+                        out.push_str(&format!("{}. ", item));
+                        *item += 1;
+                    }
+                    _ => out.push_str("- "),
+                }
+                write_markdown(children, out, None);
+                out.push('\n');
+            }
+            "div" => {
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                write_markdown(children, out, None);
+                out.push('\n');
+            }
+            _ => write_markdown(children, out, None),
+        }
+    }
+}
+
+pub fn html_to_markdown(html: &str) -> String {
+    static BLANK_LINES: OnceLock<Regex> = OnceLock::new();
+    let blank_lines = BLANK_LINES.get_or_init(|| Regex::new(r"[ \t]*\n(?:[ \t]*\n)+").unwrap());
+
+    let mut out = String::with_capacity(html.len());
+    write_markdown(&parse(html), &mut out, None);
+    let lines = out
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n");
+    blank_lines.replace_all(&lines, "\n\n").trim().to_string()
+}
+
+pub fn contains_html(text: &str) -> bool {
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    TAG.get_or_init(|| Regex::new(r"</?[a-zA-Z][^>]*>").unwrap())
+        .is_match(text)
+}
+
+// Transformer
+
+// Rewrites HTML field values as markdown. Values without tags are left alone.
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownTransformer {
+    // Field names to convert, every field if empty.
+    pub fields: Vec<String>,
+}
+
+impl CardTransformer for MarkdownTransformer {
+    fn name(&self) -> &str {
+        "html-to-markdown"
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        let mut changed = false;
+        for field in card.card.fields.iter_mut().flat_map(|f| f.values_mut()) {
+            let selected = self.fields.is_empty()
+                || card.template.as_ref().is_some_and(|t| {
+                    t.fields
+                        .iter()
+                        .flatten()
+                        .any(|(id, f)| *id == field.id && self.fields.contains(&f.name))
+                });
+            if selected && contains_html(&field.value) {
+                field.value = html_to_markdown(&field.value);
+                changed = true;
+            }
+        }
+
+        if changed {
+            TransformOutcome::Changed
+        } else {
+            TransformOutcome::Skipped("no HTML".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_html_to_markdown() {
+        assert_eq!(
+            html_to_markdown(
+                "<div><b>箸</b>&nbsp;(はし)</div><div>chopsticks<br>\
+                 <a href=\"https://jisho.org/search/箸\">jisho</a></div>"
+            ),
+            "**箸** (はし)\nchopsticks\n[jisho](https://jisho.org/search/箸)"
+        );
+        assert_eq!(
+            html_to_markdown("<p>Uses:</p><ol><li>eating</li><li><i>cooking</i></li></ol>"),
+            "Uses:\n\n1. eating\n2. *cooking*"
+        );
+        assert_eq!(
+            markdown_to_html("**箸**\n\n- eating"),
+            "<p><strong>箸</strong></p>\n<ul>\n<li>eating</li>\n</ul>\n"
+        );
+        assert!(!contains_html("a < b"));
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use crate::models::{Card, CardField, CardId, CardPatch, FieldId, Template};
+use crate::{patch_cards, BulkResult, Config, MochiError};
+
+// Template Migration
+//
+// Field values are stored under template-specific field ids, so moving a card
+// to another template means carrying each value over to the new template's
+// field of the same (or a mapped) name. Values of fields with no counterpart
+// are dropped and reported.
+
+#[derive(Debug, Clone, Default)]
+pub struct FieldMapping {
+    // Old field name to new field name. Fields named the same in both
+    // templates carry over without an entry.
+    pub renames: HashMap<String, String>,
+    // Values for new fields no old field carries over to, by name.
+    pub defaults: HashMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+pub struct MigrationPlan {
+    pub patches: Vec<(CardId, CardPatch)>,
+    // Names of old fields whose values are dropped.
+    pub dropped: Vec<String>,
+    // Cards not of the old template, left alone.
+    pub skipped: Vec<CardId>,
+}
+
+#[derive(Debug)]
+pub struct MigrationResult {
+    pub plan: MigrationPlan,
+    // None for a dry run.
+    pub updates: Option<BulkResult>,
+}
+
+fn field_id(template: &Template, name: &str) -> Result<FieldId, MochiError> {
+    template
+        .field_by_name(name)
+        .map(|f| f.id.clone())
+        .ok_or_else(|| MochiError::NotFound {
+            kind: "field",
+            name: name.to_string(),
+        })
+}
+
+// Fails if the mapping names a field either template doesn't have.
+pub fn plan_migration(
+    cards: &[Card],
+    from: &Template,
+    to: &Template,
+    mapping: &FieldMapping,
+) -> Result<MigrationPlan, MochiError> {
+    // Old field id to new field id.
+    let mut carried = HashMap::new();
+    let mut dropped = vec![];
+    let mut from_fields = from
+        .fields
+        .iter()
+        .flat_map(|f| f.values())
+        .collect::<Vec<_>>();
+    from_fields.sort_by(|a, b| a.pos.cmp(&b.pos));
+    for field in from_fields {
+        match mapping.renames.get(&field.name) {
+            Some(name) => {
+                carried.insert(field.id.clone(), field_id(to, name)?);
+            }
+            None => match to.field_by_name(&field.name) {
+                Some(new) => {
+                    carried.insert(field.id.clone(), new.id.clone());
+                }
+                None => dropped.push(field.name.clone()),
+            },
+        }
+    }
+    for old in mapping.renames.keys() {
+        field_id(from, old)?;
+    }
+    let mut defaults = vec![];
+    for (name, value) in mapping.defaults.iter() {
+        let id = field_id(to, name)?;
+        if !carried.values().any(|new| *new == id) {
+            defaults.push((id, value));
+        }
+    }
+
+    let mut plan = MigrationPlan {
+        dropped,
+        ..MigrationPlan::default()
+    };
+    for card in cards {
+        if card.template_id.as_ref() != Some(&from.id) {
+            plan.skipped.push(card.id.clone());
+            continue;
+        }
+        let mut patch = CardPatch::new().template_id(to.id.clone());
+        for (id, field) in card.fields.iter().flatten() {
+            if let Some(new) = carried.get(id) {
+                patch = patch.field(new.clone(), &field.value);
+            }
+        }
+        for (id, value) in defaults.iter() {
+            patch.fields.entry(id.clone()).or_insert_with(|| CardField {
+                id: id.clone(),
+                value: value.to_string(),
+            });
+        }
+        plan.patches.push((card.id.clone(), patch));
+    }
+    Ok(plan)
+}
+
+// Moves the cards of `from` to `to`. With `dry_run` only the plan is
+// returned.
+pub async fn migrate_cards_to_template(
+    config: &Config,
+    cards: &[Card],
+    from: &Template,
+    to: &Template,
+    mapping: &FieldMapping,
+    dry_run: bool,
+) -> Result<MigrationResult, MochiError> {
+    let plan = plan_migration(cards, from, to, mapping)?;
+    let updates = if dry_run {
+        None
+    } else {
+        Some(patch_cards(config, &plan.patches).await)
+    };
+    Ok(MigrationResult { plan, updates })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+
+    #[test]
+    fn test_plan_migration() {
+        let from = template_fixture(
+            "old",
+            "",
+            &[("a1", "Word"), ("a2", "English"), ("a3", "Scratch")],
+        );
+        let to = template_fixture(
+            "new",
+            "",
+            &[("b1", "Word"), ("b2", "Meaning"), ("b3", "Level")],
+        );
+        let cards = [
+            CardFixture::new("c1", "deck")
+                .template("old")
+                .field("a1", "箸")
+                .field("a2", "chopsticks")
+                .field("a3", "todo")
+                .build(),
+            CardFixture::new("c2", "deck").template("other").build(),
+        ];
+        let mapping = FieldMapping {
+            renames: HashMap::from([("English".to_string(), "Meaning".to_string())]),
+            defaults: HashMap::from([("Level".to_string(), "N5".to_string())]),
+        };
+
+        let plan = plan_migration(&cards, &from, &to, &mapping).unwrap();
+        assert_eq!(
+            plan.patches,
+            vec![(
+                CardId::from("c1"),
+                CardPatch::new()
+                    .template_id("new")
+                    .field("b1", "箸")
+                    .field("b2", "chopsticks")
+                    .field("b3", "N5")
+            )]
+        );
+        assert_eq!(plan.dropped, ["Scratch"]);
+        assert_eq!(plan.skipped, [CardId::from("c2")]);
+
+        let mapping = FieldMapping {
+            renames: HashMap::from([("English".to_string(), "Gloss".to_string())]),
+            ..FieldMapping::default()
+        };
+        assert!(plan_migration(&cards, &from, &to, &mapping).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+use std::error::Error;
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cache::{card_json, Cache};
+use crate::models::{Card, CardId, CardPatch, Deck, DeckId, ResolvedCard, Template};
+use crate::search::{SearchIndex, SearchQuery};
+use crate::{create_card, update_card_fields, Config, MochiError};
+
+// Offline Mode
+//
+// The same listing and search calls as the API, answered from the local cache
+// only. Changes go to the cached cards straight away and are queued in the
+// cache file, to be sent in order by `replay` once back online. Cards created
+// offline have a `local-` id until then; changes to them are folded into the
+// queued creation. Replay before refreshing: a refresh replaces the cached
+// cards of the decks it lists, offline changes included.
+
+const QUEUE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS pending (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        json TEXT NOT NULL
+    );
+";
+
+pub const LOCAL_ID_PREFIX: &str = "local-";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Mutation {
+    // The card as the API would return it, with its local id.
+    Create { card: Value },
+    Patch { card_id: CardId, patch: CardPatch },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingMutation {
+    // Replay order.
+    pub seq: i64,
+    pub mutation: Mutation,
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayResult {
+    // (local id, id the API gave the card)
+    pub created: Vec<(CardId, CardId)>,
+    pub updated: Vec<CardId>,
+    // Rejected by the API; they stay queued until discarded.
+    pub failed: Vec<(i64, MochiError)>,
+    // Still offline (or the API is down): the rest wasn't tried.
+    pub interrupted: Option<MochiError>,
+}
+
+pub struct OfflineClient {
+    cache: Cache,
+}
+
+fn not_found(card_id: &CardId) -> MochiError {
+    MochiError::NotFound {
+        kind: "card",
+        name: card_id.to_string(),
+    }
+}
+
+impl OfflineClient {
+    pub fn new(cache: Cache) -> Result<OfflineClient, Box<dyn Error>> {
+        cache.db.execute_batch(QUEUE_SCHEMA)?;
+        Ok(OfflineClient { cache })
+    }
+
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    pub fn into_cache(self) -> Cache {
+        self.cache
+    }
+
+    pub fn list_decks(&self) -> Result<Vec<Deck>, Box<dyn Error>> {
+        self.cache.decks()
+    }
+
+    pub fn list_templates(&self) -> Result<Vec<Template>, Box<dyn Error>> {
+        self.cache.templates()
+    }
+
+    pub fn list_cards(
+        &self,
+        deck_id: &DeckId,
+        limit: Option<usize>,
+    ) -> Result<Vec<Card>, Box<dyn Error>> {
+        let mut cards = self.cache.cards(deck_id)?;
+        if let Some(limit) = limit {
+            cards.truncate(limit);
+        }
+        Ok(cards)
+    }
+
+    pub fn get_card(&self, card_id: &CardId) -> Result<Card, Box<dyn Error>> {
+        Ok(self
+            .cache
+            .card(card_id)?
+            .ok_or_else(|| not_found(card_id))?)
+    }
+
+    pub fn search_cards(&self, query: &SearchQuery) -> Result<Vec<Card>, Box<dyn Error>> {
+        let templates = self.cache.templates()?;
+        let candidates = self.cache.candidates(query).unwrap_or_default();
+        let matches = ResolvedCard::resolve(&candidates, &templates)
+            .into_iter()
+            .filter(|card| query.matches(card))
+            .map(|card| card.card)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect();
+        Ok(matches)
+    }
+
+    // Cached with a local id and queued for creation.
+    pub fn create_card(&mut self, card: &Card) -> Result<Card, Box<dyn Error>> {
+        let tx = self.cache.db.transaction()?;
+        tx.execute("INSERT INTO pending (json) VALUES ('')", [])?;
+        let seq = tx.last_insert_rowid();
+        let mut card = card.clone();
+        card.id = CardId::new(format!("{}{}", LOCAL_ID_PREFIX, seq));
+        let mutation = Mutation::Create {
+            card: card_json(&card),
+        };
+        tx.execute(
+            "UPDATE pending SET json = ? WHERE seq = ?",
+            params![serde_json::to_string(&mutation)?, seq],
+        )?;
+        tx.commit()?;
+        self.cache.put_card(&card)?;
+        Ok(card)
+    }
+
+    // Applied to the cached card and queued.
+    pub fn update_card_fields(
+        &mut self,
+        card_id: &CardId,
+        patch: &CardPatch,
+    ) -> Result<Card, Box<dyn Error>> {
+        let mut card = self
+            .cache
+            .card(card_id)?
+            .ok_or_else(|| not_found(card_id))?;
+        patch.apply(&mut card);
+
+        let created = self.pending()?.into_iter().find(|p| match &p.mutation {
+            Mutation::Create { card } => card["id"] == card_id.as_str(),
+            Mutation::Patch { .. } => false,
+        });
+        let (seq, mutation) = match created {
+            Some(pending) => (
+                Some(pending.seq),
+                Mutation::Create {
+                    card: card_json(&card),
+                },
+            ),
+            None => (
+                None,
+                Mutation::Patch {
+                    card_id: card_id.clone(),
+                    patch: patch.clone(),
+                },
+            ),
+        };
+        let json = serde_json::to_string(&mutation)?;
+        match seq {
+            Some(seq) => self.cache.db.execute(
+                "UPDATE pending SET json = ? WHERE seq = ?",
+                params![json, seq],
+            )?,
+            None => self
+                .cache
+                .db
+                .execute("INSERT INTO pending (json) VALUES (?)", [json])?,
+        };
+        self.cache.put_card(&card)?;
+        Ok(card)
+    }
+
+    // Oldest first.
+    pub fn pending(&self) -> Result<Vec<PendingMutation>, Box<dyn Error>> {
+        let mut query = self
+            .cache
+            .db
+            .prepare("SELECT seq, json FROM pending ORDER BY seq")?;
+        let rows = query.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut pending = vec![];
+        for row in rows {
+            let (seq, json) = row?;
+            pending.push(PendingMutation {
+                seq,
+                mutation: serde_json::from_str(&json)?,
+            });
+        }
+        Ok(pending)
+    }
+
+    // Drops a queued change, e.g. one the API keeps rejecting. The cached
+    // card keeps the change until its deck is refreshed.
+    pub fn discard(&self, seq: i64) -> Result<(), Box<dyn Error>> {
+        self.cache
+            .db
+            .execute("DELETE FROM pending WHERE seq = ?", [seq])?;
+        Ok(())
+    }
+
+    // Sends the queued changes in order, replacing the cached cards with the
+    // API's versions. Stops at the first error that looks like being offline.
+    pub async fn replay(&mut self, config: &Config) -> Result<ReplayResult, Box<dyn Error>> {
+        let mut result = ReplayResult::default();
+        for pending in self.pending()? {
+            let sent = match &pending.mutation {
+                Mutation::Create { card } => {
+                    let local: Card = serde_json::from_value(card.clone())?;
+                    create_card(config, &local).await.map(|created| {
+                        result.created.push((local.id.clone(), created.id.clone()));
+                        (Some(local.id), created)
+                    })
+                }
+                Mutation::Patch { card_id, patch } => update_card_fields(config, card_id, patch)
+                    .await
+                    .map(|updated| {
+                        result.updated.push(card_id.clone());
+                        (None, updated)
+                    }),
+            };
+            match sent {
+                Ok((local_id, card)) => {
+                    if let Some(local_id) = local_id {
+                        self.cache.remove_card(&local_id)?;
+                    }
+                    self.cache.put_card(&card)?;
+                    self.cache.mark_stale(&card.deck_id)?;
+                    self.discard(pending.seq)?;
+                }
+                Err(err) if err.is_retryable() => {
+                    result.interrupted = Some(err);
+                    break;
+                }
+                Err(err) => result.failed.push((pending.seq, err)),
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::CardFixture;
+    use crate::models::CardBuilder;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_offline_client() {
+        let mut cache = Cache::open_in_memory().unwrap();
+        let card = CardFixture::new("c1", "n5").content("犬").json();
+        cache
+            .store_cards(&DeckId::from("n5"), &[card], SystemTime::now())
+            .unwrap();
+        let mut client = OfflineClient::new(cache).unwrap();
+
+        let new_card = CardBuilder::new("n5").content("猫").build().unwrap();
+        let created = client.create_card(&new_card).unwrap();
+        assert!(created.id.as_str().starts_with(LOCAL_ID_PREFIX));
+        client
+            .update_card_fields(&created.id, &CardPatch::new().content("猫 cat"))
+            .unwrap();
+        client
+            .update_card_fields(&CardId::from("c1"), &CardPatch::new().archived(true))
+            .unwrap();
+        assert!(client
+            .update_card_fields(&CardId::from("c9"), &CardPatch::new())
+            .is_err());
+
+        let pending = client.pending().unwrap();
+        assert_eq!(pending.len(), 2);
+        match &pending[0].mutation {
+            Mutation::Create { card } => assert_eq!(card["content"], "猫 cat"),
+            other => panic!("expected a creation, got {:?}", other),
+        }
+        assert_eq!(
+            pending[1].mutation,
+            Mutation::Patch {
+                card_id: CardId::from("c1"),
+                patch: CardPatch::new().archived(true),
+            }
+        );
+
+        let query = SearchQuery {
+            decks: vec![DeckId::from("n5")],
+            ..SearchQuery::new()
+        };
+        let found = client.search_cards(&query).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].content, "猫 cat");
+
+        client.discard(pending[1].seq).unwrap();
+        assert_eq!(client.pending().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,403 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use tracing::{info, info_span, trace, Instrument};
+
+use crate::coverage::is_blank_html;
+use crate::deinflect::{find_readings, ConjugatedForms};
+use crate::diff::{diff_cards, CardDiff};
+use crate::journal::patch_cards_with_journal;
+use crate::models::{Card, CardId, CardPatch, DeckId, ResolvedCard, Template};
+use crate::notation::{self, AccentNotation};
+use crate::phrase::{has_accents, phrase_readings, render_phrase, Tokenizer};
+use crate::{
+    list_cards, list_templates, patch_cards, AccentMap, BulkResult, ChangedCard, Config,
+    PitchHtmlStyle, WordAccents,
+};
+
+// Card Transformation Pipeline
+//
+// Transformers (pitch accent, furigana, audio, ...) run in order over each
+// card. Only cards that end up different from the original are pushed, and
+// only with the fields that changed.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransformOutcome {
+    Changed,
+    Skipped(String),
+    Error(String),
+}
+
+pub trait CardTransformer {
+    fn name(&self) -> &str;
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome;
+}
+
+// Lets a transformer be added to a pipeline by reference, to read its state
+// after the run.
+impl<T: CardTransformer + ?Sized> CardTransformer for &T {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        (**self).transform(card)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CardOutcome {
+    pub card_id: CardId,
+    // (transformer name, outcome) in pipeline order.
+    pub outcomes: Vec<(String, TransformOutcome)>,
+}
+
+impl CardOutcome {
+    pub fn has_error(&self) -> bool {
+        self.outcomes
+            .iter()
+            .any(|(_, o)| matches!(o, TransformOutcome::Error(_)))
+    }
+}
+
+#[derive(Debug)]
+pub struct PipelineRun {
+    pub cards: Vec<Card>,
+    pub outcomes: Vec<CardOutcome>,
+    // Patches for the cards that differ from the originals.
+    pub patches: Vec<(CardId, CardPatch)>,
+    // None unless the run pushed the patches.
+    pub updates: Option<BulkResult>,
+}
+
+impl PipelineRun {
+    pub fn changed_cards(&self) -> Vec<ChangedCard> {
+        self.patches
+            .iter()
+            .filter_map(|(id, patch)| {
+                let card = self.cards.iter().find(|c| c.id == *id)?;
+                Some(ChangedCard {
+                    card: card.clone(),
+                    patch: patch.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct Pipeline<'a> {
+    transformers: Vec<Box<dyn CardTransformer + 'a>>,
+    // Where `run` journals the cards before pushing, if set.
+    journal: Option<PathBuf>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new() -> Pipeline<'a> {
+        Pipeline::default()
+    }
+
+    pub fn with(mut self, transformer: impl CardTransformer + 'a) -> Pipeline<'a> {
+        self.transformers.push(Box::new(transformer));
+        self
+    }
+
+    pub fn journal(mut self, path: &Path) -> Pipeline<'a> {
+        self.journal = Some(path.to_path_buf());
+        self
+    }
+
+    // A transformer that errors has its changes to the card undone; the
+    // following transformers still run.
+    pub fn apply(&self, cards: &[Card], templates: &[Template]) -> PipelineRun {
+        let _span = info_span!("pipeline", cards = cards.len()).entered();
+        let mut run = PipelineRun {
+            cards: Vec::with_capacity(cards.len()),
+            outcomes: Vec::with_capacity(cards.len()),
+            patches: vec![],
+            updates: None,
+        };
+
+        for (original, mut resolved) in cards.iter().zip(ResolvedCard::resolve(cards, templates)) {
+            let mut outcomes = Vec::with_capacity(self.transformers.len());
+            for transformer in self.transformers.iter() {
+                let before = resolved.card.clone();
+                let outcome = transformer.transform(&mut resolved);
+                trace!(card = %original.id, transformer = transformer.name(), ?outcome);
+                if let TransformOutcome::Error(_) = outcome {
+                    resolved.card = before;
+                }
+                outcomes.push((transformer.name().to_string(), outcome));
+            }
+
+            let patch = CardPatch::between(original, &resolved.card);
+            if !patch.is_empty() {
+                run.patches.push((original.id.clone(), patch));
+            }
+            run.outcomes.push(CardOutcome {
+                card_id: original.id.clone(),
+                outcomes,
+            });
+            run.cards.push(resolved.card);
+        }
+
+        info!(changed = run.patches.len(), "applied");
+        run
+    }
+
+    // What `apply` would change on each card, for review before a run.
+    pub fn preview(&self, cards: &[Card], templates: &[Template]) -> Vec<CardDiff> {
+        let run = self.apply(cards, templates);
+        cards
+            .iter()
+            .zip(run.cards.iter())
+            .map(|(original, modified)| diff_cards(original, modified, templates))
+            .filter(|diff| !diff.is_empty())
+            .collect()
+    }
+
+    // Transform every card of the deck, pushing the changes unless `dry_run`.
+    pub async fn run(
+        &self,
+        config: &Config,
+        deck_id: &DeckId,
+        dry_run: bool,
+    ) -> Result<PipelineRun, Box<dyn Error>> {
+        async {
+            let cards = list_cards(config, deck_id, None).await?;
+            let templates = list_templates(config).await?;
+            let mut run = self.apply(&cards, &templates);
+            if !dry_run {
+                run.updates = Some(match &self.journal {
+                    Some(path) => {
+                        let names = self
+                            .transformers
+                            .iter()
+                            .map(|t| t.name())
+                            .collect::<Vec<_>>();
+                        let description = format!("pipeline {} on {}", names.join(","), deck_id);
+                        patch_cards_with_journal(config, &cards, &run.patches, path, &description)
+                            .await?
+                    }
+                    None => patch_cards(config, &run.patches).await,
+                });
+            }
+            Ok(run)
+        }
+        .instrument(info_span!("pipeline_run", deck = %deck_id))
+        .await
+    }
+}
+
+// Pitch Accent
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverwritePolicy {
+    // Rewrite the field whenever the generated HTML differs from it.
+    #[default]
+    IfDifferent,
+    // Leave fields that already have a value alone.
+    Never,
+}
+
+#[derive(Clone)]
+pub struct PitchAccentTransformer<'a> {
+    pub accents: &'a AccentMap,
+    pub word_field: String,
+    // Narrows homographs to the reading on the card, if set.
+    pub reading_field: Option<String>,
+    pub pitch_accent_field: String,
+    pub overwrite: OverwritePolicy,
+    pub notation: AccentNotation,
+    pub conjugated: ConjugatedForms,
+    // Only used by the HTML notation.
+    pub style: PitchHtmlStyle,
+    // Splits words that aren't in the dictionary into phrases, if set.
+    pub tokenizer: Option<&'a dyn Tokenizer>,
+}
+
+impl<'a> PitchAccentTransformer<'a> {
+    pub fn new(
+        accents: &'a AccentMap,
+        word_field: &str,
+        pitch_accent_field: &str,
+    ) -> PitchAccentTransformer<'a> {
+        PitchAccentTransformer {
+            accents,
+            word_field: word_field.to_string(),
+            reading_field: None,
+            pitch_accent_field: pitch_accent_field.to_string(),
+            overwrite: OverwritePolicy::default(),
+            notation: AccentNotation::default(),
+            conjugated: ConjugatedForms::default(),
+            style: PitchHtmlStyle::default(),
+            tokenizer: None,
+        }
+    }
+
+    pub fn reading<'c>(&self, card: &'c ResolvedCard) -> Option<&'c str> {
+        let reading = card.field(self.reading_field.as_ref()?)?.trim();
+        (!reading.is_empty()).then_some(reading)
+    }
+
+    // The readings for the card's word, deinflected if need be.
+    pub fn readings(&self, card: &ResolvedCard) -> Vec<WordAccents> {
+        let word = card.field(&self.word_field).unwrap_or("").to_string();
+        find_readings(&word, self.reading(card), self.accents, self.conjugated)
+    }
+}
+
+impl CardTransformer for PitchAccentTransformer<'_> {
+    fn name(&self) -> &str {
+        "pitch-accent"
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        if !card.has_field(&self.pitch_accent_field) {
+            return TransformOutcome::Skipped(format!("no {} field", self.pitch_accent_field));
+        }
+        let existing = card.field(&self.pitch_accent_field).unwrap_or("");
+        if self.overwrite == OverwritePolicy::Never && !is_blank_html(existing) {
+            return TransformOutcome::Skipped("already set".to_string());
+        }
+        let word = match card.field(&self.word_field) {
+            Some(word) => word.to_string(),
+            None => return TransformOutcome::Skipped(format!("no {} value", self.word_field)),
+        };
+
+        let readings = find_readings(&word, self.reading(card), self.accents, self.conjugated);
+        let html = match self.tokenizer {
+            Some(tokenizer) if readings.is_empty() => {
+                let tokens = match tokenizer.tokenize(&word) {
+                    Ok(tokens) => tokens,
+                    Err(e) => return TransformOutcome::Error(e.to_string()),
+                };
+                let parts = phrase_readings(&tokens, self.accents, self.conjugated);
+                if has_accents(&parts) {
+                    render_phrase(&parts, self.notation, &self.style)
+                } else {
+                    notation::render_readings(&[], self.notation, &self.style)
+                }
+            }
+            _ => notation::render_readings(
+                &readings.iter().collect::<Vec<_>>(),
+                self.notation,
+                &self.style,
+            ),
+        };
+        if card.field(&self.pitch_accent_field) == Some(html.as_str()) {
+            return TransformOutcome::Skipped("unchanged".to_string());
+        }
+        card.set_field(&self.pitch_accent_field, &html);
+        TransformOutcome::Changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+
+    struct Uppercase;
+
+    impl CardTransformer for Uppercase {
+        fn name(&self) -> &str {
+            "uppercase"
+        }
+
+        fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+            let meaning = card.field("Meaning").unwrap_or("").to_uppercase();
+            if meaning.is_empty() {
+                return TransformOutcome::Error("no meaning".to_string());
+            }
+            card.set_field("Meaning", &meaning);
+            TransformOutcome::Changed
+        }
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let template = template_fixture(
+            "vocab",
+            "",
+            &[
+                ("name", "Word"),
+                ("meaning", "Meaning"),
+                ("pitch", "PitchAccent"),
+            ],
+        );
+        let card = |id: &str, word: &str, meaning: &str| {
+            CardFixture::new(id, "deck")
+                .template("vocab")
+                .field("name", word)
+                .field("meaning", meaning)
+                .build()
+        };
+        let cards = [card("c1", "箸", "chopsticks"), card("c2", "猫", "")];
+
+        let accents = AccentMap::from([("箸".to_string(), vec![])]);
+        let pipeline = Pipeline::new()
+            .with(Uppercase)
+            .with(PitchAccentTransformer::new(&accents, "Word", "PitchAccent"));
+        let run = pipeline.apply(&cards, std::slice::from_ref(&template));
+
+        assert_eq!(run.outcomes[0].outcomes[0].1, TransformOutcome::Changed);
+        assert!(run.outcomes[1].has_error());
+        assert_eq!(
+            run.patches[0],
+            (
+                CardId::from("c1"),
+                CardPatch::new()
+                    .field("meaning", "CHOPSTICKS")
+                    .field("pitch", &crate::generate_html(&"箸".to_string(), &accents))
+            )
+        );
+        // c2 still gets its pitch accent despite the earlier error.
+        assert_eq!(run.patches.len(), 2);
+        assert_eq!(run.patches[1].1.fields.len(), 1);
+        assert_eq!(run.changed_cards()[1].card.id, "c2");
+
+        let diffs = pipeline.preview(&cards, &[template]);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].changes[0].name, "Meaning");
+        assert_eq!(diffs[0].changes[1].name, "PitchAccent");
+    }
+
+    #[test]
+    fn test_overwrite_policy() {
+        let template = template_fixture("vocab", "", &[("name", "Word"), ("pitch", "PitchAccent")]);
+        let card = |id: &str, pitch: &str| {
+            CardFixture::new(id, "deck")
+                .template("vocab")
+                .field("name", "箸")
+                .field("pitch", pitch)
+                .build()
+        };
+        let cards = [
+            card("set", "<span>hand-written</span>"),
+            card("blank", "<div style=\"text-align: center\"></div>"),
+        ];
+        let accents = AccentMap::from([("箸".to_string(), vec![])]);
+        let transformer = |overwrite| PitchAccentTransformer {
+            overwrite,
+            ..PitchAccentTransformer::new(&accents, "Word", "PitchAccent")
+        };
+
+        // The generated HTML for a word without accents is the blank wrapper.
+        let run = Pipeline::new()
+            .with(transformer(OverwritePolicy::IfDifferent))
+            .apply(&cards, std::slice::from_ref(&template));
+        assert_eq!(run.patches.len(), 1);
+        assert_eq!(run.patches[0].0, "set");
+
+        let run = Pipeline::new()
+            .with(transformer(OverwritePolicy::Never))
+            .apply(&cards, &[template]);
+        assert!(run.patches.is_empty());
+        assert_eq!(
+            run.outcomes[0].outcomes[0].1,
+            TransformOutcome::Skipped("already set".to_string())
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use std::error::Error;
+
+use crate::models::{Card, CardId, CardPatch, DeckId, FieldId};
+use crate::{list_cards, patch_cards, BulkResult, Config};
+
+// Study Presets
+//
+// Switch a deck between recognition and production study in one run. If any
+// card fails to update, the cards already changed are restored so the deck is
+// never left half switched.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StudyPreset {
+    pub name: String,
+    pub review_reverse: Option<bool>,
+    // Swap the two sides of `front\n---\nback` content.
+    pub swap_sides: bool,
+    // Swap the values of these two field ids.
+    pub swap_fields: Option<(FieldId, FieldId)>,
+}
+
+impl StudyPreset {
+    // Japanese on the front, answer with the meaning.
+    pub fn recognition() -> StudyPreset {
+        StudyPreset {
+            name: "recognition".to_string(),
+            review_reverse: Some(false),
+            swap_sides: false,
+            swap_fields: None,
+        }
+    }
+
+    // Meaning on the front, answer with the Japanese.
+    pub fn production() -> StudyPreset {
+        StudyPreset {
+            name: "production".to_string(),
+            review_reverse: Some(false),
+            swap_sides: true,
+            swap_fields: None,
+        }
+    }
+
+    pub fn both_directions() -> StudyPreset {
+        StudyPreset {
+            name: "both-directions".to_string(),
+            review_reverse: Some(true),
+            swap_sides: false,
+            swap_fields: None,
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<StudyPreset> {
+        [
+            StudyPreset::recognition(),
+            StudyPreset::production(),
+            StudyPreset::both_directions(),
+        ]
+        .into_iter()
+        .find(|p| p.name == name)
+    }
+
+    pub fn with_swapped_fields(mut self, a: &str, b: &str) -> StudyPreset {
+        self.swap_fields = Some((FieldId::from(a), FieldId::from(b)));
+        self
+    }
+}
+
+fn swap_sides(content: &str) -> Option<String> {
+    let sides = content.split("\n---\n").collect::<Vec<_>>();
+    match sides.as_slice() {
+        [front, back] => Some(format!("{}\n---\n{}", back, front)),
+        _ => None,
+    }
+}
+
+// The card as it looks with the preset applied.
+pub fn apply_to_card(card: &Card, preset: &StudyPreset) -> Card {
+    let mut card = card.clone();
+    if let Some(review_reverse) = preset.review_reverse {
+        card.review_reverse = review_reverse;
+    }
+    if preset.swap_sides {
+        if let Some(content) = swap_sides(&card.content) {
+            card.content = content;
+        }
+    }
+    if let (Some((a, b)), Some(fields)) = (&preset.swap_fields, card.fields.as_mut()) {
+        let value_a = fields.get(a).map(|f| f.value.clone());
+        let value_b = fields.get(b).map(|f| f.value.clone());
+        if let (Some(value_a), Some(value_b)) = (value_a, value_b) {
+            fields.get_mut(a).unwrap().value = value_b;
+            fields.get_mut(b).unwrap().value = value_a;
+        }
+    }
+    card
+}
+
+#[derive(Debug)]
+pub struct PresetResult {
+    pub applied: BulkResult,
+    // Set if some cards failed and the others were restored.
+    pub rolled_back: Option<BulkResult>,
+}
+
+pub async fn apply_preset(
+    config: &Config,
+    deck_id: &DeckId,
+    preset: &StudyPreset,
+) -> Result<PresetResult, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+
+    let mut patches = vec![];
+    let mut restores: Vec<(CardId, CardPatch)> = vec![];
+    for card in cards.iter() {
+        let modified = apply_to_card(card, preset);
+        let patch = CardPatch::between(card, &modified);
+        if !patch.is_empty() {
+            patches.push((card.id.clone(), patch));
+            restores.push((card.id.clone(), CardPatch::between(&modified, card)));
+        }
+    }
+
+    let applied = patch_cards(config, &patches).await;
+    if applied.is_success() {
+        return Ok(PresetResult {
+            applied,
+            rolled_back: None,
+        });
+    }
+
+    let restores = restores
+        .into_iter()
+        .filter(|(id, _)| applied.succeeded.contains(id))
+        .collect::<Vec<_>>();
+    let rolled_back = patch_cards(config, &restores).await;
+    Ok(PresetResult {
+        applied,
+        rolled_back: Some(rolled_back),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::CardFixture;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply_to_card() {
+        let card = CardFixture::new("card", "deck")
+            .content("犬\n---\ndog")
+            .field("word", "犬")
+            .field("meaning", "dog")
+            .set("review-reverse?", json!(true))
+            .build();
+
+        let production = StudyPreset::by_name("production").unwrap();
+        let modified = apply_to_card(&card, &production);
+        assert_eq!(modified.content, "dog\n---\n犬");
+        assert!(!modified.review_reverse);
+
+        let swapped = apply_to_card(
+            &card,
+            &StudyPreset::recognition().with_swapped_fields("word", "meaning"),
+        );
+        let fields = swapped.fields.as_ref().unwrap();
+        assert_eq!(fields["word"].value, "dog");
+        assert_eq!(fields["meaning"].value, "犬");
+
+        // The restore patch undoes the change.
+        let restore = CardPatch::between(&modified, &card);
+        assert_eq!(restore.content, Some(card.content.clone()));
+        assert_eq!(restore.review_reverse, Some(true));
+    }
+}
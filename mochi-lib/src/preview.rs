@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::net::SocketAddr;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, ToSocketAddrs};
+
+use crate::models::{Card, DeckId, ResolvedCard, Template};
+use crate::{list_cards, list_templates, Config};
+
+// Card Preview
+//
+// A tiny local web server showing generated field HTML inside mock card
+// frames, so markup changes can be checked by eye before updating a deck.
+
+const PREVIEW_CSS: &str = "
+body { font-family: -apple-system, 'Hiragino Sans', 'Noto Sans JP', sans-serif;
+       background: #f4f4f5; color: #18181b; margin: 0; padding: 2rem; }
+body.dark { background: #18181b; color: #e4e4e7; }
+header { display: flex; justify-content: space-between; align-items: center; }
+.cards { display: grid; grid-template-columns: repeat(auto-fill, minmax(320px, 1fr)); gap: 1rem; }
+.card { background: #fff; border-radius: 8px; padding: 1.5rem;
+        box-shadow: 0 1px 3px rgba(0, 0, 0, 0.15); }
+body.dark .card { background: #27272a; }
+.card .label { font-size: 0.75rem; opacity: 0.6; margin-bottom: 0.75rem; }
+";
+
+const TOGGLE_SCRIPT: &str =
+    "document.getElementById('toggle').onclick = () => document.body.classList.toggle('dark');";
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// A full page with one card frame per (label, field HTML) entry.
+pub fn render_preview_page(title: &str, entries: &[(String, String)]) -> String {
+    let cards = entries
+        .iter()
+        .map(|(label, html)| {
+            format!(
+                "<div class=\"card\"><div class=\"label\">{}</div>{}</div>",
+                escape_html(label),
+                html
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\
+         <style>{PREVIEW_CSS}</style></head>\n<body>\n\
+         <header><h1>{title}</h1><button id=\"toggle\">Dark / Light</button></header>\n\
+         <div class=\"cards\">\n{cards}\n</div>\n<script>{TOGGLE_SCRIPT}</script>\n</body></html>\n",
+        title = escape_html(title),
+    )
+}
+
+// (card id, field HTML) for every card whose template has the named field.
+pub fn field_entries(
+    cards: &[Card],
+    templates: &[Template],
+    field_name: &str,
+) -> Vec<(String, String)> {
+    ResolvedCard::resolve(cards, templates)
+        .into_iter()
+        .filter(|card| card.has_field(field_name))
+        .map(|card| {
+            let value = card.field(field_name).unwrap_or("").to_string();
+            (card.card.id.to_string(), value)
+        })
+        .collect()
+}
+
+pub async fn preview_deck_field(
+    config: &Config,
+    deck_id: &DeckId,
+    field_name: &str,
+) -> Result<String, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    let templates = list_templates(config).await?;
+    let entries = field_entries(&cards, &templates, field_name);
+    Ok(render_preview_page(field_name, &entries))
+}
+
+pub struct PreviewServer {
+    listener: TcpListener,
+}
+
+impl PreviewServer {
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> std::io::Result<PreviewServer> {
+        Ok(PreviewServer {
+            listener: TcpListener::bind(addr).await?,
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Answer every request with the page until the task is dropped.
+    pub async fn serve(self, page: String) -> std::io::Result<()> {
+        loop {
+            let (mut stream, _) = self.listener.accept().await?;
+            // The request itself doesn't matter, only that it was read.
+            let mut request = [0u8; 4096];
+            let _ = stream.read(&mut request).await;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                page.len(),
+                page
+            );
+            // A client hanging up early shouldn't stop the server.
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_preview_server() {
+        let entries = vec![(
+            "card <1>".to_string(),
+            "<span style=\"color: red\">は</span>".to_string(),
+        )];
+        let page = render_preview_page("PitchAccent", &entries);
+        assert!(page.contains("card &lt;1&gt;"));
+        assert!(page.contains("<span style=\"color: red\">は</span>"));
+
+        let server = PreviewServer::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = tokio::spawn(server.serve(page.clone()));
+
+        let body = reqwest::get(format!("http://{}/", addr))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(body, page);
+        handle.abort();
+    }
+}
@@ -0,0 +1,86 @@
+use std::error::Error;
+
+use crate::notation::AccentNotation;
+use crate::{Config, PitchHtmlStyle};
+
+pub use mochi_api::profiles::*;
+
+// Accent Settings
+//
+// mochi-api reads a profile's connection settings and leaves the accent ones
+// as written; they're parsed here, where the notations are known:
+//
+//   [profiles.me]
+//   accent_notation = "low-high"
+//   accent_color = "#FF6633"
+//   accent_width = "2px"
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccentSettings {
+    pub notation: AccentNotation,
+    pub style: PitchHtmlStyle,
+}
+
+impl AccentSettings {
+    pub fn from_profile(profile: &Profile) -> Result<AccentSettings, String> {
+        let mut settings = AccentSettings::default();
+        if let Some(notation) = &profile.accent_notation {
+            settings.notation = AccentNotation::by_name(notation)
+                .ok_or_else(|| format!("unknown accent notation {}", notation))?;
+        }
+        if let Some(color) = &profile.accent_color {
+            settings.style.color = color.clone();
+        }
+        if let Some(width) = &profile.accent_width {
+            settings.style.width = width.clone();
+        }
+        Ok(settings)
+    }
+
+    // The settings of the profile the config came from, the defaults if it
+    // came from none.
+    pub fn load(config: &Config) -> Result<AccentSettings, Box<dyn Error>> {
+        match config_profile(config)? {
+            Some(profile) => Ok(AccentSettings::from_profile(&profile)?),
+            None => Ok(AccentSettings::default()),
+        }
+    }
+}
+
+// The config file profile the config came from, if any.
+pub fn config_profile(config: &Config) -> Result<Option<Profile>, Box<dyn Error>> {
+    let Some(name) = &config.profile else {
+        return Ok(None);
+    };
+    let file = match ConfigFile::default_path() {
+        Some(path) => ConfigFile::load(&path)?,
+        None => ConfigFile::default(),
+    };
+    Ok(file.profiles.get(name).cloned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_accent_settings() {
+        let file = ConfigFile::parse(
+            r##"
+            [profiles.partner]
+            accent_notation = "low-high"
+            accent_color = "#3366FF"
+
+            [profiles.typo]
+            accent_notation = "hi-lo"
+            "##,
+        )
+        .unwrap();
+
+        let settings = AccentSettings::from_profile(&file.profiles["partner"]).unwrap();
+        assert_eq!(settings.notation, AccentNotation::LowHigh);
+        assert_eq!(settings.style.color, "#3366FF");
+        assert_eq!(settings.style.width, PitchHtmlStyle::default().width);
+        assert!(AccentSettings::from_profile(&file.profiles["typo"]).is_err());
+    }
+}
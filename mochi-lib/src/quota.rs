@@ -0,0 +1,236 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{Card, CardId};
+use crate::{unix_secs, update_cards, BulkResult, Config};
+
+// Quota-Aware Scheduling
+//
+// Tracks how many requests went out in the current rate-limit window and, when
+// a job would not fit in the remaining budget, spreads it across windows with
+// a checkpoint persisted after every chunk.
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateBudget {
+    pub requests: usize,
+    pub window: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuotaTracker {
+    budget: RateBudget,
+    sent: VecDeque<SystemTime>,
+}
+
+impl QuotaTracker {
+    pub fn new(budget: RateBudget) -> QuotaTracker {
+        QuotaTracker {
+            budget,
+            sent: VecDeque::new(),
+        }
+    }
+
+    fn expire(&mut self, now: SystemTime) {
+        while let Some(first) = self.sent.front() {
+            if now.duration_since(*first).unwrap_or_default() >= self.budget.window {
+                self.sent.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn record(&mut self, requests: usize, now: SystemTime) {
+        self.expire(now);
+        self.sent.extend(std::iter::repeat_n(now, requests));
+    }
+
+    pub fn remaining(&mut self, now: SystemTime) -> usize {
+        self.expire(now);
+        self.budget.requests.saturating_sub(self.sent.len())
+    }
+
+    // When the oldest tracked request leaves the window.
+    pub fn next_reset(&self, now: SystemTime) -> SystemTime {
+        self.sent
+            .front()
+            .map(|first| *first + self.budget.window)
+            .unwrap_or(now)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleWindow {
+    // Seconds since the unix epoch.
+    pub start: u64,
+    pub requests: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub windows: Vec<ScheduleWindow>,
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: usize = self.windows.iter().map(|w| w.requests).sum();
+        writeln!(f, "{} requests in {} window(s)", total, self.windows.len())?;
+        for window in self.windows.iter() {
+            writeln!(f, "  at {}: {} requests", window.start, window.requests)?;
+        }
+        Ok(())
+    }
+}
+
+// Fill what is left of the current window, then whole windows after it.
+pub fn plan_schedule(job_size: usize, tracker: &QuotaTracker, now: SystemTime) -> Schedule {
+    let mut tracker = tracker.clone();
+    let mut schedule = Schedule::default();
+    let mut left = job_size;
+
+    let first = cmp::min(tracker.remaining(now), left);
+    if first > 0 {
+        schedule.windows.push(ScheduleWindow {
+            start: unix_secs(now),
+            requests: first,
+        });
+        left -= first;
+    }
+
+    let mut start = if first > 0 && left > 0 {
+        now + tracker.budget.window
+    } else {
+        tracker.next_reset(now)
+    };
+    while left > 0 {
+        let requests = cmp::min(tracker.budget.requests.max(1), left);
+        schedule.windows.push(ScheduleWindow {
+            start: unix_secs(start),
+            requests,
+        });
+        left -= requests;
+        start += tracker.budget.window;
+    }
+
+    schedule
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub schedule: Schedule,
+    pub done: Vec<CardId>,
+    pub failed: Vec<CardId>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Option<Checkpoint>, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// Update the cards within the budget, resuming from the checkpoint file if a
+// previous run was interrupted. The checkpoint is removed once finished.
+pub async fn update_cards_scheduled(
+    config: &Config,
+    cards: &[Card],
+    tracker: &mut QuotaTracker,
+    checkpoint_path: &Path,
+) -> Result<BulkResult, Box<dyn Error>> {
+    let mut checkpoint = Checkpoint::load(checkpoint_path)?.unwrap_or_default();
+    let pending = cards
+        .iter()
+        .filter(|c| !checkpoint.done.contains(&c.id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    checkpoint.schedule = plan_schedule(pending.len(), tracker, SystemTime::now());
+    checkpoint.save(checkpoint_path)?;
+
+    let mut result = BulkResult::default();
+    let mut pending = pending.as_slice();
+    for window in checkpoint.schedule.windows.clone() {
+        let start = UNIX_EPOCH + Duration::from_secs(window.start);
+        if let Ok(wait) = start.duration_since(SystemTime::now()) {
+            tokio::time::sleep(wait).await;
+        }
+
+        let (chunk, rest) = pending.split_at(cmp::min(window.requests, pending.len()));
+        pending = rest;
+        tracker.record(chunk.len(), SystemTime::now());
+
+        let chunk_result = update_cards(config, chunk).await;
+        checkpoint
+            .done
+            .extend(chunk_result.succeeded.iter().cloned());
+        checkpoint.failed.extend(chunk_result.failed_ids());
+        checkpoint.save(checkpoint_path)?;
+
+        result.succeeded.extend(chunk_result.succeeded);
+        result.failed.extend(chunk_result.failed);
+    }
+
+    fs::remove_file(checkpoint_path)?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_plan_schedule() {
+        let budget = RateBudget {
+            requests: 10,
+            window: Duration::from_secs(60),
+        };
+        let now = UNIX_EPOCH + Duration::from_secs(1000);
+        let mut tracker = QuotaTracker::new(budget);
+        tracker.record(4, now - Duration::from_secs(30));
+        assert_eq!(tracker.remaining(now), 6);
+
+        let schedule = plan_schedule(25, &tracker, now);
+        assert_eq!(
+            schedule.windows,
+            vec![
+                ScheduleWindow {
+                    start: 1000,
+                    requests: 6
+                },
+                ScheduleWindow {
+                    start: 1060,
+                    requests: 10
+                },
+                ScheduleWindow {
+                    start: 1120,
+                    requests: 9
+                },
+            ]
+        );
+
+        // An exhausted budget waits for the oldest request to expire.
+        tracker.record(6, now);
+        let schedule = plan_schedule(3, &tracker, now);
+        assert_eq!(
+            schedule.windows,
+            vec![ScheduleWindow {
+                start: 1030,
+                requests: 3
+            }]
+        );
+    }
+}
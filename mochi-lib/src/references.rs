@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::models::{Card, CardId};
+
+// Card References
+//
+// Cards link to each other with `@[[card-id]]` in their content or fields.
+// The graph takes the links from there and from the card's `references`,
+// which Mochi fills from the content. Links to cards outside the given set are
+// kept as dangling, so a graph of a whole account shows broken links.
+
+fn reference_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"@\[\[([^\]]+)\]\]").unwrap())
+}
+
+// The ids the card refers to with `@[[card-id]]`, in its content and fields.
+pub fn card_references(card: &Card) -> BTreeSet<CardId> {
+    let texts =
+        std::iter::once(&card.content).chain(card.fields.iter().flatten().map(|(_, f)| &f.value));
+    texts
+        .flat_map(|text| reference_regex().captures_iter(text))
+        .map(|c| CardId::from(c[1].trim()))
+        .collect()
+}
+
+// The text with links to the mapped cards pointed at their new ids.
+pub fn remap_references(text: &str, ids: &HashMap<CardId, CardId>) -> String {
+    reference_regex()
+        .replace_all(text, |c: &regex::Captures| {
+            match ids.get(&CardId::from(c[1].trim())) {
+                Some(id) => link(id),
+                None => c[0].to_string(),
+            }
+        })
+        .to_string()
+}
+
+fn link(card_id: &CardId) -> String {
+    format!("@[[{}]]", card_id)
+}
+
+// The content with a link to the card on its own line at the end, unless it
+// already links to it.
+pub fn add_reference(content: &str, card_id: &CardId) -> String {
+    let link = link(card_id);
+    if content.contains(&link) {
+        return content.to_string();
+    }
+    let content = content.trim_end();
+    if content.is_empty() {
+        link
+    } else {
+        format!("{}\n\n{}", content, link)
+    }
+}
+
+// The content without links to the card. Lines left empty are removed.
+pub fn remove_reference(content: &str, card_id: &CardId) -> String {
+    let link = link(card_id);
+    content
+        .split('\n')
+        .filter_map(|line| {
+            if !line.contains(&link) {
+                return Some(line.to_string());
+            }
+            let line = line.replace(&link, "");
+            (!line.trim().is_empty()).then_some(line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceGraph {
+    // Card id to the first line of its content.
+    labels: BTreeMap<CardId, String>,
+    forward: BTreeMap<CardId, BTreeSet<CardId>>,
+    back: BTreeMap<CardId, BTreeSet<CardId>>,
+}
+
+impl ReferenceGraph {
+    pub fn new(cards: &[Card]) -> ReferenceGraph {
+        let mut graph = ReferenceGraph::default();
+        for card in cards {
+            let label = card.content.lines().next().unwrap_or("").trim();
+            let label = label.chars().take(30).collect::<String>();
+            graph.labels.insert(card.id.clone(), label);
+
+            let mut links = card_references(card);
+            links.extend(card.references.iter().map(|id| CardId::from(id.as_str())));
+            links.remove(&card.id);
+            for target in links.iter() {
+                graph
+                    .back
+                    .entry(target.clone())
+                    .or_default()
+                    .insert(card.id.clone());
+            }
+            graph.forward.insert(card.id.clone(), links);
+        }
+        graph
+    }
+
+    // The cards the card links to.
+    pub fn links(&self, card_id: &CardId) -> Vec<&CardId> {
+        self.forward.get(card_id).into_iter().flatten().collect()
+    }
+
+    // The cards linking to the card.
+    pub fn backlinks(&self, card_id: &CardId) -> Vec<&CardId> {
+        self.back.get(card_id).into_iter().flatten().collect()
+    }
+
+    // Cards with no links in either direction.
+    pub fn orphans(&self) -> Vec<&CardId> {
+        self.labels
+            .keys()
+            .filter(|id| self.links(id).is_empty() && self.backlinks(id).is_empty())
+            .collect()
+    }
+
+    // (from, to) links to cards that aren't in the graph.
+    pub fn dangling(&self) -> Vec<(&CardId, &CardId)> {
+        self.forward
+            .iter()
+            .flat_map(|(from, links)| links.iter().map(move |to| (from, to)))
+            .filter(|(_, to)| !self.labels.contains_key(*to))
+            .collect()
+    }
+
+    // Graphviz source, with dangling link targets dashed.
+    pub fn to_dot(&self) -> String {
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut dot = String::from("digraph references {\n");
+        for (id, label) in self.labels.iter() {
+            dot.push_str(&format!(
+                "  \"{}\" [label=\"{}\"];\n",
+                escape(id.as_str()),
+                escape(label)
+            ));
+        }
+        let missing = self
+            .dangling()
+            .into_iter()
+            .map(|(_, to)| to)
+            .collect::<BTreeSet<_>>();
+        for id in missing {
+            dot.push_str(&format!("  \"{}\" [style=dashed];\n", escape(id.as_str())));
+        }
+        for (from, links) in self.forward.iter() {
+            for to in links {
+                dot.push_str(&format!(
+                    "  \"{}\" -> \"{}\";\n",
+                    escape(from.as_str()),
+                    escape(to.as_str())
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::CardFixture;
+
+    #[test]
+    fn test_reference_graph() {
+        let card = |id: &str, content: &str| CardFixture::new(id, "deck").content(content).build();
+        let cards = [
+            card("c1", "# 箸\nSee @[[c2]] and @[[gone]]"),
+            card("c2", "# 橋"),
+            card("c3", "# 端"),
+        ];
+        let graph = ReferenceGraph::new(&cards);
+        assert_eq!(graph.backlinks(&CardId::from("c2")), [&CardId::from("c1")]);
+        assert_eq!(graph.orphans(), [&CardId::from("c3")]);
+        assert_eq!(
+            graph.to_dot(),
+            "digraph references {\n  \"c1\" [label=\"# 箸\"];\n  \"c2\" [label=\"# 橋\"];\n  \
+             \"c3\" [label=\"# 端\"];\n  \"gone\" [style=dashed];\n  \"c1\" -> \"c2\";\n  \
+             \"c1\" -> \"gone\";\n}\n"
+        );
+
+        let c3 = CardId::from("c3");
+        let linked = add_reference("# 端\n", &c3);
+        assert_eq!(linked, "# 端\n\n@[[c3]]");
+        assert_eq!(add_reference(&linked, &c3), linked);
+        assert_eq!(remove_reference(&linked, &c3), "# 端\n");
+    }
+}
@@ -0,0 +1,212 @@
+use std::cmp::Ordering;
+use std::error::Error;
+
+use crate::models::{Card, DeckId};
+use crate::{create_card, list_cards, unarchive_cards, BulkResult, Config, MochiError};
+
+// Staggered Release
+//
+// Import a large batch with most cards archived, then unarchive a few more
+// on every scheduled run, so the flow of new cards is controlled here rather
+// than by the app's new-card settings.
+
+pub enum ReleaseOrder<'a> {
+    // The order of the cards in the deck (`pos`).
+    Deck,
+    // Lowest rank first, e.g. a word frequency rank. Unranked cards go last.
+    Frequency(&'a dyn Fn(&Card) -> Option<usize>),
+}
+
+impl ReleaseOrder<'_> {
+    fn compare(&self, a: &Card, b: &Card) -> Ordering {
+        match self {
+            ReleaseOrder::Deck => compare_last_none(&a.pos, &b.pos),
+            ReleaseOrder::Frequency(rank) => compare_last_none(&rank(a), &rank(b)),
+        }
+    }
+}
+
+fn compare_last_none<T: Ord>(a: &Option<T>, b: &Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct StaggeredImport {
+    // The cards as the API created them, in order.
+    pub created: Vec<Card>,
+    // Cards that couldn't be created, as sent: `archived` is set only on the
+    // ones that weren't to be released.
+    pub failed: Vec<(Card, MochiError)>,
+}
+
+impl StaggeredImport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    // The cards to pass to `retry_staggered`, without the created ones.
+    pub fn failed_cards(&self) -> Vec<Card> {
+        self.failed.iter().map(|(card, _)| card.clone()).collect()
+    }
+}
+
+// Create the cards in order, leaving only the first `released` unarchived. A
+// card that fails doesn't stop the others.
+pub async fn import_staggered(config: &Config, cards: &[Card], released: usize) -> StaggeredImport {
+    let cards = cards
+        .iter()
+        .enumerate()
+        .map(|(i, card)| {
+            let mut card = card.clone();
+            card.archived = i >= released;
+            card
+        })
+        .collect::<Vec<_>>();
+    retry_staggered(config, &cards).await
+}
+
+// Create the failed cards of an import, each archived or not as it was meant
+// to be.
+pub async fn retry_staggered(config: &Config, failed_cards: &[Card]) -> StaggeredImport {
+    let mut result = StaggeredImport::default();
+    for card in failed_cards.iter() {
+        match create_card(config, card).await {
+            Ok(created) => result.created.push(created),
+            Err(err) => result.failed.push((card.clone(), err)),
+        }
+    }
+    result
+}
+
+// Pick the next `count` archived cards of the deck in the given order.
+pub fn next_release(cards: &[Card], count: usize, order: &ReleaseOrder) -> Vec<Card> {
+    let mut archived = cards
+        .iter()
+        .filter(|c| c.archived && c.trashed.is_none())
+        .cloned()
+        .collect::<Vec<_>>();
+    archived.sort_by(|a, b| order.compare(a, b));
+    archived.truncate(count);
+    archived
+}
+
+// Unarchive the next `count` cards of the deck. Meant to be run on a schedule.
+// Only `archived` is sent, so edits made since the listing are kept.
+pub async fn release_next(
+    config: &Config,
+    deck_id: &DeckId,
+    count: usize,
+    order: &ReleaseOrder<'_>,
+) -> Result<BulkResult, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    let release = next_release(&cards, count, order)
+        .into_iter()
+        .map(|c| c.id)
+        .collect::<Vec<_>>();
+    Ok(unarchive_cards(config, &release).await)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{self, CardFixture};
+    use crate::models::CardId;
+    use serde_json::json;
+
+    fn card(id: &str, pos: &str, archived: bool) -> Card {
+        CardFixture::new(id, "deck")
+            .set("pos", json!(pos))
+            .archived(archived)
+            .build()
+    }
+
+    #[test]
+    fn test_next_release() {
+        let cards = [
+            card("a", "c", true),
+            card("b", "a", false),
+            card("c", "b", true),
+            card("d", "d", true),
+        ];
+
+        let deck_order = next_release(&cards, 2, &ReleaseOrder::Deck);
+        let ids = deck_order.iter().map(|c| c.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["c", "a"]);
+
+        let rank = |c: &Card| match c.id.as_str() {
+            "d" => Some(1),
+            "a" => Some(2),
+            _ => None,
+        };
+        let frequency_order = next_release(&cards, 5, &ReleaseOrder::Frequency(&rank));
+        let ids = frequency_order
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["d", "a", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_import_staggered() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        let new_card = |content: &str| -> Card {
+            serde_json::from_value(serde_json::json!({
+                "id": "",
+                "content": content,
+                "deck-id": "N5DECK",
+            }))
+            .unwrap()
+        };
+        let cards = [new_card("一"), new_card("二"), new_card("三")];
+
+        // One failure on each side of the release boundary.
+        server.fail_next(400, serde_json::json!({ "errors": ["bad card"] }));
+        server.fail_next(400, serde_json::json!({ "errors": ["bad card"] }));
+        let result = import_staggered(&config, &cards, 1).await;
+        assert!(!result.is_success());
+        assert_eq!(result.created.len(), 1);
+        assert_eq!(result.created[0].content, "三");
+        assert!(result.created[0].archived);
+
+        let retry = retry_staggered(&config, &result.failed_cards()).await;
+        assert!(retry.is_success());
+        let contents = retry
+            .created
+            .iter()
+            .map(|c| (c.content.as_str(), c.archived))
+            .collect::<Vec<_>>();
+        assert_eq!(contents, [("一", false), ("二", true)]);
+    }
+
+    #[tokio::test]
+    async fn test_release_next() {
+        let server = mock::MockMochiServer::with_fixture().await.unwrap();
+        let config = server.config();
+        server.add_card(serde_json::json!({
+            "id": "held",
+            "content": "待つ",
+            "deck-id": "N5DECK",
+            "archived?": true,
+        }));
+
+        let result = release_next(&config, &DeckId::from("N5DECK"), 1, &ReleaseOrder::Deck)
+            .await
+            .unwrap();
+        assert_eq!(result.succeeded, [CardId::from("held")]);
+        let held = server.card("held").unwrap();
+        assert!(!held.archived);
+        assert_eq!(held.content, "待つ");
+        let sent = server
+            .requests()
+            .into_iter()
+            .find(|r| r.path == "cards/held")
+            .unwrap();
+        assert!(sent.body.unwrap().get("content").is_none());
+    }
+}
@@ -0,0 +1,180 @@
+use std::error::Error;
+
+use regex::Regex;
+
+use crate::models::{Card, CardId, CardPatch, DeckId, FieldId};
+use crate::{list_cards, patch_cards, BulkResult, Config};
+
+// Find and Replace
+//
+// A regex replacement over a deck's card content and/or field values. Only
+// cards that change are uploaded, with only the changed parts.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplaceTargets {
+    Fields,
+    Content,
+    #[default]
+    Both,
+}
+
+// Where on the card a replacement happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplaceLocation {
+    Content,
+    Field(FieldId),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChange {
+    pub card_id: CardId,
+    pub location: ReplaceLocation,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Debug)]
+pub struct ReplaceResult {
+    // One per changed content or field, in card order.
+    pub changes: Vec<TextChange>,
+    pub patches: Vec<(CardId, CardPatch)>,
+    // None for a dry run.
+    pub updates: Option<BulkResult>,
+}
+
+impl ReplaceResult {
+    // `3 cards changed (2 content, 4 fields)`
+    pub fn summary(&self) -> String {
+        let contents = self
+            .changes
+            .iter()
+            .filter(|c| c.location == ReplaceLocation::Content)
+            .count();
+        format!(
+            "{} cards changed ({} content, {} fields)",
+            self.patches.len(),
+            contents,
+            self.changes.len() - contents
+        )
+    }
+}
+
+// The changes and patches `replacement` makes to the cards. `$1` and `${name}`
+// in the replacement refer to capture groups.
+pub fn plan_replace(
+    cards: &[Card],
+    regex: &Regex,
+    replacement: &str,
+    targets: ReplaceTargets,
+) -> (Vec<TextChange>, Vec<(CardId, CardPatch)>) {
+    let mut changes = vec![];
+    let mut patches = vec![];
+    for card in cards {
+        let mut modified = card.clone();
+        let mut change = |location: ReplaceLocation, text: &mut String| {
+            let after = regex.replace_all(text, replacement);
+            if after != *text {
+                changes.push(TextChange {
+                    card_id: card.id.clone(),
+                    location,
+                    before: text.clone(),
+                    after: after.to_string(),
+                });
+                *text = after.to_string();
+            }
+        };
+
+        if targets != ReplaceTargets::Fields {
+            change(ReplaceLocation::Content, &mut modified.content);
+        }
+        if targets != ReplaceTargets::Content {
+            let mut fields = modified.fields.iter_mut().flatten().collect::<Vec<_>>();
+            // Field order is up to the map; sort for a stable summary.
+            fields.sort_by(|a, b| a.0.cmp(b.0));
+            for (id, field) in fields {
+                change(ReplaceLocation::Field(id.clone()), &mut field.value);
+            }
+        }
+
+        let patch = CardPatch::between(card, &modified);
+        if !patch.is_empty() {
+            patches.push((card.id.clone(), patch));
+        }
+    }
+    (changes, patches)
+}
+
+pub async fn bulk_replace(
+    config: &Config,
+    deck_id: &DeckId,
+    pattern: &str,
+    replacement: &str,
+    targets: ReplaceTargets,
+    dry_run: bool,
+) -> Result<ReplaceResult, Box<dyn Error>> {
+    let regex = Regex::new(pattern)?;
+    let cards = list_cards(config, deck_id, None).await?;
+    let (changes, patches) = plan_replace(&cards, &regex, replacement, targets);
+    let updates = if dry_run {
+        None
+    } else {
+        Some(patch_cards(config, &patches).await)
+    };
+    Ok(ReplaceResult {
+        changes,
+        patches,
+        updates,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::CardFixture;
+
+    #[test]
+    fn test_plan_replace() {
+        let card = |id: &str, content: &str, meaning: &str| {
+            CardFixture::new(id, "deck")
+                .content(content)
+                .template("vocab")
+                .field("name", "箸")
+                .field("meaning", meaning)
+                .build()
+        };
+        let cards = [
+            card("c1", "**箸**", "<b>chopsticks</b>"),
+            card("c2", "plain", "bridge"),
+        ];
+        let regex = Regex::new(r"<b>(.*?)</b>").unwrap();
+
+        let (changes, patches) = plan_replace(&cards, &regex, "**$1**", ReplaceTargets::Both);
+        assert_eq!(
+            changes,
+            vec![TextChange {
+                card_id: CardId::from("c1"),
+                location: ReplaceLocation::Field(FieldId::from("meaning")),
+                before: "<b>chopsticks</b>".to_string(),
+                after: "**chopsticks**".to_string(),
+            }]
+        );
+        assert_eq!(
+            patches,
+            vec![(
+                CardId::from("c1"),
+                CardPatch::new().field("meaning", "**chopsticks**")
+            )]
+        );
+
+        let (changes, _) = plan_replace(&cards, &regex, "**$1**", ReplaceTargets::Content);
+        assert!(changes.is_empty());
+
+        let regex = Regex::new("plain").unwrap();
+        let (changes, patches) = plan_replace(&cards, &regex, "", ReplaceTargets::Content);
+        assert_eq!(changes[0].location, ReplaceLocation::Content);
+        assert_eq!(
+            patches,
+            vec![(CardId::from("c2"), CardPatch::new().content(""))]
+        );
+    }
+}
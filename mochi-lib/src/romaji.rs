@@ -0,0 +1,44 @@
+use crate::models::ResolvedCard;
+use crate::pipeline::{CardTransformer, TransformOutcome};
+use crate::KanaString;
+
+pub use mochi_jp::romaji::*;
+
+// Romaji Fields
+//
+// The card side of mochi-jp's romaji: a transformer filling a romaji field
+// from a kana one.
+
+// Fills a romaji field from a kana field, e.g. the reading.
+#[derive(Debug, Clone)]
+pub struct RomajiTransformer {
+    pub kana_field: String,
+    pub romaji_field: String,
+    pub style: RomajiStyle,
+}
+
+impl CardTransformer for RomajiTransformer {
+    fn name(&self) -> &str {
+        "romaji"
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        if !card.has_field(&self.romaji_field) {
+            return TransformOutcome::Skipped(format!("no {} field", self.romaji_field));
+        }
+        let kana = match card.field(&self.kana_field).map(str::trim) {
+            Some(kana) if !kana.is_empty() => kana.to_string(),
+            _ => return TransformOutcome::Skipped(format!("no {} value", self.kana_field)),
+        };
+        if !kana.chars().all(is_kana) {
+            return TransformOutcome::Skipped(format!("{} is not kana", self.kana_field));
+        }
+
+        let romaji = KanaString::from(kana).to_romaji(self.style);
+        if card.field(&self.romaji_field) == Some(romaji.as_str()) {
+            return TransformOutcome::Skipped("unchanged".to_string());
+        }
+        card.set_field(&self.romaji_field, &romaji);
+        TransformOutcome::Changed
+    }
+}
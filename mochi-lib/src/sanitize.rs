@@ -0,0 +1,344 @@
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::models::ResolvedCard;
+use crate::pipeline::{CardTransformer, TransformOutcome};
+
+// HTML Normalization
+//
+// Field HTML written by different editors over the years: inline styles,
+// `<div>`s nested for no reason, `<b>` next to `<strong>`. The HTML is parsed
+// into a loose tree (unclosed tags are closed with their parent, stray closing
+// tags dropped), cleaned up and written back out. Comments and doctypes are
+// dropped; entities in text are kept as written.
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+// (name, raw value with its quotes), in order.
+pub(crate) type Attributes = Vec<(String, Option<String>)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Node {
+    Text(String),
+    Element {
+        name: String,
+        attributes: Attributes,
+        children: Vec<Node>,
+    },
+}
+
+fn tag_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?s)<!--.*?-->|<![^>]*>|<(/?)([a-zA-Z][a-zA-Z0-9-]*)((?:[^>'\x22]|'[^']*'|\x22[^\x22]*\x22)*?)/?>")
+            .unwrap()
+    })
+}
+
+fn attribute_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r#"([^\s"'>/=]+)(?:\s*=\s*("[^"]*"|'[^']*'|[^\s"'=<>`]+))?"#).unwrap()
+    })
+}
+
+pub(crate) fn parse(html: &str) -> Vec<Node> {
+    // Open elements as (name, attributes, children), the root at the bottom.
+    let mut stack: Vec<(String, Attributes, Vec<Node>)> = vec![(String::new(), vec![], vec![])];
+    let close_top = |stack: &mut Vec<(String, Attributes, Vec<Node>)>| {
+        let (name, attributes, children) = stack.pop().unwrap();
+        stack.last_mut().unwrap().2.push(Node::Element {
+            name,
+            attributes,
+            children,
+        });
+    };
+
+    let mut offset = 0;
+    for captures in tag_regex().captures_iter(html) {
+        let tag = captures.get(0).unwrap();
+        if tag.start() > offset {
+            let text = html[offset..tag.start()].to_string();
+            stack.last_mut().unwrap().2.push(Node::Text(text));
+        }
+        offset = tag.end();
+        let Some(name) = captures.get(2) else {
+            continue;
+        };
+        let name = name.as_str().to_lowercase();
+
+        if &captures[1] == "/" {
+            if let Some(depth) = stack.iter().skip(1).rposition(|(n, _, _)| *n == name) {
+                while stack.len() > depth + 1 {
+                    close_top(&mut stack);
+                }
+            }
+            continue;
+        }
+        let attributes = attribute_regex()
+            .captures_iter(&captures[3])
+            .map(|a| {
+                (
+                    a[1].to_lowercase(),
+                    a.get(2).map(|v| v.as_str().to_string()),
+                )
+            })
+            .collect();
+        if VOID_ELEMENTS.contains(&name.as_str()) || tag.as_str().ends_with("/>") {
+            stack.last_mut().unwrap().2.push(Node::Element {
+                name,
+                attributes,
+                children: vec![],
+            });
+        } else {
+            stack.push((name, attributes, vec![]));
+        }
+    }
+    if offset < html.len() {
+        stack
+            .last_mut()
+            .unwrap()
+            .2
+            .push(Node::Text(html[offset..].to_string()));
+    }
+    while stack.len() > 1 {
+        close_top(&mut stack);
+    }
+    stack.pop().unwrap().2
+}
+
+// What `parse` has to repair, e.g. `unclosed <b>` or `stray </div>`.
+pub(crate) fn tag_problems(html: &str) -> Vec<String> {
+    let mut problems = vec![];
+    let mut open: Vec<String> = vec![];
+    for captures in tag_regex().captures_iter(html) {
+        let Some(name) = captures.get(2) else {
+            continue;
+        };
+        let name = name.as_str().to_lowercase();
+        if &captures[1] != "/" {
+            if !VOID_ELEMENTS.contains(&name.as_str()) && !captures[0].ends_with("/>") {
+                open.push(name);
+            }
+            continue;
+        }
+        match open.iter().rposition(|n| *n == name) {
+            Some(depth) => {
+                for unclosed in open.drain(depth..).skip(1) {
+                    problems.push(format!("unclosed <{}>", unclosed));
+                }
+            }
+            None if VOID_ELEMENTS.contains(&name.as_str()) => {}
+            None => problems.push(format!("stray </{}>", name)),
+        }
+    }
+    problems.extend(open.into_iter().map(|name| format!("unclosed <{}>", name)));
+    problems
+}
+
+fn write(nodes: &[Node], out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Element {
+                name,
+                attributes,
+                children,
+            } => {
+                out.push('<');
+                out.push_str(name);
+                for (attribute, value) in attributes {
+                    out.push(' ');
+                    out.push_str(attribute);
+                    if let Some(value) = value {
+                        out.push('=');
+                        out.push_str(value);
+                    }
+                }
+                out.push('>');
+                if !VOID_ELEMENTS.contains(&name.as_str()) {
+                    write(children, out);
+                    out.push_str(&format!("</{}>", name));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlNormalizer {
+    // Removes `style` attributes and `<style>` elements.
+    pub strip_styles: bool,
+    // `<div><div>x</div></div>` to `<div>x</div>`.
+    pub collapse_divs: bool,
+    // `<b>` to `<strong>` and `<i>` to `<em>`.
+    pub semantic_tags: bool,
+    // Elements not in the list are replaced by their content, if set.
+    // `<script>` is always removed.
+    pub allowed_tags: Option<Vec<String>>,
+}
+
+impl Default for HtmlNormalizer {
+    fn default() -> HtmlNormalizer {
+        HtmlNormalizer {
+            strip_styles: true,
+            collapse_divs: true,
+            semantic_tags: true,
+            allowed_tags: None,
+        }
+    }
+}
+
+impl HtmlNormalizer {
+    pub fn normalize(&self, html: &str) -> String {
+        let mut out = String::with_capacity(html.len());
+        write(&self.clean(parse(html)), &mut out);
+        out
+    }
+
+    fn clean(&self, nodes: Vec<Node>) -> Vec<Node> {
+        let mut cleaned = vec![];
+        for node in nodes {
+            let Node::Element {
+                mut name,
+                mut attributes,
+                children,
+            } = node
+            else {
+                cleaned.push(node);
+                continue;
+            };
+            if name == "script" || (self.strip_styles && name == "style") {
+                continue;
+            }
+            let mut children = self.clean(children);
+            if self.semantic_tags {
+                name = match name.as_str() {
+                    "b" => "strong".to_string(),
+                    "i" => "em".to_string(),
+                    _ => name,
+                };
+            }
+            if let Some(allowed) = &self.allowed_tags {
+                if !allowed.contains(&name) {
+                    cleaned.extend(children);
+                    continue;
+                }
+            }
+            if self.strip_styles {
+                attributes.retain(|(attribute, _)| attribute != "style");
+            }
+            if self.collapse_divs && name == "div" && attributes.is_empty() {
+                let mut elements = children
+                    .iter()
+                    .filter(|c| !matches!(c, Node::Text(text) if text.trim().is_empty()));
+                if let (Some(Node::Element { name: inner, .. }), None) =
+                    (elements.next(), elements.next())
+                {
+                    if inner == "div" {
+                        children.retain(|c| matches!(c, Node::Element { .. }));
+                        cleaned.extend(children);
+                        continue;
+                    }
+                }
+            }
+            cleaned.push(Node::Element {
+                name,
+                attributes,
+                children,
+            });
+        }
+        cleaned
+    }
+}
+
+// Plain Text
+
+// The text with every `<...>` tag replaced, for comparing or checking what a
+// field shows rather than normalizing it.
+pub fn strip_tags<'a>(html: &'a str, replacement: &str) -> Cow<'a, str> {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = REGEX.get_or_init(|| Regex::new(r"<[^>]*>").unwrap());
+    regex.replace_all(html, replacement)
+}
+
+// Transformer
+
+#[derive(Debug, Clone, Default)]
+pub struct HtmlNormalizeTransformer {
+    pub normalizer: HtmlNormalizer,
+    // Field names to normalize, every field if empty.
+    pub fields: Vec<String>,
+}
+
+impl CardTransformer for HtmlNormalizeTransformer {
+    fn name(&self) -> &str {
+        "normalize-html"
+    }
+
+    fn transform(&self, card: &mut ResolvedCard) -> TransformOutcome {
+        let mut changed = false;
+        if self.fields.is_empty() {
+            for field in card.card.fields.iter_mut().flat_map(|f| f.values_mut()) {
+                let normalized = self.normalizer.normalize(&field.value);
+                if normalized != field.value {
+                    field.value = normalized;
+                    changed = true;
+                }
+            }
+        } else {
+            for name in self.fields.iter() {
+                let Some(value) = card.field(name) else {
+                    continue;
+                };
+                let normalized = self.normalizer.normalize(value);
+                if normalized != value {
+                    changed |= card.set_field(name, &normalized);
+                }
+            }
+        }
+
+        if changed {
+            TransformOutcome::Changed
+        } else {
+            TransformOutcome::Skipped("unchanged".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let normalizer = HtmlNormalizer::default();
+        assert_eq!(
+            normalizer.normalize(
+                "<div style=\"color: red\"><div>\n<DIV class='x'><b>箸</b> <i>hashi</i><br></DIV></div></div>"
+            ),
+            "<div class='x'><strong>箸</strong> <em>hashi</em><br></div>"
+        );
+        // Unclosed tags are closed, stray closing tags dropped.
+        assert_eq!(
+            normalizer.normalize("<span>a<b>b</span>c</p><!-- note -->"),
+            "<span>a<strong>b</strong></span>c"
+        );
+
+        let whitelist = HtmlNormalizer {
+            allowed_tags: Some(vec!["br".to_string(), "strong".to_string()]),
+            ..HtmlNormalizer::default()
+        };
+        assert_eq!(
+            whitelist.normalize("<p><b>a</b><br/><font>b</font><script>x()</script></p>"),
+            "<strong>a</strong><br>b"
+        );
+        let untouched = "<div>a</div><div>b <a href=\"https://mochi.cards\">c</a></div>";
+        assert_eq!(normalizer.normalize(untouched), untouched);
+    }
+}
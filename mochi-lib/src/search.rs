@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use futures::stream::{Stream, StreamExt};
+use regex::Regex;
+
+use crate::models::{Card, DeckId, ResolvedCard, Template};
+use crate::tags::has_tag;
+use crate::{list_templates, stream, stream_cards, Config, MochiError};
+
+// Card Search
+//
+// The API can only list cards by deck, so searching is client-side: the
+// cards are streamed page by page and filtered as they arrive. A local index
+// can stand in for the API listing when one is at hand.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StateFilter {
+    #[default]
+    Exclude,
+    Include,
+    Only,
+}
+
+impl StateFilter {
+    fn allows(self, state: bool) -> bool {
+        match self {
+            StateFilter::Exclude => !state,
+            StateFilter::Include => true,
+            StateFilter::Only => state,
+        }
+    }
+}
+
+// Every set condition has to match.
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    // Every deck if empty.
+    pub decks: Vec<DeckId>,
+    // Cards with all of these tags.
+    pub tags: Vec<String>,
+    // (field name, pattern) pairs the field values have to match.
+    pub fields: Vec<(String, Regex)>,
+    pub content: Option<Regex>,
+    // Matches the content or any field.
+    pub text: Option<Regex>,
+    pub archived: StateFilter,
+    pub trashed: StateFilter,
+    // Stops after this many matches.
+    pub limit: Option<usize>,
+}
+
+impl SearchQuery {
+    pub fn new() -> SearchQuery {
+        SearchQuery::default()
+    }
+
+    pub fn matches(&self, card: &ResolvedCard) -> bool {
+        let c = &card.card;
+        self.archived.allows(c.archived)
+            && self.trashed.allows(c.trashed.is_some())
+            && (self.decks.is_empty() || self.decks.contains(&c.deck_id))
+            && self.tags.iter().all(|tag| has_tag(c, tag))
+            && self.content.as_ref().is_none_or(|r| r.is_match(&c.content))
+            && self.fields.iter().all(|(name, pattern)| {
+                card.field(name)
+                    .is_some_and(|value| pattern.is_match(value))
+            })
+            && self.text.as_ref().is_none_or(|r| {
+                r.is_match(&c.content)
+                    || c.fields.iter().flatten().any(|(_, f)| r.is_match(&f.value))
+            })
+    }
+}
+
+// A local copy of the cards, e.g. a cache kept by a sync job.
+pub trait SearchIndex {
+    // Cards that might match the query, a superset of the matches. None if
+    // the index can't answer it, e.g. it doesn't cover the decks.
+    fn candidates(&self, query: &SearchQuery) -> Option<Vec<Card>>;
+}
+
+// Cards held in memory, looked up by deck and tag.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryIndex {
+    cards: Vec<Card>,
+    by_deck: HashMap<DeckId, Vec<usize>>,
+    by_tag: HashMap<String, Vec<usize>>,
+}
+
+impl MemoryIndex {
+    pub fn new(cards: Vec<Card>) -> MemoryIndex {
+        let mut by_deck: HashMap<DeckId, Vec<usize>> = HashMap::new();
+        let mut by_tag: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, card) in cards.iter().enumerate() {
+            by_deck.entry(card.deck_id.clone()).or_default().push(i);
+            let mut tags = card
+                .tags
+                .iter()
+                .chain(card.manual_tags.iter().flatten())
+                .collect::<Vec<_>>();
+            tags.sort();
+            tags.dedup();
+            for tag in tags {
+                by_tag.entry(tag.clone()).or_default().push(i);
+            }
+        }
+        MemoryIndex {
+            cards,
+            by_deck,
+            by_tag,
+        }
+    }
+}
+
+impl SearchIndex for MemoryIndex {
+    fn candidates(&self, query: &SearchQuery) -> Option<Vec<Card>> {
+        let empty = vec![];
+        // The shortest of the lists the matches have to be in.
+        let lists = query
+            .tags
+            .iter()
+            .map(|tag| self.by_tag.get(tag).unwrap_or(&empty))
+            .collect::<Vec<_>>();
+        let indices = match lists.iter().min_by_key(|l| l.len()) {
+            Some(list) => list.to_vec(),
+            None if query.decks.is_empty() => (0..self.cards.len()).collect(),
+            None => {
+                let mut indices = query
+                    .decks
+                    .iter()
+                    .flat_map(|deck| self.by_deck.get(deck).unwrap_or(&empty))
+                    .copied()
+                    .collect::<Vec<_>>();
+                indices.sort_unstable();
+                indices
+            }
+        };
+        Some(indices.into_iter().map(|i| self.cards[i].clone()).collect())
+    }
+}
+
+async fn collect_matches<S>(
+    cards: S,
+    query: &SearchQuery,
+    templates: &[Template],
+    matches: &mut Vec<Card>,
+) -> Result<(), MochiError>
+where
+    S: Stream<Item = Result<Card, MochiError>>,
+{
+    let mut cards = std::pin::pin!(cards);
+    while let Some(card) = cards.next().await {
+        if query.limit.is_some_and(|limit| matches.len() >= limit) {
+            break;
+        }
+        let card = ResolvedCard::resolve(&[card?], templates).remove(0);
+        if query.matches(&card) {
+            matches.push(card.card);
+        }
+    }
+    Ok(())
+}
+
+pub async fn search_cards(
+    config: &Config,
+    query: &SearchQuery,
+) -> Result<Vec<Card>, Box<dyn Error>> {
+    search_cards_with_index(config, query, None).await
+}
+
+// Falls back to listing the API if the index can't answer the query.
+pub async fn search_cards_with_index(
+    config: &Config,
+    query: &SearchQuery,
+    index: Option<&dyn SearchIndex>,
+) -> Result<Vec<Card>, Box<dyn Error>> {
+    // Field names only resolve through the templates.
+    let templates = if query.fields.is_empty() {
+        Box::new([])
+    } else {
+        list_templates(config).await?
+    };
+
+    let mut matches = vec![];
+    if let Some(candidates) = index.and_then(|index| index.candidates(query)) {
+        let candidates = candidates.into_iter().map(Ok);
+        collect_matches(
+            futures::stream::iter(candidates),
+            query,
+            &templates,
+            &mut matches,
+        )
+        .await?;
+    } else if query.decks.is_empty() {
+        let args = HashMap::from([("limit".to_string(), serde_json::to_value(100)?)]);
+        collect_matches(
+            stream("cards", args, config),
+            query,
+            &templates,
+            &mut matches,
+        )
+        .await?;
+    } else {
+        for deck_id in query.decks.iter() {
+            collect_matches(
+                stream_cards(config, deck_id),
+                query,
+                &templates,
+                &mut matches,
+            )
+            .await?;
+        }
+    }
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::CardFixture;
+
+    #[test]
+    fn test_search_index() {
+        let card = |id: &str, deck: &str, content: &str, tags: &[&str], archived: bool| {
+            CardFixture::new(id, deck)
+                .content(content)
+                .tags(tags)
+                .archived(archived)
+                .build()
+        };
+        let index = MemoryIndex::new(vec![
+            card("c1", "n5", "箸 chopstciks", &["food"], false),
+            card("c2", "n5", "橋 bridge", &[], false),
+            card("c3", "n4", "茶 tea", &["food"], true),
+            card("c4", "n4", "肉 meat", &["food"], false),
+        ]);
+        let search = |query: &SearchQuery| -> Vec<String> {
+            index
+                .candidates(query)
+                .unwrap()
+                .into_iter()
+                .filter(|c| query.matches(&ResolvedCard::resolve(std::slice::from_ref(c), &[])[0]))
+                .map(|c| c.id.to_string())
+                .collect()
+        };
+
+        let typo = SearchQuery {
+            text: Some(Regex::new("stciks").unwrap()),
+            ..SearchQuery::new()
+        };
+        assert_eq!(search(&typo), ["c1"]);
+
+        let food = SearchQuery {
+            tags: vec!["food".to_string()],
+            ..SearchQuery::new()
+        };
+        assert_eq!(search(&food), ["c1", "c4"]);
+
+        let archived = SearchQuery {
+            decks: vec![DeckId::from("n4")],
+            archived: StateFilter::Only,
+            ..SearchQuery::new()
+        };
+        assert_eq!(search(&archived), ["c3"]);
+
+        let none = SearchQuery {
+            tags: vec!["food".to_string()],
+            decks: vec![DeckId::from("n3")],
+            ..SearchQuery::new()
+        };
+        assert!(search(&none).is_empty());
+    }
+}
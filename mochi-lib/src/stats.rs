@@ -0,0 +1,153 @@
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::coverage::is_stats_card;
+use crate::leeches::{is_leech, LEECH_THRESHOLD};
+use crate::models::{Card, DeckId};
+use crate::{list_cards, Config};
+
+// Review Statistics
+//
+// A deck at a glance, for a morning "what's due" report outside the app:
+//
+//   120 cards: 14 due today (3 overdue), 20 new, 2 leeches
+//   retention 87% over 950 reviews
+//
+// Archived and trashed cards are left out, as is the coverage stats card.
+
+// How far ahead "due today" looks. Without the user's time zone the day is
+// the next 24 hours.
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeckStats {
+    pub cards: usize,
+    pub new: usize,
+    // Overdue cards included.
+    pub due_today: usize,
+    pub overdue: usize,
+    pub leeches: usize,
+    pub reviews: usize,
+    pub remembered: usize,
+}
+
+impl DeckStats {
+    pub fn from_cards(cards: &[Card], now: SystemTime, leech_threshold: usize) -> DeckStats {
+        let mut stats = DeckStats::default();
+        let cards = cards
+            .iter()
+            .filter(|c| !c.archived && c.trashed.is_none() && !is_stats_card(c));
+        for card in cards {
+            stats.cards += 1;
+            if card.reviews.is_empty() {
+                stats.new += 1;
+            }
+            if card.is_due(now + DAY) {
+                stats.due_today += 1;
+            }
+            if card.is_due(now) {
+                stats.overdue += 1;
+            }
+            if is_leech(card, leech_threshold) {
+                stats.leeches += 1;
+            }
+            stats.reviews += card.reviews.len();
+            stats.remembered += card.times_remembered();
+        }
+        stats
+    }
+
+    // Percent of reviews remembered, None before the first review.
+    pub fn retention(&self) -> Option<usize> {
+        (self.reviews > 0).then(|| self.remembered * 100 / self.reviews)
+    }
+}
+
+impl fmt::Display for DeckStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} cards: {} due today ({} overdue), {} new, {} leeches",
+            self.cards, self.due_today, self.overdue, self.new, self.leeches
+        )?;
+        match self.retention() {
+            Some(retention) => {
+                writeln!(f, "retention {}% over {} reviews", retention, self.reviews)
+            }
+            None => writeln!(f, "no reviews yet"),
+        }
+    }
+}
+
+pub async fn deck_stats(config: &Config, deck_id: &DeckId) -> Result<DeckStats, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    Ok(DeckStats::from_cards(
+        &cards,
+        SystemTime::now(),
+        LEECH_THRESHOLD,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::CardFixture;
+    use crate::models::Timestamp;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_deck_stats() {
+        let review = |due: &str, remembered: bool| {
+            json!({
+                "date": { "date": "2024-03-01T08:00:00Z" },
+                "due": { "date": due },
+                "remembered?": remembered,
+            })
+        };
+        let card = |id: &str, reviews: Vec<Value>| {
+            CardFixture::new(id, "deck")
+                .set("reviews", json!(reviews))
+                .build()
+        };
+        let mut archived = card("archived", vec![]);
+        archived.archived = true;
+        let cards = [
+            card("new", vec![]),
+            card("overdue", vec![review("2024-03-09T08:00:00Z", true)]),
+            card("today", vec![review("2024-03-10T20:00:00Z", true)]),
+            card("later", vec![review("2024-04-01T08:00:00Z", true)]),
+            card(
+                "leech",
+                vec![
+                    review("2024-03-02T08:00:00Z", false),
+                    review("2024-03-02T08:00:00Z", false),
+                ],
+            ),
+            archived,
+        ];
+
+        let now = Timestamp::new("2024-03-10T08:00:00Z")
+            .system_time()
+            .unwrap();
+        let stats = DeckStats::from_cards(&cards, now, 2);
+        assert_eq!(
+            stats,
+            DeckStats {
+                cards: 5,
+                new: 1,
+                due_today: 3,
+                overdue: 2,
+                leeches: 1,
+                reviews: 5,
+                remembered: 3,
+            }
+        );
+        assert_eq!(stats.retention(), Some(60));
+        assert_eq!(
+            stats.to_string(),
+            "5 cards: 3 due today (2 overdue), 1 new, 1 leeches\nretention 60% over 5 reviews\n"
+        );
+        assert_eq!(DeckStats::default().retention(), None);
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::encoding;
+use crate::models::{Card, CardBuildError, CardBuilder, DeckId, ResolvedCard, Template};
+use crate::phrase::{PhraseToken, Tokenizer};
+use crate::sanitize::strip_tags;
+use crate::{create_card, Config};
+
+// Subtitle Sentence Mining
+//
+// Lines of SRT or ASS subtitles become sentence cards when they contain a
+// word from a target list or, going by the cards already studied, exactly
+// one unknown word (an "i+1" sentence). The card keeps the line, where in
+// the episode it was said and the words it was mined for, marked in bold.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtitleLine {
+    pub start: Duration,
+    pub end: Duration,
+    pub text: String,
+}
+
+// Override blocks in ASS, e.g. `{\i1}`.
+fn override_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"\{[^}]*\}").unwrap())
+}
+
+fn clean_text(text: &str) -> String {
+    // HTML tags in SRT.
+    let text = strip_tags(text, "");
+    let text = override_regex().replace_all(&text, "");
+    text.replace("\\N", "\n")
+        .replace("\\n", "\n")
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// `01:02:03,456` (SRT) or `1:02:03.45` (ASS).
+fn parse_timestamp(raw: &str) -> Option<Duration> {
+    let (hms, fraction) = raw.trim().split_once([',', '.'])?;
+    let parts = hms
+        .split(':')
+        .map(|p| p.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let [hours, minutes, seconds] = parts.as_slice() else {
+        return None;
+    };
+    // `.45` is 450 ms, `,456` 456 ms.
+    let millis = format!("{:0<3}", fraction).get(..3)?.parse::<u64>().ok()?;
+    Some(Duration::from_millis(
+        ((hours * 60 + minutes) * 60 + seconds) * 1000 + millis,
+    ))
+}
+
+// `HH:MM:SS`, for the card.
+pub fn format_timestamp(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+pub fn parse_srt(text: &str) -> Vec<SubtitleLine> {
+    let text = text.replace("\r\n", "\n");
+    let mut lines = vec![];
+    for block in text.split("\n\n") {
+        let mut rows = block.lines().skip_while(|l| !l.contains("-->"));
+        let Some((start, end)) = rows.next().and_then(|l| l.split_once("-->")) else {
+            continue;
+        };
+        let (Some(start), Some(end)) = (parse_timestamp(start), parse_timestamp(end)) else {
+            continue;
+        };
+        let text = clean_text(&rows.collect::<Vec<_>>().join("\n"));
+        if !text.is_empty() {
+            lines.push(SubtitleLine { start, end, text });
+        }
+    }
+    lines
+}
+
+// The `Dialogue` lines of the `[Events]` section, by its `Format` line.
+pub fn parse_ass(text: &str) -> Vec<SubtitleLine> {
+    let mut format: Vec<String> = vec![];
+    let mut in_events = false;
+    let mut lines = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+        if let Some(columns) = line.strip_prefix("Format:") {
+            format = columns
+                .split(',')
+                .map(|c| c.trim().to_lowercase())
+                .collect();
+            continue;
+        }
+        let Some(values) = line.strip_prefix("Dialogue:") else {
+            continue;
+        };
+        // The text is last and may itself contain commas.
+        let values = values.splitn(format.len().max(1), ',').collect::<Vec<_>>();
+        let column = |name: &str| {
+            let i = format.iter().position(|c| c == name)?;
+            values.get(i).copied()
+        };
+        let (Some(start), Some(end), Some(text)) = (
+            column("start").and_then(parse_timestamp),
+            column("end").and_then(parse_timestamp),
+            column("text"),
+        ) else {
+            continue;
+        };
+        let text = clean_text(text);
+        if !text.is_empty() {
+            lines.push(SubtitleLine { start, end, text });
+        }
+    }
+    lines
+}
+
+// Reads an `.srt` or `.ass`/`.ssa` file in any encoding `encoding::decode`
+// detects.
+pub fn read_subtitles(path: &Path) -> Result<Vec<SubtitleLine>, Box<dyn Error>> {
+    let (text, _) = encoding::decode(&std::fs::read(path)?);
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match extension.to_lowercase().as_str() {
+        "srt" => Ok(parse_srt(&text)),
+        "ass" | "ssa" => Ok(parse_ass(&text)),
+        _ => Err(format!("unknown subtitle format {}", path.display()).into()),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum WordFilter {
+    // Lines with any of these words.
+    Targets(HashSet<String>),
+    // Lines with exactly one word that isn't known. Needs a tokenizer.
+    OneUnknown(HashSet<String>),
+}
+
+// Whether the token is a word worth mining rather than punctuation or a
+// lone kana particle.
+fn is_content_word(word: &str) -> bool {
+    let mut chars = word.chars().filter(|c| c.is_alphanumeric());
+    match (chars.next(), chars.next()) {
+        (None, _) => false,
+        (Some(c), None) => !('\u{3041}'..='\u{309F}').contains(&c),
+        _ => true,
+    }
+}
+
+fn token_word(token: &PhraseToken) -> &str {
+    token.base_form.as_deref().unwrap_or(&token.surface)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinedSentence {
+    pub line: SubtitleLine,
+    // The words the line was mined for, as they appear in it.
+    pub words: Vec<String>,
+}
+
+// The lines that pass the filter, each text once. Without a tokenizer target
+// words are matched by substring.
+pub fn mine_sentences(
+    lines: &[SubtitleLine],
+    filter: &WordFilter,
+    tokenizer: Option<&dyn Tokenizer>,
+) -> Result<Vec<MinedSentence>, Box<dyn Error>> {
+    let mut seen = HashSet::new();
+    let mut mined = vec![];
+    for line in lines {
+        if !seen.insert(line.text.as_str()) {
+            continue;
+        }
+        let words = match (filter, tokenizer) {
+            (WordFilter::Targets(targets), None) => {
+                let mut words = targets
+                    .iter()
+                    .filter(|t| line.text.contains(t.as_str()))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                words.sort();
+                words
+            }
+            (WordFilter::Targets(targets), Some(tokenizer)) => tokenizer
+                .tokenize(&line.text)?
+                .iter()
+                .filter(|t| targets.contains(token_word(t)) || targets.contains(&t.surface))
+                .map(|t| t.surface.clone())
+                .collect(),
+            (WordFilter::OneUnknown(known), Some(tokenizer)) => {
+                let unknown = tokenizer
+                    .tokenize(&line.text)?
+                    .into_iter()
+                    .filter(|t| is_content_word(token_word(t)))
+                    .filter(|t| !known.contains(token_word(t)) && !known.contains(&t.surface))
+                    .map(|t| t.surface)
+                    .collect::<HashSet<_>>();
+                match unknown.len() {
+                    1 => unknown.into_iter().collect(),
+                    _ => vec![],
+                }
+            }
+            (WordFilter::OneUnknown(_), None) => {
+                return Err("the unknown-word filter needs a tokenizer".into())
+            }
+        };
+        if !words.is_empty() {
+            mined.push(MinedSentence {
+                line: line.clone(),
+                words,
+            });
+        }
+    }
+    Ok(mined)
+}
+
+// The values of the field over the cards, e.g. the words of a vocab deck for
+// `WordFilter::OneUnknown`.
+pub fn known_words(cards: &[Card], templates: &[Template], field: &str) -> HashSet<String> {
+    ResolvedCard::resolve(cards, templates)
+        .iter()
+        .filter_map(|c| c.field(field))
+        .map(|w| w.trim().to_string())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentenceFields {
+    pub sentence: String,
+    // Gets the episode and timestamp.
+    pub source: String,
+}
+
+// The fields of the gallery's Japanese Sentence template.
+impl Default for SentenceFields {
+    fn default() -> SentenceFields {
+        SentenceFields {
+            sentence: "Sentence".to_string(),
+            source: "Notes".to_string(),
+        }
+    }
+}
+
+// A sentence card with the mined words in bold and the source as
+// `episode @ 00:12:34`.
+pub fn sentence_card(
+    deck_id: &DeckId,
+    template: &Template,
+    fields: &SentenceFields,
+    sentence: &MinedSentence,
+    episode: &str,
+) -> Result<Card, CardBuildError> {
+    let mut text = sentence.line.text.replace('\n', " ");
+    for word in sentence.words.iter() {
+        text = text.replace(word.as_str(), &format!("**{}**", word));
+    }
+    let source = format!("{} @ {}", episode, format_timestamp(sentence.line.start));
+    let mut builder = CardBuilder::new(deck_id.clone())
+        .template(template)
+        .field(&fields.sentence, &text);
+    if template.field_by_name(&fields.source).is_some() {
+        builder = builder.field(&fields.source, source.trim_start());
+    }
+    builder.build()
+}
+
+#[derive(Debug, Default)]
+pub struct MiningResult {
+    pub mined: Vec<MinedSentence>,
+    pub created: Vec<Card>,
+    // Sentences that couldn't be made into cards, by text.
+    pub failed: Vec<(String, Box<dyn Error>)>,
+}
+
+// Mines the subtitle file and, unless `dry_run`, creates a card per mined
+// sentence. The episode defaults to the file name.
+#[allow(clippy::too_many_arguments)]
+pub async fn import_subtitles(
+    config: &Config,
+    path: &Path,
+    deck_id: &DeckId,
+    template: &Template,
+    fields: &SentenceFields,
+    filter: &WordFilter,
+    tokenizer: Option<&dyn Tokenizer>,
+    episode: Option<&str>,
+    dry_run: bool,
+) -> Result<MiningResult, Box<dyn Error>> {
+    let lines = read_subtitles(path)?;
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let episode = episode.unwrap_or(stem);
+    let mut result = MiningResult {
+        mined: mine_sentences(&lines, filter, tokenizer)?,
+        ..MiningResult::default()
+    };
+    if dry_run {
+        return Ok(result);
+    }
+    for sentence in result.mined.iter() {
+        let created = match sentence_card(deck_id, template, fields, sentence, episode) {
+            Ok(card) => create_card(config, &card).await.map_err(Into::into),
+            Err(err) => Err(err.into()),
+        };
+        match created {
+            Ok(card) => result.created.push(card),
+            Err(err) => result.failed.push((sentence.line.text.clone(), err)),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gallery::JAPANESE_SENTENCE;
+
+    struct Spaces;
+
+    impl Tokenizer for Spaces {
+        fn tokenize(&self, text: &str) -> Result<Vec<PhraseToken>, Box<dyn Error>> {
+            Ok(text.split_whitespace().map(PhraseToken::new).collect())
+        }
+    }
+
+    #[test]
+    fn test_mine_sentences() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,500\n<i>犬 が 好き</i>\n\n\
+                   2\n00:12:34,100 --> 00:12:36,000\n猫 と 犬\n\n";
+        let ass = "[Script Info]\nTitle: Test\n\n[Events]\n\
+                   Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                   Dialogue: 0,0:00:01.00,0:00:02.50,Default,,0,0,0,,{\\i1}犬 が 好き{\\i0}\n\
+                   Dialogue: 0,0:12:34.10,0:12:36.00,Default,,0,0,0,,猫 と 犬\n";
+        let lines = parse_srt(srt);
+        assert_eq!(lines, parse_ass(ass));
+        assert_eq!(lines[1].start, Duration::from_millis(754_100));
+
+        let known = HashSet::from(["犬".to_string()]);
+        let mined = mine_sentences(&lines, &WordFilter::OneUnknown(known), Some(&Spaces)).unwrap();
+        assert_eq!(
+            mined.iter().map(|m| m.words.clone()).collect::<Vec<_>>(),
+            [vec!["好き".to_string()], vec!["猫".to_string()]]
+        );
+        let targets = WordFilter::Targets(HashSet::from(["猫".to_string()]));
+        let mined = mine_sentences(&lines, &targets, None).unwrap();
+        assert_eq!(mined.len(), 1);
+
+        let mut template = JAPANESE_SENTENCE.to_template();
+        template.id = "sentence".into();
+        let card = sentence_card(
+            &DeckId::from("mined"),
+            &template,
+            &SentenceFields::default(),
+            &mined[0],
+            "S01E02",
+        )
+        .unwrap();
+        let field = |name: &str| card.field_by_name(&template, name).unwrap().value.clone();
+        assert_eq!(field("Sentence"), "**猫** と 犬");
+        assert_eq!(field("Notes"), "S01E02 @ 00:12:34");
+    }
+}
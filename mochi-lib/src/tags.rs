@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::models::{Card, CardId, CardPatch, DeckId};
+use crate::{get_card, list_all_cards, list_cards, patch_cards, update_card_fields};
+use crate::{BulkResult, Config, MochiError};
+
+// Tags
+//
+// A card's tags come from its manual tags and from `#tag`s written in its
+// content, so edits touch both: manual tags are rewritten and content
+// hashtags are renamed or removed in place.
+
+// Renames (Some) or removes (None) the content's `#tag`s in one pass. A
+// hashtag is a whole whitespace separated word; the whitespace around it is
+// kept, so adjacent hashtags like `#a #a` are all found.
+fn replace_hashtags(content: &str, replacements: &HashMap<&str, Option<&str>>) -> String {
+    let mut replaced = String::with_capacity(content.len());
+    for piece in content.split_inclusive(char::is_whitespace) {
+        let word = piece.trim_end_matches(char::is_whitespace);
+        let replacement = word.strip_prefix('#').and_then(|tag| replacements.get(tag));
+        match replacement {
+            Some(Some(new_tag)) => {
+                replaced.push('#');
+                replaced.push_str(new_tag);
+            }
+            Some(None) => {}
+            None => replaced.push_str(word),
+        }
+        replaced.push_str(&piece[word.len()..]);
+    }
+    replaced
+}
+
+pub(crate) fn manual_tags(card: &Card) -> Vec<String> {
+    card.manual_tags
+        .clone()
+        .unwrap_or_else(|| card.tags.clone())
+}
+
+pub fn has_tag(card: &Card, tag: &str) -> bool {
+    card.tags.iter().any(|t| t == tag)
+        || card
+            .manual_tags
+            .as_ref()
+            .map(|tags| tags.iter().any(|t| t == tag))
+            .unwrap_or(false)
+}
+
+// The patch turning the card's tags into (tags + add - remove), renaming
+// content hashtags from `remove[i]` to `add[i]` where both are given by
+// `rename`. None if nothing changes.
+fn tag_patch(card: &Card, add: &[String], remove: &[String], rename: bool) -> Option<CardPatch> {
+    let mut tags = manual_tags(card);
+    tags.retain(|t| !remove.contains(t));
+    for tag in add.iter() {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+
+    let replacements = remove
+        .iter()
+        .enumerate()
+        .map(|(i, tag)| {
+            let new_tag = add.get(i).filter(|_| rename).map(String::as_str);
+            (tag.as_str(), new_tag)
+        })
+        .collect::<HashMap<_, _>>();
+    let content = replace_hashtags(&card.content, &replacements);
+
+    let mut patch = CardPatch::new();
+    if Some(&tags) != card.manual_tags.as_ref() && tags != card.tags {
+        patch = patch.manual_tags(&tags);
+    }
+    if content != card.content {
+        patch = patch.content(&content);
+    }
+
+    if patch.is_empty() {
+        None
+    } else {
+        Some(patch)
+    }
+}
+
+pub fn add_tags_patch(card: &Card, tags: &[String]) -> Option<CardPatch> {
+    tag_patch(card, tags, &[], false)
+}
+
+pub fn remove_tags_patch(card: &Card, tags: &[String]) -> Option<CardPatch> {
+    tag_patch(card, &[], tags, false)
+}
+
+pub fn retag_patch(card: &Card, from: &str, to: &str) -> Option<CardPatch> {
+    if !has_tag(card, from) {
+        return None;
+    }
+    tag_patch(card, &[to.to_string()], &[from.to_string()], true)
+}
+
+pub async fn add_tags(
+    config: &Config,
+    card_id: &CardId,
+    tags: &[String],
+) -> Result<Card, MochiError> {
+    let card = get_card(config, card_id).await?;
+    match add_tags_patch(&card, tags) {
+        Some(patch) => update_card_fields(config, card_id, &patch).await,
+        None => Ok(card),
+    }
+}
+
+pub async fn remove_tags(
+    config: &Config,
+    card_id: &CardId,
+    tags: &[String],
+) -> Result<Card, MochiError> {
+    let card = get_card(config, card_id).await?;
+    match remove_tags_patch(&card, tags) {
+        Some(patch) => update_card_fields(config, card_id, &patch).await,
+        None => Ok(card),
+    }
+}
+
+// Rename a tag on every card of the deck.
+pub async fn retag_deck(
+    config: &Config,
+    deck_id: &DeckId,
+    from: &str,
+    to: &str,
+) -> Result<BulkResult, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    let patches = cards
+        .iter()
+        .filter_map(|c| retag_patch(c, from, to).map(|p| (c.id.clone(), p)))
+        .collect::<Vec<_>>();
+
+    Ok(patch_cards(config, &patches).await)
+}
+
+pub async fn list_cards_by_tag(config: &Config, tag: &str) -> Result<Vec<Card>, Box<dyn Error>> {
+    let cards = list_all_cards(config).await?;
+    Ok(cards
+        .into_vec()
+        .into_iter()
+        .map(|c| c.card)
+        .filter(|c| has_tag(c, tag))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::CardFixture;
+
+    fn card(content: &str, tags: &[&str]) -> Card {
+        CardFixture::new("card", "deck")
+            .content(content)
+            .tags(tags)
+            .build()
+    }
+
+    #[test]
+    fn test_tag_patches() {
+        let c = card("犬 #noun", &["noun", "n5"]);
+
+        let patch = add_tags_patch(&c, &["animal".to_string()]).unwrap();
+        assert_eq!(
+            patch.manual_tags,
+            Some(vec![
+                "noun".to_string(),
+                "n5".to_string(),
+                "animal".to_string()
+            ])
+        );
+        assert_eq!(patch.content, None);
+        assert!(add_tags_patch(&c, &["n5".to_string()]).is_none());
+
+        let patch = remove_tags_patch(&c, &["noun".to_string()]).unwrap();
+        assert_eq!(patch.manual_tags, Some(vec!["n5".to_string()]));
+        assert_eq!(patch.content, Some("犬 ".to_string()));
+
+        let patch = retag_patch(&c, "n5", "jlpt-n5").unwrap();
+        assert_eq!(
+            patch.manual_tags,
+            Some(vec!["noun".to_string(), "jlpt-n5".to_string()])
+        );
+        let patch = retag_patch(&c, "noun", "meishi").unwrap();
+        assert_eq!(patch.content, Some("犬 #meishi".to_string()));
+        assert!(retag_patch(&c, "verb", "doushi").is_none());
+    }
+
+    #[test]
+    fn test_adjacent_hashtags() {
+        let c = card("#a #a\n#ab #a", &["a"]);
+        let patch = retag_patch(&c, "a", "b").unwrap();
+        assert_eq!(patch.content.as_deref(), Some("#b #b\n#ab #b"));
+
+        let patch = remove_tags_patch(&c, &["a".to_string()]).unwrap();
+        assert_eq!(patch.content.as_deref(), Some(" \n#ab "));
+
+        // Not a hashtag: part of a word or a heading.
+        let c = card("C# # a", &["a"]);
+        assert_eq!(
+            remove_tags_patch(&c, &["a".to_string()]).unwrap().content,
+            None
+        );
+    }
+}
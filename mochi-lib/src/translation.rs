@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{Read, Write};
+
+use crate::models::{Card, CardId, CardPatch, DeckId, ResolvedCard, Template};
+use crate::{list_cards, list_templates, patch_cards, BulkResult, Config};
+
+// Translation Export/Import
+//
+// Only the selected fields go out to a spreadsheet (one row per card, keyed by
+// card id) and only those fields come back in, so collaborators can improve
+// translations without touching anything else on the card.
+
+const CARD_ID_COLUMN: &str = "card_id";
+
+// Write a CSV with a `card_id` column and one column per field name. Cards
+// whose template has none of the fields are left out.
+pub fn export_fields<W: Write>(
+    cards: &[Card],
+    templates: &[Template],
+    field_names: &[&str],
+    writer: W,
+) -> Result<usize, Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    let mut header = vec![CARD_ID_COLUMN];
+    header.extend(field_names);
+    writer.write_record(&header)?;
+
+    let mut exported = 0;
+    for card in ResolvedCard::resolve(cards, templates) {
+        if !field_names.iter().any(|name| card.has_field(name)) {
+            continue;
+        }
+
+        let mut row = vec![card.card.id.as_str()];
+        row.extend(
+            field_names
+                .iter()
+                .map(|name| card.field(name).unwrap_or("")),
+        );
+        writer.write_record(&row)?;
+        exported += 1;
+    }
+
+    writer.flush()?;
+    Ok(exported)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldImportPlan {
+    pub patches: Vec<(CardId, CardPatch)>,
+    // Rows whose card id is not among the cards.
+    pub unknown_cards: Vec<CardId>,
+    // (card id, column) for columns the card's template has no field for.
+    pub unknown_fields: Vec<(CardId, String)>,
+}
+
+// Patches setting only the exported fields whose value changed.
+pub fn plan_field_import<R: Read>(
+    cards: &[Card],
+    templates: &[Template],
+    reader: R,
+) -> Result<FieldImportPlan, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let header = reader.headers()?.clone();
+    if header.get(0) != Some(CARD_ID_COLUMN) {
+        return Err(format!("first column must be {}", CARD_ID_COLUMN).into());
+    }
+
+    let resolved = ResolvedCard::resolve(cards, templates);
+    let cards_by_id = resolved
+        .iter()
+        .map(|c| (&c.card.id, c))
+        .collect::<HashMap<_, _>>();
+    let mut plan = FieldImportPlan::default();
+    for row in reader.records() {
+        let row = row?;
+        let card_id = CardId::from(row.get(0).unwrap_or(""));
+        let card = match cards_by_id.get(&card_id) {
+            Some(card) => card,
+            None => {
+                plan.unknown_cards.push(card_id);
+                continue;
+            }
+        };
+
+        let mut patch = CardPatch::new();
+        for (name, value) in header.iter().zip(row.iter()).skip(1) {
+            let field = card.template.as_ref().and_then(|t| t.field_by_name(name));
+            match field {
+                Some(field) if card.field(name).unwrap_or("") != value => {
+                    patch = patch.field(&field.id, value);
+                }
+                Some(_) => {}
+                None => plan
+                    .unknown_fields
+                    .push((card_id.clone(), name.to_string())),
+            }
+        }
+        if !patch.is_empty() {
+            plan.patches.push((card_id, patch));
+        }
+    }
+
+    Ok(plan)
+}
+
+pub async fn export_deck_fields<W: Write>(
+    config: &Config,
+    deck_id: &DeckId,
+    field_names: &[&str],
+    writer: W,
+) -> Result<usize, Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    let templates = list_templates(config).await?;
+    export_fields(&cards, &templates, field_names, writer)
+}
+
+// Apply an edited export back onto the deck. With `dry_run` nothing is sent.
+pub async fn import_deck_fields<R: Read>(
+    config: &Config,
+    deck_id: &DeckId,
+    reader: R,
+    dry_run: bool,
+) -> Result<(FieldImportPlan, Option<BulkResult>), Box<dyn Error>> {
+    let cards = list_cards(config, deck_id, None).await?;
+    let templates = list_templates(config).await?;
+    let plan = plan_field_import(&cards, &templates, reader)?;
+    if dry_run {
+        return Ok((plan, None));
+    }
+    let result = patch_cards(config, &plan.patches).await;
+    Ok((plan, Some(result)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+
+    #[test]
+    fn test_export_and_import_fields() {
+        let template = template_fixture(
+            "vocab",
+            "",
+            &[("name", "Word"), ("meaning", "Meaning"), ("notes", "Notes")],
+        );
+        let card = |id: &str, word: &str, meaning: &str| {
+            CardFixture::new(id, "deck")
+                .template("vocab")
+                .field("name", word)
+                .field("meaning", meaning)
+                .field("notes", "private")
+                .build()
+        };
+        let cards = [card("c1", "犬", "dog"), card("c2", "猫", "cat, kitty")];
+        let templates = [template];
+
+        let mut exported = vec![];
+        let count = export_fields(&cards, &templates, &["Word", "Meaning"], &mut exported).unwrap();
+        assert_eq!(count, 2);
+        let exported = String::from_utf8(exported).unwrap();
+        assert_eq!(
+            exported,
+            "card_id,Word,Meaning\nc1,犬,dog\nc2,猫,\"cat, kitty\"\n"
+        );
+
+        let edited = "card_id,Word,Meaning\nc1,犬,a dog\nc2,猫,\"cat, kitty\"\nc9,鳥,bird\n";
+        let plan = plan_field_import(&cards, &templates, edited.as_bytes()).unwrap();
+        assert_eq!(
+            plan.patches,
+            vec![("c1".into(), CardPatch::new().field("meaning", "a dog"))]
+        );
+        assert_eq!(plan.unknown_cards, vec![CardId::from("c9")]);
+        assert!(plan.unknown_fields.is_empty());
+
+        let renamed = "card_id,Word,Reading\nc1,犬,いぬ\n";
+        let plan = plan_field_import(&cards, &templates, renamed.as_bytes()).unwrap();
+        assert!(plan.patches.is_empty());
+        assert_eq!(
+            plan.unknown_fields,
+            vec![("c1".into(), "Reading".to_string())]
+        );
+    }
+}
@@ -0,0 +1,502 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::decks::DeckTree;
+use crate::models::{Card, CardId, CardPatch, DeckId, Template};
+use crate::tags::manual_tags;
+use crate::{list_cards_for_decks, list_templates, patch_cards, BulkResult, Config};
+
+// Markdown Vault Sync
+//
+// Each card is a Markdown file in a folder named after its deck path, with
+// its fields and tags in the front matter and its content as the body:
+//
+//   ---
+//   id: "a1b2c3d4"
+//   deck: "n5"
+//   template: "vocab"
+//   tags: ["food"]
+//   fields:
+//     "Word": "箸"
+//   ---
+//   # 箸
+//
+// Values are JSON strings, which YAML reads as double-quoted strings, so
+// Obsidian and other editors show the front matter as properties.
+//
+// The vault keeps the hash of each file as of the last sync. A file whose hash
+// changed was edited locally; a card whose rendering no longer has that hash
+// was edited in Mochi. Cards edited on both sides are conflicts and are left
+// alone. Files are only ever matched to cards by the id in their front matter,
+// so they can be renamed and moved freely.
+
+pub const STATE_FILE: &str = ".mochi-sync.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncState {
+    // File hash per card as of the last sync.
+    pub hashes: BTreeMap<CardId, String>,
+}
+
+impl SyncState {
+    pub fn load(dir: &Path) -> Result<SyncState, Box<dyn Error>> {
+        match fs::read_to_string(dir.join(STATE_FILE)) {
+            Ok(raw) => Ok(serde_json::from_str(&raw)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(SyncState::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(dir.join(STATE_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+pub fn content_hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Files
+
+fn quote(text: &str) -> String {
+    serde_json::to_string(text).unwrap()
+}
+
+pub fn render(card: &Card, template: Option<&Template>) -> String {
+    let mut out = String::from("---\n");
+    out.push_str(&format!("id: {}\n", quote(card.id.as_str())));
+    out.push_str(&format!("deck: {}\n", quote(card.deck_id.as_str())));
+    if let Some(template_id) = &card.template_id {
+        out.push_str(&format!("template: {}\n", quote(template_id.as_str())));
+    }
+    if card.archived {
+        out.push_str("archived: true\n");
+    }
+    let tags = manual_tags(card);
+    out.push_str(&format!(
+        "tags: {}\n",
+        serde_json::to_string(&tags).unwrap()
+    ));
+
+    if let Some(template) = template {
+        let mut fields = template
+            .fields
+            .iter()
+            .flat_map(|f| f.values())
+            .collect::<Vec<_>>();
+        fields.sort_by(|a, b| a.pos.cmp(&b.pos));
+        if !fields.is_empty() {
+            out.push_str("fields:\n");
+        }
+        for field in fields {
+            let value = card.field_by_name(template, &field.name);
+            let value = value.map(|f| f.value.as_str()).unwrap_or("");
+            out.push_str(&format!("  {}: {}\n", quote(&field.name), quote(value)));
+        }
+    }
+    out.push_str("---\n");
+    out.push_str(&card.content);
+    out.push('\n');
+    out
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VaultFile {
+    pub id: CardId,
+    pub deck: DeckId,
+    pub archived: bool,
+    pub tags: Vec<String>,
+    // By field name, in file order.
+    pub fields: Vec<(String, String)>,
+    pub content: String,
+}
+
+// JSON strings, or plain text for values typed in by hand.
+fn unquote(raw: &str) -> String {
+    let raw = raw.trim();
+    match serde_json::from_str::<Value>(raw) {
+        Ok(Value::String(text)) => text,
+        _ => raw.to_string(),
+    }
+}
+
+fn parse_list(raw: &str) -> Vec<String> {
+    match serde_json::from_str::<Vec<String>>(raw.trim()) {
+        Ok(items) => items,
+        Err(_) => raw
+            .trim()
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split(',')
+            .map(|item| unquote(item).trim_matches('\'').to_string())
+            .filter(|item| !item.is_empty())
+            .collect(),
+    }
+}
+
+pub fn parse(text: &str) -> Result<VaultFile, String> {
+    let text = text.replace("\r\n", "\n");
+    let rest = text.strip_prefix("---\n").ok_or("no front matter")?;
+    let (front, body) = if let Some(body) = rest.strip_prefix("---\n") {
+        ("", body)
+    } else if let Some(end) = rest.find("\n---\n") {
+        (&rest[..end], &rest[end + 5..])
+    } else if let Some(front) = rest.strip_suffix("\n---") {
+        (front, "")
+    } else {
+        return Err("unterminated front matter".to_string());
+    };
+
+    let mut file = VaultFile {
+        content: body.strip_suffix('\n').unwrap_or(body).to_string(),
+        ..VaultFile::default()
+    };
+    let mut in_fields = false;
+    for line in front.lines().filter(|l| !l.trim().is_empty()) {
+        let nested = line.starts_with([' ', '\t']);
+        let (key, value) = if nested && line.trim_start().starts_with('"') {
+            // A quoted key may contain `: `.
+            let line = line.trim_start();
+            let mut de = serde_json::Deserializer::from_str(line).into_iter::<String>();
+            let key = de.next().and_then(Result::ok).ok_or("bad field name")?;
+            let value = line[de.byte_offset()..].trim_start();
+            (
+                key,
+                value.strip_prefix(':').ok_or("missing `:`")?.to_string(),
+            )
+        } else {
+            let (key, value) = line.split_once(':').ok_or("missing `:`")?;
+            (key.trim().to_string(), value.to_string())
+        };
+
+        if nested && in_fields {
+            file.fields.push((key, unquote(&value)));
+            continue;
+        }
+        in_fields = false;
+        match key.as_str() {
+            "id" => file.id = CardId::from(unquote(&value).as_str()),
+            "deck" => file.deck = DeckId::from(unquote(&value).as_str()),
+            "archived" => file.archived = value.trim() == "true",
+            "tags" => file.tags = parse_list(&value),
+            "fields" => in_fields = true,
+            _ => {}
+        }
+    }
+    if file.id.as_str().is_empty() {
+        return Err("no id".to_string());
+    }
+    Ok(file)
+}
+
+// The card with the file's edits.
+pub fn apply(file: &VaultFile, card: &Card, template: Option<&Template>) -> Card {
+    let mut card = card.clone();
+    card.content = file.content.clone();
+    card.archived = file.archived;
+    if file.tags != manual_tags(&card) {
+        card.manual_tags = Some(file.tags.clone());
+    }
+    if let Some(template) = template {
+        for (name, value) in file.fields.iter() {
+            let current = card.field_by_name(template, name).map(|f| f.value.as_str());
+            if current.unwrap_or("") != value {
+                card.set_field_by_name(template, name, value);
+            }
+        }
+    }
+    card
+}
+
+// Deck paths as relative folders, without characters file systems reject.
+pub fn deck_folder(path: &str) -> PathBuf {
+    path.split('/')
+        .map(|part| {
+            part.chars()
+                .map(|c| match c {
+                    '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                    c if c.is_control() => '_',
+                    c => c,
+                })
+                .collect::<String>()
+                .trim_matches(['.', ' '])
+                .to_string()
+        })
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+// Every Markdown file under the directory, with its text.
+pub fn read_vault(dir: &Path) -> std::io::Result<Vec<(PathBuf, String)>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let hidden = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with('.'));
+            if hidden {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "md") {
+                let text = fs::read_to_string(&path)?;
+                files.push((path, text));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Planning
+
+#[derive(Debug, Default)]
+pub struct SyncPlan {
+    // Files to write, with the card as it will be after the sync. Includes
+    // the canonical form of pushed files.
+    pub writes: Vec<(CardId, PathBuf, String)>,
+    pub pushes: Vec<(CardId, CardPatch)>,
+    // Cards edited on both sides.
+    pub conflicts: Vec<(CardId, PathBuf)>,
+    // Synced cards whose file is gone. Nothing is trashed.
+    pub deleted_local: Vec<CardId>,
+    // Files of synced cards that are gone from the decks.
+    pub deleted_remote: Vec<PathBuf>,
+    pub invalid: Vec<(PathBuf, String)>,
+    // Cards already the same on both sides, with their file hashes.
+    pub unchanged: Vec<(CardId, String)>,
+}
+
+// `folder` is where files of new cards go.
+pub fn plan_sync(
+    cards: &[Card],
+    templates: &[Template],
+    files: &[(PathBuf, String)],
+    state: &SyncState,
+    folder: impl Fn(&DeckId) -> PathBuf,
+) -> SyncPlan {
+    let mut plan = SyncPlan::default();
+    let mut local = HashMap::new();
+    for (path, text) in files {
+        match parse(text) {
+            Ok(file) => {
+                local.insert(file.id.clone(), (path, text, file));
+            }
+            Err(err) => plan.invalid.push((path.clone(), err)),
+        }
+    }
+
+    for card in cards {
+        let template = card
+            .template_id
+            .as_ref()
+            .and_then(|id| templates.iter().find(|t| t.id == *id));
+        let rendered = render(card, template);
+        let remote_hash = content_hash(&rendered);
+        let Some((path, text, file)) = local.remove(&card.id) else {
+            if state.hashes.contains_key(&card.id) {
+                plan.deleted_local.push(card.id.clone());
+            } else {
+                let path = folder(&card.deck_id).join(format!("{}.md", card.id));
+                plan.writes.push((card.id.clone(), path, rendered));
+            }
+            continue;
+        };
+
+        let local_hash = content_hash(text);
+        if local_hash == remote_hash {
+            plan.unchanged.push((card.id.clone(), local_hash));
+            continue;
+        }
+        let base = state.hashes.get(&card.id);
+        let local_changed = base != Some(&local_hash);
+        let remote_changed = base != Some(&remote_hash);
+        if local_changed && remote_changed {
+            plan.conflicts.push((card.id.clone(), path.clone()));
+        } else if remote_changed {
+            plan.writes.push((card.id.clone(), path.clone(), rendered));
+        } else {
+            let edited = apply(&file, card, template);
+            let patch = CardPatch::between(card, &edited);
+            if !patch.is_empty() {
+                plan.pushes.push((card.id.clone(), patch));
+            }
+            plan.writes
+                .push((card.id.clone(), path.clone(), render(&edited, template)));
+        }
+    }
+
+    for (id, (path, _, _)) in local {
+        if state.hashes.contains_key(&id) {
+            plan.deleted_remote.push(path.clone());
+        }
+    }
+    plan.deleted_remote.sort();
+    plan
+}
+
+#[derive(Debug)]
+pub struct SyncReport {
+    pub plan: SyncPlan,
+    // None for a dry run.
+    pub updates: Option<BulkResult>,
+}
+
+// Syncs the decks with the vault directory. With `dry_run` only the plan is
+// returned and nothing is written on either side.
+pub async fn sync_vault(
+    config: &Config,
+    deck_ids: &[DeckId],
+    dir: &Path,
+    dry_run: bool,
+) -> Result<SyncReport, Box<dyn Error>> {
+    let mut cards_by_deck = list_cards_for_decks(config, deck_ids, None).await?;
+    let cards = deck_ids
+        .iter()
+        .filter_map(|id| cards_by_deck.remove(id))
+        .flat_map(|cards| cards.into_vec())
+        .filter(|card| card.trashed.is_none())
+        .collect::<Vec<_>>();
+    let templates = list_templates(config).await?;
+    let tree = DeckTree::load(config).await?;
+
+    fs::create_dir_all(dir)?;
+    let mut state = SyncState::load(dir)?;
+    // Files of other decks in the same vault aren't this sync's business.
+    let files = read_vault(dir)?
+        .into_iter()
+        .filter(|(_, text)| match parse(text) {
+            Ok(file) => deck_ids.contains(&file.deck),
+            Err(_) => true,
+        })
+        .collect::<Vec<_>>();
+    let plan = plan_sync(&cards, &templates, &files, &state, |deck_id| {
+        dir.join(deck_folder(
+            &tree.path(deck_id).unwrap_or(deck_id.to_string()),
+        ))
+    });
+    if dry_run {
+        return Ok(SyncReport {
+            plan,
+            updates: None,
+        });
+    }
+
+    let updates = patch_cards(config, &plan.pushes).await;
+    let failed = updates.failed_ids();
+    for (card_id, path, text) in plan.writes.iter() {
+        // Keep the local edit to push again next time.
+        if failed.contains(card_id) {
+            continue;
+        }
+        fs::create_dir_all(path.parent().unwrap_or(dir))?;
+        fs::write(path, text)?;
+        state.hashes.insert(card_id.clone(), content_hash(text));
+    }
+    for (card_id, hash) in plan.unchanged.iter() {
+        state.hashes.insert(card_id.clone(), hash.clone());
+    }
+    for card_id in plan.deleted_local.iter() {
+        state.hashes.remove(card_id);
+    }
+    state.save(dir)?;
+
+    Ok(SyncReport {
+        plan,
+        updates: Some(updates),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{template_fixture, CardFixture};
+
+    #[test]
+    fn test_plan_sync() {
+        let template = template_fixture(
+            "vocab",
+            "",
+            &[("word", "Word"), ("meaning", "Meaning: English")],
+        );
+        let card = |id: &str, meaning: &str| {
+            CardFixture::new(id, "n5")
+                .content("# << Word >>")
+                .template("vocab")
+                .field("word", "箸")
+                .field("meaning", meaning)
+                .tags(&["food"])
+                .build()
+        };
+        let templates = [template];
+
+        let original = render(&card("c1", "chopsticks"), Some(&templates[0]));
+        assert_eq!(
+            original,
+            "---\nid: \"c1\"\ndeck: \"n5\"\ntemplate: \"vocab\"\ntags: [\"food\"]\nfields:\n  \
+             \"Word\": \"箸\"\n  \"Meaning: English\": \"chopsticks\"\n---\n# << Word >>\n"
+        );
+        let file = parse(&original).unwrap();
+        assert_eq!(
+            file.fields[1],
+            ("Meaning: English".to_string(), "chopsticks".to_string())
+        );
+        assert!(parse("# << Word >>\n").is_err());
+
+        let state = SyncState {
+            hashes: BTreeMap::from([
+                (CardId::from("c1"), content_hash(&original)),
+                (
+                    CardId::from("c2"),
+                    content_hash(&original.replace("c1", "c2")),
+                ),
+            ]),
+        };
+        let edited = original.replace("chopsticks", "chopsticks (for eating)");
+        let files = [
+            (PathBuf::from("n5/c1.md"), edited),
+            (
+                PathBuf::from("n5/c2.md"),
+                original.replace("c1", "c2").replace("chopsticks", "hashi"),
+            ),
+        ];
+        let cards = [
+            card("c1", "chopsticks"),
+            card("c2", "chopstick"),
+            card("c3", ""),
+        ];
+
+        let plan = plan_sync(&cards, &templates, &files, &state, |_| PathBuf::from("n5"));
+        assert_eq!(
+            plan.pushes,
+            [(
+                CardId::from("c1"),
+                CardPatch::new().field("meaning", "chopsticks (for eating)")
+            )]
+        );
+        assert_eq!(
+            plan.conflicts,
+            [(CardId::from("c2"), PathBuf::from("n5/c2.md"))]
+        );
+        let written = plan
+            .writes
+            .iter()
+            .map(|(id, path, _)| (id.as_str(), path.to_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(written, [("c1", "n5/c1.md"), ("c3", "n5/c3.md")]);
+    }
+}
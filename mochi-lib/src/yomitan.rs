@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::dictionary::{parse_yomitan_bank, read_yomitan_banks, MergeStrategy};
+use crate::models::{Card, CardBuildError, CardBuilder, DeckId, Template};
+use crate::{
+    create_card, generate_html_for_readings, word_accents, AccentMap, Config, PitchHtmlStyle,
+};
+
+// Yomitan Deck Generator
+//
+// Builds vocab cards for a word list from Yomitan (Yomichan) dictionaries:
+// the reading and glossary from term banks, pitch accents and frequency
+// ranks from term meta banks. Dictionaries are given in priority order; the
+// reading comes from the first that has the word, glossaries from all of
+// them. A word's best (lowest) rank across the frequency dictionaries tags
+// it with the first bucket it falls in, e.g. `freq-5k`.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct YomitanTerm {
+    pub term: String,
+    pub reading: String,
+    // Definition tags, e.g. parts of speech.
+    pub tags: Vec<String>,
+    // Each definition as plain text.
+    pub glossary: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct YomitanDictionary {
+    pub title: String,
+    pub terms: HashMap<String, Vec<YomitanTerm>>,
+    // Term to (reading, if given, rank).
+    pub frequencies: HashMap<String, Vec<(Option<String>, u64)>>,
+    pub accents: AccentMap,
+}
+
+fn read_index(path: &Path) -> Result<Value, Box<dyn Error>> {
+    let mut raw = String::new();
+    if path.is_dir() {
+        raw = std::fs::read_to_string(path.join("index.json"))?;
+    } else {
+        let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+        archive.by_name("index.json")?.read_to_string(&mut raw)?;
+    }
+    Ok(serde_json::from_str(&raw)?)
+}
+
+// Structured content as text, block elements on their own lines.
+fn content_text(value: &Value, text: &mut String) {
+    match value {
+        Value::String(s) => text.push_str(s),
+        Value::Array(values) => values.iter().for_each(|v| content_text(v, text)),
+        Value::Object(object) => {
+            let tag = object.get("tag").and_then(Value::as_str).unwrap_or("");
+            let block = matches!(tag, "br" | "div" | "li" | "p" | "ol" | "ul" | "tr");
+            if block {
+                text.push('\n');
+            }
+            if let Some(content) = object.get("content") {
+                content_text(content, text);
+            }
+            if block {
+                text.push('\n');
+            }
+        }
+        _ => {}
+    }
+}
+
+// A glossary item: a string, `{"type": "text"}` or structured content. Images
+// are dropped.
+fn glossary_text(value: &Value) -> String {
+    let mut text = String::new();
+    match value["type"].as_str() {
+        Some("text") => text.push_str(value["text"].as_str().unwrap_or("")),
+        Some("structured-content") => content_text(&value["content"], &mut text),
+        Some(_) => {}
+        None => content_text(value, &mut text),
+    }
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// `[term, reading, definition tags, rules, score, glossary, sequence, term tags]`
+fn parse_term_bank(
+    raw: &str,
+    terms: &mut HashMap<String, Vec<YomitanTerm>>,
+) -> Result<(), Box<dyn Error>> {
+    let entries: Vec<Value> = serde_json::from_str(raw)?;
+    for entry in entries.iter() {
+        let (term, reading, tags, glossary) = match (&entry[0], &entry[1], &entry[2], &entry[5]) {
+            (Value::String(term), Value::String(reading), tags, Value::Array(glossary)) => {
+                (term, reading, tags, glossary)
+            }
+            _ => return Err(format!("malformed entry {}", entry).into()),
+        };
+        let glossary = glossary
+            .iter()
+            .map(glossary_text)
+            .filter(|g| !g.is_empty())
+            .collect::<Vec<_>>();
+        if glossary.is_empty() {
+            continue;
+        }
+        terms.entry(term.clone()).or_default().push(YomitanTerm {
+            term: term.clone(),
+            reading: if reading.is_empty() { term } else { reading }.clone(),
+            tags: tags
+                .as_str()
+                .unwrap_or("")
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+            glossary,
+        });
+    }
+    Ok(())
+}
+
+// A rank as a number, a string starting with one or `{"value"}`.
+fn rank(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => {
+            let digits = s
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>();
+            digits.parse().ok()
+        }
+        Value::Object(object) => rank(object.get("value")?),
+        _ => None,
+    }
+}
+
+// `[term, "freq", rank]` or `[term, "freq", {"reading", "frequency": rank}]`.
+fn parse_frequency_bank(
+    raw: &str,
+    frequencies: &mut HashMap<String, Vec<(Option<String>, u64)>>,
+) -> Result<(), Box<dyn Error>> {
+    let entries: Vec<Value> = serde_json::from_str(raw)?;
+    for entry in entries.iter() {
+        let (Some(term), Some("freq")) = (entry[0].as_str(), entry[1].as_str()) else {
+            continue;
+        };
+        let data = &entry[2];
+        let (reading, value) = match data.get("frequency") {
+            Some(frequency) => (data["reading"].as_str().map(str::to_string), frequency),
+            None => (None, data),
+        };
+        if let Some(rank) = rank(value) {
+            frequencies
+                .entry(term.to_string())
+                .or_default()
+                .push((reading, rank));
+        }
+    }
+    Ok(())
+}
+
+impl YomitanDictionary {
+    // Reads the dictionary zip or its unpacked directory.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<YomitanDictionary, Box<dyn Error>> {
+        let path = path.as_ref();
+        let index = read_index(path)?;
+        let mut dictionary = YomitanDictionary {
+            title: index["title"].as_str().unwrap_or("").to_string(),
+            ..YomitanDictionary::default()
+        };
+        for bank in read_yomitan_banks(path, "term_bank_")? {
+            parse_term_bank(&bank, &mut dictionary.terms)?;
+        }
+        for bank in read_yomitan_banks(path, "term_meta_bank_")? {
+            parse_yomitan_bank(&bank, &mut dictionary.accents)?;
+            parse_frequency_bank(&bank, &mut dictionary.frequencies)?;
+        }
+        Ok(dictionary)
+    }
+
+    // The word's entries, narrowed to the reading if any entry has it.
+    pub fn lookup(&self, word: &str, reading: Option<&str>) -> Vec<&YomitanTerm> {
+        let all = self.terms.get(word).map(|t| t.iter().collect::<Vec<_>>());
+        let all = all.unwrap_or_default();
+        let matching = all
+            .iter()
+            .filter(|t| Some(t.reading.as_str()) == reading)
+            .copied()
+            .collect::<Vec<_>>();
+        if matching.is_empty() {
+            all
+        } else {
+            matching
+        }
+    }
+
+    // The word's best rank, for the reading if the dictionary distinguishes
+    // readings.
+    pub fn rank(&self, word: &str, reading: Option<&str>) -> Option<u64> {
+        self.frequencies
+            .get(word)?
+            .iter()
+            .filter(|(r, _)| r.is_none() || reading.is_none() || r.as_deref() == reading)
+            .map(|(_, rank)| *rank)
+            .min()
+    }
+}
+
+// `word` or `word<TAB>reading` lines; blank and `#` lines are skipped.
+pub fn parse_word_list(text: &str) -> Vec<(String, Option<String>)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|line| {
+            let mut parts = line.split('\t').map(str::trim);
+            let word = parts.next().unwrap_or("").to_string();
+            let reading = parts.next().filter(|r| !r.is_empty()).map(str::to_string);
+            (word, reading)
+        })
+        .collect()
+}
+
+// The template fields the generator fills. Fields the template lacks are
+// left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VocabFields {
+    pub word: String,
+    pub reading: String,
+    pub meaning: String,
+    pub pitch_accent: String,
+}
+
+// The fields of the gallery's Japanese Vocab template.
+impl Default for VocabFields {
+    fn default() -> VocabFields {
+        VocabFields {
+            word: "Word".to_string(),
+            reading: "Reading".to_string(),
+            meaning: "Meaning".to_string(),
+            pitch_accent: "PitchAccent".to_string(),
+        }
+    }
+}
+
+pub struct DeckGenerator<'a> {
+    pub dictionaries: &'a [YomitanDictionary],
+    pub fields: VocabFields,
+    // Glossaries per dictionary, all if None.
+    pub max_glossaries: Option<usize>,
+    pub style: PitchHtmlStyle,
+    // Upper rank bounds, smallest first; a word gets the tag of the first
+    // it's within.
+    pub frequency_buckets: Vec<u64>,
+    accents: AccentMap,
+}
+
+#[derive(Debug, Default)]
+pub struct GeneratedDeck {
+    pub cards: Vec<Card>,
+    // Words no dictionary has.
+    pub not_found: Vec<String>,
+    // Cards that couldn't be built or created, by word.
+    pub failed: Vec<(String, Box<dyn Error>)>,
+}
+
+impl<'a> DeckGenerator<'a> {
+    pub fn new(dictionaries: &'a [YomitanDictionary]) -> DeckGenerator<'a> {
+        let accents = AccentMap::merge(
+            dictionaries.iter().map(|d| d.accents.clone()),
+            MergeStrategy::FirstWins,
+        );
+        DeckGenerator {
+            dictionaries,
+            fields: VocabFields::default(),
+            max_glossaries: None,
+            style: PitchHtmlStyle::default(),
+            frequency_buckets: vec![1000, 5000, 10000, 20000],
+            accents,
+        }
+    }
+
+    // `1. (n) chopsticks<br>2. ...` per dictionary, separated by `<hr>`.
+    pub fn meaning_html(&self, word: &str, reading: Option<&str>) -> String {
+        self.dictionaries
+            .iter()
+            .map(|d| d.lookup(word, reading))
+            .filter(|terms| !terms.is_empty())
+            .map(|terms| {
+                terms
+                    .iter()
+                    .flat_map(|t| t.glossary.iter().map(move |g| (&t.tags, g)))
+                    .take(self.max_glossaries.unwrap_or(usize::MAX))
+                    .enumerate()
+                    .map(|(i, (tags, gloss))| match tags.is_empty() {
+                        true => format!("{}. {}", i + 1, gloss),
+                        false => format!("{}. ({}) {}", i + 1, tags.join(", "), gloss),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("<br>")
+            })
+            .collect::<Vec<_>>()
+            .join("<hr>")
+    }
+
+    pub fn frequency_tag(&self, word: &str, reading: Option<&str>) -> Option<String> {
+        let rank = self
+            .dictionaries
+            .iter()
+            .filter_map(|d| d.rank(word, reading))
+            .min()?;
+        let bucket = self.frequency_buckets.iter().find(|b| rank <= **b)?;
+        Some(match bucket % 1000 {
+            0 => format!("freq-{}k", bucket / 1000),
+            _ => format!("freq-{}", bucket),
+        })
+    }
+
+    // The card for the word, or None if no dictionary has it.
+    pub fn card(
+        &self,
+        deck_id: &DeckId,
+        template: &Template,
+        word: &str,
+        reading: Option<&str>,
+    ) -> Option<Result<Card, CardBuildError>> {
+        let reading = reading.map(str::to_string).or_else(|| {
+            let terms = self.dictionaries.iter().map(|d| d.lookup(word, None));
+            terms.flatten().next().map(|t| t.reading.clone())
+        })?;
+        let meaning = self.meaning_html(word, Some(&reading));
+        if meaning.is_empty() {
+            return None;
+        }
+        let accents = word_accents(&word.to_string(), Some(&reading), &self.accents);
+        let pitch = match accents.is_empty() {
+            true => String::new(),
+            false => generate_html_for_readings(&accents, &self.style),
+        };
+
+        let mut builder = CardBuilder::new(deck_id.clone()).template(template);
+        let values = [
+            (&self.fields.word, word),
+            (&self.fields.reading, reading.as_str()),
+            (&self.fields.meaning, meaning.as_str()),
+            (&self.fields.pitch_accent, pitch.as_str()),
+        ];
+        for (name, value) in values {
+            if template.field_by_name(name).is_some() && !value.is_empty() {
+                builder = builder.field(name, value);
+            }
+        }
+        if let Some(tag) = self.frequency_tag(word, Some(&reading)) {
+            builder = builder.tag(&tag);
+        }
+        Some(builder.build())
+    }
+
+    // Cards for the words, in order.
+    pub fn generate(
+        &self,
+        deck_id: &DeckId,
+        template: &Template,
+        words: &[(String, Option<String>)],
+    ) -> GeneratedDeck {
+        let mut deck = GeneratedDeck::default();
+        for (word, reading) in words {
+            match self.card(deck_id, template, word, reading.as_deref()) {
+                Some(Ok(card)) => deck.cards.push(card),
+                Some(Err(err)) => deck.failed.push((word.clone(), err.into())),
+                None => deck.not_found.push(word.clone()),
+            }
+        }
+        deck
+    }
+}
+
+// Generates the cards and, unless `dry_run`, creates them in the deck. The
+// returned cards are the created ones.
+pub async fn generate_deck(
+    config: &Config,
+    generator: &DeckGenerator<'_>,
+    deck_id: &DeckId,
+    template: &Template,
+    words: &[(String, Option<String>)],
+    dry_run: bool,
+) -> GeneratedDeck {
+    let mut deck = generator.generate(deck_id, template, words);
+    if dry_run {
+        return deck;
+    }
+    let mut created = vec![];
+    for card in deck.cards.iter() {
+        let word = generator.fields.word.as_str();
+        let word = card.field_by_name(template, word).map(|f| f.value.clone());
+        match create_card(config, card).await {
+            Ok(card) => created.push(card),
+            Err(err) => deck.failed.push((word.unwrap_or_default(), err.into())),
+        }
+    }
+    deck.cards = created;
+    deck
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gallery::JAPANESE_VOCAB;
+
+    const TERMS: &str = r#"[
+        ["箸", "はし", "n", "", 0, ["chopsticks"], 1, ""],
+        ["橋", "はし", "n", "", 0, [{"type": "structured-content", "content": [
+            {"tag": "li", "content": "bridge"},
+            {"tag": "li", "content": ["span ", {"tag": "b", "content": "(over water)"}]}
+        ]}], 2, ""]
+    ]"#;
+
+    const META: &str = r#"[
+        ["箸", "pitch", {"reading": "はし", "pitches": [{"position": 1}]}],
+        ["箸", "freq", {"reading": "はし", "frequency": {"value": 4200, "displayValue": "4200㋕"}}],
+        ["橋", "freq", 25000]
+    ]"#;
+
+    #[test]
+    fn test_generate() {
+        let dir = std::env::temp_dir().join(format!("mochi-yomitan-gen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.json"), r#"{"title": "Test"}"#).unwrap();
+        std::fs::write(dir.join("term_bank_1.json"), TERMS).unwrap();
+        std::fs::write(dir.join("term_meta_bank_1.json"), META).unwrap();
+        let dictionary = YomitanDictionary::from_path(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(dictionary.title, "Test");
+        assert_eq!(
+            dictionary.lookup("橋", None)[0].glossary,
+            ["bridge; span (over water)"]
+        );
+
+        let mut template = JAPANESE_VOCAB.to_template();
+        template.id = "vocab".into();
+        let dictionaries = [dictionary];
+        let generator = DeckGenerator::new(&dictionaries);
+        let words = parse_word_list("# N5\n箸\n橋\tはし\n箸箱\n");
+        let deck = generator.generate(&DeckId::from("n5"), &template, &words);
+        assert_eq!(deck.not_found, ["箸箱"]);
+        assert_eq!(deck.cards.len(), 2);
+
+        let field =
+            |card: &Card, name: &str| card.field_by_name(&template, name).unwrap().value.clone();
+        let hashi = &deck.cards[0];
+        assert_eq!(field(hashi, "Reading"), "はし");
+        assert_eq!(field(hashi, "Meaning"), "1. (n) chopsticks");
+        assert!(field(hashi, "PitchAccent").contains("は"));
+        assert_eq!(hashi.manual_tags, Some(vec!["freq-5k".to_string()]));
+        let bridge = &deck.cards[1];
+        assert!(bridge.field_by_name(&template, "PitchAccent").is_none());
+        assert_eq!(bridge.manual_tags, None);
+    }
+}